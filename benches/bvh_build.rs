@@ -0,0 +1,46 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use ray_tracing::bvh_node::BVHNode;
+use ray_tracing::hittable_list::HittableList;
+use ray_tracing::lambertian::Lambertian;
+use ray_tracing::qbvh::QBVH;
+use ray_tracing::rtweekend::with_seed;
+use ray_tracing::sphere::Sphere;
+use ray_tracing::vector_3::Vec3;
+use std::sync::Arc;
+
+// Time to build a BVH over many spheres, reported as primitives per second
+fn bvh_build(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("bvh_build");
+    group.sample_size(10);
+    for count in [1_000, 10_000, 100_000] {
+        let list = with_seed(0, || {
+            let mat = Arc::new(Lambertian::from_color(Vec3::new(0.5, 0.5, 0.5)));
+            let mut list = HittableList::new();
+            for _ in 0..count {
+                list.add(Arc::new(Sphere::new(
+                    Vec3::random_range(-100.0, 100.0),
+                    0.5,
+                    mat.clone(),
+                )));
+            }
+            list
+        });
+
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(count),
+            &list,
+            |bencher, list| {
+                // The split axes are random, so every build starts from the same seed
+                bencher.iter(|| with_seed(0, || BVHNode::from_list(list)))
+            },
+        );
+        group.bench_with_input(BenchmarkId::new("qbvh", count), &list, |bencher, list| {
+            bencher.iter(|| QBVH::from_list(list))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bvh_build);
+criterion_main!(benches);
@@ -0,0 +1,229 @@
+use criterion::measurement::WallTime;
+use criterion::{criterion_group, criterion_main, BenchmarkGroup, Criterion, Throughput};
+use ray_tracing::aabb::AABBf;
+use ray_tracing::aabb::AABB;
+use ray_tracing::aabb4::{BoxRay, AABB4};
+use ray_tracing::bvh_node::BVHNode;
+use ray_tracing::hittable::{HitRecord, Hittable};
+use ray_tracing::hittable_list::HittableList;
+use ray_tracing::interval::{Interval, Intervalf};
+use ray_tracing::lambertian::Lambertian;
+use ray_tracing::qbvh::QBVH;
+use ray_tracing::quad::Quad;
+use ray_tracing::ray::{Ray3, Ray3f};
+use ray_tracing::rtweekend::{random_f64_range, with_seed};
+use ray_tracing::simd::Vec3x4;
+use ray_tracing::sphere::Sphere;
+use ray_tracing::vector_3::{Vec3, Vec3f};
+use std::hint::black_box;
+use std::sync::Arc;
+
+// Rays per iteration, so the throughput criterion reports is rays per second
+const RAYS: usize = 4096;
+
+// Rays from a shell around the origin towards points scattered over a box of the given
+// half size, so some hit the object at the centre and some miss
+fn random_rays(spread: f64) -> Vec<Ray3> {
+    with_seed(0, || {
+        (0..RAYS)
+            .map(|_| {
+                let origin = Vec3::random_unit_vector() * 8.0;
+                let target = Vec3::random_range(-spread, spread);
+                Ray3::new(origin, target - origin, 0.0)
+            })
+            .collect()
+    })
+}
+
+fn trace(group: &mut BenchmarkGroup<WallTime>, name: &str, object: &dyn Hittable, spread: f64) {
+    let rays = random_rays(spread);
+    group.bench_function(name, |bencher| {
+        bencher.iter(|| {
+            let mut hits = 0;
+            let mut record = HitRecord::new();
+            for ray in &rays {
+                if object.hit(ray, Interval::new(0.001, f64::INFINITY), &mut record) {
+                    hits += 1;
+                }
+            }
+            black_box(hits)
+        })
+    });
+}
+
+fn primitives(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("intersection");
+    group.throughput(Throughput::Elements(RAYS as u64));
+
+    let mat = Arc::new(Lambertian::from_color(Vec3::new(0.5, 0.5, 0.5)));
+    let sphere = Sphere::new(Vec3::empty(), 1.0, mat.clone());
+    trace(&mut group, "sphere", &sphere, 1.5);
+
+    let quad = Quad::new(
+        Vec3::new(-1.0, -1.0, 0.0),
+        Vec3::new(2.0, 0.0, 0.0),
+        Vec3::new(0.0, 2.0, 0.2),
+        mat,
+    );
+    trace(&mut group, "quad", &quad, 1.5);
+
+    let bbox = AABB::from_vec3s(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0));
+    let rays = random_rays(1.5);
+    group.bench_function("aabb", |bencher| {
+        bencher.iter(|| {
+            rays.iter()
+                .filter(|ray| bbox.hit(ray, Interval::new(0.001, f64::INFINITY)))
+                .count()
+        })
+    });
+
+    // Four boxes around the origin, one at a time and all together
+    let boxes: Vec<AABB> = [(-1.0, -1.0), (0.0, -1.0), (-1.0, 0.0), (0.0, 0.0)]
+        .iter()
+        .map(|&(x, y)| AABB::from_vec3s(Vec3::new(x, y, -1.0), Vec3::new(x + 1.0, y + 1.0, 1.0)))
+        .collect();
+    group.bench_function("aabb x4", |bencher| {
+        bencher.iter(|| {
+            let interval = Interval::new(0.001, f64::INFINITY);
+            rays.iter()
+                .map(|ray| {
+                    boxes
+                        .iter()
+                        .filter(|bbox| bbox.hit(ray, interval.copy()))
+                        .count()
+                })
+                .sum::<usize>()
+        })
+    });
+    // Traversals prepare the ray once for all the nodes they visit, so that is left out
+    let aabb4 = AABB4::new(&boxes);
+    let box_rays: Vec<BoxRay> = rays.iter().map(BoxRay::new).collect();
+    group.bench_function("aabb4", |bencher| {
+        bencher.iter(|| {
+            let interval = Interval::new(0.001, f64::INFINITY);
+            box_rays
+                .iter()
+                .map(|ray| aabb4.hit(ray, &interval).0.count_ones())
+                .sum::<u32>()
+        })
+    });
+    group.finish();
+}
+
+// Closest hits through a BVH over small spheres filling a box, like the scenes do
+fn bvh_traversal(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("intersection");
+    group.throughput(Throughput::Elements(RAYS as u64));
+    group.sample_size(10);
+    for count in [1_000, 100_000] {
+        let list = with_seed(1, || {
+            let mat = Arc::new(Lambertian::from_color(Vec3::new(0.5, 0.5, 0.5)));
+            let mut list = HittableList::new();
+            let radius = 0.5 / (count as f64).cbrt();
+            for _ in 0..count {
+                list.add(Arc::new(Sphere::new(
+                    Vec3::random_range(-2.0, 2.0),
+                    radius * random_f64_range(0.5, 1.5),
+                    mat.clone(),
+                )));
+            }
+            list
+        });
+        let bvh = with_seed(1, || BVHNode::from_list(&list));
+        trace(&mut group, &format!("bvh/{} spheres", count), &bvh, 2.5);
+        let qbvh = QBVH::from_list(&list);
+        trace(&mut group, &format!("qbvh/{} spheres", count), &qbvh, 2.5);
+    }
+    group.finish();
+}
+
+// The core math in f64 and in f32 over the same rays, and vector operations on one
+// vector at a time against four at once in SIMD lanes
+fn precision(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("precision");
+    group.throughput(Throughput::Elements(RAYS as u64));
+    let rays = random_rays(1.5);
+    let rays_f: Vec<Ray3f> = rays
+        .iter()
+        .map(|ray| Ray3f::new(ray.origin().cast(), ray.direction().cast(), 0.0))
+        .collect();
+
+    group.bench_function("sphere f64", |bencher| {
+        let center = Vec3::empty();
+        bencher.iter(|| {
+            let interval = Interval::new(0.001, f64::INFINITY);
+            rays.iter()
+                .filter_map(|ray| Sphere::intersect(&center, 1.0, ray, &interval))
+                .count()
+        })
+    });
+    group.bench_function("sphere f32", |bencher| {
+        let center = Vec3f::empty();
+        bencher.iter(|| {
+            let interval = Intervalf::new(0.001, f32::INFINITY);
+            rays_f
+                .iter()
+                .filter_map(|ray| Sphere::intersect(&center, 1.0, ray, &interval))
+                .count()
+        })
+    });
+
+    let bbox = AABB::from_vec3s(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0));
+    let bbox_f = AABBf::from_vec3s(Vec3f::new(-1.0, -1.0, -1.0), Vec3f::new(1.0, 1.0, 1.0));
+    group.bench_function("aabb f64", |bencher| {
+        bencher.iter(|| {
+            rays.iter()
+                .filter(|ray| bbox.hit(ray, Interval::new(0.001, f64::INFINITY)))
+                .count()
+        })
+    });
+    group.bench_function("aabb f32", |bencher| {
+        bencher.iter(|| {
+            rays_f
+                .iter()
+                .filter(|ray| bbox_f.hit(ray, Intervalf::new(0.001, f32::INFINITY)))
+                .count()
+        })
+    });
+
+    // Cross and dot products of each ray's origin and direction
+    group.bench_function("vec3 f64", |bencher| {
+        bencher.iter(|| {
+            rays.iter()
+                .map(|ray| ray.origin().cross(ray.direction()).dot(ray.origin()))
+                .sum::<f64>()
+        })
+    });
+    group.bench_function("vec3 f32", |bencher| {
+        bencher.iter(|| {
+            rays_f
+                .iter()
+                .map(|ray| ray.origin().cross(ray.direction()).dot(ray.origin()))
+                .sum::<f32>()
+        })
+    });
+    let lanes: Vec<(Vec3x4, Vec3x4)> = rays_f
+        .chunks_exact(4)
+        .map(|rays| {
+            let origins = std::array::from_fn(|n| *rays[n].origin());
+            let directions = std::array::from_fn(|n| *rays[n].direction());
+            (
+                Vec3x4::from_vectors(origins),
+                Vec3x4::from_vectors(directions),
+            )
+        })
+        .collect();
+    group.bench_function("vec3x4", |bencher| {
+        bencher.iter(|| {
+            lanes
+                .iter()
+                .map(|&(origin, direction)| origin.cross(direction).dot(origin).to_array())
+                .map(|sums| sums.iter().sum::<f32>())
+                .sum::<f32>()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, primitives, bvh_traversal, precision);
+criterion_main!(benches);
@@ -0,0 +1,88 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use ray_tracing::accelerator::Accelerator;
+use ray_tracing::film::Film;
+use ray_tracing::render_progress::CancelToken;
+use ray_tracing::rtweekend::with_seed;
+use ray_tracing::scenes::{build_scene, SCENE_COUNT};
+use std::sync::Arc;
+
+const WIDTH: i32 = 64;
+const SAMPLES: i32 = 4;
+const DEPTH: i32 = 8;
+
+// The figure quoted for the Book 1 scene: its final render at 200 pixels wide, 16
+// samples per pixel and the default depth of 50, on one thread
+const BOOK1_WIDTH: i32 = 200;
+const BOOK1_SAMPLES: i32 = 16;
+const BOOK1_DEPTH: i32 = 50;
+
+// Full frames of every built-in scene at a fixed number of samples. The throughput is
+// camera rays, one per sample, whatever number of bounces each path takes. Renders run
+// on one thread so the numbers don't depend on the machine's core count.
+fn render(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("render");
+    group.sample_size(10);
+    for index in 1..=SCENE_COUNT {
+        let mut scene = with_seed(0, || {
+            build_scene(index, WIDTH, SAMPLES, DEPTH, Accelerator::BVHNode)
+        });
+        scene.cam.initalize();
+        let (width, height) = (scene.cam.render_width(), scene.cam.render_height());
+
+        group.throughput(Throughput::Elements(
+            width as u64 * height as u64 * SAMPLES as u64,
+        ));
+        group.bench_function(scene.name, |bencher| {
+            bencher.iter(|| {
+                let mut film = Film::new(width, height);
+                scene.cam.render_film(
+                    Arc::clone(&scene.world),
+                    scene.lights.as_ref().map(Arc::clone),
+                    1,
+                    &mut film,
+                    SAMPLES,
+                    |_| {},
+                    &CancelToken::new(),
+                );
+                film
+            })
+        });
+    }
+    group.finish();
+}
+
+// The Book 1 scene in each hierarchy. Its spheres are all drawn before the hierarchy is
+// built, so both render the same geometry.
+fn book1(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("book1");
+    group.sample_size(16);
+    for (name, accelerator) in [("bvh", Accelerator::BVHNode), ("qbvh", Accelerator::QBVH)] {
+        let mut scene = with_seed(0, || {
+            build_scene(1, BOOK1_WIDTH, BOOK1_SAMPLES, BOOK1_DEPTH, accelerator)
+        });
+        scene.cam.initalize();
+        let (width, height) = (scene.cam.render_width(), scene.cam.render_height());
+        group.throughput(Throughput::Elements(
+            width as u64 * height as u64 * BOOK1_SAMPLES as u64,
+        ));
+        group.bench_function(name, |bencher| {
+            bencher.iter(|| {
+                let mut film = Film::new(width, height);
+                scene.cam.render_film(
+                    Arc::clone(&scene.world),
+                    scene.lights.as_ref().map(Arc::clone),
+                    1,
+                    &mut film,
+                    BOOK1_SAMPLES,
+                    |_| {},
+                    &CancelToken::new(),
+                );
+                film
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, render, book1);
+criterion_main!(benches);
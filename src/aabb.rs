@@ -1,78 +1,99 @@
 use std::ops;
 
-use crate::interval::Interval;
-use crate::ray::Ray3;
-use crate::vector_3::Vec3;
-pub struct AABB {
-    x: Interval,
-    y: Interval,
-    z: Interval,
+use crate::interval::Span;
+use crate::ray::Ray;
+use crate::ray_stats::{self, Counter};
+use crate::real::Real;
+use crate::vector_3::Vector3;
+
+// Generic over the precision like `Vector3`: `AABB` bounds the scene's objects and
+// `AABBf` is the f32 box
+#[derive(Debug)]
+pub struct BoundingBox<T> {
+    x: Span<T>,
+    y: Span<T>,
+    z: Span<T>,
+}
+
+pub type AABB = BoundingBox<f64>;
+pub type AABBf = BoundingBox<f32>;
+
+// 1 + 2 * gamma(3) from PBRT, which bounds the rounding error of the slab distances
+fn rounding_bound<T: Real>() -> T {
+    let gamma = T::from_f64(1.5) * T::EPSILON;
+    T::ONE + (T::ONE + T::ONE) * gamma / (T::ONE - gamma)
 }
 
-impl AABB {
-    pub fn new(x: Interval, y: Interval, z: Interval) -> AABB {
-        AABB { x, y, z }
+impl<T: Real> BoundingBox<T> {
+    pub fn new(x: Span<T>, y: Span<T>, z: Span<T>) -> BoundingBox<T> {
+        BoundingBox { x, y, z }
     }
 
-    pub fn copy(&self) -> AABB {
-        AABB::new(
-            Interval::copy(&self.x),
-            Interval::copy(&self.y),
-            Interval::copy(&self.z),
-        )
+    pub fn copy(&self) -> BoundingBox<T> {
+        BoundingBox::new(self.x.copy(), self.y.copy(), self.z.copy())
     }
 
-    pub fn empty() -> AABB {
-        AABB::new(Interval::empty(), Interval::empty(), Interval::empty())
+    pub fn empty() -> BoundingBox<T> {
+        BoundingBox::new(Span::empty(), Span::empty(), Span::empty())
     }
 
-    pub fn from_vec3s(a: Vec3, b: Vec3) -> AABB {
-        let x = Interval::new(a.x.min(b.x), a.x.max(b.x));
-        let y = Interval::new(a.y.min(b.y), a.y.max(b.y));
-        let z = Interval::new(a.z.min(b.z), a.z.max(b.z));
-        AABB::new(x, y, z)
+    pub fn from_vec3s(a: Vector3<T>, b: Vector3<T>) -> BoundingBox<T> {
+        let x = Span::new(a.x.min(b.x), a.x.max(b.x));
+        let y = Span::new(a.y.min(b.y), a.y.max(b.y));
+        let z = Span::new(a.z.min(b.z), a.z.max(b.z));
+        BoundingBox::new(x, y, z)
     }
 
-    pub fn from_aabbs(a: &AABB, b: &AABB) -> AABB {
-        let x = Interval::from_intervals(Interval::copy(&a.x), Interval::copy(&b.x));
-        let y = Interval::from_intervals(Interval::copy(&a.y), Interval::copy(&b.y));
-        let z = Interval::from_intervals(Interval::copy(&a.z), Interval::copy(&b.z));
-        AABB::new(x, y, z)
+    pub fn from_aabbs(a: &BoundingBox<T>, b: &BoundingBox<T>) -> BoundingBox<T> {
+        let x = Span::from_intervals(a.x.copy(), b.x.copy());
+        let y = Span::from_intervals(a.y.copy(), b.y.copy());
+        let z = Span::from_intervals(a.z.copy(), b.z.copy());
+        BoundingBox::new(x, y, z)
     }
 
-    pub fn axis(&self, n: u32) -> Interval {
+    pub fn axis(&self, n: u32) -> Span<T> {
         if n == 0 {
-            Interval::copy(&self.x)
+            self.x.copy()
         } else if n == 1 {
-            Interval::copy(&self.y)
+            self.y.copy()
         } else {
-            Interval::copy(&self.z)
+            self.z.copy()
         }
     }
 
-    pub fn hit(&self, ray_in: &Ray3, ray_interval: Interval) -> bool {
-        let mut ray_interval = ray_interval;
-        let mut hit = true;
-        for a in 0..2 {
-            let inv_d = 1.0 / ray_in.direction().at(a);
-            let origin = ray_in.origin().at(a);
-            let mut t0 = (self.axis(a).min() - origin) * inv_d;
-            let mut t1 = (self.axis(a).max() - origin) * inv_d;
-            if inv_d < 0.0 {
-                std::mem::swap(&mut t0, &mut t1);
+    // Slab test over the three axes. A direction component of zero divides to an
+    // infinity, which puts the slab at -inf..inf when the origin is between its planes
+    // and out of reach when it isn't. An origin exactly on one of the planes gives
+    // 0 * inf = NaN, and min and max drop NaNs, so that end of the slab doesn't limit
+    // the ray and a ray running along a face counts as hitting the box.
+    pub fn hit(&self, ray_in: &Ray<T>, ray_interval: Span<T>) -> bool {
+        ray_stats::count(Counter::AabbTest);
+        let origin = ray_in.origin();
+        let inv_direction = ray_in.inverse_direction();
+        let slab = |interval: &Span<T>, origin: T, inv_d: T| {
+            let t0 = (interval.min() - origin) * inv_d;
+            let t1 = (interval.max() - origin) * inv_d;
+            // Ordered by the direction's sign rather than by value, so an empty
+            // interval stays empty
+            if inv_d < T::ZERO {
+                (t1, t0)
+            } else {
+                (t0, t1)
             }
-            ray_interval.set_min(t0.max(ray_interval.min()));
-            ray_interval.set_max(t1.min(ray_interval.max()));
+        };
+        let (x_near, x_far) = slab(&self.x, origin.x, inv_direction.x);
+        let (y_near, y_far) = slab(&self.y, origin.y, inv_direction.y);
+        let (z_near, z_far) = slab(&self.z, origin.z, inv_direction.z);
 
-            if ray_interval.max() <= ray_interval.min() {
-                hit = false
-            }
-        }
-        hit
+        let t_min = ray_interval.min().max(x_near).max(y_near).max(z_near);
+        // Each distance is off by at most a few rounding errors, so the far end is
+        // pushed out by their bound to never lose a ray that grazes the box
+        let t_max = ray_interval.max().min(x_far).min(y_far).min(z_far) * rounding_bound();
+        t_min <= t_max
     }
 
-    pub fn pad(&self) -> AABB {
-        let delta = 0.0001;
+    pub fn pad(&self) -> BoundingBox<T> {
+        let delta = T::from_f64(0.0001);
         let x = if self.x.size() < delta {
             self.x.expand(delta)
         } else {
@@ -88,16 +109,16 @@ impl AABB {
         } else {
             self.z.copy()
         };
-        AABB::new(x, y, z)
+        BoundingBox::new(x, y, z)
     }
 }
 
 // Add
-impl ops::Add<Vec3> for AABB {
-    type Output = AABB;
+impl<T: Real> ops::Add<Vector3<T>> for BoundingBox<T> {
+    type Output = BoundingBox<T>;
 
-    fn add(self, rhs: Vec3) -> AABB {
-        AABB {
+    fn add(self, rhs: Vector3<T>) -> BoundingBox<T> {
+        BoundingBox {
             x: self.x + rhs.x,
             y: self.y + rhs.y,
             z: self.z + rhs.z,
@@ -105,38 +126,268 @@ impl ops::Add<Vec3> for AABB {
     }
 }
 
-impl<'a> ops::Add<Vec3> for &'a AABB {
-    type Output = AABB;
+impl<T: Real> ops::Add<Vector3<T>> for &BoundingBox<T> {
+    type Output = BoundingBox<T>;
 
-    fn add(self, rhs: Vec3) -> AABB {
-        AABB {
-            x: self.x.copy() + rhs.x,
-            y: self.y.copy() + rhs.y,
-            z: self.z.copy() + rhs.z,
-        }
+    fn add(self, rhs: Vector3<T>) -> BoundingBox<T> {
+        self.copy() + rhs
     }
 }
 
-impl ops::Add<AABB> for Vec3 {
-    type Output = AABB;
+impl<T: Real> ops::Add<BoundingBox<T>> for Vector3<T> {
+    type Output = BoundingBox<T>;
 
-    fn add(self, rhs: AABB) -> AABB {
-        AABB {
-            x: self.x + rhs.x,
-            y: self.y + rhs.y,
-            z: self.z + rhs.z,
-        }
+    fn add(self, rhs: BoundingBox<T>) -> BoundingBox<T> {
+        rhs + self
     }
 }
 
-impl<'a> ops::Add<&'a AABB> for Vec3 {
-    type Output = AABB;
+impl<'a, T: Real> ops::Add<&'a BoundingBox<T>> for Vector3<T> {
+    type Output = BoundingBox<T>;
+
+    fn add(self, rhs: &'a BoundingBox<T>) -> BoundingBox<T> {
+        rhs.copy() + self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::{HitRecord, Hittable};
+    use crate::interval::{Interval, Intervalf};
+    use crate::lambertian::Lambertian;
+    use crate::quad::Quad;
+    use crate::ray::{Ray3, Ray3f};
+    use crate::rtweekend::{random_f64, random_f64_range, with_seed};
+    use crate::vector_3::{Vec3, Vec3f};
+    use std::sync::Arc;
+
+    fn unit_box() -> AABB {
+        AABB::from_vec3s(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0))
+    }
 
-    fn add(self, rhs: &'a AABB) -> AABB {
-        AABB {
-            x: self.x + rhs.x.copy(),
-            y: self.y + rhs.y.copy(),
-            z: self.z + rhs.z.copy(),
+    fn hits(bbox: &AABB, origin: Vec3, direction: Vec3) -> bool {
+        bbox.hit(
+            &Ray3::new(origin, direction, 0.0),
+            Interval::new(0.001, f64::INFINITY),
+        )
+    }
+
+    #[test]
+    fn rejects_boxes_apart_in_z() {
+        let bbox = AABB::from_vec3s(Vec3::new(-1.0, -1.0, 5.0), Vec3::new(1.0, 1.0, 6.0));
+        assert!(!hits(
+            &bbox,
+            Vec3::new(-3.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0)
+        ));
+        assert!(hits(
+            &bbox,
+            Vec3::new(-3.0, 0.0, 5.5),
+            Vec3::new(1.0, 0.0, 0.0)
+        ));
+    }
+
+    #[test]
+    fn axis_aligned_rays() {
+        let bbox = unit_box();
+        for axis in 0..3 {
+            for sign in [1.0, -1.0] {
+                let mut direction = Vec3::empty();
+                direction.set(axis, sign);
+                let mut origin = Vec3::new(0.5, -0.5, 0.25);
+                origin.set(axis, -3.0 * sign);
+                assert!(hits(&bbox, origin, direction));
+                // Pointing away, and beside the box
+                assert!(!hits(&bbox, origin, -1.0 * direction));
+                let mut beside = origin;
+                beside.set((axis + 1) % 3, 1.5);
+                assert!(!hits(&bbox, beside, direction));
+            }
         }
+        // A negative zero component divides to -inf, which must work the same
+        assert!(hits(
+            &bbox,
+            Vec3::new(0.0, -3.0, 0.0),
+            Vec3::new(-0.0, 1.0, -0.0)
+        ));
+        assert!(!hits(
+            &bbox,
+            Vec3::new(2.0, -3.0, 0.0),
+            Vec3::new(-0.0, 1.0, 0.0)
+        ));
+    }
+
+    #[test]
+    fn rays_along_a_face() {
+        let bbox = unit_box();
+        // In the plane of a face the slab gives NaN, which must not reject the ray
+        assert!(hits(
+            &bbox,
+            Vec3::new(1.0, -3.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0)
+        ));
+        assert!(hits(
+            &bbox,
+            Vec3::new(-1.0, 1.0, -3.0),
+            Vec3::new(0.0, 0.0, 1.0)
+        ));
+        // Just outside the face, parallel to it
+        assert!(!hits(
+            &bbox,
+            Vec3::new(1.0 + 1e-9, -3.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0)
+        ));
+    }
+
+    #[test]
+    fn grazing_rays() {
+        let bbox = unit_box();
+        // Rays through the box's edges and corners
+        assert!(hits(
+            &bbox,
+            Vec3::new(-3.0, -3.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0)
+        ));
+        assert!(hits(
+            &bbox,
+            Vec3::new(-3.0, -3.0, -3.0),
+            Vec3::new(1.0, 1.0, 1.0)
+        ));
+        assert!(hits(
+            &bbox,
+            Vec3::new(-3.0, 1.0, 0.0),
+            Vec3::new(1.0, -1e-12, 0.0)
+        ));
+        assert!(!hits(
+            &bbox,
+            Vec3::new(-3.0, 1.0 + 1e-6, 0.0),
+            Vec3::new(1.0, 1e-9, 0.0)
+        ));
+    }
+
+    #[test]
+    fn respects_the_ray_interval() {
+        let bbox = unit_box();
+        let ray = Ray3::new(Vec3::new(-3.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        assert!(bbox.hit(&ray, Interval::new(0.0, 2.5)));
+        assert!(!bbox.hit(&ray, Interval::new(0.0, 1.5)));
+        assert!(!bbox.hit(&ray, Interval::new(4.5, f64::INFINITY)));
+    }
+
+    #[test]
+    fn degenerate_boxes_and_directions() {
+        assert!(!hits(
+            &AABB::empty(),
+            Vec3::empty(),
+            Vec3::new(1.0, 0.0, 0.0)
+        ));
+        assert!(!hits(
+            &AABB::empty(),
+            Vec3::empty(),
+            Vec3::new(0.0, 0.0, 0.0)
+        ));
+        // An infinite component leaves that slab at once, so only t = 0 is inside it
+        let bbox = unit_box();
+        let fast = Vec3::new(f64::INFINITY, 0.0, 0.0);
+        assert!(!hits(&bbox, Vec3::new(-3.0, 0.0, 0.0), fast));
+        assert!(bbox.hit(
+            &Ray3::new(Vec3::new(-3.0, 0.0, 0.0), fast, 0.0),
+            Interval::new(0.0, f64::INFINITY)
+        ));
+    }
+
+    #[test]
+    fn thin_quad_boxes_are_never_missed() {
+        with_seed(7, || {
+            let mat = Arc::new(Lambertian::from_color(Vec3::new(0.5, 0.5, 0.5)));
+            // Axis aligned, so their boxes are flat before padding, at awkward offsets
+            let quads = [
+                (
+                    Vec3::new(0.1, 0.2, 0.3),
+                    Vec3::new(1.7, 0.0, 0.0),
+                    Vec3::new(0.0, 2.3, 0.0),
+                ),
+                (
+                    Vec3::new(555.0, 0.0, 0.0),
+                    Vec3::new(0.0, 555.0, 0.0),
+                    Vec3::new(0.0, 0.0, 555.0),
+                ),
+                (
+                    Vec3::new(-1e3, 1e-3, -1e3),
+                    Vec3::new(2e3, 0.0, 0.0),
+                    Vec3::new(0.0, 0.0, 2e3),
+                ),
+            ];
+            for (corner, u, v) in quads {
+                let quad = Quad::new(corner, u, v, mat.clone());
+                let bbox = quad.bounding_box();
+                let mut quad_hits = 0;
+                for _ in 0..20_000 {
+                    // Towards a point on the quad from any side, so some rays graze it
+                    let target = corner + random_f64() * u + random_f64() * v;
+                    let direction = Vec3::random_unit_vector();
+                    let distance = random_f64_range(1e-2, 1e4);
+                    let origin = target - distance * direction;
+                    let ray = Ray3::new(origin, direction, 0.0);
+                    let interval = Interval::new(0.001, f64::INFINITY);
+                    if quad.hit(&ray, interval.copy(), &mut HitRecord::new()) {
+                        quad_hits += 1;
+                        assert!(
+                            bbox.hit(&ray, interval),
+                            "ray from {:?} along {:?} missed the box",
+                            origin,
+                            direction
+                        );
+                    }
+                }
+                assert!(quad_hits > 10_000);
+            }
+        })
+    }
+
+    #[test]
+    fn f32_boxes() {
+        let bbox = AABBf::from_vec3s(Vec3f::new(-1.0, -1.0, -1.0), Vec3f::new(1.0, 1.0, 1.0));
+        let hits = |origin: Vec3f, direction: Vec3f| {
+            bbox.hit(
+                &Ray3f::new(origin, direction, 0.0),
+                Intervalf::new(0.001, f32::INFINITY),
+            )
+        };
+        assert!(hits(Vec3f::new(-3.0, 0.5, 0.25), Vec3f::new(1.0, 0.0, 0.0)));
+        assert!(!hits(
+            Vec3f::new(-3.0, 0.5, 0.25),
+            Vec3f::new(-1.0, 0.0, 0.0)
+        ));
+        assert!(!hits(
+            Vec3f::new(-3.0, 1.5, 0.25),
+            Vec3f::new(1.0, 0.0, 0.0)
+        ));
+        assert!(hits(Vec3f::new(1.0, -3.0, 0.0), Vec3f::new(0.0, 1.0, 0.0)));
+        assert!(hits(
+            Vec3f::new(-3.0, -3.0, -3.0),
+            Vec3f::new(1.0, 1.0, 1.0)
+        ));
+        assert!(!hits(
+            Vec3f::new(1.0 + 1e-6, -3.0, 0.0),
+            Vec3f::new(0.0, 1.0, 0.0)
+        ));
+        let ray = Ray3f::new(Vec3f::new(-3.0, 0.0, 0.0), Vec3f::new(1.0, 0.0, 0.0), 0.0);
+        assert!(bbox.hit(&ray, Intervalf::new(0.0, 2.5)));
+        assert!(!bbox.hit(&ray, Intervalf::new(0.0, 1.5)));
+        // The same boxes and rays in f64 agree
+        with_seed(11, || {
+            for _ in 0..10_000 {
+                let origin = 3.0 * Vec3::random_range(-1.0, 1.0);
+                let direction = Vec3::random_unit_vector();
+                let (origin_f, direction_f): (Vec3f, Vec3f) = (origin.cast(), direction.cast());
+                let expected = unit_box().hit(
+                    &Ray3::new(origin_f.cast(), direction_f.cast(), 0.0),
+                    Interval::new(0.001, f64::INFINITY),
+                );
+                assert_eq!(hits(origin_f, direction_f), expected);
+            }
+        })
     }
 }
@@ -0,0 +1,234 @@
+use crate::aabb::AABB;
+use crate::interval::Interval;
+use crate::ray::Ray3;
+use crate::ray_stats::{self, Counter};
+use crate::simd::{round_down, round_up, F32x4, Vec3x4};
+use crate::vector_3::Vec3f;
+
+// 1 + 2 * gamma(3) in f32, for the subtraction, the multiplication and the rounded
+// inverse direction behind each slab distance
+const ROUNDING_BOUND: f32 =
+    1.0 + 2.0 * (3.0 * f32::EPSILON * 0.5) / (1.0 - 3.0 * f32::EPSILON * 0.5);
+
+// Four boxes in f32, one per lane, stored axis by axis so they are tested together.
+// The corners are rounded outwards, so each box holds its f64 box.
+#[derive(Debug, Clone, Copy)]
+pub struct AABB4 {
+    // Minimum then maximum corners
+    bounds: [Vec3x4; 2],
+}
+
+// A ray prepared for AABB4 tests, worth making once per traversal. Its f64 origin falls
+// between two f32 values, and each slab distance uses whichever of the two keeps the
+// box largest, so rounding the origin can't lose a hit either.
+pub struct BoxRay {
+    near_side: [usize; 3],
+    near_origin: Vec3x4,
+    far_origin: Vec3x4,
+    inv_direction: Vec3x4,
+}
+
+impl AABB4 {
+    // Lanes without a box hold empty ones, which no ray hits
+    pub fn new(boxes: &[AABB]) -> AABB4 {
+        assert!(boxes.len() <= 4, "an AABB4 holds at most four boxes");
+        let corner = |axis: u32, max: bool| {
+            let empty = if max {
+                f32::NEG_INFINITY
+            } else {
+                f32::INFINITY
+            };
+            let mut lanes = [empty; 4];
+            for (lane, bbox) in lanes.iter_mut().zip(boxes) {
+                let interval = bbox.axis(axis);
+                *lane = if max {
+                    round_up(interval.max())
+                } else {
+                    round_down(interval.min())
+                };
+            }
+            F32x4::from_array(lanes)
+        };
+        AABB4 {
+            bounds: [
+                Vec3x4::new(corner(0, false), corner(1, false), corner(2, false)),
+                Vec3x4::new(corner(0, true), corner(1, true), corner(2, true)),
+            ],
+        }
+    }
+
+    pub fn empty() -> AABB4 {
+        AABB4::new(&[])
+    }
+
+    // Slab test of all four boxes, the same as AABB::hit. Bit n of the mask is set when
+    // box n is hit, and the distances are where the ray enters each box.
+    pub fn hit(&self, ray: &BoxRay, ray_interval: &Interval) -> (u32, F32x4) {
+        ray_stats::count(Counter::AabbTest);
+        let t_min = F32x4::splat(round_down(ray_interval.min()));
+        let t_max = F32x4::splat(round_up(ray_interval.max()));
+        let [near_x, near_y, near_z] = ray.near_side;
+        let near_corner = Vec3x4::new(
+            self.bounds[near_x].x,
+            self.bounds[near_y].y,
+            self.bounds[near_z].z,
+        );
+        let far_corner = Vec3x4::new(
+            self.bounds[1 - near_x].x,
+            self.bounds[1 - near_y].y,
+            self.bounds[1 - near_z].z,
+        );
+        let near = (near_corner - ray.near_origin) * ray.inv_direction;
+        let far = (far_corner - ray.far_origin) * ray.inv_direction;
+        // NaNs go first, so they are dropped like in AABB::hit
+        let t_min = near.z.max(near.y.max(near.x.max(t_min)));
+        let t_max = far.z.min(far.y.min(far.x.min(t_max)));
+        let t_max = t_max * F32x4::splat(ROUNDING_BOUND);
+        (t_min.le_mask(t_max), t_min)
+    }
+}
+
+impl BoxRay {
+    pub fn new(ray_in: &Ray3) -> BoxRay {
+        let origin = ray_in.origin();
+        let inv_direction = ray_in.inverse_direction();
+        let mut near_side = [0; 3];
+        let mut near_origin = [0.0; 3];
+        let mut far_origin = [0.0; 3];
+        for axis in 0..3 {
+            let low = round_down(origin.at(axis as u32));
+            let high = round_up(origin.at(axis as u32));
+            // Going forwards the ray enters at the minimum corner, backwards at the maximum
+            if inv_direction.at(axis as u32) < 0.0 {
+                near_side[axis] = 1;
+                near_origin[axis] = low;
+                far_origin[axis] = high;
+            } else {
+                near_origin[axis] = high;
+                far_origin[axis] = low;
+            }
+        }
+        BoxRay {
+            near_side,
+            near_origin: Vec3x4::splat(Vec3f::new(near_origin[0], near_origin[1], near_origin[2])),
+            far_origin: Vec3x4::splat(Vec3f::new(far_origin[0], far_origin[1], far_origin[2])),
+            inv_direction: Vec3x4::splat(inv_direction.cast()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rtweekend::{random_f64, random_f64_range, with_seed};
+    use crate::vector_3::Vec3;
+
+    fn random_box(scale: f64) -> AABB {
+        let corner = Vec3::random_range(-scale, scale);
+        // Some boxes are flat along an axis, padded like a quad's
+        let mut size = Vec3::random_range(0.0, scale * 0.2);
+        if random_f64() < 0.3 {
+            size.set((random_f64() * 3.0) as u32, 0.0);
+        }
+        AABB::from_vec3s(corner, corner + size).pad()
+    }
+
+    // Rays from all around, some aimed at a box corner or along an axis
+    fn random_ray(boxes: &[AABB], scale: f64) -> Ray3 {
+        let origin = Vec3::random_range(-2.0 * scale, 2.0 * scale);
+        let bbox = &boxes[(random_f64() * boxes.len() as f64) as usize];
+        let target = Vec3::new(
+            if random_f64() < 0.5 {
+                bbox.axis(0).min()
+            } else {
+                bbox.axis(0).max()
+            },
+            random_f64_range(bbox.axis(1).min(), bbox.axis(1).max()),
+            random_f64_range(bbox.axis(2).min(), bbox.axis(2).max()),
+        );
+        let mut direction = match (random_f64() * 4.0) as u32 {
+            0 => Vec3::random_unit_vector(),
+            _ => target - origin,
+        };
+        if random_f64() < 0.2 {
+            direction.set((random_f64() * 3.0) as u32, 0.0);
+        }
+        Ray3::new(origin, direction, 0.0)
+    }
+
+    #[test]
+    fn never_misses_what_aabb_hits() {
+        with_seed(3, || {
+            for scale in [1.0, 300.0, 1e5] {
+                let mut hits = 0;
+                let mut extra = 0;
+                for _ in 0..2_000 {
+                    let boxes: Vec<AABB> = (0..4).map(|_| random_box(scale)).collect();
+                    let aabb4 = AABB4::new(&boxes);
+                    for _ in 0..20 {
+                        let ray = random_ray(&boxes, scale);
+                        let interval = Interval::new(0.001, random_f64_range(1.0, 10.0) * scale);
+                        let (mask, near) = aabb4.hit(&BoxRay::new(&ray), &interval);
+                        for (lane, bbox) in boxes.iter().enumerate() {
+                            let hit = bbox.hit(&ray, interval.copy());
+                            let hit4 = mask & (1 << lane) != 0;
+                            assert!(hit4 || !hit, "box {} was missed at scale {}", lane, scale);
+                            if hit {
+                                hits += 1;
+                                let entry = near.to_array()[lane] as f64;
+                                assert!(entry <= interval.max().max(interval.min()));
+                            } else if hit4 {
+                                extra += 1;
+                            }
+                        }
+                    }
+                }
+                // Rounding outwards may only let a few rays that just miss through
+                assert!(hits > 10_000);
+                assert!(extra * 100 < hits, "{} extra hits out of {}", extra, hits);
+            }
+        })
+    }
+
+    #[test]
+    fn empty_lanes_are_never_hit() {
+        let boxes = [AABB::from_vec3s(
+            Vec3::new(-1.0, -1.0, -1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+        )];
+        let aabb4 = AABB4::new(&boxes);
+        let interval = Interval::new(0.001, f64::INFINITY);
+        for direction in [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(-0.3, 0.2, 1.0),
+        ] {
+            let ray = Ray3::new(-4.0 * direction, direction, 0.0);
+            assert_eq!(aabb4.hit(&BoxRay::new(&ray), &interval).0, 1);
+        }
+        let ray = Ray3::new(Vec3::empty(), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        assert_eq!(AABB4::empty().hit(&BoxRay::new(&ray), &interval).0, 0);
+    }
+
+    #[test]
+    fn axis_aligned_rays_and_faces() {
+        let unit = |offset: f64| {
+            let corner = Vec3::new(offset, 0.0, 0.0);
+            AABB::from_vec3s(corner, corner + Vec3::new(1.0, 1.0, 1.0))
+        };
+        let aabb4 = AABB4::new(&[unit(0.0), unit(2.0), unit(4.0), unit(-3.0)]);
+        let interval = Interval::new(0.001, f64::INFINITY);
+        // Along x through every box but the one behind the origin
+        let ray = Ray3::new(Vec3::new(-1.0, 0.5, 0.5), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        let (mask, near) = aabb4.hit(&BoxRay::new(&ray), &interval);
+        assert_eq!(mask, 0b0111);
+        assert_eq!(&near.to_array()[..3], &[1.0, 3.0, 5.0]);
+        // Along y through the second box, and in the plane of its faces
+        let ray = Ray3::new(Vec3::new(2.5, -1.0, 0.5), Vec3::new(0.0, 1.0, 0.0), 0.0);
+        assert_eq!(aabb4.hit(&BoxRay::new(&ray), &interval).0, 0b0010);
+        let ray = Ray3::new(Vec3::new(3.0, -1.0, 1.0), Vec3::new(0.0, 1.0, -0.0), 0.0);
+        assert_eq!(aabb4.hit(&BoxRay::new(&ray), &interval).0, 0b0010);
+        let ray = Ray3::new(Vec3::new(3.5, -1.0, 1.5), Vec3::new(0.0, 1.0, 0.0), 0.0);
+        assert_eq!(aabb4.hit(&BoxRay::new(&ray), &interval).0, 0);
+    }
+}
@@ -0,0 +1,23 @@
+use crate::bvh_node::BVHNode;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::qbvh::QBVH;
+use std::sync::Arc;
+
+// Which hierarchy the built-in scenes put their objects in. BVHNode draws its split axes
+// from the scene's random numbers and QBVH draws none, so a scene that draws more objects
+// after building one is laid out differently with the other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Accelerator {
+    BVHNode,
+    QBVH,
+}
+
+impl Accelerator {
+    pub fn build(&self, list: &HittableList) -> Arc<dyn Hittable + Sync + Send> {
+        match self {
+            Accelerator::BVHNode => Arc::new(BVHNode::from_list(list)),
+            Accelerator::QBVH => Arc::new(QBVH::from_list(list)),
+        }
+    }
+}
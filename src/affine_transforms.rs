@@ -4,15 +4,16 @@ use crate::interval::Interval;
 use crate::ray::Ray3;
 use crate::rtweekend;
 use crate::vector_3::Vec3;
-use std::f64::INFINITY;
 use std::sync::Arc;
 
+#[derive(Debug)]
 pub struct Translate {
     object: Arc<dyn Hittable + Sync + Send>,
     bbox: AABB,
     offset: Vec3,
 }
 
+#[derive(Debug)]
 pub struct RotateY {
     object: Arc<dyn Hittable + Sync + Send>,
     bbox: AABB,
@@ -24,12 +25,13 @@ impl Hittable for Translate {
     fn hit(&self, ray_in: &Ray3, time: Interval, hit_record: &mut HitRecord) -> bool {
         let offset_ray = Ray3::new(
             ray_in.origin() - self.offset,
-            ray_in.direction(),
+            *ray_in.direction(),
             ray_in.time(),
         );
 
         if self.object.hit(&offset_ray, time, hit_record) {
             hit_record.point += self.offset;
+            hit_record.object = self as *const Translate as usize;
             true
         } else {
             false
@@ -39,32 +41,28 @@ impl Hittable for Translate {
     fn bounding_box(&self) -> AABB {
         self.bbox.copy()
     }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        self.object.pdf_value(&(origin - self.offset), direction)
+    }
+
+    fn random(&self, origin: &Vec3) -> Vec3 {
+        self.object.random(&(origin - self.offset))
+    }
 }
 
 impl Hittable for RotateY {
     fn hit(&self, ray_in: &Ray3, time: Interval, hit_record: &mut HitRecord) -> bool {
-        let mut origin = ray_in.origin();
-        let mut direction = ray_in.direction();
-
-        origin.x = self.cos_theta * ray_in.origin().x - self.sin_theta * ray_in.origin().z;
-        origin.z = self.sin_theta * ray_in.origin().x + self.cos_theta * ray_in.origin().z;
-
-        direction.x = self.cos_theta * ray_in.direction().x - self.sin_theta * ray_in.direction().z;
-        direction.z = self.sin_theta * ray_in.direction().x + self.cos_theta * ray_in.direction().z;
-
-        let rotated_ray = Ray3::new(origin, direction, ray_in.time());
+        let rotated_ray = Ray3::new(
+            self.to_object(ray_in.origin()),
+            self.to_object(ray_in.direction()),
+            ray_in.time(),
+        );
 
         if self.object.hit(&rotated_ray, time, hit_record) {
-            let mut point = hit_record.point.clone();
-            point.x = self.cos_theta * hit_record.point.x + self.sin_theta * hit_record.point.z;
-            point.z = -self.sin_theta * hit_record.point.x + self.cos_theta * hit_record.point.z;
-
-            let mut normal = hit_record.normal.clone();
-            normal.x = self.cos_theta * hit_record.normal.x + self.sin_theta * hit_record.normal.z;
-            normal.z = -self.sin_theta * hit_record.normal.x + self.cos_theta * hit_record.normal.z;
-
-            hit_record.point = point;
-            hit_record.normal = normal;
+            hit_record.point = self.to_world(&hit_record.point);
+            hit_record.normal = self.to_world(&hit_record.normal);
+            hit_record.object = self as *const RotateY as usize;
 
             true
         } else {
@@ -75,6 +73,16 @@ impl Hittable for RotateY {
     fn bounding_box(&self) -> AABB {
         self.bbox.copy()
     }
+
+    // Rotations keep solid angles, so only the origin and direction need turning
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        self.object
+            .pdf_value(&self.to_object(origin), &self.to_object(direction))
+    }
+
+    fn random(&self, origin: &Vec3) -> Vec3 {
+        self.to_world(&self.object.random(&self.to_object(origin)))
+    }
 }
 
 impl Translate {
@@ -95,8 +103,8 @@ impl RotateY {
         let cos_theta = radians.cos();
         let bbox = object.bounding_box();
 
-        let mut min = Vec3::new(INFINITY, INFINITY, INFINITY);
-        let mut max = Vec3::new(-INFINITY, -INFINITY, -INFINITY);
+        let mut min = Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut max = Vec3::new(-f64::INFINITY, -f64::INFINITY, -f64::INFINITY);
 
         for i in 0..2 {
             for j in 0..2 {
@@ -131,4 +139,20 @@ impl RotateY {
             cos_theta,
         }
     }
+
+    fn to_object(&self, vec: &Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * vec.x - self.sin_theta * vec.z,
+            vec.y,
+            self.sin_theta * vec.x + self.cos_theta * vec.z,
+        )
+    }
+
+    fn to_world(&self, vec: &Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * vec.x + self.sin_theta * vec.z,
+            vec.y,
+            -self.sin_theta * vec.x + self.cos_theta * vec.z,
+        )
+    }
 }
@@ -0,0 +1,368 @@
+use crate::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::ray::Ray3;
+use crate::rtweekend::degree_to_radians;
+use crate::vector_3::Vec3;
+use std::sync::Arc;
+
+// Steps between each pair of keyframes when bounding the motion
+const BOUND_STEPS: usize = 32;
+
+// Pose of an object at one time: scaled first, then rotated about the axis by degrees and
+// then translated
+#[derive(Debug, Clone, Copy)]
+pub struct Keyframe {
+    pub time: f64,
+    pub translation: Vec3,
+    pub rotation_axis: Vec3,
+    pub rotation_angle: f64,
+    pub scale: Vec3,
+}
+
+impl Keyframe {
+    pub fn new(time: f64) -> Keyframe {
+        Keyframe {
+            time,
+            translation: Vec3::empty(),
+            rotation_axis: Vec3::new(0.0, 1.0, 0.0),
+            rotation_angle: 0.0,
+            scale: Vec3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Quaternion {
+    w: f64,
+    v: Vec3,
+}
+
+impl Quaternion {
+    fn from_axis_angle(axis: Vec3, degrees: f64) -> Quaternion {
+        let half = degree_to_radians(degrees) / 2.0;
+        Quaternion {
+            w: half.cos(),
+            v: axis.unit_vector() * half.sin(),
+        }
+    }
+
+    fn dot(&self, rhs: &Quaternion) -> f64 {
+        self.w * rhs.w + self.v.dot(&rhs.v)
+    }
+
+    fn slerp(&self, rhs: &Quaternion, t: f64) -> Quaternion {
+        // Take the short way round
+        let mut rhs = *rhs;
+        let mut cos_theta = self.dot(&rhs);
+        if cos_theta < 0.0 {
+            rhs = Quaternion {
+                w: -rhs.w,
+                v: -rhs.v,
+            };
+            cos_theta = -cos_theta;
+        }
+
+        let (a, b) = if cos_theta > 0.9995 {
+            (1.0 - t, t)
+        } else {
+            let theta = cos_theta.clamp(-1.0, 1.0).acos();
+            let sin_theta = theta.sin();
+            (
+                ((1.0 - t) * theta).sin() / sin_theta,
+                (t * theta).sin() / sin_theta,
+            )
+        };
+        let w = a * self.w + b * rhs.w;
+        let v = a * self.v + b * rhs.v;
+        let length = (w * w + v.length_squared()).sqrt();
+        Quaternion {
+            w: w / length,
+            v: v / length,
+        }
+    }
+
+    fn rotate(&self, vec: &Vec3) -> Vec3 {
+        let t = 2.0 * self.v.cross(vec);
+        *vec + self.w * t + self.v.cross(&t)
+    }
+
+    fn inverse_rotate(&self, vec: &Vec3) -> Vec3 {
+        Quaternion {
+            w: self.w,
+            v: -self.v,
+        }
+        .rotate(vec)
+    }
+}
+
+#[derive(Debug)]
+struct Pose {
+    translation: Vec3,
+    rotation: Quaternion,
+    scale: Vec3,
+}
+
+// Moves, turns and scales any object over time by interpolating between keyframes.
+// Translation and scale are interpolated linearly and rotation along the shortest arc.
+// Before the first and after the last keyframe the object holds still.
+#[derive(Debug)]
+pub struct AnimatedTransform {
+    object: Arc<dyn Hittable + Sync + Send>,
+    keyframes: Vec<Keyframe>,
+    rotations: Vec<Quaternion>,
+    bbox: AABB,
+}
+
+impl Hittable for AnimatedTransform {
+    fn hit(&self, ray_in: &Ray3, time: Interval, hit_record: &mut HitRecord) -> bool {
+        let pose = self.pose(ray_in.time());
+
+        // Into object space, where the ray parameter stays the same
+        let origin = pose
+            .rotation
+            .inverse_rotate(&(ray_in.origin() - pose.translation))
+            / pose.scale;
+        let direction = pose.rotation.inverse_rotate(ray_in.direction()) / pose.scale;
+        let object_ray = Ray3::new(origin, direction, ray_in.time());
+
+        if self.object.hit(&object_ray, time, hit_record) {
+            hit_record.point =
+                pose.rotation.rotate(&(hit_record.point * pose.scale)) + pose.translation;
+            hit_record.normal = pose
+                .rotation
+                .rotate(&(hit_record.normal / pose.scale))
+                .unit_vector();
+            hit_record.object = self as *const AnimatedTransform as usize;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox.copy()
+    }
+}
+
+impl AnimatedTransform {
+    pub fn new(
+        object: Arc<dyn Hittable + Sync + Send>,
+        keyframes: Vec<Keyframe>,
+    ) -> AnimatedTransform {
+        let mut keyframes = keyframes;
+        if keyframes.is_empty() {
+            keyframes.push(Keyframe::new(0.0));
+        }
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        let rotations = keyframes
+            .iter()
+            .map(|key| Quaternion::from_axis_angle(key.rotation_axis, key.rotation_angle))
+            .collect();
+
+        let mut transform = AnimatedTransform {
+            object,
+            keyframes,
+            rotations,
+            bbox: AABB::empty(),
+        };
+        transform.bbox = transform.motion_bounds();
+        transform
+    }
+
+    fn pose(&self, time: f64) -> Pose {
+        let last = self.keyframes.len() - 1;
+        let index = self.keyframes.partition_point(|key| key.time <= time);
+        if index == 0 || index > last {
+            let index = index.min(last);
+            let key = &self.keyframes[index];
+            return Pose {
+                translation: key.translation,
+                rotation: self.rotations[index],
+                scale: key.scale,
+            };
+        }
+
+        let (a, b) = (&self.keyframes[index - 1], &self.keyframes[index]);
+        let t = (time - a.time) / (b.time - a.time);
+        Pose {
+            translation: a.translation + t * (b.translation - a.translation),
+            rotation: self.rotations[index - 1].slerp(&self.rotations[index], t),
+            scale: a.scale + t * (b.scale - a.scale),
+        }
+    }
+
+    // Union of the object's box at many poses. Between the poses the corners move on arcs,
+    // so the box is grown by how far an arc can bulge past its chord.
+    fn motion_bounds(&self) -> AABB {
+        let object_box = self.object.bounding_box();
+        let corners: Vec<Vec3> = (0..8)
+            .map(|corner| {
+                Vec3::new(
+                    Self::corner_value(&object_box, 0, corner & 1),
+                    Self::corner_value(&object_box, 1, corner & 2),
+                    Self::corner_value(&object_box, 2, corner & 4),
+                )
+            })
+            .collect();
+
+        // Every keyframe is one of the poses, as the motion can turn sharply there
+        let mut times = vec![(self.keyframes[0].time, 0.0)];
+        for pair in self.keyframes.windows(2) {
+            let step = (pair[1].time - pair[0].time) / BOUND_STEPS as f64;
+            times.extend((1..=BOUND_STEPS).map(|n| (pair[0].time + n as f64 * step, step)));
+        }
+
+        let mut min = Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut max = Vec3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+        let mut bulge: f64 = 0.0;
+        for (time, step) in times {
+            let pose = self.pose(time);
+            // Angle turned through since the previous pose
+            let step_angle = self.step_angle(time - step, step);
+            for corner in &corners {
+                let scaled = *corner * pose.scale;
+                let point = pose.rotation.rotate(&scaled) + pose.translation;
+                for axis in 0..3 {
+                    min.set(axis, min.at(axis).min(point.at(axis)));
+                    max.set(axis, max.at(axis).max(point.at(axis)));
+                }
+
+                // Sagitta of the arc turned through in one step
+                bulge = bulge.max(scaled.length() * (1.0 - (step_angle / 2.0).cos()));
+            }
+        }
+
+        let bulge = Vec3::new(bulge, bulge, bulge);
+        AABB::from_vec3s(min - bulge, max + bulge).pad()
+    }
+
+    fn corner_value(bbox: &AABB, axis: u32, use_max: usize) -> f64 {
+        if use_max != 0 {
+            bbox.axis(axis).max()
+        } else {
+            bbox.axis(axis).min()
+        }
+    }
+
+    // Angle the rotation turns through between time and time + step
+    fn step_angle(&self, time: f64, step: f64) -> f64 {
+        let a = self.pose(time).rotation;
+        let b = self.pose(time + step).rotation;
+        2.0 * a.dot(&b).abs().min(1.0).acos()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lambertian::Lambertian;
+    use crate::quad::quad_box;
+    use crate::rtweekend::{random_f64, with_seed};
+
+    // A box off its own origin, so turning it sweeps the corners round wide arcs
+    fn spinning_box() -> AnimatedTransform {
+        let mat = Arc::new(Lambertian::from_color(Vec3::new(0.5, 0.5, 0.5)));
+        let object = quad_box(Vec3::new(1.0, 0.0, -0.5), Vec3::new(3.0, 1.0, 0.5), mat);
+        let mut start = Keyframe::new(0.0);
+        start.rotation_axis = Vec3::new(0.2, 1.0, 0.1);
+        let mut middle = Keyframe::new(0.4);
+        middle.translation = Vec3::new(0.5, 2.0, 0.0);
+        middle.rotation_axis = start.rotation_axis;
+        middle.rotation_angle = 170.0;
+        middle.scale = Vec3::new(1.5, 0.5, 1.0);
+        let mut end = middle;
+        end.time = 1.0;
+        end.rotation_axis = Vec3::new(1.0, 0.0, 0.0);
+        end.rotation_angle = -60.0;
+        end.translation = Vec3::new(-1.0, 0.0, 3.0);
+        AnimatedTransform::new(object, vec![end, start, middle])
+    }
+
+    fn contains(bbox: &AABB, point: &Vec3) -> bool {
+        (0..3).all(|axis| bbox.axis(axis).contains(point.at(axis)))
+    }
+
+    #[test]
+    fn motion_bounds_contain_every_pose() {
+        let transform = spinning_box();
+        let bbox = transform.bounding_box();
+        let object_box = transform.object.bounding_box();
+        with_seed(8, || {
+            for _ in 0..2_000 {
+                // Including times before the first and after the last keyframe
+                let time = 1.4 * random_f64() - 0.2;
+                let pose = transform.pose(time);
+                let local = Vec3::new(
+                    object_box.axis(0).min() + random_f64() * object_box.axis(0).size(),
+                    object_box.axis(1).min() + random_f64() * object_box.axis(1).size(),
+                    object_box.axis(2).min() + random_f64() * object_box.axis(2).size(),
+                );
+                for corner in 0..8 {
+                    let corner = Vec3::new(
+                        AnimatedTransform::corner_value(&object_box, 0, corner & 1),
+                        AnimatedTransform::corner_value(&object_box, 1, corner & 2),
+                        AnimatedTransform::corner_value(&object_box, 2, corner & 4),
+                    );
+                    for point in [corner, local] {
+                        let world = pose.rotation.rotate(&(point * pose.scale)) + pose.translation;
+                        assert!(
+                            contains(&bbox, &world),
+                            "{:?} at {} outside {:?}",
+                            world,
+                            time,
+                            bbox
+                        );
+                    }
+                }
+
+                // Whatever a ray hits is inside the box too
+                let origin = 20.0 * Vec3::random_unit_vector();
+                let ray = Ray3::new(origin, pose.translation - origin, time);
+                let mut hit_record = HitRecord::new();
+                if transform.hit(&ray, Interval::new(0.001, f64::INFINITY), &mut hit_record) {
+                    assert!(contains(&bbox, &hit_record.point));
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn slerp_reaches_both_ends() {
+        let vec = Vec3::new(0.3, -1.2, 2.0);
+        let pairs = [
+            (
+                Vec3::new(0.0, 1.0, 0.0),
+                10.0,
+                Vec3::new(1.0, 1.0, 0.0),
+                100.0,
+            ),
+            // More than half a turn apart, so the short way goes through the flipped end
+            (
+                Vec3::new(0.0, 0.0, 1.0),
+                0.0,
+                Vec3::new(0.0, 0.0, 1.0),
+                270.0,
+            ),
+            // Close enough to interpolate linearly
+            (
+                Vec3::new(1.0, 0.0, 0.0),
+                20.0,
+                Vec3::new(1.0, 0.0, 0.0),
+                20.01,
+            ),
+        ];
+        for (axis_a, angle_a, axis_b, angle_b) in pairs {
+            let a = Quaternion::from_axis_angle(axis_a, angle_a);
+            let b = Quaternion::from_axis_angle(axis_b, angle_b);
+            assert!((a.slerp(&b, 0.0).rotate(&vec) - a.rotate(&vec)).length() < 1e-9);
+            assert!((a.slerp(&b, 1.0).rotate(&vec) - b.rotate(&vec)).length() < 1e-9);
+
+            // Half way turns through half the angle from either end
+            let half = a.slerp(&b, 0.5);
+            let to_a = a.dot(&half).abs().min(1.0).acos();
+            let to_b = b.dot(&half).abs().min(1.0).acos();
+            assert!((to_a - to_b).abs() < 1e-9);
+        }
+    }
+}
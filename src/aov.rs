@@ -0,0 +1,325 @@
+use crate::tile_scheduler::Tile;
+use crate::vector_3::Vec3;
+use exr::error::Error;
+use exr::image::{AnyChannel, AnyChannels, Encoding, FlatSamples, Image, Layer};
+use exr::math::Vec2;
+use exr::meta::header::{ImageAttributes, LayerAttributes};
+use exr::prelude::{IntegerBounds, SmallVec, WritableImage};
+use std::collections::HashMap;
+
+// Arbitrary output variables, what the camera's first hit saw in each pixel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aov {
+    Depth,
+    Normal,
+    Albedo,
+    Position,
+    Uv,
+    MaterialId,
+    ObjectId,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 7] = [
+        Aov::Depth,
+        Aov::Normal,
+        Aov::Albedo,
+        Aov::Position,
+        Aov::Uv,
+        Aov::MaterialId,
+        Aov::ObjectId,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::Position => "position",
+            Aov::Uv => "uv",
+            Aov::MaterialId => "material_id",
+            Aov::ObjectId => "object_id",
+        }
+    }
+}
+
+// First hit outputs for a block of pixels, averaged over the samples of a pixel that hit
+// something. Pixels where nothing was hit have infinite depth and zeroes elsewhere.
+pub struct AovFrame {
+    pub region: Tile,
+    // Distance from the camera along the ray
+    pub depth: Vec<f64>,
+    pub normal: Vec<Vec3>,
+    pub albedo: Vec<Vec3>,
+    pub position: Vec<Vec3>,
+    pub uv: Vec<(f64, f64)>,
+    // Addresses of the material and the object the pixel's first hit landed on, 0 for
+    // none. They are numbered densely when written out. A box from `quad_box` gets one
+    // ID per face unless it is placed by a transform or instance.
+    pub material: Vec<usize>,
+    pub object: Vec<usize>,
+}
+
+impl AovFrame {
+    pub fn new(region: Tile) -> AovFrame {
+        let pixel_count = region.pixel_count();
+        AovFrame {
+            region,
+            depth: vec![f64::INFINITY; pixel_count],
+            normal: vec![Vec3::empty(); pixel_count],
+            albedo: vec![Vec3::empty(); pixel_count],
+            position: vec![Vec3::empty(); pixel_count],
+            uv: vec![(0.0, 0.0); pixel_count],
+            material: vec![0; pixel_count],
+            object: vec![0; pixel_count],
+        }
+    }
+
+    // Index of pixel i, j of the image, which has to be inside the region
+    pub fn index(&self, i: i32, j: i32) -> usize {
+        ((j - self.region.y) * self.region.width + (i - self.region.x)) as usize
+    }
+
+    // Copy in the pixels of a tile rendered for part of the frame
+    pub fn add_tile(&mut self, tile: &AovFrame) {
+        for (i, j) in tile.region.pixels() {
+            let index = self.index(i, j);
+            let tile_index = tile.index(i, j);
+            self.depth[index] = tile.depth[tile_index];
+            self.normal[index] = tile.normal[tile_index];
+            self.albedo[index] = tile.albedo[tile_index];
+            self.position[index] = tile.position[tile_index];
+            self.uv[index] = tile.uv[tile_index];
+            self.material[index] = tile.material[tile_index];
+            self.object[index] = tile.object[tile_index];
+        }
+    }
+
+    // Material IDs from 1 in the order they first appear row by row, 0 where nothing was hit
+    pub fn material_ids(&self) -> Vec<u32> {
+        dense_ids(&self.material)
+    }
+
+    pub fn object_ids(&self) -> Vec<u32> {
+        dense_ids(&self.object)
+    }
+
+    // Every output as a layer of one EXR file
+    pub fn write_exr(&self, path: &str) -> Result<(), Error> {
+        let layers: Vec<_> = Aov::ALL.iter().map(|&aov| self.layer(aov)).collect();
+        let attributes = ImageAttributes::new(IntegerBounds::from_dimensions(self.size()));
+        Image::from_layers(attributes, layers).write().to_file(path)
+    }
+
+    // Every output in its own EXR file, named prefix_name.exr
+    pub fn write_separate(&self, prefix: &str) -> Result<(), Error> {
+        for aov in Aov::ALL {
+            Image::from_layer(self.layer(aov)).write().to_file(format!(
+                "{}_{}.exr",
+                prefix,
+                aov.name()
+            ))?;
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> Vec2<usize> {
+        Vec2(self.region.width as usize, self.region.height as usize)
+    }
+
+    fn layer(&self, aov: Aov) -> Layer<AnyChannels<FlatSamples>> {
+        let floats = |name: &str, values: Vec<f32>| AnyChannel::new(name, FlatSamples::F32(values));
+        let vectors = |names: [&str; 3], values: &[Vec3]| {
+            (0..3)
+                .map(|axis| {
+                    floats(
+                        names[axis],
+                        values
+                            .iter()
+                            .map(|value| value.at(axis as u32) as f32)
+                            .collect(),
+                    )
+                })
+                .collect::<SmallVec<_>>()
+        };
+
+        let channels: SmallVec<[AnyChannel<FlatSamples>; 4]> = match aov {
+            Aov::Depth => SmallVec::from_elem(
+                floats("Z", self.depth.iter().map(|&depth| depth as f32).collect()),
+                1,
+            ),
+            Aov::Normal => vectors(["X", "Y", "Z"], &self.normal),
+            Aov::Albedo => vectors(["R", "G", "B"], &self.albedo),
+            Aov::Position => vectors(["X", "Y", "Z"], &self.position),
+            Aov::Uv => SmallVec::from_vec(vec![
+                floats("U", self.uv.iter().map(|&(u, _)| u as f32).collect()),
+                floats("V", self.uv.iter().map(|&(_, v)| v as f32).collect()),
+            ]),
+            Aov::MaterialId => SmallVec::from_elem(
+                AnyChannel::new("id", FlatSamples::U32(self.material_ids())),
+                1,
+            ),
+            Aov::ObjectId => SmallVec::from_elem(
+                AnyChannel::new("id", FlatSamples::U32(self.object_ids())),
+                1,
+            ),
+        };
+
+        Layer::new(
+            self.size(),
+            LayerAttributes::named(aov.name()),
+            Encoding::FAST_LOSSLESS,
+            AnyChannels::sort(channels),
+        )
+    }
+}
+
+fn dense_ids(keys: &[usize]) -> Vec<u32> {
+    let mut ids = HashMap::new();
+    keys.iter()
+        .map(|&key| {
+            if key == 0 {
+                0
+            } else {
+                let next = ids.len() as u32 + 1;
+                *ids.entry(key).or_insert(next)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::affine_transforms::Translate;
+    use crate::camera::Camera;
+    use crate::hittable::Hittable;
+    use crate::hittable_list::HittableList;
+    use crate::lambertian::Lambertian;
+    use crate::quad::quad_box;
+    use crate::sphere::Sphere;
+    use std::collections::HashSet;
+    use std::f64::consts::PI;
+    use std::sync::Arc;
+
+    fn camera(look_from: Vec3, look_at: Vec3) -> Camera {
+        let mut cam = Camera::new();
+        cam.image_width = 41;
+        cam.aspect_ratio = 1.0;
+        cam.vfov = 60.0;
+        cam.look_from = look_from;
+        cam.look_at = look_at;
+        cam.v_up = Vec3::new(0.0, 1.0, 0.0);
+        cam.defocus_angle = 0.0;
+        cam
+    }
+
+    fn gray() -> Arc<Lambertian> {
+        Arc::new(Lambertian::from_color(Vec3::new(0.2, 0.4, 0.6)))
+    }
+
+    #[test]
+    fn sphere_outputs_match_its_geometry() {
+        let center = Vec3::new(0.3, -0.2, -4.0);
+        let radius = 1.2;
+        let mut world = HittableList::new();
+        world.add(Arc::new(Sphere::new(center, radius, gray())));
+        let look_from = Vec3::new(0.0, 0.0, 0.0);
+        let mut cam = camera(look_from, Vec3::new(0.0, 0.0, -1.0));
+
+        // With one ray a pixel every output describes the same point
+        let frame = cam.render_aovs(Arc::new(world), 2, 1);
+        let mut hits = 0;
+        for index in 0..frame.depth.len() {
+            if frame.depth[index].is_infinite() {
+                assert_eq!(frame.object[index], 0);
+                assert_eq!(frame.normal[index].length_squared(), 0.0);
+                continue;
+            }
+            hits += 1;
+            let position = frame.position[index];
+            assert!(((position - center).length() - radius).abs() < 1e-9);
+            assert!((frame.depth[index] - (position - look_from).length()).abs() < 1e-9);
+            let outward = (position - center) / radius;
+            assert!((frame.normal[index] - outward).length() < 1e-9);
+            assert!(
+                frame.normal[index].z > 0.0,
+                "the normal faces away from the camera"
+            );
+            assert!((frame.albedo[index] - Vec3::new(0.2, 0.4, 0.6)).length() < 1e-12);
+            let (mut u, mut v) = (0.0, 0.0);
+            Sphere::sphere_uv(outward, &mut u, &mut v);
+            assert!((frame.uv[index].0 - u).abs() < 1e-9);
+            assert!((frame.uv[index].1 - v).abs() < 1e-9);
+        }
+
+        // The sphere covers about the fraction of the frame its angular size predicts
+        let disk_radius = (radius / center.length()).asin().tan();
+        let frame_width = 2.0 * 30.0f64.to_radians().tan();
+        let expected = PI * disk_radius * disk_radius / (frame_width * frame_width);
+        let covered = hits as f64 / frame.depth.len() as f64;
+        assert!(
+            (covered - expected).abs() < 0.05,
+            "{} against {}",
+            covered,
+            expected
+        );
+        assert!(frame.object_ids().iter().all(|&id| id <= 1));
+        assert!(frame.material_ids().iter().all(|&id| id <= 1));
+    }
+
+    #[test]
+    fn ids_are_numbered_in_order_of_appearance() {
+        assert_eq!(dense_ids(&[0, 7, 7, 3, 0, 7, 9]), vec![0, 1, 1, 2, 0, 1, 3]);
+
+        // Two spheres sharing a material, left one first in every row
+        let mat = gray();
+        let mut world = HittableList::new();
+        world.add(Arc::new(Sphere::new(
+            Vec3::new(-1.2, 0.0, -4.0),
+            1.0,
+            mat.clone(),
+        )));
+        world.add(Arc::new(Sphere::new(Vec3::new(1.2, 0.0, -4.0), 1.0, mat)));
+        let world: Arc<dyn Hittable + Sync + Send> = Arc::new(world);
+        let render = |threads| {
+            camera(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0)).render_aovs(
+                Arc::clone(&world),
+                threads,
+                4,
+            )
+        };
+        let frame = render(1);
+        let ids = frame.object_ids();
+        assert_eq!(ids.iter().copied().max(), Some(2));
+        assert!(frame.material_ids().iter().all(|&id| id <= 1));
+        for (i, j) in frame.region.pixels() {
+            let id = ids[frame.index(i, j)];
+            if id != 0 {
+                assert_eq!(id, if i < 20 { 1 } else { 2 }, "pixel {},{}", i, j);
+            }
+        }
+
+        // The numbering depends only on the image, not on how it was rendered
+        let again = render(3);
+        assert_eq!(again.object_ids(), ids);
+        assert_eq!(again.material_ids(), frame.material_ids());
+    }
+
+    #[test]
+    fn bare_boxes_get_an_id_per_face() {
+        let look_from = Vec3::new(3.0, 2.5, 4.0);
+        let corner = Vec3::new(-0.5, -0.5, -0.5);
+        let size = Vec3::new(1.0, 1.0, 1.0);
+        let distinct_ids = |world: Arc<dyn Hittable + Sync + Send>| {
+            let frame = camera(look_from, Vec3::empty()).render_aovs(world, 1, 1);
+            let ids: HashSet<u32> = frame.object_ids().into_iter().collect();
+            ids.len() - 1
+        };
+
+        // Three faces are in view
+        assert_eq!(distinct_ids(quad_box(corner, corner + size, gray())), 3);
+        let placed = Translate::new(quad_box(corner, corner + size, gray()), Vec3::empty());
+        assert_eq!(distinct_ids(Arc::new(placed)), 1);
+    }
+}
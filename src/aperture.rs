@@ -0,0 +1,172 @@
+use crate::rtweekend::{degree_to_radians, random_f64};
+use crate::vector_3::Vec3;
+use image::error::{ParameterError, ParameterErrorKind};
+use image::{io, ImageError};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// Shape of the thin lens opening, which is also the shape of out of focus highlights
+#[derive(Debug, Clone)]
+pub enum Aperture {
+    Circle,
+    // Regular polygon with `blades` corners on the unit circle, rotated by degrees
+    Polygon { blades: u32, rotation: f64 },
+    Image(Arc<ApertureMask>),
+}
+
+impl Aperture {
+    // Random point on the aperture. Circles and polygons lie within the unit disk, while
+    // an image fills [-1,1] along its longer side, so a mask drawn out to the corners of
+    // a square image reaches past the disk there.
+    pub fn sample(&self) -> Vec3 {
+        match self {
+            Aperture::Circle => Vec3::random_in_unit_disk(),
+            Aperture::Polygon { blades, rotation } => {
+                let blades = (*blades).max(3);
+                let blade_angle = 2.0 * PI / blades as f64;
+                let blade = ((random_f64() * blades as f64) as u32).min(blades - 1);
+                let start = degree_to_radians(*rotation) + blade as f64 * blade_angle;
+                let corner_a = Vec3::new(start.cos(), start.sin(), 0.0);
+                let corner_b = Vec3::new(
+                    (start + blade_angle).cos(),
+                    (start + blade_angle).sin(),
+                    0.0,
+                );
+
+                // Uniform point in the triangle between the centre and the two corners
+                let mut a = random_f64();
+                let mut b = random_f64();
+                if a + b > 1.0 {
+                    a = 1.0 - a;
+                    b = 1.0 - b;
+                }
+                a * corner_a + b * corner_b
+            }
+            Aperture::Image(mask) => mask.sample(),
+        }
+    }
+}
+
+// Aperture drawn in an image. Bright pixels let more light through, so points are
+// sampled in proportion to luminance.
+#[derive(Debug)]
+pub struct ApertureMask {
+    width: u32,
+    height: u32,
+    cdf: Vec<f64>,
+}
+
+impl ApertureMask {
+    pub fn new(path: &str) -> Result<ApertureMask, ImageError> {
+        let image = io::Reader::open(path)?.decode()?.into_rgb8();
+
+        let mut total = 0.0;
+        let mut cdf = Vec::with_capacity((image.width() * image.height()) as usize);
+        for pixel in image.pixels() {
+            let luminance = 0.2126 * pixel.0[0] as f64
+                + 0.7152 * pixel.0[1] as f64
+                + 0.0722 * pixel.0[2] as f64;
+            total += luminance;
+            cdf.push(total);
+        }
+        if total <= 0.0 {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic(format!("aperture image {} is black", path)),
+            )));
+        }
+        cdf.iter_mut().for_each(|value| *value /= total);
+
+        Ok(ApertureMask {
+            width: image.width(),
+            height: image.height(),
+            cdf,
+        })
+    }
+
+    fn sample(&self) -> Vec3 {
+        let u = random_f64();
+        let index = self
+            .cdf
+            .partition_point(|&value| value <= u)
+            .min(self.cdf.len() - 1) as u32;
+        let x = (index % self.width) as f64 + random_f64();
+        let y = (index / self.width) as f64 + random_f64();
+
+        // The longer side of the image spans -1 to 1, centred on the lens
+        let size = self.width.max(self.height) as f64;
+        Vec3::new(
+            (2.0 * x - self.width as f64) / size,
+            (self.height as f64 - 2.0 * y) / size,
+            0.0,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rtweekend::with_seed;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn polygon_samples_stay_inside_the_polygon() {
+        with_seed(2, || {
+            for (blades, rotation) in [(3, 0.0), (5, 18.0), (6, 0.0), (9, 45.0)] {
+                let aperture = Aperture::Polygon { blades, rotation };
+                let corners: Vec<Vec3> = (0..blades)
+                    .map(|n| {
+                        let angle =
+                            degree_to_radians(rotation) + 2.0 * PI * n as f64 / blades as f64;
+                        Vec3::new(angle.cos(), angle.sin(), 0.0)
+                    })
+                    .collect();
+                for _ in 0..20_000 {
+                    let point = aperture.sample();
+                    assert_eq!(point.z, 0.0);
+                    // Left of every edge going round anticlockwise
+                    for n in 0..blades as usize {
+                        let edge = corners[(n + 1) % corners.len()] - corners[n];
+                        let to_point = point - corners[n];
+                        assert!(edge.cross(&to_point).z >= -1e-12, "{:?} is outside", point);
+                    }
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn mask_samples_stay_on_bright_pixels() {
+        // A ring and a dim dot in a wide image, so the mask is not square
+        let (width, height) = (24, 15);
+        let image = RgbImage::from_fn(width, height, |x, y| {
+            let (dx, dy) = (x as f64 - 11.5, y as f64 - 7.0);
+            let radius = (dx * dx + dy * dy).sqrt();
+            if (4.0..6.5).contains(&radius) {
+                Rgb([255, 255, 255])
+            } else if (x, y) == (1, 1) {
+                Rgb([0, 40, 0])
+            } else {
+                Rgb([0, 0, 0])
+            }
+        });
+        let path =
+            std::env::temp_dir().join(format!("ray_tracing_mask_{}.png", std::process::id()));
+        image.save(&path).unwrap();
+        let mask = ApertureMask::new(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let aperture = Aperture::Image(Arc::new(mask));
+        let size = width.max(height) as f64;
+        with_seed(4, || {
+            for _ in 0..20_000 {
+                let point = aperture.sample();
+                assert!(point.x.abs() <= 1.0 && point.y.abs() <= height as f64 / size);
+                // Back to the pixel the point came from
+                let x = ((point.x * size + width as f64) / 2.0).floor() as u32;
+                let y = ((height as f64 - point.y * size) / 2.0).floor() as u32;
+                let pixel = image.get_pixel(x.min(width - 1), y.min(height - 1));
+                assert_ne!(pixel.0, [0, 0, 0], "sampled black pixel {},{}", x, y);
+            }
+        })
+    }
+}
@@ -4,11 +4,13 @@ use crate::hittable::Hittable;
 use crate::hittable_list::HittableList;
 use crate::interval::Interval;
 use crate::ray::Ray3;
+use crate::ray_stats::{self, Counter};
 use crate::rtweekend::random_u32_range;
 
 use std::cmp::Ordering;
 use std::sync::Arc;
 
+#[derive(Debug)]
 pub struct BVHNode {
     left: Arc<dyn Hittable + Sync + Send>,
     right: Arc<dyn Hittable + Sync + Send>,
@@ -17,7 +19,8 @@ pub struct BVHNode {
 
 impl Hittable for BVHNode {
     fn hit(&self, ray_in: &Ray3, time: Interval, hit_record: &mut HitRecord) -> bool {
-        let hit_anything = if self.bbox.hit(ray_in, time.copy()) {
+        ray_stats::count(Counter::BvhNodeVisit);
+        if self.bbox.hit(ray_in, time.copy()) {
             let hit_left = self.left.hit(ray_in, time.copy(), hit_record);
             let hit_right = self.right.hit(
                 ray_in,
@@ -34,8 +37,7 @@ impl Hittable for BVHNode {
             hit_left || hit_right
         } else {
             false
-        };
-        hit_anything
+        }
     }
 
     fn bounding_box(&self) -> AABB {
@@ -45,7 +47,7 @@ impl Hittable for BVHNode {
 
 impl BVHNode {
     pub fn from_vec(
-        objects: &Vec<Arc<dyn Hittable + Sync + Send>>,
+        objects: &[Arc<dyn Hittable + Sync + Send>],
         start: usize,
         end: usize,
     ) -> BVHNode {
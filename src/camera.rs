@@ -1,17 +1,66 @@
+use crate::aov::AovFrame;
+use crate::aperture::Aperture;
+use crate::crop_window::CropWindow;
+use crate::film::{Film, FilmTile};
+use crate::filter::{Filter, FilterMode, FilterSampler};
 use crate::hittable::HitRecord;
 use crate::hittable::Hittable;
 use crate::hittable_pdf::HittablePDF;
 use crate::interval::Interval;
+use crate::lens_system::{LensError, LensSystem};
 use crate::material::ScatterPDF;
 use crate::material::ScatterRecord;
 use crate::mixture_pdf::MixturePDF;
 use crate::pdf::PDF;
+use crate::projection::Projection;
 use crate::ray::Ray3;
-use crate::rtweekend::degree_to_radians;
+use crate::ray_stats::{self, Counter};
+use crate::render_progress::{CancelToken, RenderProgress};
+use crate::rtweekend::{degree_to_radians, mix_seed, random_f64, with_seed};
+use crate::shutter::Shutter;
+use crate::stereo::Eye;
+use crate::tile_scheduler::{Tile, TileOrder, TileScheduler};
 use crate::vector_3::Vec3;
 use scoped_threadpool::Pool;
+use std::fmt;
+use std::sync::mpsc;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+pub enum CameraError {
+    EmptyCrop {
+        window: CropWindow,
+        width: i32,
+        height: i32,
+    },
+    Lens(LensError),
+}
+
+impl fmt::Display for CameraError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CameraError::EmptyCrop {
+                window,
+                width,
+                height,
+            } => write!(
+                f,
+                "crop window {},{},{},{} covers no pixels of the {}x{} image",
+                window.x_min, window.x_max, window.y_min, window.y_max, width, height
+            ),
+            CameraError::Lens(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for CameraError {}
+
+impl From<LensError> for CameraError {
+    fn from(err: LensError) -> CameraError {
+        CameraError::Lens(err)
+    }
+}
 
 pub struct Camera {
     pub samples_per_pixel: i32,
@@ -24,22 +73,40 @@ pub struct Camera {
     pub v_up: Vec3,
     pub defocus_angle: f64,
     pub focus_dist: f64,
+    pub aperture: Aperture,
+    pub optical_vignetting: f64,
+    pub shutter: Shutter,
+    pub filter: Filter,
+    pub filter_mode: FilterMode,
     pub background: Vec3,
+    pub tile_size: i32,
+    pub tile_order: TileOrder,
+    pub crop_window: Option<CropWindow>,
+    pub seed: u64,
+    pub projection: Projection,
+    pub eye: Eye,
+    pub interpupillary_distance: f64,
+    pub lens_system: Option<LensSystem>,
     image_height: i32,
+    last_render_time: Duration,
     camera_center: Vec3,
-    pixel_delta_u: Vec3,
-    pixel_delta_v: Vec3,
-    pixel00_loc: Vec3,
     u: Vec3,
     v: Vec3,
     w: Vec3,
     defocus_disk_u: Vec3,
     defocus_disk_v: Vec3,
+    filter_sampler: FilterSampler,
 }
 
 unsafe impl Sync for Camera {}
 unsafe impl Send for Camera {}
 
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Camera {
     pub fn new() -> Camera {
         Camera {
@@ -50,46 +117,32 @@ impl Camera {
             vfov: 90.0,
             defocus_angle: 0.0,
             focus_dist: 10.0,
+            aperture: Aperture::Circle,
+            optical_vignetting: 0.0,
+            shutter: Shutter::new(),
+            filter: Filter::Box { radius: 0.5 },
+            filter_mode: FilterMode::ImportanceSampling,
             look_from: Vec3::new(0.0, 0.0, -1.0),
             look_at: Vec3::new(0.0, 0.0, -1.0),
             v_up: Vec3::new(0.0, 1.0, 0.0),
             image_height: 0,
             camera_center: Vec3::new(0.0, 0.0, 0.0),
-            pixel_delta_u: Vec3::new(0.0, 0.0, 0.0),
-            pixel_delta_v: Vec3::new(0.0, 0.0, 0.0),
-            pixel00_loc: Vec3::new(0.0, 0.0, 0.0),
             u: Vec3::new(0.0, 0.0, 0.0),
             v: Vec3::new(0.0, 0.0, 0.0),
             w: Vec3::new(0.0, 0.0, 0.0),
             defocus_disk_u: Vec3::new(0.0, 0.0, 0.0),
             defocus_disk_v: Vec3::new(0.0, 0.0, 0.0),
+            filter_sampler: FilterSampler::new(Filter::Box { radius: 0.5 }),
             background: Vec3::new(0.7, 0.8, 1.0),
-        }
-    }
-
-    fn self_clone(&self) -> Camera {
-        Camera {
-            samples_per_pixel: self.samples_per_pixel,
-            image_width: self.image_width,
-            aspect_ratio: self.aspect_ratio,
-            max_depth: self.max_depth,
-            vfov: self.vfov,
-            look_from: self.look_from,
-            look_at: self.look_at,
-            v_up: self.v_up,
-            defocus_angle: self.defocus_angle,
-            focus_dist: self.focus_dist,
-            image_height: self.image_height,
-            camera_center: self.camera_center,
-            pixel_delta_u: self.pixel_delta_u,
-            pixel_delta_v: self.pixel_delta_v,
-            pixel00_loc: self.pixel00_loc,
-            u: self.u,
-            v: self.v,
-            w: self.w,
-            defocus_disk_u: self.defocus_disk_u,
-            defocus_disk_v: self.defocus_disk_v,
-            background: self.background,
+            tile_size: 32,
+            tile_order: TileOrder::Spiral,
+            crop_window: None,
+            seed: 0,
+            projection: Projection::Perspective,
+            eye: Eye::Center,
+            interpupillary_distance: 0.064,
+            lens_system: None,
+            last_render_time: Duration::ZERO,
         }
     }
 
@@ -108,78 +161,520 @@ impl Camera {
         multi_thread: bool,
         threads: u32,
     ) -> Vec<u32> {
+        let threads = if multi_thread { threads } else { 1 };
+        let buffer = self.render_with_progress(world, lights, threads, |_| {}, &CancelToken::new());
+        println!("\rDone! Took {} seconds", self.last_render_time.as_secs());
+        buffer
+    }
+
+    // Reports progress after every finished tile. Once the token is cancelled the
+    // workers stop taking new tiles and the unfinished part of the image is left black.
+    pub fn render_with_progress<F>(
+        &mut self,
+        world: Arc<dyn Hittable + Sync + Send>,
+        lights: Option<Arc<dyn Hittable + Sync + Send>>,
+        threads: u32,
+        on_progress: F,
+        cancel: &CancelToken,
+    ) -> Vec<u32>
+    where
+        F: FnMut(&RenderProgress),
+    {
         self.initalize();
 
+        let mut film = Film::new(self.image_width, self.image_height);
+        let samples = self.samples_per_pixel;
+        self.render_film(
+            world,
+            lights,
+            threads,
+            &mut film,
+            samples,
+            on_progress,
+            cancel,
+        );
+        film.to_buffer()
+    }
+
+    // Left and right eye images, rendered one after the other
+    pub fn render_stereo<F>(
+        &mut self,
+        world: Arc<dyn Hittable + Sync + Send>,
+        lights: Option<Arc<dyn Hittable + Sync + Send>>,
+        threads: u32,
+        mut on_progress: F,
+        cancel: &CancelToken,
+    ) -> (Vec<u32>, Vec<u32>)
+    where
+        F: FnMut(&RenderProgress),
+    {
+        let eye = self.eye;
+        let mut render_eye = |cam: &mut Camera, eye| {
+            cam.eye = eye;
+            cam.render_with_progress(
+                Arc::clone(&world),
+                lights.as_ref().map(Arc::clone),
+                threads,
+                &mut on_progress,
+                cancel,
+            )
+        };
+        let left = render_eye(self, Eye::Left);
+        let right = render_eye(self, Eye::Right);
+        self.eye = eye;
+        (left, right)
+    }
+
+    // Adds `samples` more samples to every pixel of the film. Used for progressive
+    // previews and for resuming a render from a checkpoint.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_film<F>(
+        &mut self,
+        world: Arc<dyn Hittable + Sync + Send>,
+        lights: Option<Arc<dyn Hittable + Sync + Send>>,
+        threads: u32,
+        film: &mut Film,
+        samples: i32,
+        mut on_progress: F,
+        cancel: &CancelToken,
+    ) where
+        F: FnMut(&RenderProgress),
+    {
+        self.initalize();
+        assert!(
+            film.width() == self.image_width && film.height() == self.image_height,
+            "film is {}x{} but the camera renders {}x{}",
+            film.width(),
+            film.height(),
+            self.image_width,
+            self.image_height
+        );
+
         let start_time = Instant::now();
 
-        // Render to buffer
-        let image_size = self.image_width * self.image_height;
-        let mut buffer: Vec<u32> = Vec::with_capacity(image_size as usize);
-        if multi_thread {
-            let arc_cam = Arc::new(self.self_clone());
-            for _ in 0..image_size {
-                buffer.push(0);
+        let crop = self.crop_pixels();
+        let region = self.sample_region();
+        let first_sample = film.min_samples_in(&crop);
+        let mut progress = RenderProgress::new(
+            self.tile_count(&region),
+            region.pixel_count() as u64 * samples as u64,
+        );
+        self.render_tiles(
+            &region,
+            &world,
+            &lights,
+            threads,
+            first_sample,
+            samples,
+            cancel,
+            |tile, mut film_tile| {
+                if self.crop_window.is_some() {
+                    film_tile.retain(&crop);
+                }
+                film.add_tile(tile, &film_tile, samples as u32);
+                let tile_samples = tile.pixel_count() as u64 * samples as u64;
+                progress.update(1, tile_samples, start_time.elapsed());
+                on_progress(&progress);
+            },
+        );
+
+        self.last_render_time = start_time.elapsed();
+    }
+
+    pub fn last_render_time(&self) -> Duration {
+        self.last_render_time
+    }
+
+    // Weighted radiance the samples of the region leave on the film, rendered on all
+    // threads. Splatted samples also reach the pixels around the region.
+    pub fn render_region(
+        &mut self,
+        world: Arc<dyn Hittable + Sync + Send>,
+        lights: Option<Arc<dyn Hittable + Sync + Send>>,
+        threads: u32,
+        region: &Tile,
+        samples: i32,
+    ) -> FilmTile {
+        self.initalize();
+
+        let mut film_region = FilmTile::new(self.film_region(region));
+        let cancel = CancelToken::new();
+        self.render_tiles(
+            region,
+            &world,
+            &lights,
+            threads,
+            0,
+            samples,
+            &cancel,
+            |_, film_tile| film_region.accumulate(&film_tile),
+        );
+        film_region
+    }
+
+    pub fn full_frame(&self) -> Tile {
+        Tile::new(0, 0, self.image_width, self.image_height)
+    }
+
+    // Pixels of the crop window, or the full frame without one. A window too small to
+    // cover a pixel at this resolution is an error, as nothing would ever be rendered.
+    pub fn crop_region(&self) -> Result<Tile, CameraError> {
+        let region = self.crop_pixels();
+        match &self.crop_window {
+            Some(window) if region.pixel_count() == 0 => Err(CameraError::EmptyCrop {
+                window: *window,
+                width: self.image_width,
+                height: self.image_height,
+            }),
+            _ => Ok(region),
+        }
+    }
+
+    fn crop_pixels(&self) -> Tile {
+        match &self.crop_window {
+            Some(window) => window.pixels(self.image_width, self.image_height),
+            None => self.full_frame(),
+        }
+    }
+
+    // Pixels that are sampled to fill the crop region. Splatted samples from around the
+    // crop also land in it, so they are taken as well.
+    pub fn sample_region(&self) -> Tile {
+        self.film_region(&self.crop_pixels())
+    }
+
+    pub fn tile_count(&self, region: &Tile) -> usize {
+        TileScheduler::ordered_tiles(region, self.tile_size, self.tile_order).len()
+    }
+
+    // Pixels a tile's samples reach: the tile itself, or with splatting the tile grown by
+    // the filter radius as far as the image goes
+    fn film_region(&self, tile: &Tile) -> Tile {
+        if self.filter_mode == FilterMode::ImportanceSampling {
+            return *tile;
+        }
+        let margin = (self.filter.radius() - 0.5).ceil().max(0.0) as i32;
+        let x = (tile.x - margin).max(0);
+        let y = (tile.y - margin).max(0);
+        Tile::new(
+            x,
+            y,
+            (tile.x + tile.width + margin).min(self.image_width) - x,
+            (tile.y + tile.height + margin).min(self.image_height) - y,
+        )
+    }
+
+    // Filter weighted radiance of `samples` samples for every pixel of the tile. Every
+    // pixel draws from its own generator, seeded by the pixel and by how many samples it
+    // already has, so a pixel comes out the same whichever tile or thread renders it.
+    pub fn render_tile(
+        &self,
+        tile: &Tile,
+        first_sample: u32,
+        samples: i32,
+        world: &Arc<dyn Hittable + Sync + Send>,
+        lights: &Option<Arc<dyn Hittable + Sync + Send>>,
+    ) -> FilmTile {
+        let mut film_tile = FilmTile::new(self.film_region(tile));
+        for (i, j) in tile.pixels() {
+            let seed = mix_seed(&[self.seed, i as u64, j as u64, first_sample as u64]);
+            let cost = ray_stats::traversal_cost();
+            with_seed(seed, || {
+                self.render_pixel(&mut film_tile, i, j, samples, world, lights)
+            });
+            ray_stats::add_pixel_cost(i, j, ray_stats::traversal_cost() - cost, samples as u64);
+        }
+        ray_stats::flush();
+        film_tile
+    }
+
+    fn render_pixel(
+        &self,
+        film_tile: &mut FilmTile,
+        i: i32,
+        j: i32,
+        samples: i32,
+        world: &Arc<dyn Hittable + Sync + Send>,
+        lights: &Option<Arc<dyn Hittable + Sync + Send>>,
+    ) {
+        for _ in 0..samples {
+            match self.filter_mode {
+                FilterMode::ImportanceSampling => {
+                    let (dx, dy, weight) = self.filter_sampler.sample();
+                    let x = i as f64 + 0.5 + dx;
+                    let y = j as f64 + 0.5 + dy;
+                    let radiance = self.sample_radiance(x, y, world, lights);
+                    film_tile.add(i, j, weight * radiance, 1.0);
+                }
+                FilterMode::Splatting => {
+                    let x = i as f64 + random_f64();
+                    let y = j as f64 + random_f64();
+                    let radiance = self.sample_radiance(x, y, world, lights);
+                    self.splat(film_tile, x, y, radiance);
+                }
             }
-            {
-                let mut pool = Pool::new(threads);
-                pool.scoped(|scope| {
-                    for (j, buffer_slice) in
-                        buffer.chunks_mut(self.image_width as usize).enumerate()
-                    {
-                        let cam_clone = Arc::clone(&arc_cam);
-                        let world_clone = Arc::clone(&world);
-                        let lights_clone = match &lights {
-                            None => None,
-                            Some(unwrapped_lights) => Some(Arc::clone(&unwrapped_lights)),
-                        };
-                        scope.execute(move || {
-                            Self::thread_render(
-                                cam_clone,
-                                world_clone,
-                                lights_clone,
-                                j as i32,
-                                buffer_slice,
-                            )
-                        });
-                    }
-                });
+        }
+    }
+
+    // Radiance arriving through image position x, y, in pixels
+    fn sample_radiance(
+        &self,
+        x: f64,
+        y: f64,
+        world: &Arc<dyn Hittable + Sync + Send>,
+        lights: &Option<Arc<dyn Hittable + Sync + Send>>,
+    ) -> Vec3 {
+        // Pixels outside a fisheye's image circle stay black
+        match self.get_ray(x, y) {
+            Some((ray_sample, weight)) => {
+                weight * self.ray_color(ray_sample, self.max_depth, world, lights)
             }
-        } else {
-            for j in 0..self.image_height {
-                for i in 0..self.image_width {
-                    let mut color_vec = Vec3::new(0.0, 0.0, 0.0);
-                    for _ in 0..self.samples_per_pixel {
-                        let ray_sample = self.get_ray(i, j);
-                        color_vec =
-                            color_vec + self.ray_color(ray_sample, self.max_depth, &world, &lights);
-                    }
-                    buffer.push(crate::color::vec_to_val(&color_vec, self.samples_per_pixel));
+            None => Vec3::empty(),
+        }
+    }
+
+    // Adds the sample to every pixel of the tile whose centre is within the filter radius
+    fn splat(&self, film_tile: &mut FilmTile, x: f64, y: f64, radiance: Vec3) {
+        let radius = self.filter.radius();
+        let region = film_tile.region;
+        let i_min = ((x - 0.5 - radius).ceil() as i32).max(region.x);
+        let i_max = ((x - 0.5 + radius).floor() as i32).min(region.x + region.width - 1);
+        let j_min = ((y - 0.5 - radius).ceil() as i32).max(region.y);
+        let j_max = ((y - 0.5 + radius).floor() as i32).min(region.y + region.height - 1);
+        for j in j_min..=j_max {
+            for i in i_min..=i_max {
+                let weight = self
+                    .filter
+                    .evaluate(x - (i as f64 + 0.5), y - (j as f64 + 0.5));
+                if weight != 0.0 {
+                    film_tile.add(i, j, weight * radiance, weight);
                 }
             }
         }
-        let elapsed_time = start_time.elapsed();
-        println!("\rDone! Took {} seconds", elapsed_time.as_secs());
-        buffer
     }
 
-    pub fn thread_render(
-        cam: Arc<Camera>,
+    #[allow(clippy::too_many_arguments)]
+    fn render_tiles<F>(
+        &self,
+        region: &Tile,
+        world: &Arc<dyn Hittable + Sync + Send>,
+        lights: &Option<Arc<dyn Hittable + Sync + Send>>,
+        threads: u32,
+        first_sample: u32,
+        samples: i32,
+        cancel: &CancelToken,
+        on_tile: F,
+    ) where
+        F: FnMut(&Tile, FilmTile),
+    {
+        self.run_tiles(
+            region,
+            threads,
+            cancel,
+            |tile| self.render_tile(tile, first_sample, samples, world, lights),
+            on_tile,
+        );
+    }
+
+    // Workers pull tiles from the scheduler, render them and send them back to this thread
+    // as they finish
+    fn run_tiles<T, R, F>(
+        &self,
+        region: &Tile,
+        threads: u32,
+        cancel: &CancelToken,
+        render: R,
+        mut on_tile: F,
+    ) where
+        T: Send,
+        R: Fn(&Tile) -> T + Sync,
+        F: FnMut(&Tile, T),
+    {
+        let threads = threads.max(1);
+        let scheduler =
+            TileScheduler::new(region, self.tile_size, self.tile_order, threads as usize);
+        let (sender, receiver) = mpsc::channel();
+
+        let mut pool = Pool::new(threads);
+        pool.scoped(|scope| {
+            for worker in 0..threads as usize {
+                let scheduler = &scheduler;
+                let render = &render;
+                let sender = sender.clone();
+                scope.execute(move || {
+                    while let Some(tile) = scheduler.next(worker) {
+                        if cancel.is_cancelled() {
+                            break;
+                        }
+                        if sender.send((tile, render(&tile))).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(sender);
+
+            for (tile, rendered) in receiver {
+                on_tile(&tile, rendered);
+            }
+        });
+    }
+
+    // First hit outputs for the crop region, from `samples` rays spread over each pixel
+    pub fn render_aovs(
+        &mut self,
         world: Arc<dyn Hittable + Sync + Send>,
-        lights: Option<Arc<dyn Hittable + Sync + Send>>,
-        j_idx: i32,
-        buffer: &mut [u32],
+        threads: u32,
+        samples: i32,
+    ) -> AovFrame {
+        self.initalize();
+
+        let region = self.crop_pixels();
+        let mut frame = AovFrame::new(region);
+        let cancel = CancelToken::new();
+        self.run_tiles(
+            &region,
+            threads,
+            &cancel,
+            |tile| self.aov_tile(tile, samples, &world),
+            |_, aov_tile| frame.add_tile(&aov_tile),
+        );
+        frame
+    }
+
+    fn aov_tile(
+        &self,
+        tile: &Tile,
+        samples: i32,
+        world: &Arc<dyn Hittable + Sync + Send>,
+    ) -> AovFrame {
+        let mut aov_tile = AovFrame::new(*tile);
+        for (i, j) in tile.pixels() {
+            // Kept apart from the seeds of the pixel's radiance samples
+            let seed = mix_seed(&[self.seed, i as u64, j as u64, u64::MAX]);
+            with_seed(seed, || self.aov_pixel(&mut aov_tile, i, j, samples, world));
+        }
+        aov_tile
+    }
+
+    fn aov_pixel(
+        &self,
+        aov_tile: &mut AovFrame,
+        i: i32,
+        j: i32,
+        samples: i32,
+        world: &Arc<dyn Hittable + Sync + Send>,
     ) {
-        for (i, val) in buffer.iter_mut().enumerate() {
-            let mut color_vec = Vec3::new(0.0, 0.0, 0.0);
-            for _ in 0..cam.samples_per_pixel {
-                let ray_sample = cam.get_ray(i as i32, j_idx);
-                color_vec = color_vec + cam.ray_color(ray_sample, cam.max_depth, &world, &lights);
+        let mut hits = 0;
+        let mut depth = 0.0;
+        let mut normal = Vec3::empty();
+        let mut albedo = Vec3::empty();
+        let mut position = Vec3::empty();
+        let (mut u, mut v) = (0.0, 0.0);
+        let index = aov_tile.index(i, j);
+        for _ in 0..samples {
+            let ray = match self.get_ray(i as f64 + random_f64(), j as f64 + random_f64()) {
+                Some((ray, _)) => ray,
+                None => continue,
+            };
+            let mut hit_record = HitRecord::new();
+            if !world.hit(&ray, Interval::new(0.0001, f64::INFINITY), &mut hit_record) {
+                continue;
             }
-            *val = crate::color::vec_to_val(&color_vec, cam.samples_per_pixel);
+
+            // IDs can't be averaged, so they come from the first sample that hits
+            if hits == 0 {
+                aov_tile.material[index] = Arc::as_ptr(&hit_record.mat) as *const () as usize;
+                aov_tile.object[index] = hit_record.object;
+            }
+            hits += 1;
+            depth += hit_record.time * ray.direction().length();
+            normal += hit_record.normal;
+            albedo += hit_record.mat.albedo(&hit_record);
+            position += hit_record.point;
+            u += hit_record.u;
+            v += hit_record.v;
+        }
+
+        if hits > 0 {
+            let hits = hits as f64;
+            aov_tile.depth[index] = depth / hits;
+            aov_tile.normal[index] = if normal.length_squared() > 0.0 {
+                normal.unit_vector()
+            } else {
+                normal
+            };
+            aov_tile.albedo[index] = albedo / hits;
+            aov_tile.position[index] = position / hits;
+            aov_tile.uv[index] = (u / hits, v / hits);
         }
     }
 
-    fn initalize(&mut self) {
+    // Rotate the camera around look_at, yaw about v_up and pitch about the camera's right axis
+    pub fn orbit(&mut self, yaw: f64, pitch: f64) {
+        let (u, _, _) = self.basis();
+        let up = self.v_up.unit_vector();
+        let offset = self.look_from - self.look_at;
+        let offset = rotate_about(&offset, &up, degree_to_radians(yaw));
+        let pitched = rotate_about(&offset, &u, degree_to_radians(pitch));
+
+        // Stop short of the poles so the basis never degenerates
+        if pitched.unit_vector().dot(&up).abs() < 0.99 {
+            self.look_from = self.look_at + pitched;
+        } else {
+            self.look_from = self.look_at + offset;
+        }
+    }
+
+    // Move look_from and look_at together in the camera's local frame
+    pub fn move_by(&mut self, forward: f64, right: f64, up: f64) {
+        let (u, v, w) = self.basis();
+        let offset = (-forward * w) + (right * u) + (up * v);
+        self.look_from += offset;
+        self.look_at += offset;
+    }
+
+    pub fn zoom(&mut self, factor: f64) {
+        self.vfov = (self.vfov * factor).clamp(1.0, 170.0);
+    }
+
+    // Current camera parameters as they would be written in a scene function
+    pub fn scene_snippet(&self) -> String {
+        format!(
+            "cam.vfov = {:?};\n\
+             cam.look_from = Vec3::new({:?}, {:?}, {:?});\n\
+             cam.look_at = Vec3::new({:?}, {:?}, {:?});\n\
+             cam.v_up = Vec3::new({:?}, {:?}, {:?});\n\
+             \n\
+             cam.defocus_angle = {:?};\n\
+             cam.focus_dist = {:?};{}",
+            self.vfov,
+            self.look_from.x,
+            self.look_from.y,
+            self.look_from.z,
+            self.look_at.x,
+            self.look_at.y,
+            self.look_at.z,
+            self.v_up.x,
+            self.v_up.y,
+            self.v_up.z,
+            self.defocus_angle,
+            self.focus_dist,
+            match self.projection {
+                Projection::Perspective => String::new(),
+                projection => format!("\ncam.projection = Projection::{:?};", projection),
+            }
+        )
+    }
+
+    fn basis(&self) -> (Vec3, Vec3, Vec3) {
+        let w = (self.look_from - self.look_at).unit_vector();
+        let u = self.v_up.cross(&w).unit_vector();
+        let v = w.cross(&u);
+        (u, v, w)
+    }
+
+    pub fn initalize(&mut self) {
         // Calculate the height and ensure it is at least 1
         self.image_height = (self.image_width as f64 / self.aspect_ratio) as i32;
         self.image_height = if self.image_height < 1 {
@@ -190,63 +685,141 @@ impl Camera {
 
         self.camera_center = self.look_from;
 
-        // Camera
-        let theta = degree_to_radians(self.vfov);
-        let h = (theta / 2.0).tan();
-        let viewport_height = 2.0 * h * self.focus_dist;
-        let viewport_width = viewport_height * (self.image_width as f64 / self.image_height as f64);
-
         // Calculate the u, v, w unit basis vectors for the camera coordinate frame.
-        self.w = (self.look_from - self.look_at).unit_vector();
-        self.u = self.v_up.cross(&self.w).unit_vector();
-        self.v = self.w.cross(&self.u);
-
-        // Calculate the vectors across the horizontal and down the vertical viewport edges
-        let viewport_u = viewport_width * self.u;
-        let viewport_v = viewport_height * (-self.v);
-
-        // Calculate the horizontal and vertical delta vectors from pixel to pixel
-        self.pixel_delta_u = viewport_u.clone() / self.image_width as f64;
-        self.pixel_delta_v = viewport_v.clone() / self.image_height as f64;
-
-        // Calculate the location of the upper left pixel
-        let viewport_upper_left = self.camera_center
-            - (self.focus_dist * self.w)
-            - (viewport_u / 2.0)
-            - (viewport_v / 2.0);
-        self.pixel00_loc = viewport_upper_left + (0.5 * (self.pixel_delta_u + self.pixel_delta_v));
+        (self.u, self.v, self.w) = self.basis();
 
         // Calculate the camera defocus disk basis vector
         let defocus_radius = self.focus_dist * degree_to_radians(self.defocus_angle / 2.0).tan();
         self.defocus_disk_u = self.u * defocus_radius;
         self.defocus_disk_v = self.v * defocus_radius;
+
+        self.filter_sampler = FilterSampler::new(self.filter);
+
+        // A lens that cannot focus renders black, validate reports why
+        let _ = self.focus_lens();
     }
 
-    fn get_ray(&self, i: i32, j: i32) -> Ray3 {
-        // Get a randomly-sampled camera ray for the picel at location i,j, originating the the camera defocus disk.
-        let pixel_center =
-            self.pixel00_loc + (i as f64 * self.pixel_delta_u) + (j as f64 * self.pixel_delta_v);
-        let pixel_sample = pixel_center + self.pixel_sample_square();
+    // Checks the settings that would leave nothing to render: a lens system that cannot
+    // focus at focus_dist, or a crop window that covers no pixels
+    pub fn validate(&mut self) -> Result<(), CameraError> {
+        self.initalize();
+        self.focus_lens()?;
+        self.crop_region()?;
+        Ok(())
+    }
 
-        let ray_origin = if self.defocus_angle <= 0.0 {
-            self.camera_center.clone()
-        } else {
-            self.defocus_disk_sample()
+    // A lens system focuses by moving the film
+    fn focus_lens(&mut self) -> Result<(), LensError> {
+        let aspect = self.image_width as f64 / self.image_height as f64;
+        match &mut self.lens_system {
+            Some(lens) => lens.focus(self.focus_dist * lens.mm_per_unit, aspect),
+            None => Ok(()),
+        }
+    }
+
+    // Camera ray through image position x, y in pixels, originating on the camera defocus
+    // disk. The ray's radiance is scaled by the weight, which is below 1 where a lens
+    // system vignettes.
+    fn get_ray(&self, x: f64, y: f64) -> Option<(Ray3, f64)> {
+        let s = x / self.image_width as f64;
+        let t = y / self.image_height as f64;
+
+        // A lens system replaces both the projection and the thin lens
+        if let Some(lens) = &self.lens_system {
+            let (ray, weight) = lens.sample_ray(s, t, random_f64(), random_f64())?;
+            let ray = Ray3::new(
+                self.camera_center + self.camera_to_world(*ray.origin()),
+                self.camera_to_world(*ray.direction()),
+                self.shutter.sample_time(),
+            );
+            return Some((ray, weight));
+        }
+
+        let ray = self.projected_ray(s, t, self.shutter.sample_time())?;
+
+        // Only the perspective projection has a lens to defocus with
+        if self.projection != Projection::Perspective || self.defocus_angle <= 0.0 {
+            return Some((ray, 1.0));
+        }
+        let focus_point = ray.origin() + ray.direction();
+        let ray_origin = self.defocus_disk_sample(*ray.origin(), s, t)?;
+        Some((
+            Ray3::new(ray_origin, focus_point - ray_origin, ray.time()),
+            1.0,
+        ))
+    }
+
+    // Ray through the centre of pixel i,j from the centre of the lens, without any jitter
+    pub fn pixel_center_ray(&self, i: i32, j: i32) -> Option<Ray3> {
+        let s = (i as f64 + 0.5) / self.image_width as f64;
+        let t = (j as f64 + 0.5) / self.image_height as f64;
+        self.projected_ray(s, t, 0.0)
+    }
+
+    // World space ray through image point s, t for the camera's projection
+    fn projected_ray(&self, s: f64, t: f64, time: f64) -> Option<Ray3> {
+        let aspect = self.image_width as f64 / self.image_height as f64;
+        let (origin, direction) =
+            self.projection
+                .camera_ray(s, t, aspect, self.vfov, self.focus_dist)?;
+
+        // Perspective eyes converge on the focus plane, the others look parallel
+        let eye_offset = self.eye_offset(&direction);
+        let origin = origin + eye_offset;
+        let direction = match self.projection {
+            Projection::Perspective => direction - eye_offset,
+            _ => direction,
         };
-        let ray_direction = pixel_sample - ray_origin;
-        Ray3::new(ray_origin, ray_direction, rand::random::<f64>())
+
+        Some(Ray3::new(
+            self.camera_center + self.camera_to_world(origin),
+            self.camera_to_world(direction),
+            time,
+        ))
     }
 
-    fn pixel_sample_square(&self) -> Vec3 {
-        let px = -0.5 + rand::random::<f64>();
-        let py = -0.5 + rand::random::<f64>();
-        (px * self.pixel_delta_u) + (py * self.pixel_delta_v)
+    fn camera_to_world(&self, vec: Vec3) -> Vec3 {
+        (vec.x * self.u) + (vec.y * self.v) + (vec.z * self.w)
     }
 
-    fn defocus_disk_sample(&self) -> Vec3 {
+    // Camera space offset of the eye from look_from. For omni-directional stereo the eyes
+    // turn with the panorama's longitude, so every direction sees the same parallax.
+    fn eye_offset(&self, direction: &Vec3) -> Vec3 {
+        let half_distance = match self.eye {
+            Eye::Center => return Vec3::empty(),
+            Eye::Left => -self.interpupillary_distance / 2.0,
+            Eye::Right => self.interpupillary_distance / 2.0,
+        };
+        match self.projection {
+            Projection::Equirectangular => {
+                let horizontal = Vec3::new(direction.x, 0.0, direction.z);
+                if horizontal.length() == 0.0 {
+                    return Vec3::empty();
+                }
+                let horizontal = horizontal.unit_vector();
+                Vec3::new(-horizontal.z, 0.0, horizontal.x) * half_distance
+            }
+            _ => Vec3::new(half_distance, 0.0, 0.0),
+        }
+    }
+
+    fn defocus_disk_sample(&self, lens_center: Vec3, s: f64, t: f64) -> Option<Vec3> {
         // Returns a random point in the camera defocus disk
-        let point = Vec3::random_in_unit_disk();
-        self.camera_center + (point.x * self.defocus_disk_u) + (point.y * self.defocus_disk_v)
+        let point = self.aperture.sample();
+
+        // Optical vignetting: towards the edges of the image the lens barrel cuts into the
+        // aperture from the outside, giving cat's eye shaped highlights and darker corners
+        if self.optical_vignetting > 0.0 {
+            let aspect = self.image_width as f64 / self.image_height as f64;
+            let half_diagonal = (aspect * aspect + 1.0).sqrt();
+            let offset = Vec3::new((2.0 * s - 1.0) * aspect, 1.0 - 2.0 * t, 0.0)
+                * (self.optical_vignetting / half_diagonal);
+            if (point - offset).length_squared() > 1.0 {
+                return None;
+            }
+        }
+
+        Some(lens_center + (point.x * self.defocus_disk_u) + (point.y * self.defocus_disk_v))
     }
 
     fn ray_color(
@@ -257,9 +830,19 @@ impl Camera {
         lights: &Option<Arc<dyn Hittable + Sync + Send>>,
     ) -> Vec3 {
         let mut hit_record = HitRecord::new();
+        // Rays this path has traced, counting this one
+        let path_length = (self.max_depth - depth + 1) as usize;
         if depth <= 0 {
-            Vec3::new(0.0, 0.0, 0.0)
-        } else if world.hit(&r, Interval::new(0.0001, f64::INFINITY), &mut hit_record) {
+            ray_stats::record_path(path_length - 1);
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        ray_stats::count(if depth == self.max_depth {
+            Counter::PrimaryRay
+        } else {
+            Counter::SecondaryRay
+        });
+
+        if world.hit(&r, Interval::new(0.0001, f64::INFINITY), &mut hit_record) {
             let mut color_emission = hit_record.mat.emitted(
                 &r,
                 &hit_record,
@@ -284,24 +867,33 @@ impl Camera {
                         };
 
                         let scattered = Ray3::new(hit_record.point, pdf.generate(), r.time());
-                        let pdf_val = pdf.value(&scattered.direction());
+                        let pdf_val = pdf.value(scattered.direction());
 
                         let scattered_pdf =
                             hit_record.mat.scattering_pdf(&r, &hit_record, &scattered);
-                        let sample_color = self.ray_color(scattered, depth - 1, &world, &lights);
+                        let sample_color = self.ray_color(scattered, depth - 1, world, lights);
                         color_scattered =
                             (scatter_rec.attenuation * scattered_pdf * sample_color) / pdf_val;
                     }
                     ScatterPDF::Skip(ray) => {
                         color_emission = Vec3::empty();
-                        let sample_color = self.ray_color(ray, depth - 1, &world, &lights);
+                        let sample_color = self.ray_color(ray, depth - 1, world, lights);
                         color_scattered = scatter_rec.attenuation * sample_color;
                     }
                 }
+            } else {
+                ray_stats::record_path(path_length);
             }
             color_emission + color_scattered
         } else {
+            ray_stats::record_path(path_length);
             self.background
         }
     }
 }
+
+// Rodrigues' rotation of a vector around a unit axis
+fn rotate_about(vec: &Vec3, axis: &Vec3, radians: f64) -> Vec3 {
+    let (sin, cos) = radians.sin_cos();
+    (vec * cos) + (axis.cross(vec) * sin) + (axis * (axis.dot(vec) * (1.0 - cos)))
+}
@@ -3,6 +3,7 @@ use crate::texture::Texture;
 use crate::vector_3::Vec3;
 use std::sync::Arc;
 
+#[derive(Debug)]
 pub struct CheckerTexture {
     inv_scale: f64,
     even: Arc<dyn Texture + Sync + Send>,
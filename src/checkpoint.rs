@@ -0,0 +1,728 @@
+use crate::camera::{Camera, CameraError};
+use crate::distributed::RenderJob;
+use crate::film::Film;
+use crate::hittable::Hittable;
+use crate::render_progress::{CancelToken, RenderProgress};
+use crate::vector_3::Vec3;
+use std::fmt::{self, Debug, Write as _};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+
+const MAGIC: &[u8; 8] = b"RTCKPT03";
+// Version of what the built-in scenes build, part of every scene hash
+const SCENE_VERSION: u32 = 1;
+// Magic, scene hash, width, height, samples per pixel and max depth
+const HEADER_BYTES: u64 = 8 + 8 + 4 * 4;
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(io::Error),
+    Format(String),
+    SceneMismatch { expected: u64, found: u64 },
+    SettingsMismatch(String),
+    Camera(CameraError),
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Io(err) => write!(f, "checkpoint I/O error: {}", err),
+            CheckpointError::Format(msg) => write!(f, "invalid checkpoint file: {}", msg),
+            CheckpointError::SceneMismatch { expected, found } => write!(
+                f,
+                "checkpoint was rendered from a different scene (scene hash {:016x}, checkpoint has {:016x})",
+                expected, found
+            ),
+            CheckpointError::SettingsMismatch(msg) => {
+                write!(f, "checkpoint render settings do not match: {}", msg)
+            }
+            CheckpointError::Camera(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+impl From<io::Error> for CheckpointError {
+    fn from(err: io::Error) -> CheckpointError {
+        CheckpointError::Io(err)
+    }
+}
+
+impl From<CameraError> for CheckpointError {
+    fn from(err: CameraError) -> CheckpointError {
+        CheckpointError::Camera(err)
+    }
+}
+
+pub struct Checkpoint {
+    pub scene_hash: u64,
+    pub samples_per_pixel: i32,
+    pub max_depth: i32,
+    pub film: Film,
+}
+
+impl Checkpoint {
+    pub fn new(scene_hash: u64, cam: &Camera, film: Film) -> Checkpoint {
+        Checkpoint {
+            scene_hash,
+            samples_per_pixel: cam.samples_per_pixel,
+            max_depth: cam.max_depth,
+            film,
+        }
+    }
+
+    // Written to a temporary file first so a crash mid-write keeps the previous checkpoint
+    pub fn save(&self, path: &Path) -> Result<(), CheckpointError> {
+        let temp_path = path.with_extension("tmp");
+        {
+            let mut writer = BufWriter::new(File::create(&temp_path)?);
+            writer.write_all(MAGIC)?;
+            writer.write_all(&self.scene_hash.to_le_bytes())?;
+            writer.write_all(&self.film.width().to_le_bytes())?;
+            writer.write_all(&self.film.height().to_le_bytes())?;
+            writer.write_all(&self.samples_per_pixel.to_le_bytes())?;
+            writer.write_all(&self.max_depth.to_le_bytes())?;
+            let film = &self.film;
+            for (((color, weight), squared), samples) in film
+                .radiance()
+                .iter()
+                .zip(film.weights())
+                .zip(film.squared())
+                .zip(film.samples())
+            {
+                writer.write_all(&color.x.to_le_bytes())?;
+                writer.write_all(&color.y.to_le_bytes())?;
+                writer.write_all(&color.z.to_le_bytes())?;
+                writer.write_all(&weight.to_le_bytes())?;
+                writer.write_all(&squared.x.to_le_bytes())?;
+                writer.write_all(&squared.y.to_le_bytes())?;
+                writer.write_all(&squared.z.to_le_bytes())?;
+                writer.write_all(&samples.to_le_bytes())?;
+            }
+            writer.flush()?;
+        }
+        std::fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Checkpoint, CheckpointError> {
+        let file = File::open(path)?;
+        let file_length = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+
+        let magic: [u8; 8] = read_bytes(&mut reader)?;
+        if &magic != MAGIC {
+            return Err(CheckpointError::Format(format!(
+                "{} is not a checkpoint",
+                path.display()
+            )));
+        }
+
+        let scene_hash = u64::from_le_bytes(read_bytes(&mut reader)?);
+        let width = i32::from_le_bytes(read_bytes(&mut reader)?);
+        let height = i32::from_le_bytes(read_bytes(&mut reader)?);
+        let samples_per_pixel = i32::from_le_bytes(read_bytes(&mut reader)?);
+        let max_depth = i32::from_le_bytes(read_bytes(&mut reader)?);
+        if width <= 0 || height <= 0 {
+            return Err(CheckpointError::Format(format!(
+                "bad image size {}x{}",
+                width, height
+            )));
+        }
+
+        // The size is checked against the file before anything is allocated for it
+        // Radiance, weight, squared radiance and sample count
+        let pixel_bytes = 8 * 3 + 8 + 8 * 3 + 4;
+        let pixel_count = (width as u64).checked_mul(height as u64);
+        let expected_length = pixel_count
+            .and_then(|count| count.checked_mul(pixel_bytes))
+            .and_then(|bytes| bytes.checked_add(HEADER_BYTES));
+        if expected_length != Some(file_length) {
+            return Err(CheckpointError::Format(format!(
+                "file is {} bytes, which does not match a {}x{} checkpoint",
+                file_length, width, height
+            )));
+        }
+        let pixel_count = (width as usize) * (height as usize);
+        let mut radiance = Vec::with_capacity(pixel_count);
+        let mut weights = Vec::with_capacity(pixel_count);
+        let mut squares = Vec::with_capacity(pixel_count);
+        let mut samples = Vec::with_capacity(pixel_count);
+        for _ in 0..pixel_count {
+            let x = f64::from_le_bytes(read_bytes(&mut reader)?);
+            let y = f64::from_le_bytes(read_bytes(&mut reader)?);
+            let z = f64::from_le_bytes(read_bytes(&mut reader)?);
+            radiance.push(Vec3::new(x, y, z));
+            weights.push(f64::from_le_bytes(read_bytes(&mut reader)?));
+            let x = f64::from_le_bytes(read_bytes(&mut reader)?);
+            let y = f64::from_le_bytes(read_bytes(&mut reader)?);
+            let z = f64::from_le_bytes(read_bytes(&mut reader)?);
+            squares.push(Vec3::new(x, y, z));
+            samples.push(u32::from_le_bytes(read_bytes(&mut reader)?));
+        }
+
+        Ok(Checkpoint {
+            scene_hash,
+            samples_per_pixel,
+            max_depth,
+            film: Film::from_parts(width, height, radiance, weights, squares, samples),
+        })
+    }
+
+    // Samples can only be added to a render of the same scene with the same settings
+    pub fn check(&self, scene_hash: u64, cam: &Camera) -> Result<(), CheckpointError> {
+        if self.scene_hash != scene_hash {
+            return Err(CheckpointError::SceneMismatch {
+                expected: scene_hash,
+                found: self.scene_hash,
+            });
+        }
+        if self.film.width() != cam.render_width() || self.film.height() != cam.render_height() {
+            return Err(CheckpointError::SettingsMismatch(format!(
+                "checkpoint is {}x{} but the camera renders {}x{}",
+                self.film.width(),
+                self.film.height(),
+                cam.render_width(),
+                cam.render_height()
+            )));
+        }
+        if self.max_depth != cam.max_depth {
+            return Err(CheckpointError::SettingsMismatch(format!(
+                "checkpoint used max depth {} but the camera uses {}",
+                self.max_depth, cam.max_depth
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn read_bytes<const N: usize>(reader: &mut impl Read) -> Result<[u8; N], CheckpointError> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes).map_err(|err| {
+        if err.kind() == io::ErrorKind::UnexpectedEof {
+            CheckpointError::Format("file is truncated".to_owned())
+        } else {
+            CheckpointError::Io(err)
+        }
+    })?;
+    Ok(bytes)
+}
+
+// Fingerprint of the job a scene was built from and of every camera setting that changes
+// the image. The world is not hashed: the scene index, seed and build parameters decide
+// it, and SCENE_VERSION is bumped when a built-in scene changes what it builds. The
+// samples are left out so a render can be resumed with more of them.
+pub fn scene_hash(cam: &mut Camera, job: &RenderJob) -> u64 {
+    cam.initalize();
+
+    let lens = cam
+        .lens_system
+        .as_ref()
+        .map(|lens| (lens.elements(), lens.film_diagonal, lens.mm_per_unit));
+    let settings: [&dyn Debug; 17] = [
+        &cam.look_from,
+        &cam.look_at,
+        &cam.v_up,
+        &cam.vfov,
+        &cam.aspect_ratio,
+        &cam.defocus_angle,
+        &cam.focus_dist,
+        &cam.aperture,
+        &cam.optical_vignetting,
+        &cam.shutter,
+        &cam.filter,
+        &cam.filter_mode,
+        &cam.background,
+        &cam.projection,
+        &cam.eye,
+        &cam.interpupillary_distance,
+        &lens,
+    ];
+
+    // Writing to the hasher cannot fail
+    let mut hasher = Fnv1a::new();
+    let _ = write!(
+        hasher,
+        "{};{};{};{};{};{:?};",
+        SCENE_VERSION, job.scene, job.seed, job.width, job.depth, job.accelerator
+    );
+    for setting in settings {
+        let _ = write!(hasher, "{:?};", setting);
+    }
+    hasher.finish()
+}
+
+// Renders in passes of `pass_samples` samples per pixel, writing a checkpoint after each
+// pass. With `resume` an existing checkpoint is loaded and samples are added to it until
+// the camera's samples_per_pixel is reached.
+#[allow(clippy::too_many_arguments)]
+pub fn render_checkpointed<F>(
+    cam: &mut Camera,
+    world: Arc<dyn Hittable + Sync + Send>,
+    lights: Option<Arc<dyn Hittable + Sync + Send>>,
+    job: &RenderJob,
+    threads: u32,
+    path: &Path,
+    resume: bool,
+    pass_samples: i32,
+    mut on_progress: F,
+    cancel: &CancelToken,
+) -> Result<Film, CheckpointError>
+where
+    F: FnMut(&RenderProgress),
+{
+    cam.validate()?;
+    let hash = scene_hash(cam, job);
+    // Only the crop window is rendered, so only it has to reach the sample count
+    let crop = cam.crop_region()?;
+
+    let mut film = if resume && path.exists() {
+        let checkpoint = Checkpoint::load(path)?;
+        checkpoint.check(hash, cam)?;
+        checkpoint.film
+    } else {
+        Film::new(cam.render_width(), cam.render_height())
+    };
+
+    // One progress over all the passes, so the ETA covers the whole render
+    let start_time = Instant::now();
+    let pass_samples = pass_samples.max(1);
+    let remaining = (cam.samples_per_pixel - film.min_samples_in(&crop) as i32).max(0);
+    let region = cam.sample_region();
+    let passes = (remaining + pass_samples - 1) / pass_samples;
+    let mut progress = RenderProgress::new(
+        cam.tile_count(&region) * passes as usize,
+        region.pixel_count() as u64 * remaining as u64,
+    );
+
+    while (film.min_samples_in(&crop) as i32) < cam.samples_per_pixel && !cancel.is_cancelled() {
+        let remaining = cam.samples_per_pixel - film.min_samples_in(&crop) as i32;
+        let samples = remaining.min(pass_samples);
+        let mut pass_done = RenderProgress::new(0, 0);
+        cam.render_film(
+            Arc::clone(&world),
+            lights.as_ref().map(Arc::clone),
+            threads,
+            &mut film,
+            samples,
+            |pass| {
+                progress.update(
+                    pass.tiles_done - pass_done.tiles_done,
+                    pass.samples_done - pass_done.samples_done,
+                    start_time.elapsed(),
+                );
+                pass_done = *pass;
+                on_progress(&progress);
+            },
+            cancel,
+        );
+        Checkpoint::new(hash, cam, film.clone()).save(path)?;
+    }
+
+    Ok(film)
+}
+
+// FNV-1a, used instead of DefaultHasher so hashes stay stable between builds
+struct Fnv1a {
+    state: u64,
+}
+
+impl Fnv1a {
+    fn new() -> Fnv1a {
+        Fnv1a {
+            state: 0xcbf29ce484222325,
+        }
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.state ^= byte as u64;
+            self.state = self.state.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.state
+    }
+}
+
+impl fmt::Write for Fnv1a {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        self.write_bytes(text.as_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aabb::AABB;
+    use crate::accelerator::Accelerator;
+    use crate::aperture::Aperture;
+    use crate::diffuse_light::DiffuseLight;
+    use crate::hittable::HitRecord;
+    use crate::hittable_list::HittableList;
+    use crate::interval::Interval;
+    use crate::lambertian::Lambertian;
+    use crate::quad::Quad;
+    use crate::ray::Ray3;
+    use crate::solid_texture::SolidTexture;
+    use crate::sphere::Sphere;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Cancels the render once it has made `limit` hit tests
+    #[derive(Debug)]
+    struct CancelAfter {
+        object: Arc<dyn Hittable + Sync + Send>,
+        cancel: CancelToken,
+        calls: AtomicUsize,
+        limit: AtomicUsize,
+    }
+
+    impl Hittable for CancelAfter {
+        fn hit(&self, r: &Ray3, time: Interval, hit_record: &mut HitRecord) -> bool {
+            if self.calls.fetch_add(1, Ordering::Relaxed) >= self.limit.load(Ordering::Relaxed) {
+                self.cancel.cancel();
+            }
+            self.object.hit(r, time, hit_record)
+        }
+
+        fn bounding_box(&self) -> AABB {
+            self.object.bounding_box()
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ray_tracing_{}_{}.ckpt", name, std::process::id()))
+    }
+
+    fn test_camera() -> Camera {
+        let mut cam = Camera::new();
+        cam.image_width = 8;
+        cam.aspect_ratio = 2.0;
+        cam.samples_per_pixel = 2;
+        cam.max_depth = 3;
+        cam.tile_size = 4;
+        cam
+    }
+
+    type Scene = (
+        Arc<dyn Hittable + Sync + Send>,
+        Option<Arc<dyn Hittable + Sync + Send>>,
+    );
+
+    fn test_job() -> RenderJob {
+        RenderJob {
+            scene: 1,
+            seed: 0,
+            width: 8,
+            samples: 2,
+            depth: 3,
+            projection: None,
+            accelerator: Accelerator::BVHNode,
+        }
+    }
+
+    fn test_scene() -> Scene {
+        let mut world = HittableList::new();
+        let mat = Arc::new(Lambertian::from_color(Vec3::new(0.5, 0.5, 0.5)));
+        world.add(Arc::new(Sphere::new(Vec3::new(0.0, 0.0, -1.0), 0.5, mat)));
+        let emit = Arc::new(SolidTexture::new(Vec3::new(4.0, 4.0, 4.0)));
+        let lamp: Arc<dyn Hittable + Sync + Send> = Arc::new(Quad::new(
+            Vec3::new(-1.0, 2.0, -2.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 2.0),
+            Arc::new(DiffuseLight::new(emit)),
+        ));
+        world.add(Arc::clone(&lamp));
+        (Arc::new(world), Some(lamp))
+    }
+
+    fn test_film() -> Film {
+        let (width, height) = (5, 3);
+        let count = (width * height) as usize;
+        let values = |offset: f64| {
+            (0..count)
+                .map(|index| Vec3::new(index as f64 + offset, 0.1 * index as f64, -offset))
+                .collect()
+        };
+        Film::from_parts(
+            width,
+            height,
+            values(0.25),
+            (0..count).map(|index| 1.5 * index as f64).collect(),
+            values(7.0),
+            (0..count).map(|index| index as u32 * 3).collect(),
+        )
+    }
+
+    fn bits(values: &[Vec3]) -> Vec<[u64; 3]> {
+        values
+            .iter()
+            .map(|value| [value.x.to_bits(), value.y.to_bits(), value.z.to_bits()])
+            .collect()
+    }
+
+    #[test]
+    fn checkpoints_survive_a_round_trip() {
+        let path = temp_path("round_trip");
+        let mut cam = test_camera();
+        cam.initalize();
+        Checkpoint::new(0x1234_5678_9abc_def0, &cam, test_film())
+            .save(&path)
+            .unwrap();
+        let loaded = Checkpoint::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let film = test_film();
+        assert_eq!(loaded.scene_hash, 0x1234_5678_9abc_def0);
+        assert_eq!(loaded.samples_per_pixel, cam.samples_per_pixel);
+        assert_eq!(loaded.max_depth, cam.max_depth);
+        assert_eq!(
+            (loaded.film.width(), loaded.film.height()),
+            (film.width(), film.height())
+        );
+        assert_eq!(bits(loaded.film.radiance()), bits(film.radiance()));
+        assert_eq!(bits(loaded.film.squared()), bits(film.squared()));
+        assert_eq!(loaded.film.weights(), film.weights());
+        assert_eq!(loaded.film.samples(), film.samples());
+    }
+
+    #[test]
+    fn other_scenes_and_settings_are_rejected() {
+        let mut cam = test_camera();
+        cam.initalize();
+        let film = Film::new(cam.render_width(), cam.render_height());
+        let checkpoint = Checkpoint::new(42, &cam, film);
+        assert!(checkpoint.check(42, &cam).is_ok());
+        assert!(matches!(
+            checkpoint.check(43, &cam),
+            Err(CheckpointError::SceneMismatch {
+                expected: 43,
+                found: 42
+            })
+        ));
+
+        let mut wider = test_camera();
+        wider.image_width = 16;
+        wider.initalize();
+        assert!(matches!(
+            checkpoint.check(42, &wider),
+            Err(CheckpointError::SettingsMismatch(_))
+        ));
+
+        let mut deeper = test_camera();
+        deeper.max_depth = 4;
+        deeper.initalize();
+        assert!(matches!(
+            checkpoint.check(42, &deeper),
+            Err(CheckpointError::SettingsMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn truncated_and_oversized_files_are_rejected() {
+        let path = temp_path("truncated");
+        let mut cam = test_camera();
+        cam.initalize();
+        Checkpoint::new(1, &cam, test_film()).save(&path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+
+        let load = |contents: &[u8]| {
+            std::fs::write(&path, contents).unwrap();
+            Checkpoint::load(&path)
+        };
+        for length in [4, HEADER_BYTES as usize, bytes.len() - 1] {
+            assert!(matches!(
+                load(&bytes[..length]),
+                Err(CheckpointError::Format(_))
+            ));
+        }
+        let mut longer = bytes.clone();
+        longer.push(0);
+        assert!(matches!(load(&longer), Err(CheckpointError::Format(_))));
+
+        // A huge size in the header fails on the file length, before any allocation
+        let mut huge = bytes.clone();
+        huge[16..20].copy_from_slice(&i32::MAX.to_le_bytes());
+        huge[20..24].copy_from_slice(&i32::MAX.to_le_bytes());
+        assert!(matches!(load(&huge), Err(CheckpointError::Format(_))));
+
+        assert!(matches!(
+            load(b"PNG not a checkpoint"),
+            Err(CheckpointError::Format(_))
+        ));
+        assert!(load(&bytes).is_ok());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn scene_hash_covers_the_job_and_camera() {
+        let base = scene_hash(&mut test_camera(), &test_job());
+        assert_eq!(base, scene_hash(&mut test_camera(), &test_job()));
+
+        // Resuming adds samples, so they are the one part of the job left out
+        let more_samples = RenderJob {
+            samples: 8,
+            ..test_job()
+        };
+        assert_eq!(base, scene_hash(&mut test_camera(), &more_samples));
+
+        let jobs: [fn(&mut RenderJob); 5] = [
+            |job| job.scene = 2,
+            |job| job.seed = 1,
+            |job| job.width = 16,
+            |job| job.depth = 4,
+            |job| job.accelerator = Accelerator::QBVH,
+        ];
+        for change in jobs {
+            let mut job = test_job();
+            change(&mut job);
+            assert_ne!(base, scene_hash(&mut test_camera(), &job));
+        }
+
+        let changes: [fn(&mut Camera); 5] = [
+            |cam| cam.background = Vec3::new(0.1, 0.2, 0.3),
+            |cam| {
+                cam.aperture = Aperture::Polygon {
+                    blades: 6,
+                    rotation: 0.0,
+                }
+            },
+            |cam| cam.optical_vignetting = 0.5,
+            |cam| cam.defocus_angle = 1.0,
+            |cam| cam.vfov = 40.0,
+        ];
+        for change in changes {
+            let mut cam = test_camera();
+            change(&mut cam);
+            assert_ne!(base, scene_hash(&mut cam, &test_job()));
+        }
+    }
+
+    #[test]
+    fn resuming_adds_samples_to_the_checkpoint() {
+        let path = temp_path("resume");
+        let render = |cam: &mut Camera, resume| {
+            let (world, lights) = test_scene();
+            render_checkpointed(
+                cam,
+                world,
+                lights,
+                &test_job(),
+                2,
+                &path,
+                resume,
+                1,
+                |_| {},
+                &CancelToken::new(),
+            )
+        };
+
+        let film = render(&mut test_camera(), false).unwrap();
+        assert!(film.samples().iter().all(|&samples| samples == 2));
+
+        let mut cam = test_camera();
+        cam.samples_per_pixel = 5;
+        let film = render(&mut cam, true).unwrap();
+        assert!(film.samples().iter().all(|&samples| samples == 5));
+
+        let mut cam = test_camera();
+        cam.samples_per_pixel = 6;
+        cam.background = Vec3::new(1.0, 0.0, 0.0);
+        assert!(matches!(
+            render(&mut cam, true),
+            Err(CheckpointError::SceneMismatch { .. })
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn one_progress_covers_every_pass() {
+        let path = temp_path("progress");
+        let mut cam = test_camera();
+        cam.samples_per_pixel = 4;
+        let (world, lights) = test_scene();
+        let mut reports: Vec<RenderProgress> = Vec::new();
+        render_checkpointed(
+            &mut cam,
+            world,
+            lights,
+            &test_job(),
+            2,
+            &path,
+            false,
+            1,
+            |progress| reports.push(*progress),
+            &CancelToken::new(),
+        )
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // Four passes of one sample, counted as one render that never starts over
+        let tiles = cam.tile_count(&cam.sample_region());
+        let last = reports.last().unwrap();
+        assert_eq!(reports.len(), 4 * tiles);
+        assert_eq!((last.tiles_done, last.tiles_total), (4 * tiles, 4 * tiles));
+        assert_eq!(
+            last.samples_total,
+            4 * cam.sample_region().pixel_count() as u64
+        );
+        assert_eq!(last.samples_done, last.samples_total);
+        assert_eq!(last.fraction(), 1.0);
+        for pair in reports.windows(2) {
+            assert!(pair[1].samples_done > pair[0].samples_done);
+            assert!(pair[1].elapsed >= pair[0].elapsed);
+        }
+    }
+
+    #[test]
+    fn cancelling_returns_a_partial_film() {
+        let path = temp_path("cancel");
+        let mut cam = test_camera();
+        cam.samples_per_pixel = 4;
+        let (world, lights) = test_scene();
+        let cancel = CancelToken::new();
+        let world = Arc::new(CancelAfter {
+            object: world,
+            cancel: cancel.clone(),
+            calls: AtomicUsize::new(0),
+            limit: AtomicUsize::new(usize::MAX),
+        });
+        let film = render_checkpointed(
+            &mut cam,
+            world.clone(),
+            lights,
+            &test_job(),
+            1,
+            &path,
+            false,
+            1,
+            |progress| {
+                // The worker can run ahead of the progress, so the cancel comes from the
+                // scene a quarter of a pass into the second pass, inside its first tile
+                if progress.tiles_done == progress.tiles_total / 4 {
+                    let calls = world.calls.load(Ordering::Relaxed);
+                    world.limit.store(calls + calls / 4, Ordering::Relaxed);
+                }
+            },
+            &cancel,
+        )
+        .unwrap();
+
+        // The first pass is complete, the second only has the tiles rendered before
+        // the cancel, and the checkpoint holds the same film
+        let samples = film.samples();
+        assert!(samples.iter().all(|&count| count == 1 || count == 2));
+        assert!(samples.contains(&1) && samples.contains(&2));
+        let saved = Checkpoint::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(saved.film.samples(), samples);
+        assert_eq!(bits(saved.film.radiance()), bits(film.radiance()));
+    }
+}
@@ -5,11 +5,12 @@ use crate::interval::Interval;
 use crate::isotropic::Isotropic;
 use crate::material::Material;
 use crate::ray::Ray3;
+use crate::rtweekend::random_f64;
 //use crate::texture::Texture;
 use crate::vector_3::Vec3;
-use std::f64::INFINITY;
 use std::sync::Arc;
 
+#[derive(Debug)]
 pub struct ConstantMedium {
     boundary: Arc<dyn Hittable + Sync + Send>,
     phase_func: Arc<dyn Material + Sync + Send>,
@@ -21,12 +22,13 @@ impl Hittable for ConstantMedium {
         let mut rec_1 = HitRecord::new();
         let mut rec_2 = HitRecord::new();
 
-        let has_hit = if self.boundary.hit(ray_in, Interval::univeral(), &mut rec_1)
+        if self.boundary.hit(ray_in, Interval::univeral(), &mut rec_1)
             && self.boundary.hit(
                 ray_in,
-                Interval::new(rec_1.time + 0.0001, INFINITY),
+                Interval::new(rec_1.time + 0.0001, f64::INFINITY),
                 &mut rec_2,
-            ) {
+            )
+        {
             if rec_1.time < time.min() {
                 rec_1.time = time.min();
             }
@@ -43,7 +45,7 @@ impl Hittable for ConstantMedium {
 
                 let ray_length = ray_in.direction().length();
                 let distance_inside_boundary = (rec_2.time - rec_1.time) * ray_length;
-                let hit_dist = self.neg_inv_density * rand::random::<f64>().log10();
+                let hit_dist = self.neg_inv_density * random_f64().log10();
 
                 if hit_dist > distance_inside_boundary {
                     false
@@ -53,14 +55,13 @@ impl Hittable for ConstantMedium {
                     hit_record.normal = Vec3::new(1.0, 0.0, 0.0);
                     hit_record.front_face = true;
                     hit_record.mat = Arc::clone(&self.phase_func);
+                    hit_record.object = self as *const ConstantMedium as usize;
                     true
                 }
             }
         } else {
             false
-        };
-
-        has_hit
+        }
     }
 
     fn bounding_box(&self) -> AABB {
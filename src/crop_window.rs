@@ -0,0 +1,137 @@
+use crate::tile_scheduler::Tile;
+
+// Part of the frame to render, as fractions of the image width and height measured from
+// the top left corner, so the same window works at any resolution
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CropWindow {
+    pub x_min: f64,
+    pub x_max: f64,
+    pub y_min: f64,
+    pub y_max: f64,
+}
+
+impl CropWindow {
+    pub fn new(x_min: f64, x_max: f64, y_min: f64, y_max: f64) -> CropWindow {
+        CropWindow {
+            x_min,
+            x_max,
+            y_min,
+            y_max,
+        }
+    }
+
+    // Pixels whose top left corner falls inside the window
+    pub fn pixels(&self, width: i32, height: i32) -> Tile {
+        let edge =
+            |fraction: f64, size: i32| (fraction.clamp(0.0, 1.0) * size as f64).ceil() as i32;
+        let x = edge(self.x_min, width);
+        let y = edge(self.y_min, height);
+        Tile::new(
+            x,
+            y,
+            (edge(self.x_max, width) - x).max(0),
+            (edge(self.y_max, height) - y).max(0),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+    use crate::film::Film;
+    use crate::filter::{Filter, FilterMode};
+    use crate::hittable::Hittable;
+    use crate::hittable_list::HittableList;
+    use crate::lambertian::Lambertian;
+    use crate::render_progress::CancelToken;
+    use crate::sphere::Sphere;
+    use crate::vector_3::Vec3;
+    use std::sync::Arc;
+
+    fn test_camera(crop_window: Option<CropWindow>, filter_mode: FilterMode) -> Camera {
+        let mut cam = Camera::new();
+        cam.image_width = 40;
+        cam.aspect_ratio = 4.0 / 3.0;
+        cam.samples_per_pixel = 3;
+        cam.max_depth = 4;
+        cam.tile_size = 8;
+        cam.seed = 7;
+        cam.filter = Filter::Mitchell {
+            radius: 2.0,
+            b: 1.0 / 3.0,
+            c: 1.0 / 3.0,
+        };
+        cam.filter_mode = filter_mode;
+        cam.crop_window = crop_window;
+        cam.initalize();
+        cam
+    }
+
+    fn render(cam: &mut Camera) -> Film {
+        let mut world = HittableList::new();
+        let mat = Arc::new(Lambertian::from_color(Vec3::new(0.5, 0.5, 0.5)));
+        world.add(Arc::new(Sphere::new(Vec3::new(0.0, 0.0, -1.0), 0.5, mat)));
+        let world: Arc<dyn Hittable + Sync + Send> = Arc::new(world);
+
+        let mut film = Film::new(cam.render_width(), cam.render_height());
+        let samples = cam.samples_per_pixel;
+        cam.render_film(
+            world,
+            None,
+            3,
+            &mut film,
+            samples,
+            |_| {},
+            &CancelToken::new(),
+        );
+        film
+    }
+
+    #[test]
+    fn cropped_pixels_match_the_full_frame() {
+        let window = CropWindow::new(0.3, 0.65, 0.2, 0.9);
+        for filter_mode in [FilterMode::ImportanceSampling, FilterMode::Splatting] {
+            let full = render(&mut test_camera(None, filter_mode));
+            let mut cam = test_camera(Some(window), filter_mode);
+            let cropped = render(&mut cam);
+
+            let region = cam.crop_region().unwrap();
+            assert!(region.pixel_count() > 0);
+            for (i, j) in region.pixels() {
+                let (expected, found) = (full.pixel(i, j), cropped.pixel(i, j));
+                if filter_mode == FilterMode::ImportanceSampling {
+                    // Every sample belongs to one pixel, so nothing depends on tile order
+                    assert_eq!(expected.x.to_bits(), found.x.to_bits(), "pixel {},{}", i, j);
+                    assert_eq!(expected.y.to_bits(), found.y.to_bits(), "pixel {},{}", i, j);
+                    assert_eq!(expected.z.to_bits(), found.z.to_bits(), "pixel {},{}", i, j);
+                } else {
+                    // Splats from neighbouring tiles may be summed in another order
+                    assert!((expected - found).length() < 1e-12, "pixel {},{}", i, j);
+                }
+            }
+            // Nothing outside the window is kept
+            assert_eq!(cropped.pixel(0, 0).length(), 0.0);
+        }
+    }
+
+    #[test]
+    fn windows_without_pixels_are_rejected() {
+        let crop_region = |window| {
+            let mut cam = test_camera(Some(window), FilterMode::ImportanceSampling);
+            cam.image_width = 48;
+            cam.initalize();
+            cam.crop_region()
+        };
+        // Both edges round up to column 5
+        assert!(crop_region(CropWindow::new(0.1, 0.101, 0.0, 1.0)).is_err());
+        // Clamped to the right edge of the image
+        assert!(crop_region(CropWindow::new(1.2, 1.5, 0.0, 1.0)).is_err());
+
+        let region = crop_region(CropWindow::new(0.1, 0.2, 0.0, 1.0)).unwrap();
+        assert_eq!(
+            (region.x, region.y, region.width, region.height),
+            (5, 0, 5, 36)
+        );
+    }
+}
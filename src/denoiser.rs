@@ -0,0 +1,266 @@
+use crate::aov::AovFrame;
+use crate::film::Film;
+use crate::vector_3::Vec3;
+use scoped_threadpool::Pool;
+
+// Non-local means filter for rendered images, guided by the first hit outputs. Each pixel
+// becomes an average of the pixels around it, weighted by how alike their neighbourhoods
+// look given the noise the film measured in them, and by how alike their albedo, normal
+// and depth are, so edges and texture that the noise would hide are kept.
+#[derive(Debug, Clone, Copy)]
+pub struct Denoiser {
+    // Half the width of the window a pixel is averaged over
+    pub radius: i32,
+    // Half the width of the neighbourhoods that are compared
+    pub patch_radius: i32,
+    // How far apart colours may be, in standard deviations of their noise
+    pub color_strength: f64,
+    pub albedo_sigma: f64,
+    pub normal_sigma: f64,
+    // Relative to the pixel's depth
+    pub depth_sigma: f64,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Denoiser {
+    pub fn new() -> Denoiser {
+        Denoiser {
+            radius: 7,
+            patch_radius: 1,
+            color_strength: 0.45,
+            albedo_sigma: 0.1,
+            normal_sigma: 0.3,
+            depth_sigma: 0.05,
+        }
+    }
+
+    // Denoised radiance of the film over the pixels the features were rendered for
+    pub fn denoise_film(&self, film: &Film, features: &AovFrame, threads: u32) -> Vec<Vec3> {
+        self.denoise(
+            &film.pixels_in(&features.region),
+            &film.variance_in(&features.region),
+            features,
+            threads,
+        )
+    }
+
+    // Color and its variance are given row by row over the features' region
+    pub fn denoise(
+        &self,
+        color: &[Vec3],
+        variance: &[Vec3],
+        features: &AovFrame,
+        threads: u32,
+    ) -> Vec<Vec3> {
+        let width = features.region.width;
+        let mut output = vec![Vec3::empty(); features.region.pixel_count()];
+        if width <= 0 {
+            return output;
+        }
+
+        let image = Image {
+            width,
+            height: features.region.height,
+            color,
+            variance,
+            features,
+        };
+        let mut pool = Pool::new(threads.max(1));
+        pool.scoped(|scope| {
+            for (y, row) in output.chunks_mut(width as usize).enumerate() {
+                let image = &image;
+                scope.execute(move || {
+                    for (x, pixel) in row.iter_mut().enumerate() {
+                        *pixel = self.denoise_pixel(image, x as i32, y as i32);
+                    }
+                });
+            }
+        });
+        output
+    }
+
+    fn denoise_pixel(&self, image: &Image, x: i32, y: i32) -> Vec3 {
+        let mut sum = Vec3::empty();
+        let mut total = 0.0;
+        for qy in (y - self.radius).max(0)..=(y + self.radius).min(image.height - 1) {
+            for qx in (x - self.radius).max(0)..=(x + self.radius).min(image.width - 1) {
+                let weight = self.feature_weight(image, image.index(x, y), image.index(qx, qy));
+                if weight < 1e-4 {
+                    continue;
+                }
+                let weight = weight * self.color_weight(image, x, y, qx, qy);
+                sum += weight * image.color[image.index(qx, qy)];
+                total += weight;
+            }
+        }
+        // The pixel itself always has weight one
+        sum / total
+    }
+
+    // Compares the patches around p and q, discounting the difference their noise alone
+    // would make
+    fn color_weight(&self, image: &Image, px: i32, py: i32, qx: i32, qy: i32) -> f64 {
+        let k = self.color_strength;
+        let mut distance = 0.0;
+        let mut count = 0;
+        for dy in -self.patch_radius..=self.patch_radius {
+            for dx in -self.patch_radius..=self.patch_radius {
+                let p = image.clamped_index(px + dx, py + dy);
+                let q = image.clamped_index(qx + dx, qy + dy);
+                for axis in 0..3 {
+                    let var_p = image.variance[p].at(axis);
+                    let var_q = image.variance[q].at(axis);
+                    let difference = image.color[p].at(axis) - image.color[q].at(axis);
+                    distance += (difference * difference - (var_p + var_p.min(var_q)))
+                        / (1e-10 + k * k * (var_p + var_q));
+                }
+                count += 3;
+            }
+        }
+        (-(distance / count as f64).max(0.0)).exp()
+    }
+
+    fn feature_weight(&self, image: &Image, p: usize, q: usize) -> f64 {
+        let features = image.features;
+        let (depth_p, depth_q) = (features.depth[p], features.depth[q]);
+        // Only background goes with background
+        if depth_p.is_infinite() || depth_q.is_infinite() {
+            return if depth_p.is_infinite() && depth_q.is_infinite() {
+                1.0
+            } else {
+                0.0
+            };
+        }
+
+        let albedo = (features.albedo[p] - features.albedo[q]).length_squared()
+            / (self.albedo_sigma * self.albedo_sigma);
+        let normal = (features.normal[p] - features.normal[q]).length_squared()
+            / (self.normal_sigma * self.normal_sigma);
+        let depth_sigma = self.depth_sigma * depth_p.max(1e-6);
+        let depth = (depth_p - depth_q) * (depth_p - depth_q) / (depth_sigma * depth_sigma);
+        (-0.5 * (albedo + normal + depth)).exp()
+    }
+}
+
+struct Image<'a> {
+    width: i32,
+    height: i32,
+    color: &'a [Vec3],
+    variance: &'a [Vec3],
+    features: &'a AovFrame,
+}
+
+impl Image<'_> {
+    fn index(&self, x: i32, y: i32) -> usize {
+        (y * self.width + x) as usize
+    }
+
+    // Pixels past the edge repeat the edge
+    fn clamped_index(&self, x: i32, y: i32) -> usize {
+        self.index(x.clamp(0, self.width - 1), y.clamp(0, self.height - 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rtweekend::{random_f64, with_seed};
+    use crate::tile_scheduler::Tile;
+
+    const WIDTH: i32 = 32;
+    const HEIGHT: i32 = 24;
+    const SIGMA: f64 = 0.05;
+
+    // A flat wall facing the camera, with a darker left half painted in another albedo
+    fn wall() -> AovFrame {
+        let mut features = AovFrame::new(Tile::new(0, 0, WIDTH, HEIGHT));
+        for (x, y) in features.region.pixels() {
+            let index = features.index(x, y);
+            features.depth[index] = 2.0;
+            features.normal[index] = Vec3::new(0.0, 0.0, 1.0);
+            features.albedo[index] = wall_color(x);
+            features.position[index] = Vec3::new(x as f64, y as f64, -2.0);
+        }
+        features
+    }
+
+    fn wall_color(x: i32) -> Vec3 {
+        if x < WIDTH / 2 {
+            Vec3::new(0.2, 0.2, 0.2)
+        } else {
+            Vec3::new(0.8, 0.8, 0.8)
+        }
+    }
+
+    // The wall with Gaussian noise of standard deviation SIGMA
+    fn noisy_wall() -> Vec<Vec3> {
+        with_seed(5, || {
+            let gaussian = || {
+                let (a, b) = (random_f64().max(1e-12), random_f64());
+                SIGMA * (-2.0 * a.ln()).sqrt() * (2.0 * std::f64::consts::PI * b).cos()
+            };
+            Tile::new(0, 0, WIDTH, HEIGHT)
+                .pixels()
+                .map(|(x, _)| wall_color(x) + Vec3::new(gaussian(), gaussian(), gaussian()))
+                .collect()
+        })
+    }
+
+    // Mean squared error against the clean wall over the columns, and the mean of each
+    fn errors(image: &[Vec3], columns: std::ops::Range<i32>) -> (f64, f64) {
+        let pixels: Vec<(i32, Vec3)> = Tile::new(0, 0, WIDTH, HEIGHT)
+            .pixels()
+            .zip(image)
+            .filter(|((x, _), _)| columns.contains(x))
+            .map(|((x, _), &color)| (x, color))
+            .collect();
+        let count = pixels.len() as f64;
+        let error = pixels
+            .iter()
+            .map(|(x, color)| (*color - wall_color(*x)).length_squared() / 3.0)
+            .sum::<f64>();
+        let mean = pixels.iter().map(|(_, color)| color.x).sum::<f64>();
+        (error / count, mean / count)
+    }
+
+    #[test]
+    fn constant_images_come_back_unchanged() {
+        let features = wall();
+        let color = vec![Vec3::new(0.3, 0.6, 0.9); features.region.pixel_count()];
+        for variance in [0.0, 1e-3] {
+            let variance = vec![Vec3::new(variance, variance, variance); color.len()];
+            let denoised = Denoiser::new().denoise(&color, &variance, &features, 2);
+            for pixel in denoised {
+                assert!((pixel - Vec3::new(0.3, 0.6, 0.9)).length() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn noise_drops_without_blurring_the_edge() {
+        let features = wall();
+        let color = noisy_wall();
+        let variance = vec![Vec3::new(SIGMA * SIGMA, SIGMA * SIGMA, SIGMA * SIGMA); color.len()];
+        let denoised = Denoiser::new().denoise(&color, &variance, &features, 2);
+        for columns in [0..WIDTH / 2, WIDTH / 2..WIDTH] {
+            let (before, _) = errors(&color, columns.clone());
+            let (after, _) = errors(&denoised, columns);
+            assert!(
+                after < before / 4.0,
+                "{} only went down to {}",
+                before,
+                after
+            );
+        }
+        // The columns either side of the edge keep their own side's level
+        let (_, left) = errors(&denoised, WIDTH / 2 - 1..WIDTH / 2);
+        let (_, right) = errors(&denoised, WIDTH / 2..WIDTH / 2 + 1);
+        assert!((left - 0.2).abs() < 0.01, "{}", left);
+        assert!((right - 0.8).abs() < 0.01, "{}", right);
+    }
+}
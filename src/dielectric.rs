@@ -1,6 +1,7 @@
 use crate::hittable::HitRecord;
 use crate::material::{Material, ScatterPDF, ScatterRecord};
 use crate::ray::Ray3;
+use crate::rtweekend::random_f64;
 use crate::vector_3::Vec3;
 
 #[derive(Debug, Clone, Copy)]
@@ -23,17 +24,22 @@ impl Material for Dielectric {
         let sin_theta = (1.0 - (cos_theta * cos_theta)).sqrt();
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let direction: Vec3;
-        if cannot_refract || Dielectric::reflectance(cos_theta, refraction_ratio) > rand::random() {
-            direction = unit_dir.reflect(&hit_rec.normal);
+        let direction = if cannot_refract
+            || Dielectric::reflectance(cos_theta, refraction_ratio) > random_f64()
+        {
+            unit_dir.reflect(&hit_rec.normal)
         } else {
-            direction = Vec3::refract(&unit_dir, &hit_rec.normal, refraction_ratio);
-        }
+            Vec3::refract(&unit_dir, &hit_rec.normal, refraction_ratio)
+        };
 
         let ray_out = Ray3::new(hit_rec.point, direction, ray_in.time());
         scatter_rec.pdf = ScatterPDF::Skip(ray_out);
         true
     }
+
+    fn albedo(&self, _hit_rec: &HitRecord) -> Vec3 {
+        Vec3::new(1.0, 1.0, 1.0)
+    }
 }
 
 impl Dielectric {
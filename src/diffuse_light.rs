@@ -6,6 +6,7 @@ use crate::texture::Texture;
 use crate::vector_3::Vec3;
 use std::sync::Arc;
 
+#[derive(Debug)]
 pub struct DiffuseLight {
     emit: Arc<dyn Texture + Sync + Send>,
 }
@@ -0,0 +1,698 @@
+use crate::accelerator::Accelerator;
+use crate::camera::{Camera, CameraError};
+use crate::checkpoint::scene_hash;
+use crate::film::{Film, FilmTile};
+use crate::projection::Projection;
+use crate::render_progress::{CancelToken, RenderProgress};
+use crate::scene::Scene;
+use crate::tile_scheduler::{Tile, TileScheduler};
+use crate::vector_3::Vec3;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Protocol, all values little-endian:
+//   coordinator -> worker: MAGIC, job
+//   worker -> coordinator: MAGIC, status, scene hash
+//   then for every tile:
+//   coordinator -> worker: TAG_TILE, x, y, width, height
+//   worker -> coordinator: x, y, width, height of the pixels the tile's samples reached,
+//   then the weighted radiance of each as three f64, its filter weight as one f64 and its
+//   weighted squared sample values as three f64
+//   and finally coordinator -> worker: TAG_DONE
+const MAGIC: &[u8; 8] = b"RTDIST03";
+const TAG_DONE: u8 = 0;
+const TAG_TILE: u8 = 1;
+const STATUS_OK: u8 = 0;
+const STATUS_UNKNOWN_SCENE: u8 = 1;
+
+// Everything a worker needs to build the same scene as the coordinator. A projection
+// overrides the one the scene sets up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderJob {
+    pub scene: i32,
+    pub seed: u64,
+    pub width: i32,
+    pub samples: i32,
+    pub depth: i32,
+    pub projection: Option<Projection>,
+    pub accelerator: Accelerator,
+}
+
+impl RenderJob {
+    fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(&self.scene.to_le_bytes())?;
+        writer.write_all(&self.seed.to_le_bytes())?;
+        writer.write_all(&self.width.to_le_bytes())?;
+        writer.write_all(&self.samples.to_le_bytes())?;
+        writer.write_all(&self.depth.to_le_bytes())?;
+
+        let (tag, fov) = match self.projection {
+            None => (0u8, 0.0),
+            Some(Projection::Perspective) => (1, 0.0),
+            Some(Projection::Orthographic) => (2, 0.0),
+            Some(Projection::FisheyeEquidistant { fov }) => (3, fov),
+            Some(Projection::FisheyeEquisolid { fov }) => (4, fov),
+            Some(Projection::Equirectangular) => (5, 0.0),
+        };
+        writer.write_all(&[tag])?;
+        writer.write_all(&fov.to_le_bytes())?;
+
+        let tag = match self.accelerator {
+            Accelerator::BVHNode => 0u8,
+            Accelerator::QBVH => 1,
+        };
+        writer.write_all(&[tag])
+    }
+
+    fn read(reader: &mut impl Read) -> io::Result<RenderJob> {
+        let scene = i32::from_le_bytes(read_bytes(reader)?);
+        let seed = u64::from_le_bytes(read_bytes(reader)?);
+        let width = i32::from_le_bytes(read_bytes(reader)?);
+        let samples = i32::from_le_bytes(read_bytes(reader)?);
+        let depth = i32::from_le_bytes(read_bytes(reader)?);
+
+        let tag = u8::from_le_bytes(read_bytes(reader)?);
+        let fov = f64::from_le_bytes(read_bytes(reader)?);
+        let projection = match tag {
+            0 => None,
+            1 => Some(Projection::Perspective),
+            2 => Some(Projection::Orthographic),
+            3 => Some(Projection::FisheyeEquidistant { fov }),
+            4 => Some(Projection::FisheyeEquisolid { fov }),
+            5 => Some(Projection::Equirectangular),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown projection {}", tag),
+                ))
+            }
+        };
+
+        let tag = u8::from_le_bytes(read_bytes(reader)?);
+        let accelerator = match tag {
+            0 => Accelerator::BVHNode,
+            1 => Accelerator::QBVH,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown accelerator {}", tag),
+                ))
+            }
+        };
+
+        Ok(RenderJob {
+            scene,
+            seed,
+            width,
+            samples,
+            depth,
+            projection,
+            accelerator,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum DistributedError {
+    Io(io::Error),
+    Protocol(String),
+    NoWorkers,
+    WorkersFailed { tiles_left: usize },
+    Camera(CameraError),
+}
+
+impl fmt::Display for DistributedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DistributedError::Io(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                write!(f, "connection closed by peer")
+            }
+            DistributedError::Io(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                write!(f, "peer stopped responding")
+            }
+            DistributedError::Io(err) => write!(f, "network error: {}", err),
+            DistributedError::Protocol(msg) => write!(f, "protocol error: {}", msg),
+            DistributedError::NoWorkers => write!(f, "no workers given"),
+            DistributedError::WorkersFailed { tiles_left } => write!(
+                f,
+                "every worker failed with {} tiles still to render",
+                tiles_left
+            ),
+            DistributedError::Camera(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for DistributedError {}
+
+impl From<io::Error> for DistributedError {
+    fn from(err: io::Error) -> DistributedError {
+        DistributedError::Io(err)
+    }
+}
+
+impl From<CameraError> for DistributedError {
+    fn from(err: CameraError) -> DistributedError {
+        DistributedError::Camera(err)
+    }
+}
+
+// Tiles waiting to be sent out, and how many are being rendered right now
+struct WorkQueue {
+    pending: VecDeque<Tile>,
+    in_flight: usize,
+}
+
+enum NextTile {
+    Render(Tile),
+    Wait,
+    Finished,
+}
+
+impl WorkQueue {
+    fn next(&mut self) -> NextTile {
+        match self.pending.pop_front() {
+            Some(tile) => {
+                self.in_flight += 1;
+                NextTile::Render(tile)
+            }
+            // A tile still being rendered may come back if its worker dies
+            None if self.in_flight > 0 => NextTile::Wait,
+            None => NextTile::Finished,
+        }
+    }
+
+    fn finish(&mut self) {
+        self.in_flight -= 1;
+    }
+
+    fn requeue(&mut self, tile: Tile) {
+        self.in_flight -= 1;
+        self.pending.push_front(tile);
+    }
+}
+
+// Renders the camera's image on remote workers. Every worker builds the scene from `job`
+// itself and has to arrive at the same scene hash as the coordinator. Tiles of a worker
+// that disconnects, or that sends nothing for `timeout`, are handed to the remaining
+// workers, so the timeout has to be longer than a worker takes to render a tile.
+#[allow(clippy::too_many_arguments)]
+pub fn render_distributed<F>(
+    cam: &mut Camera,
+    job: &RenderJob,
+    workers: &[String],
+    timeout: Duration,
+    mut on_progress: F,
+    cancel: &CancelToken,
+) -> Result<Film, DistributedError>
+where
+    F: FnMut(&RenderProgress),
+{
+    if workers.is_empty() {
+        return Err(DistributedError::NoWorkers);
+    }
+
+    cam.validate()?;
+    let hash = scene_hash(cam, job);
+    let crop = cam.crop_region()?;
+    let region = cam.sample_region();
+    let tiles = TileScheduler::ordered_tiles(&region, cam.tile_size, cam.tile_order);
+    let tiles_total = tiles.len();
+    let samples = cam.samples_per_pixel;
+
+    let queue = Mutex::new(WorkQueue {
+        pending: tiles.into(),
+        in_flight: 0,
+    });
+    let frame = cam.full_frame();
+    let mut film = Film::new(cam.render_width(), cam.render_height());
+    let mut progress =
+        RenderProgress::new(tiles_total, region.pixel_count() as u64 * samples as u64);
+    let start_time = Instant::now();
+
+    thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        for address in workers {
+            let sender = sender.clone();
+            let queue = &queue;
+            let frame = &frame;
+            scope.spawn(move || {
+                let worker = Worker {
+                    address,
+                    frame,
+                    timeout,
+                };
+                if let Err(err) = drive_worker(&worker, job, hash, queue, &sender, cancel) {
+                    eprintln!("\nworker {}: {}", address, err);
+                }
+            });
+        }
+        drop(sender);
+
+        for (tile, mut film_tile) in receiver {
+            if cam.crop_window.is_some() {
+                film_tile.retain(&crop);
+            }
+            film.add_tile(&tile, &film_tile, samples as u32);
+            let tile_samples = tile.pixel_count() as u64 * samples as u64;
+            progress.update(1, tile_samples, start_time.elapsed());
+            on_progress(&progress);
+        }
+    });
+
+    if progress.tiles_done < tiles_total && !cancel.is_cancelled() {
+        return Err(DistributedError::WorkersFailed {
+            tiles_left: tiles_total - progress.tiles_done,
+        });
+    }
+    Ok(film)
+}
+
+// A connection to one worker, rendering tiles of `frame`
+struct Worker<'a> {
+    address: &'a str,
+    frame: &'a Tile,
+    timeout: Duration,
+}
+
+fn drive_worker(
+    worker: &Worker,
+    job: &RenderJob,
+    hash: u64,
+    queue: &Mutex<WorkQueue>,
+    sender: &mpsc::Sender<(Tile, FilmTile)>,
+    cancel: &CancelToken,
+) -> Result<(), DistributedError> {
+    let stream = connect(worker.address, worker.timeout)?;
+    stream.set_nodelay(true)?;
+    // A worker that hangs with its socket open is treated like one that disconnected
+    stream.set_read_timeout(Some(worker.timeout))?;
+    stream.set_write_timeout(Some(worker.timeout))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    writer.write_all(MAGIC)?;
+    job.write(&mut writer)?;
+    writer.flush()?;
+
+    read_magic(&mut reader)?;
+    let status = u8::from_le_bytes(read_bytes(&mut reader)?);
+    if status == STATUS_UNKNOWN_SCENE {
+        return Err(DistributedError::Protocol(format!(
+            "worker does not know scene {}",
+            job.scene
+        )));
+    }
+    let worker_hash = u64::from_le_bytes(read_bytes(&mut reader)?);
+    if worker_hash != hash {
+        return Err(DistributedError::Protocol(format!(
+            "worker built a different scene (scene hash {:016x}, worker has {:016x})",
+            hash, worker_hash
+        )));
+    }
+
+    while !cancel.is_cancelled() {
+        let next = queue.lock().unwrap().next();
+        let tile = match next {
+            NextTile::Render(tile) => tile,
+            NextTile::Wait => {
+                thread::sleep(Duration::from_millis(10));
+                continue;
+            }
+            NextTile::Finished => break,
+        };
+
+        match request_tile(&mut reader, &mut writer, &tile, worker.frame) {
+            Ok(film_tile) => {
+                queue.lock().unwrap().finish();
+                let _ = sender.send((tile, film_tile));
+            }
+            Err(err) => {
+                queue.lock().unwrap().requeue(tile);
+                return Err(err);
+            }
+        }
+    }
+
+    writer.write_all(&[TAG_DONE])?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn connect(address: &str, timeout: Duration) -> io::Result<TcpStream> {
+    let mut last_err = io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{} does not resolve to an address", address),
+    );
+    for socket_address in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&socket_address, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_err = err,
+        }
+    }
+    Err(last_err)
+}
+
+fn request_tile(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    tile: &Tile,
+    frame: &Tile,
+) -> Result<FilmTile, DistributedError> {
+    writer.write_all(&[TAG_TILE])?;
+    write_tile(writer, tile)?;
+    writer.flush()?;
+
+    // Splatted samples may reach past the tile, but never past the image
+    let region = read_tile(reader)?;
+    if !inside(&region, frame)
+        || region.x > tile.x
+        || region.y > tile.y
+        || region.x + region.width < tile.x + tile.width
+        || region.y + region.height < tile.y + tile.height
+    {
+        return Err(DistributedError::Protocol(format!(
+            "worker returned {:?} for tile {:?}",
+            region, tile
+        )));
+    }
+
+    let mut film_tile = FilmTile::new(region);
+    for index in 0..region.pixel_count() {
+        let x = f64::from_le_bytes(read_bytes(reader)?);
+        let y = f64::from_le_bytes(read_bytes(reader)?);
+        let z = f64::from_le_bytes(read_bytes(reader)?);
+        film_tile.radiance[index] = Vec3::new(x, y, z);
+        film_tile.weights[index] = f64::from_le_bytes(read_bytes(reader)?);
+        let x = f64::from_le_bytes(read_bytes(reader)?);
+        let y = f64::from_le_bytes(read_bytes(reader)?);
+        let z = f64::from_le_bytes(read_bytes(reader)?);
+        film_tile.squared[index] = Vec3::new(x, y, z);
+    }
+    Ok(film_tile)
+}
+
+// Serves coordinators one at a time. `build` turns a job into a scene, or None when the
+// scene is unknown to this worker.
+pub fn serve_worker<F>(listener: TcpListener, threads: u32, build: F) -> io::Result<()>
+where
+    F: Fn(&RenderJob) -> Option<Scene>,
+{
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let peer = stream.peer_addr()?;
+        println!("Coordinator {} connected", peer);
+        match serve_coordinator(stream, threads, &build) {
+            Ok(tiles) => println!("Coordinator {} done, rendered {} tiles", peer, tiles),
+            Err(err) => eprintln!("Coordinator {}: {}", peer, err),
+        }
+    }
+    Ok(())
+}
+
+fn serve_coordinator<F>(
+    stream: TcpStream,
+    threads: u32,
+    build: &F,
+) -> Result<usize, DistributedError>
+where
+    F: Fn(&RenderJob) -> Option<Scene>,
+{
+    stream.set_nodelay(true)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    read_magic(&mut reader)?;
+    let job = RenderJob::read(&mut reader)?;
+
+    writer.write_all(MAGIC)?;
+    let Some(scene) = build(&job) else {
+        writer.write_all(&[STATUS_UNKNOWN_SCENE])?;
+        writer.flush()?;
+        return Err(DistributedError::Protocol(format!(
+            "unknown scene {}",
+            job.scene
+        )));
+    };
+    let Scene {
+        mut cam,
+        world,
+        lights,
+        ..
+    } = scene;
+    writer.write_all(&[STATUS_OK])?;
+    writer.write_all(&scene_hash(&mut cam, &job).to_le_bytes())?;
+    writer.flush()?;
+
+    let mut tiles = 0;
+    loop {
+        match u8::from_le_bytes(read_bytes(&mut reader)?) {
+            TAG_DONE => return Ok(tiles),
+            TAG_TILE => {}
+            tag => {
+                return Err(DistributedError::Protocol(format!(
+                    "unexpected message {}",
+                    tag
+                )))
+            }
+        }
+
+        let tile = read_tile(&mut reader)?;
+        if !inside(&tile, &cam.full_frame()) {
+            return Err(DistributedError::Protocol(format!(
+                "tile {:?} is outside the image",
+                tile
+            )));
+        }
+
+        let samples = cam.samples_per_pixel;
+        let film_tile = cam.render_region(
+            Arc::clone(&world),
+            lights.as_ref().map(Arc::clone),
+            threads,
+            &tile,
+            samples,
+        );
+        write_tile(&mut writer, &film_tile.region)?;
+        for ((color, weight), squared) in film_tile
+            .radiance
+            .iter()
+            .zip(&film_tile.weights)
+            .zip(&film_tile.squared)
+        {
+            writer.write_all(&color.x.to_le_bytes())?;
+            writer.write_all(&color.y.to_le_bytes())?;
+            writer.write_all(&color.z.to_le_bytes())?;
+            writer.write_all(&weight.to_le_bytes())?;
+            writer.write_all(&squared.x.to_le_bytes())?;
+            writer.write_all(&squared.y.to_le_bytes())?;
+            writer.write_all(&squared.z.to_le_bytes())?;
+        }
+        writer.flush()?;
+        tiles += 1;
+    }
+}
+
+// Whether a tile read from a peer is non-empty and lies within the frame. The sizes are
+// compared by subtraction so that huge values cannot overflow.
+fn inside(tile: &Tile, frame: &Tile) -> bool {
+    tile.width > 0
+        && tile.height > 0
+        && tile.x >= frame.x
+        && tile.y >= frame.y
+        && tile.width <= frame.x + frame.width - tile.x
+        && tile.height <= frame.y + frame.height - tile.y
+}
+
+fn write_tile(writer: &mut impl Write, tile: &Tile) -> io::Result<()> {
+    writer.write_all(&tile.x.to_le_bytes())?;
+    writer.write_all(&tile.y.to_le_bytes())?;
+    writer.write_all(&tile.width.to_le_bytes())?;
+    writer.write_all(&tile.height.to_le_bytes())
+}
+
+fn read_tile(reader: &mut impl Read) -> io::Result<Tile> {
+    Ok(Tile::new(
+        i32::from_le_bytes(read_bytes(reader)?),
+        i32::from_le_bytes(read_bytes(reader)?),
+        i32::from_le_bytes(read_bytes(reader)?),
+        i32::from_le_bytes(read_bytes(reader)?),
+    ))
+}
+
+fn read_magic(reader: &mut impl Read) -> Result<(), DistributedError> {
+    let magic: [u8; 8] = read_bytes(reader)?;
+    if &magic != MAGIC {
+        return Err(DistributedError::Protocol(
+            "peer is not a ray tracer".to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+fn read_bytes<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable_list::HittableList;
+    use crate::lambertian::Lambertian;
+    use crate::sphere::Sphere;
+
+    fn test_scene(job: &RenderJob) -> Option<Scene> {
+        if job.scene != 1 {
+            return None;
+        }
+
+        let mut world = HittableList::new();
+        let mat = Arc::new(Lambertian::from_color(Vec3::new(0.5, 0.5, 0.5)));
+        world.add(Arc::new(Sphere::new(Vec3::new(0.0, 0.0, -1.0), 0.5, mat)));
+
+        let mut cam = Camera::new();
+        cam.image_width = job.width;
+        cam.samples_per_pixel = job.samples;
+        cam.max_depth = job.depth;
+        cam.tile_size = 4;
+        if let Some(projection) = job.projection {
+            cam.projection = projection;
+        }
+        Some(Scene {
+            name: "test",
+            cam,
+            world: Arc::new(world),
+            lights: None,
+        })
+    }
+
+    fn spawn_worker() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || serve_worker(listener, 2, test_scene));
+        address
+    }
+
+    // Answers the handshake like a real worker, then calls `misbehave` on the first tile
+    fn spawn_faulty_worker(misbehave: fn(&mut TcpStream)) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_magic(&mut stream).unwrap();
+            let job = RenderJob::read(&mut stream).unwrap();
+            let Scene { mut cam, .. } = test_scene(&job).unwrap();
+            stream.write_all(MAGIC).unwrap();
+            stream.write_all(&[STATUS_OK]).unwrap();
+            stream
+                .write_all(&scene_hash(&mut cam, &job).to_le_bytes())
+                .unwrap();
+            let _tag: [u8; 1] = read_bytes(&mut stream).unwrap();
+            read_tile(&mut stream).unwrap();
+            misbehave(&mut stream);
+        });
+        address
+    }
+
+    fn render_with(workers: &[String], timeout: Duration) -> Result<Film, DistributedError> {
+        let job = RenderJob {
+            scene: 1,
+            seed: 0,
+            width: 24,
+            samples: 2,
+            depth: 4,
+            projection: None,
+            accelerator: Accelerator::BVHNode,
+        };
+        let Scene { mut cam, .. } = test_scene(&job).unwrap();
+        render_distributed(
+            &mut cam,
+            &job,
+            workers,
+            timeout,
+            |_| {},
+            &CancelToken::new(),
+        )
+    }
+
+    #[test]
+    fn tiles_of_a_dead_worker_are_rendered_by_the_others() {
+        let workers = vec![spawn_faulty_worker(|_| {}), spawn_worker(), spawn_worker()];
+        let film = render_with(&workers, Duration::from_secs(10)).unwrap();
+        assert_eq!(film.min_samples(), 2);
+        assert!(film.samples().iter().all(|&samples| samples == 2));
+    }
+
+    #[test]
+    fn tiles_of_a_hanging_worker_are_rendered_by_the_others() {
+        // Keeps the connection open without ever answering
+        let hang = |stream: &mut TcpStream| {
+            thread::sleep(Duration::from_secs(60));
+            let _ = stream.flush();
+        };
+        let workers = vec![spawn_faulty_worker(hang), spawn_worker()];
+        let start = Instant::now();
+        let film = render_with(&workers, Duration::from_millis(300)).unwrap();
+        assert!(film.samples().iter().all(|&samples| samples == 2));
+        assert!(start.elapsed() < Duration::from_secs(30));
+    }
+
+    #[test]
+    fn regions_past_the_image_are_rejected() {
+        // Claims a region far larger than the image before anything is allocated for it
+        let oversized = |stream: &mut TcpStream| {
+            write_tile(stream, &Tile::new(0, 0, i32::MAX, i32::MAX)).unwrap();
+            thread::sleep(Duration::from_secs(1));
+        };
+        let workers = vec![spawn_faulty_worker(oversized), spawn_worker()];
+        let film = render_with(&workers, Duration::from_secs(10)).unwrap();
+        assert!(film.samples().iter().all(|&samples| samples == 2));
+
+        let frame = Tile::new(0, 0, 24, 13);
+        assert!(inside(&Tile::new(0, 0, 24, 13), &frame));
+        assert!(!inside(&Tile::new(i32::MAX, 0, i32::MAX, 1), &frame));
+        assert!(!inside(&Tile::new(-1, 0, 4, 4), &frame));
+        assert!(!inside(&Tile::new(20, 0, 5, 4), &frame));
+        assert!(!inside(&Tile::new(0, 0, 0, 4), &frame));
+    }
+
+    #[test]
+    fn unknown_scene_is_rejected() {
+        let job = RenderJob {
+            scene: 2,
+            seed: 0,
+            width: 8,
+            samples: 1,
+            depth: 2,
+            projection: None,
+            accelerator: Accelerator::BVHNode,
+        };
+        let workers = vec![spawn_worker()];
+
+        let Scene { mut cam, .. } = test_scene(&RenderJob { scene: 1, ..job }).unwrap();
+        let result = render_distributed(
+            &mut cam,
+            &job,
+            &workers,
+            Duration::from_secs(10),
+            |_| {},
+            &CancelToken::new(),
+        );
+        assert!(matches!(
+            result,
+            Err(DistributedError::WorkersFailed { .. })
+        ));
+    }
+}
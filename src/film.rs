@@ -0,0 +1,211 @@
+use crate::tile_scheduler::Tile;
+use crate::vector_3::Vec3;
+
+// Linear radiance accumulated per pixel, weighted by the reconstruction filter, along with
+// the weight it is divided by and how many samples were taken for the pixel. The weight is
+// the sample count when the filter is importance sampled and the summed filter weight when
+// samples are splatted. The weighted sum of squared sample values gives each pixel's
+// variance.
+#[derive(Clone)]
+pub struct Film {
+    width: i32,
+    height: i32,
+    radiance: Vec<Vec3>,
+    weights: Vec<f64>,
+    squared: Vec<Vec3>,
+    samples: Vec<u32>,
+}
+
+// Weighted radiance and filter weights that one tile of samples added to a block of
+// pixels. When samples are splatted the block is the tile grown by the filter radius.
+pub struct FilmTile {
+    pub region: Tile,
+    pub radiance: Vec<Vec3>,
+    pub weights: Vec<f64>,
+    pub squared: Vec<Vec3>,
+}
+
+impl FilmTile {
+    pub fn new(region: Tile) -> FilmTile {
+        FilmTile {
+            region,
+            radiance: vec![Vec3::empty(); region.pixel_count()],
+            weights: vec![0.0; region.pixel_count()],
+            squared: vec![Vec3::empty(); region.pixel_count()],
+        }
+    }
+
+    // Index of pixel i, j of the image, which has to be inside the region
+    pub fn index(&self, i: i32, j: i32) -> usize {
+        ((j - self.region.y) * self.region.width + (i - self.region.x)) as usize
+    }
+
+    pub fn add(&mut self, i: i32, j: i32, radiance: Vec3, weight: f64) {
+        let index = self.index(i, j);
+        self.radiance[index] += radiance;
+        self.weights[index] += weight;
+        // The sample's value is radiance / weight, counted with the weight
+        if weight != 0.0 {
+            self.squared[index] += radiance * radiance / weight.abs();
+        }
+    }
+
+    // Add everything another tile left on pixels of this one
+    pub fn accumulate(&mut self, other: &FilmTile) {
+        for (i, j) in other.region.pixels() {
+            let index = self.index(i, j);
+            let other_index = other.index(i, j);
+            self.radiance[index] += other.radiance[other_index];
+            self.weights[index] += other.weights[other_index];
+            self.squared[index] += other.squared[other_index];
+        }
+    }
+
+    // Drop everything that landed outside the window
+    pub fn retain(&mut self, window: &Tile) {
+        for (i, j) in self.region.pixels() {
+            if i < window.x
+                || j < window.y
+                || i >= window.x + window.width
+                || j >= window.y + window.height
+            {
+                let index = self.index(i, j);
+                self.radiance[index] = Vec3::empty();
+                self.weights[index] = 0.0;
+                self.squared[index] = Vec3::empty();
+            }
+        }
+    }
+}
+
+impl Film {
+    pub fn new(width: i32, height: i32) -> Film {
+        let pixel_count = (width * height) as usize;
+        Film {
+            width,
+            height,
+            radiance: vec![Vec3::empty(); pixel_count],
+            weights: vec![0.0; pixel_count],
+            squared: vec![Vec3::empty(); pixel_count],
+            samples: vec![0; pixel_count],
+        }
+    }
+
+    pub fn from_parts(
+        width: i32,
+        height: i32,
+        radiance: Vec<Vec3>,
+        weights: Vec<f64>,
+        squared: Vec<Vec3>,
+        samples: Vec<u32>,
+    ) -> Film {
+        Film {
+            width,
+            height,
+            radiance,
+            weights,
+            squared,
+            samples,
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn radiance(&self) -> &[Vec3] {
+        &self.radiance
+    }
+
+    pub fn weights(&self) -> &[f64] {
+        &self.weights
+    }
+
+    pub fn squared(&self) -> &[Vec3] {
+        &self.squared
+    }
+
+    pub fn samples(&self) -> &[u32] {
+        &self.samples
+    }
+
+    pub fn min_samples(&self) -> u32 {
+        self.samples.iter().copied().min().unwrap_or(0)
+    }
+
+    // Fewest samples of any pixel in the region
+    pub fn min_samples_in(&self, region: &Tile) -> u32 {
+        region
+            .pixels()
+            .map(|(i, j)| self.samples[(j * self.width + i) as usize])
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub fn clear(&mut self) {
+        self.radiance.fill(Vec3::empty());
+        self.weights.fill(0.0);
+        self.squared.fill(Vec3::empty());
+        self.samples.fill(0);
+    }
+
+    // Add what `samples` samples per pixel of the tile left on the film
+    pub fn add_tile(&mut self, tile: &Tile, film_tile: &FilmTile, samples: u32) {
+        for (i, j) in film_tile.region.pixels() {
+            if i < 0 || j < 0 || i >= self.width || j >= self.height {
+                continue;
+            }
+            let index = (j * self.width + i) as usize;
+            let tile_index = film_tile.index(i, j);
+            self.radiance[index] += film_tile.radiance[tile_index];
+            self.weights[index] += film_tile.weights[tile_index];
+            self.squared[index] += film_tile.squared[tile_index];
+        }
+        for (i, j) in tile.pixels() {
+            self.samples[(j * self.width + i) as usize] += samples;
+        }
+    }
+
+    // Filtered radiance of a pixel, black if nothing has reached it yet
+    pub fn pixel(&self, i: i32, j: i32) -> Vec3 {
+        let index = (j * self.width + i) as usize;
+        if self.weights[index] <= 0.0 {
+            Vec3::empty()
+        } else {
+            self.radiance[index] / self.weights[index]
+        }
+    }
+
+    // Variance of a pixel's filtered radiance, estimated from the spread of its samples
+    pub fn variance(&self, i: i32, j: i32) -> Vec3 {
+        let index = (j * self.width + i) as usize;
+        let weight = self.weights[index];
+        if weight <= 0.0 || self.samples[index] == 0 {
+            return Vec3::empty();
+        }
+        let mean = self.radiance[index] / weight;
+        let spread = self.squared[index] / weight - mean * mean;
+        Vec3::new(spread.x.max(0.0), spread.y.max(0.0), spread.z.max(0.0))
+            / self.samples[index] as f64
+    }
+
+    // Filtered radiance of the pixels of a region, row by row
+    pub fn pixels_in(&self, region: &Tile) -> Vec<Vec3> {
+        region.pixels().map(|(i, j)| self.pixel(i, j)).collect()
+    }
+
+    pub fn variance_in(&self, region: &Tile) -> Vec<Vec3> {
+        region.pixels().map(|(i, j)| self.variance(i, j)).collect()
+    }
+
+    pub fn to_buffer(&self) -> Vec<u32> {
+        (0..self.height)
+            .flat_map(|j| (0..self.width).map(move |i| (i, j)))
+            .map(|(i, j)| crate::color::vec_to_val(&self.pixel(i, j), 1))
+            .collect()
+    }
+}
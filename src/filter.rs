@@ -0,0 +1,287 @@
+use crate::rtweekend::random_f64;
+use std::f64::consts::PI;
+
+// Bins per axis of the table filter offsets are importance sampled from
+const SAMPLER_BINS: usize = 256;
+
+// Pixel reconstruction filter. Filters are separable and extend `radius` pixels from the
+// pixel centre along each axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    Box { radius: f64 },
+    Tent { radius: f64 },
+    // Gaussian of standard deviation sigma, shifted down to reach zero at the radius
+    Gaussian { radius: f64, sigma: f64 },
+    // Mitchell-Netravali cubic, b = c = 1/3 is the one its authors recommend
+    Mitchell { radius: f64, b: f64, c: f64 },
+    // Sinc windowed by a sinc stretched over the radius
+    Lanczos { radius: f64 },
+}
+
+// How samples are turned into pixels. Importance sampling draws each pixel's samples in
+// proportion to the filter around it, so every sample belongs to one pixel. Splatting
+// spreads each sample over all the pixels the filter reaches, weighted by the filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterMode {
+    ImportanceSampling,
+    Splatting,
+}
+
+impl Filter {
+    pub fn radius(&self) -> f64 {
+        match *self {
+            Filter::Box { radius }
+            | Filter::Tent { radius }
+            | Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius, .. }
+            | Filter::Lanczos { radius } => radius,
+        }
+    }
+
+    // Weight of a sample x, y pixels away from the pixel centre
+    pub fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.evaluate_1d(x) * self.evaluate_1d(y)
+    }
+
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        let x = x.abs();
+        let radius = self.radius();
+        if x > radius {
+            return 0.0;
+        }
+        match *self {
+            Filter::Box { .. } => 1.0,
+            Filter::Tent { .. } => radius - x,
+            Filter::Gaussian { sigma, .. } => {
+                let gaussian = |x: f64| (-x * x / (2.0 * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(radius)).max(0.0)
+            }
+            Filter::Mitchell { b, c, .. } => {
+                // The cubic is defined over [-2, 2]
+                let x = 2.0 * x / radius;
+                if x < 1.0 {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                        + (6.0 - 2.0 * b))
+                        / 6.0
+                } else {
+                    ((-b - 6.0 * c) * x * x * x
+                        + (6.0 * b + 30.0 * c) * x * x
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c))
+                        / 6.0
+                }
+            }
+            Filter::Lanczos { .. } => sinc(x) * sinc(x / radius),
+        }
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x < 1e-5 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+// Draws pixel offsets with density proportional to the filter's magnitude. The weights
+// average to one, and filters with negative lobes give offsets there a negative weight.
+pub struct FilterSampler {
+    filter: Filter,
+    // Cumulative magnitude of the filter over the bins of [-radius, radius]
+    cdf: Vec<f64>,
+    signs: Vec<f64>,
+    // Integral of the magnitude over the integral of the filter, along one axis
+    scale: f64,
+}
+
+impl FilterSampler {
+    pub fn new(filter: Filter) -> FilterSampler {
+        let radius = filter.radius();
+        let bin_width = 2.0 * radius / SAMPLER_BINS as f64;
+
+        let mut total = 0.0;
+        let mut signed_total = 0.0;
+        let mut cdf = Vec::with_capacity(SAMPLER_BINS);
+        let mut signs = Vec::with_capacity(SAMPLER_BINS);
+        for bin in 0..SAMPLER_BINS {
+            let value = filter.evaluate_1d(-radius + (bin as f64 + 0.5) * bin_width);
+            total += value.abs();
+            signed_total += value;
+            cdf.push(total);
+            signs.push(if value < 0.0 { -1.0 } else { 1.0 });
+        }
+        if total > 0.0 {
+            cdf.iter_mut().for_each(|value| *value /= total);
+        }
+
+        let scale = if signed_total > 0.0 {
+            total / signed_total
+        } else {
+            1.0
+        };
+
+        FilterSampler {
+            filter,
+            cdf,
+            signs,
+            scale,
+        }
+    }
+
+    pub fn filter(&self) -> Filter {
+        self.filter
+    }
+
+    // Offset from the pixel centre and the sample's weight
+    pub fn sample(&self) -> (f64, f64, f64) {
+        // A box is sampled exactly, without going through the table
+        if let Filter::Box { radius } = self.filter {
+            let x = (2.0 * random_f64() - 1.0) * radius;
+            let y = (2.0 * random_f64() - 1.0) * radius;
+            return (x, y, 1.0);
+        }
+
+        let (x, sign_x) = self.sample_1d(random_f64());
+        let (y, sign_y) = self.sample_1d(random_f64());
+        (x, y, sign_x * sign_y * self.scale * self.scale)
+    }
+
+    fn sample_1d(&self, u: f64) -> (f64, f64) {
+        let bin = self
+            .cdf
+            .partition_point(|&value| value <= u)
+            .min(SAMPLER_BINS - 1);
+        let start = if bin == 0 { 0.0 } else { self.cdf[bin - 1] };
+        let width = self.cdf[bin] - start;
+        let within = if width > 0.0 {
+            (u - start) / width
+        } else {
+            0.5
+        };
+
+        let radius = self.filter.radius();
+        let bin_width = 2.0 * radius / SAMPLER_BINS as f64;
+        (-radius + (bin as f64 + within) * bin_width, self.signs[bin])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+    use crate::film::Film;
+    use crate::hittable_list::HittableList;
+    use crate::render_progress::CancelToken;
+    use crate::rtweekend::with_seed;
+    use crate::vector_3::Vec3;
+    use std::sync::Arc;
+
+    fn filters() -> [Filter; 5] {
+        [
+            Filter::Box { radius: 0.5 },
+            Filter::Tent { radius: 1.0 },
+            Filter::Gaussian {
+                radius: 1.5,
+                sigma: 0.5,
+            },
+            Filter::Mitchell {
+                radius: 2.0,
+                b: 1.0 / 3.0,
+                c: 1.0 / 3.0,
+            },
+            Filter::Lanczos { radius: 3.0 },
+        ]
+    }
+
+    // Integral of the filter times g over the integral of the filter, along one axis
+    fn normalised_integral(filter: &Filter, g: impl Fn(f64) -> f64) -> f64 {
+        let steps = 100_000;
+        let radius = filter.radius();
+        let step = 2.0 * radius / steps as f64;
+        let (mut weighted, mut total) = (0.0, 0.0);
+        for n in 0..steps {
+            let x = -radius + (n as f64 + 0.5) * step;
+            weighted += filter.evaluate_1d(x) * g(x);
+            total += filter.evaluate_1d(x);
+        }
+        weighted / total
+    }
+
+    #[test]
+    fn importance_sampling_is_unbiased() {
+        let count = 400_000;
+        for filter in filters() {
+            let sampler = FilterSampler::new(filter);
+            let radius = filter.radius();
+            let wave = |x: f64| (PI * x / radius).cos();
+            with_seed(6, || {
+                let samples: Vec<(f64, f64, f64)> = (0..count).map(|_| sampler.sample()).collect();
+                // The estimate of the filtered mean of g must match the quadrature within
+                // four standard errors, plus what the binned table is allowed to miss by
+                let check = |g: &dyn Fn(f64, f64) -> f64, expected: f64| {
+                    let values: Vec<f64> = samples.iter().map(|&(x, y, w)| w * g(x, y)).collect();
+                    let mean = values.iter().sum::<f64>() / count as f64;
+                    let variance =
+                        values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / count as f64;
+                    let tolerance = 4.0 * (variance / count as f64).sqrt() + 2e-3;
+                    assert!(
+                        (mean - expected).abs() < tolerance,
+                        "{:?}: {} against {}",
+                        filter,
+                        mean,
+                        expected
+                    );
+                };
+                // The weights average to one
+                check(&|_, _| 1.0, 1.0);
+                check(&|x, _| x * x, normalised_integral(&filter, |x| x * x));
+                check(&|_, y| wave(y), normalised_integral(&filter, wave));
+                assert!(samples
+                    .iter()
+                    .all(|&(x, y, _)| x.abs() <= radius && y.abs() <= radius));
+            })
+        }
+    }
+
+    #[test]
+    fn splatting_a_constant_image_reproduces_it() {
+        let color = Vec3::new(0.2, 0.5, 0.9);
+        for filter in filters() {
+            let mut cam = Camera::new();
+            cam.image_width = 12;
+            cam.aspect_ratio = 1.5;
+            cam.samples_per_pixel = 8;
+            cam.background = color;
+            cam.filter = filter;
+            cam.filter_mode = FilterMode::Splatting;
+            cam.initalize();
+            let mut film = Film::new(cam.render_width(), cam.render_height());
+            with_seed(7, || {
+                cam.render_film(
+                    Arc::new(HittableList::new()),
+                    None,
+                    1,
+                    &mut film,
+                    8,
+                    |_| {},
+                    &CancelToken::new(),
+                )
+            });
+            // Including the edges, which only get samples from one side
+            for j in 0..film.height() {
+                for i in 0..film.width() {
+                    let pixel = film.pixel(i, j);
+                    assert!(
+                        (pixel - color).length() < 1e-9,
+                        "{:?} at {},{}",
+                        pixel,
+                        i,
+                        j
+                    );
+                }
+            }
+        }
+    }
+}
@@ -4,6 +4,7 @@ use crate::lambertian::Lambertian;
 use crate::material::Material;
 use crate::ray::Ray3;
 use crate::vector_3::Vec3;
+use std::fmt::Debug;
 use std::sync::Arc;
 
 pub struct HitRecord {
@@ -14,9 +15,13 @@ pub struct HitRecord {
     pub u: f64,
     pub v: f64,
     pub front_face: bool,
+    // Address of the object that was hit, the outermost instance or transform when the
+    // geometry is placed by one. Lists are not objects, so each quad of a bare
+    // `quad_box` is its own object.
+    pub object: usize,
 }
 
-pub trait Hittable {
+pub trait Hittable: Debug {
     fn hit(&self, r: &Ray3, time: Interval, hit_record: &mut HitRecord) -> bool;
 
     fn bounding_box(&self) -> AABB;
@@ -30,6 +35,12 @@ pub trait Hittable {
     }
 }
 
+impl Default for HitRecord {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl HitRecord {
     pub fn new() -> HitRecord {
         HitRecord {
@@ -40,6 +51,7 @@ impl HitRecord {
             u: 0.0,
             v: 0.0,
             front_face: false,
+            object: 0,
         }
     }
 
@@ -60,5 +72,6 @@ impl HitRecord {
         self.u = rhs.u;
         self.v = rhs.v;
         self.front_face = rhs.front_face;
+        self.object = rhs.object;
     }
 }
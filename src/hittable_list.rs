@@ -7,6 +7,7 @@ use crate::rtweekend::random_u32_range;
 use crate::vector_3::Vec3;
 use std::sync::Arc;
 
+#[derive(Debug)]
 pub struct HittableList {
     pub objects: Vec<Arc<dyn Hittable + Sync + Send>>,
     bbox: AABB,
@@ -56,6 +57,12 @@ impl Hittable for HittableList {
     }
 }
 
+impl Default for HittableList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl HittableList {
     pub fn new() -> HittableList {
         HittableList {
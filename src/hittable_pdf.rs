@@ -1,5 +1,6 @@
 use crate::hittable::Hittable;
 use crate::pdf::PDF;
+use crate::ray_stats::{self, Counter};
 use crate::vector_3::Vec3;
 use std::sync::Arc;
 
@@ -14,6 +15,7 @@ impl PDF for HittablePDF {
     }
 
     fn generate(&self) -> Vec3 {
+        ray_stats::count(Counter::ShadowRay);
         self.objects.random(&self.origin)
     }
 }
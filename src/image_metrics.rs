@@ -0,0 +1,675 @@
+use crate::vector_3::Vec3;
+use image::{ImageError, Rgb, RgbImage};
+use std::f64::consts::PI;
+use std::fmt;
+use std::path::Path;
+
+// Offset that keeps the relative error finite where the reference is black
+const RELATIVE_EPSILON: f64 = 0.01;
+
+// Viewing conditions FLIP assumes: a 0.7 m wide 4K monitor seen from 0.7 m
+const PIXELS_PER_DEGREE: f64 = 67.0;
+const FLIP_QC: f64 = 0.7;
+const FLIP_QF: f64 = 0.5;
+const FLIP_PC: f64 = 0.4;
+const FLIP_PT: f64 = 0.95;
+// Width in degrees of the edges and points FLIP looks for
+const FLIP_FEATURE_WIDTH: f64 = 0.082;
+
+// Linear sRGB to CIE XYZ, and the D65 white it maps (1, 1, 1) to
+const RGB_TO_XYZ: [[f64; 3]; 3] = [
+    [0.41238656, 0.35759149, 0.18045049],
+    [0.21263682, 0.71518298, 0.0721802],
+    [0.01933062, 0.11919716, 0.95037259],
+];
+const XYZ_TO_RGB: [[f64; 3]; 3] = [
+    [3.24096994, -1.53738318, -0.49861076],
+    [-0.96924364, 1.8759675, 0.04155506],
+    [0.05563008, -0.20397696, 1.05697151],
+];
+const WHITE: [f64; 3] = [0.95042854, 1.0, 1.08890037];
+
+#[derive(Debug)]
+pub enum MetricsError {
+    Image(ImageError),
+    SizeMismatch {
+        expected: (u32, u32),
+        found: (u32, u32),
+    },
+}
+
+impl fmt::Display for MetricsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetricsError::Image(err) => write!(f, "cannot read image: {}", err),
+            MetricsError::SizeMismatch { expected, found } => write!(
+                f,
+                "images differ in size: {}x{} and {}x{}",
+                expected.0, expected.1, found.0, found.1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MetricsError {}
+
+impl From<ImageError> for MetricsError {
+    fn from(err: ImageError) -> MetricsError {
+        MetricsError::Image(err)
+    }
+}
+
+// An image as displayed, with every channel between 0 and 1
+#[derive(Clone)]
+pub struct MetricImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<Vec3>,
+}
+
+impl MetricImage {
+    pub fn load(path: &Path) -> Result<MetricImage, MetricsError> {
+        let image = image::open(path)?.to_rgb32f();
+        Ok(MetricImage {
+            width: image.width(),
+            height: image.height(),
+            pixels: image
+                .pixels()
+                .map(|pixel| Vec3::new(pixel[0] as f64, pixel[1] as f64, pixel[2] as f64))
+                .collect(),
+        })
+    }
+
+    // From a framebuffer of 0RGB pixels, as the camera renders
+    pub fn from_buffer(buffer: &[u32], width: u32, height: u32) -> MetricImage {
+        let channel = |value: u32, shift: u32| ((value >> shift) & 0xFF) as f64 / 255.0;
+        MetricImage {
+            width,
+            height,
+            pixels: buffer
+                .iter()
+                .map(|&value| Vec3::new(channel(value, 16), channel(value, 8), channel(value, 0)))
+                .collect(),
+        }
+    }
+
+    fn channel(&self, axis: u32) -> Vec<f64> {
+        self.pixels.iter().map(|pixel| pixel.at(axis)).collect()
+    }
+}
+
+// How far a test image is from a reference. SSIM is 1 for identical images, the rest 0.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Metrics {
+    pub mse: f64,
+    pub rel_mse: f64,
+    pub psnr: f64,
+    pub ssim: f64,
+    pub flip: f64,
+}
+
+impl Metrics {
+    pub fn compare(reference: &MetricImage, test: &MetricImage) -> Result<Metrics, MetricsError> {
+        check_sizes(reference, test)?;
+        let mse = mse(reference, test);
+        Ok(Metrics {
+            mse,
+            rel_mse: rel_mse(reference, test),
+            psnr: psnr(mse),
+            ssim: mean(&ssim_map(reference, test)),
+            flip: mean(&flip_map(reference, test)),
+        })
+    }
+}
+
+pub fn check_sizes(reference: &MetricImage, test: &MetricImage) -> Result<(), MetricsError> {
+    if reference.width != test.width || reference.height != test.height {
+        return Err(MetricsError::SizeMismatch {
+            expected: (reference.width, reference.height),
+            found: (test.width, test.height),
+        });
+    }
+    Ok(())
+}
+
+pub fn mse(reference: &MetricImage, test: &MetricImage) -> f64 {
+    mean(&squared_error_map(reference, test))
+}
+
+// Squared error divided by the squared reference, so dark and bright regions count alike
+pub fn rel_mse(reference: &MetricImage, test: &MetricImage) -> f64 {
+    let errors: Vec<f64> = reference
+        .pixels
+        .iter()
+        .zip(&test.pixels)
+        .map(|(&expected, &found)| {
+            let difference = expected - found;
+            let relative = difference * difference
+                / (expected * expected + Vec3::new(1.0, 1.0, 1.0) * RELATIVE_EPSILON);
+            (relative.x + relative.y + relative.z) / 3.0
+        })
+        .collect();
+    mean(&errors)
+}
+
+// Peak signal to noise ratio in decibels for a peak of 1
+pub fn psnr(mse: f64) -> f64 {
+    if mse <= 0.0 {
+        f64::INFINITY
+    } else {
+        -10.0 * mse.log10()
+    }
+}
+
+// Squared error of each pixel, averaged over the channels
+pub fn squared_error_map(reference: &MetricImage, test: &MetricImage) -> Vec<f64> {
+    reference
+        .pixels
+        .iter()
+        .zip(&test.pixels)
+        .map(|(expected, found)| (expected - found).length_squared() / 3.0)
+        .collect()
+}
+
+// Structural similarity of each pixel's neighbourhood, compared through a Gaussian window
+// of 1.5 pixels and averaged over the channels
+pub fn ssim_map(reference: &MetricImage, test: &MetricImage) -> Vec<f64> {
+    let (c1, c2) = (0.01 * 0.01, 0.03 * 0.03);
+    let (width, height) = (reference.width as usize, reference.height as usize);
+    let window = gaussian_kernel(1.5, 5);
+    let blur = |values: &[f64]| convolve_separable(values, width, height, &window, &window);
+
+    let mut map = vec![0.0; width * height];
+    for axis in 0..3 {
+        let x = reference.channel(axis);
+        let y = test.channel(axis);
+        let product =
+            |a: &[f64], b: &[f64]| -> Vec<f64> { a.iter().zip(b).map(|(a, b)| a * b).collect() };
+        let (mean_x, mean_y) = (blur(&x), blur(&y));
+        let (xx, yy, xy) = (
+            blur(&product(&x, &x)),
+            blur(&product(&y, &y)),
+            blur(&product(&x, &y)),
+        );
+        for index in 0..map.len() {
+            let (mx, my) = (mean_x[index], mean_y[index]);
+            let var_x = xx[index] - mx * mx;
+            let var_y = yy[index] - my * my;
+            let covariance = xy[index] - mx * my;
+            map[index] += ((2.0 * mx * my + c1) * (2.0 * covariance + c2))
+                / ((mx * mx + my * my + c1) * (var_x + var_y + c2))
+                / 3.0;
+        }
+    }
+    map
+}
+
+// LDR-FLIP: the perceived difference of each pixel between 0 and 1, from the colour
+// difference after blurring by the eye's contrast sensitivity, raised by the difference in
+// edges and points
+pub fn flip_map(reference: &MetricImage, test: &MetricImage) -> Vec<f64> {
+    let (width, height) = (reference.width as usize, reference.height as usize);
+
+    let color_reference = flip_color(reference);
+    let color_test = flip_color(test);
+    let hunt = |rgb: Vec3| hunt_adjust(lab_from_xyz(&transform(&RGB_TO_XYZ, &rgb)));
+    let max_distance = hyab(
+        &hunt(Vec3::new(0.0, 1.0, 0.0)),
+        &hunt(Vec3::new(0.0, 0.0, 1.0)),
+    )
+    .powf(FLIP_QC);
+
+    let features_reference = flip_features(reference, width, height);
+    let features_test = flip_features(test, width, height);
+
+    (0..width * height)
+        .map(|index| {
+            let distance = hyab(&color_reference[index], &color_test[index]).powf(FLIP_QC);
+            let color = if distance < FLIP_PC * max_distance {
+                FLIP_PT / (FLIP_PC * max_distance) * distance
+            } else {
+                FLIP_PT
+                    + (distance - FLIP_PC * max_distance) / (max_distance - FLIP_PC * max_distance)
+                        * (1.0 - FLIP_PT)
+            };
+
+            let (edge_reference, point_reference) = features_reference[index];
+            let (edge_test, point_test) = features_test[index];
+            let feature = ((edge_reference - edge_test)
+                .abs()
+                .max((point_reference - point_test).abs())
+                / 2f64.sqrt())
+            .powf(FLIP_QF);
+
+            color.powf(1.0 - feature)
+        })
+        .collect()
+}
+
+// Hunt adjusted L*a*b* of every pixel after the contrast sensitivity filters
+fn flip_color(image: &MetricImage) -> Vec<Vec3> {
+    let (width, height) = (image.width as usize, image.height as usize);
+    let opponent: Vec<Vec3> = image
+        .pixels
+        .iter()
+        .map(|pixel| ycxcz_from_xyz(&transform(&RGB_TO_XYZ, &srgb_to_linear(pixel))))
+        .collect();
+
+    // Achromatic, red-green and blue-yellow sensitivity, each a sum of two Gaussians
+    let parameters = [
+        [(1.0, 0.0047), (0.0, 1e-5)],
+        [(1.0, 0.0053), (0.0, 1e-5)],
+        [(34.1, 0.04), (13.5, 0.025)],
+    ];
+    let radius = (3.0 * (0.04 / (2.0 * PI * PI)).sqrt() * PIXELS_PER_DEGREE).ceil() as i32;
+    let mut filtered = vec![Vec3::empty(); width * height];
+    for (axis, terms) in parameters.iter().enumerate() {
+        let channel: Vec<f64> = opponent.iter().map(|pixel| pixel.at(axis as u32)).collect();
+        let mut sum = vec![0.0; width * height];
+        let mut kernel_sum = 0.0;
+        for &(amplitude, spread) in terms {
+            if amplitude == 0.0 {
+                continue;
+            }
+            let kernel: Vec<f64> = (-radius..=radius)
+                .map(|x| {
+                    let x = x as f64 / PIXELS_PER_DEGREE;
+                    (-PI * PI * x * x / spread).exp()
+                })
+                .collect();
+            let scale = amplitude * (PI / spread).sqrt();
+            let total: f64 = kernel.iter().sum();
+            kernel_sum += scale * total * total;
+            let blurred = convolve_separable(&channel, width, height, &kernel, &kernel);
+            for (sum, value) in sum.iter_mut().zip(blurred) {
+                *sum += scale * value;
+            }
+        }
+        for (pixel, value) in filtered.iter_mut().zip(sum) {
+            pixel.set(axis as u32, value / kernel_sum);
+        }
+    }
+
+    filtered
+        .iter()
+        .map(|pixel| {
+            let rgb = transform(&XYZ_TO_RGB, &xyz_from_ycxcz(pixel));
+            let rgb = Vec3::new(
+                rgb.x.clamp(0.0, 1.0),
+                rgb.y.clamp(0.0, 1.0),
+                rgb.z.clamp(0.0, 1.0),
+            );
+            hunt_adjust(lab_from_xyz(&transform(&RGB_TO_XYZ, &rgb)))
+        })
+        .collect()
+}
+
+// Strength of the edge and of the point feature at every pixel of the image's luminance
+fn flip_features(image: &MetricImage, width: usize, height: usize) -> Vec<(f64, f64)> {
+    let luminance: Vec<f64> = image
+        .pixels
+        .iter()
+        .map(|pixel| transform(&RGB_TO_XYZ, &srgb_to_linear(pixel)).y / WHITE[1])
+        .collect();
+
+    let deviation = 0.5 * FLIP_FEATURE_WIDTH * PIXELS_PER_DEGREE;
+    let radius = (3.0 * deviation).ceil() as i32;
+    let offsets: Vec<f64> = (-radius..=radius).map(|x| x as f64).collect();
+    let gaussian: Vec<f64> = offsets
+        .iter()
+        .map(|x| (-x * x / (2.0 * deviation * deviation)).exp())
+        .collect();
+    let first: Vec<f64> = offsets.iter().zip(&gaussian).map(|(x, g)| -x * g).collect();
+    let second: Vec<f64> = offsets
+        .iter()
+        .zip(&gaussian)
+        .map(|(x, g)| (x * x / (deviation * deviation) - 1.0) * g)
+        .collect();
+
+    // The derivative kernels are normalised so their positive and negative weights sum to
+    // one and minus one, and the smoothing across them sums to one
+    let total: f64 = gaussian.iter().sum();
+    let smooth: Vec<f64> = gaussian.iter().map(|g| g / total).collect();
+    let (first, second) = (balance(&first), balance(&second));
+
+    let edge_x = convolve_separable(&luminance, width, height, &first, &smooth);
+    let edge_y = convolve_separable(&luminance, width, height, &smooth, &first);
+    let point_x = convolve_separable(&luminance, width, height, &second, &smooth);
+    let point_y = convolve_separable(&luminance, width, height, &smooth, &second);
+    (0..width * height)
+        .map(|index| {
+            (
+                edge_x[index].hypot(edge_y[index]),
+                point_x[index].hypot(point_y[index]),
+            )
+        })
+        .collect()
+}
+
+fn balance(kernel: &[f64]) -> Vec<f64> {
+    let positive: f64 = kernel.iter().filter(|&&k| k > 0.0).sum();
+    let negative: f64 = -kernel.iter().filter(|&&k| k < 0.0).sum::<f64>();
+    kernel
+        .iter()
+        .map(|&k| if k > 0.0 { k / positive } else { k / negative })
+        .collect()
+}
+
+fn srgb_to_linear(color: &Vec3) -> Vec3 {
+    let decode = |value: f64| {
+        if value <= 0.04045 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    Vec3::new(decode(color.x), decode(color.y), decode(color.z))
+}
+
+fn transform(matrix: &[[f64; 3]; 3], vec: &Vec3) -> Vec3 {
+    let row = |r: &[f64; 3]| r[0] * vec.x + r[1] * vec.y + r[2] * vec.z;
+    Vec3::new(row(&matrix[0]), row(&matrix[1]), row(&matrix[2]))
+}
+
+fn ycxcz_from_xyz(xyz: &Vec3) -> Vec3 {
+    let (x, y, z) = (xyz.x / WHITE[0], xyz.y / WHITE[1], xyz.z / WHITE[2]);
+    Vec3::new(116.0 * y - 16.0, 500.0 * (x - y), 200.0 * (y - z))
+}
+
+fn xyz_from_ycxcz(ycxcz: &Vec3) -> Vec3 {
+    let y = (ycxcz.x + 16.0) / 116.0;
+    Vec3::new(
+        (y + ycxcz.y / 500.0) * WHITE[0],
+        y * WHITE[1],
+        (y - ycxcz.z / 200.0) * WHITE[2],
+    )
+}
+
+fn lab_from_xyz(xyz: &Vec3) -> Vec3 {
+    let delta: f64 = 6.0 / 29.0;
+    let f = |t: f64| {
+        if t > delta * delta * delta {
+            t.cbrt()
+        } else {
+            t / (3.0 * delta * delta) + 4.0 / 29.0
+        }
+    };
+    let (x, y, z) = (
+        f(xyz.x / WHITE[0]),
+        f(xyz.y / WHITE[1]),
+        f(xyz.z / WHITE[2]),
+    );
+    Vec3::new(116.0 * y - 16.0, 500.0 * (x - y), 200.0 * (y - z))
+}
+
+fn hunt_adjust(lab: Vec3) -> Vec3 {
+    Vec3::new(lab.x, 0.01 * lab.x * lab.y, 0.01 * lab.x * lab.z)
+}
+
+// Lightness difference plus the Euclidean distance in a*b*
+fn hyab(a: &Vec3, b: &Vec3) -> f64 {
+    (a.x - b.x).abs() + (a.y - b.y).hypot(a.z - b.z)
+}
+
+fn gaussian_kernel(deviation: f64, radius: i32) -> Vec<f64> {
+    let kernel: Vec<f64> = (-radius..=radius)
+        .map(|x| (-(x * x) as f64 / (2.0 * deviation * deviation)).exp())
+        .collect();
+    let total: f64 = kernel.iter().sum();
+    kernel.iter().map(|k| k / total).collect()
+}
+
+// Convolves along x with one kernel and along y with another, repeating the edge pixels
+fn convolve_separable(
+    values: &[f64],
+    width: usize,
+    height: usize,
+    kernel_x: &[f64],
+    kernel_y: &[f64],
+) -> Vec<f64> {
+    let pass = |values: &[f64], kernel: &[f64], horizontal: bool| -> Vec<f64> {
+        let radius = (kernel.len() / 2) as i64;
+        let mut out = vec![0.0; values.len()];
+        for y in 0..height as i64 {
+            for x in 0..width as i64 {
+                out[y as usize * width + x as usize] = kernel
+                    .iter()
+                    .enumerate()
+                    .map(|(k, weight)| {
+                        let offset = k as i64 - radius;
+                        let (sx, sy) = if horizontal {
+                            ((x + offset).clamp(0, width as i64 - 1), y)
+                        } else {
+                            (x, (y + offset).clamp(0, height as i64 - 1))
+                        };
+                        weight * values[sy as usize * width + sx as usize]
+                    })
+                    .sum();
+            }
+        }
+        out
+    };
+    pass(&pass(values, kernel_x, true), kernel_y, false)
+}
+
+pub fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        0.0
+    } else {
+        values.iter().sum::<f64>() / values.len() as f64
+    }
+}
+
+// Colours a map of values between 0 and 1 with the magma colour map, black to yellow
+pub fn false_color(map: &[f64], width: u32, height: u32) -> RgbImage {
+    const MAGMA: [[f64; 3]; 9] = [
+        [0.0, 0.0, 4.0],
+        [28.0, 16.0, 68.0],
+        [79.0, 18.0, 123.0],
+        [129.0, 37.0, 129.0],
+        [181.0, 54.0, 122.0],
+        [229.0, 80.0, 100.0],
+        [251.0, 135.0, 97.0],
+        [254.0, 194.0, 135.0],
+        [252.0, 253.0, 191.0],
+    ];
+    RgbImage::from_fn(width, height, |x, y| {
+        let value = map[(y * width + x) as usize];
+        let position = if value.is_nan() {
+            0.0
+        } else {
+            value.clamp(0.0, 1.0)
+        } * 8.0;
+        let index = (position.floor() as usize).min(7);
+        let t = position - index as f64;
+        let channel =
+            |c: usize| (MAGMA[index][c] * (1.0 - t) + MAGMA[index + 1][c] * t).round() as u8;
+        Rgb([channel(0), channel(1), channel(2)])
+    })
+}
+
+// Colour of a plotted line and its points as samples per pixel and error
+pub type Series = (Rgb<u8>, Vec<(u32, f64)>);
+
+// Log-log plot of error against samples per pixel, one line per series in its colour.
+// Grid lines mark powers of two samples and powers of ten of error, and the grey line is
+// the slope of 1/spp that an unbiased estimator's mean squared error falls at.
+pub fn plot_convergence(series: &[Series], width: u32, height: u32) -> RgbImage {
+    let mut image = RgbImage::from_pixel(width, height, Rgb([255, 255, 255]));
+    let points = series
+        .iter()
+        .flat_map(|(_, points)| points.iter())
+        .filter(|(spp, error)| *spp > 0 && *error > 0.0 && error.is_finite());
+    let (mut spp_min, mut spp_max) = (f64::INFINITY, f64::NEG_INFINITY);
+    let (mut error_min, mut error_max) = (f64::INFINITY, f64::NEG_INFINITY);
+    for &(spp, error) in points {
+        spp_min = spp_min.min((spp as f64).log2().floor());
+        spp_max = spp_max.max((spp as f64).log2().ceil());
+        error_min = error_min.min(error.log10().floor());
+        error_max = error_max.max(error.log10().ceil());
+    }
+    if !spp_min.is_finite() || !error_min.is_finite() {
+        return image;
+    }
+    let spp_max = spp_max.max(spp_min + 1.0);
+    let error_max = error_max.max(error_min + 1.0);
+
+    let margin = 20.0;
+    let to_x = |log_spp: f64| {
+        margin + (log_spp - spp_min) / (spp_max - spp_min) * (width as f64 - 2.0 * margin)
+    };
+    let to_y = |log_error: f64| {
+        height as f64
+            - margin
+            - (log_error - error_min) / (error_max - error_min) * (height as f64 - 2.0 * margin)
+    };
+
+    let grid = Rgb([220, 220, 220]);
+    for power in spp_min as i32..=spp_max as i32 {
+        let x = to_x(power as f64);
+        draw_line(&mut image, (x, to_y(error_min)), (x, to_y(error_max)), grid);
+    }
+    for power in error_min as i32..=error_max as i32 {
+        let y = to_y(power as f64);
+        draw_line(&mut image, (to_x(spp_min), y), (to_x(spp_max), y), grid);
+    }
+    let axis = Rgb([0, 0, 0]);
+    draw_line(
+        &mut image,
+        (to_x(spp_min), to_y(error_min)),
+        (to_x(spp_max), to_y(error_min)),
+        axis,
+    );
+    draw_line(
+        &mut image,
+        (to_x(spp_min), to_y(error_min)),
+        (to_x(spp_min), to_y(error_max)),
+        axis,
+    );
+
+    // 1/spp through the top left corner
+    let slope = std::f64::consts::LOG10_2;
+    draw_line(
+        &mut image,
+        (to_x(spp_min), to_y(error_max)),
+        (to_x(spp_max), to_y(error_max - slope * (spp_max - spp_min))),
+        Rgb([150, 150, 150]),
+    );
+
+    for (color, points) in series {
+        let points: Vec<(f64, f64)> = points
+            .iter()
+            .filter(|(spp, error)| *spp > 0 && *error > 0.0 && error.is_finite())
+            .map(|&(spp, error)| (to_x((spp as f64).log2()), to_y(error.log10())))
+            .collect();
+        for pair in points.windows(2) {
+            draw_line(&mut image, pair[0], pair[1], *color);
+        }
+        for &(x, y) in &points {
+            draw_line(&mut image, (x - 2.0, y), (x + 2.0, y), *color);
+            draw_line(&mut image, (x, y - 2.0), (x, y + 2.0), *color);
+        }
+    }
+    image
+}
+
+fn draw_line(image: &mut RgbImage, from: (f64, f64), to: (f64, f64), color: Rgb<u8>) {
+    let steps = (to.0 - from.0)
+        .abs()
+        .max((to.1 - from.1).abs())
+        .ceil()
+        .max(1.0) as i32;
+    for step in 0..=steps {
+        let t = step as f64 / steps as f64;
+        let x = (from.0 + (to.0 - from.0) * t).round();
+        let y = (from.1 + (to.1 - from.1) * t).round();
+        if x >= 0.0 && y >= 0.0 && (x as u32) < image.width() && (y as u32) < image.height() {
+            image.put_pixel(x as u32, y as u32, color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A gradient with a checker pattern over it, every channel below 0.9
+    fn pattern(width: u32, height: u32) -> MetricImage {
+        MetricImage {
+            width,
+            height,
+            pixels: (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| {
+                    let checker = if (x / 4 + y / 4) % 2 == 0 { 0.3 } else { 0.0 };
+                    let ramp = 0.5 * x as f64 / width as f64;
+                    Vec3::new(
+                        ramp + checker,
+                        0.1 + checker,
+                        0.5 * y as f64 / height as f64,
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn identical_images_have_no_error() {
+        let image = pattern(40, 30);
+        let metrics = Metrics::compare(&image, &image.clone()).unwrap();
+        assert_eq!(metrics.mse, 0.0);
+        assert_eq!(metrics.rel_mse, 0.0);
+        assert_eq!(metrics.psnr, f64::INFINITY);
+        assert!((metrics.ssim - 1.0).abs() < 1e-12, "SSIM {}", metrics.ssim);
+        assert!(metrics.flip.abs() < 1e-12, "FLIP {}", metrics.flip);
+    }
+
+    #[test]
+    fn a_constant_offset_gives_its_square() {
+        let reference = pattern(40, 30);
+        let mut test = reference.clone();
+        test.pixels
+            .iter_mut()
+            .for_each(|pixel| *pixel += Vec3::new(0.1, 0.1, 0.1));
+        let metrics = Metrics::compare(&reference, &test).unwrap();
+        assert!((metrics.mse - 0.01).abs() < 1e-12);
+        assert!((metrics.psnr - 20.0).abs() < 1e-9);
+
+        let expected_rel_mse = reference
+            .pixels
+            .iter()
+            .map(|pixel| {
+                (0..3)
+                    .map(|axis| 0.01 / (pixel.at(axis) * pixel.at(axis) + RELATIVE_EPSILON))
+                    .sum::<f64>()
+                    / 3.0
+            })
+            .sum::<f64>()
+            / reference.pixels.len() as f64;
+        assert!((metrics.rel_mse - expected_rel_mse).abs() < 1e-12);
+
+        // The structure is unchanged, so SSIM only loses a little to the brightness
+        assert!(
+            metrics.ssim < 1.0 && metrics.ssim > 0.9,
+            "SSIM {}",
+            metrics.ssim
+        );
+        assert!(
+            metrics.flip > 0.0 && metrics.flip < 1.0,
+            "FLIP {}",
+            metrics.flip
+        );
+    }
+
+    #[test]
+    fn images_of_different_sizes_are_refused() {
+        let result = Metrics::compare(&pattern(40, 30), &pattern(30, 40));
+        match result {
+            Err(MetricsError::SizeMismatch { expected, found }) => {
+                assert_eq!(expected, (40, 30));
+                assert_eq!(found, (30, 40));
+            }
+            other => panic!("expected a size mismatch, got {:?}", other),
+        }
+    }
+}
@@ -4,6 +4,7 @@ use crate::vector_3::Vec3;
 use image::io;
 use image::RgbImage;
 
+#[derive(Debug)]
 pub struct ImageTexture {
     image: RgbImage,
 }
@@ -0,0 +1,119 @@
+use crate::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::matrix_4::Matrix4;
+use crate::ray::Ray3;
+use crate::transform::Transform;
+use crate::vector_3::Vec3;
+use std::sync::Arc;
+
+// One placement of shared geometry, usually a BVH built once and handed to every instance.
+// The geometry is only referenced, so each instance costs a transform rather than a copy.
+// A BVH over the instances makes the top level of a two level acceleration structure.
+#[derive(Debug)]
+pub struct Instance {
+    transform: Transform,
+    // Replaces the geometry's own materials when set
+    material: Option<Arc<dyn Material + Sync + Send>>,
+}
+
+impl Hittable for Instance {
+    fn hit(&self, ray_in: &Ray3, time: Interval, hit_record: &mut HitRecord) -> bool {
+        if self.transform.hit(ray_in, time, hit_record) {
+            if let Some(material) = &self.material {
+                hit_record.mat = Arc::clone(material);
+            }
+            hit_record.object = self as *const Instance as usize;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.transform.bounding_box()
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        self.transform.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &Vec3) -> Vec3 {
+        self.transform.random(origin)
+    }
+}
+
+impl Instance {
+    pub fn new(
+        geometry: Arc<dyn Hittable + Sync + Send>,
+        object_to_world: Matrix4,
+        material: Option<Arc<dyn Material + Sync + Send>>,
+    ) -> Option<Instance> {
+        Some(Instance {
+            transform: Transform::new(geometry, object_to_world)?,
+            material,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable_list::HittableList;
+    use crate::lambertian::Lambertian;
+    use crate::metal::Metal;
+    use crate::sphere::Sphere;
+
+    fn material_of(hit_record: &HitRecord) -> *const () {
+        Arc::as_ptr(&hit_record.mat) as *const ()
+    }
+
+    #[test]
+    fn instances_share_geometry_and_keep_their_own_material() {
+        let own: Arc<dyn Material + Sync + Send> =
+            Arc::new(Lambertian::from_color(Vec3::new(0.5, 0.5, 0.5)));
+        let mut prototype = HittableList::new();
+        prototype.add(Arc::new(Sphere::new(Vec3::empty(), 1.0, Arc::clone(&own))));
+        let geometry: Arc<dyn Hittable + Sync + Send> = Arc::new(prototype);
+
+        let override_material: Arc<dyn Material + Sync + Send> =
+            Arc::new(Metal::new(Vec3::new(0.9, 0.9, 0.9), 0.0));
+        let left = Instance::new(
+            Arc::clone(&geometry),
+            Matrix4::translation(Vec3::new(-5.0, 0.0, 0.0)),
+            Some(Arc::clone(&override_material)),
+        )
+        .unwrap();
+        let right = Instance::new(
+            Arc::clone(&geometry),
+            Matrix4::translation(Vec3::new(5.0, 0.0, 0.0))
+                * Matrix4::scale(Vec3::new(2.0, 2.0, 2.0)),
+            None,
+        )
+        .unwrap();
+        // Both hold the one prototype rather than a copy of it
+        assert_eq!(Arc::strong_count(&geometry), 3);
+
+        let interval = || Interval::new(0.001, f64::INFINITY);
+        let down = |x: f64| Ray3::new(Vec3::new(x, 10.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let (mut left_hit, mut right_hit) = (HitRecord::new(), HitRecord::new());
+        assert!(left.hit(&down(-5.0), interval(), &mut left_hit));
+        assert!(right.hit(&down(5.0), interval(), &mut right_hit));
+        assert!(!left.hit(&down(5.0), interval(), &mut HitRecord::new()));
+        assert!((left_hit.point - Vec3::new(-5.0, 1.0, 0.0)).length() < 1e-9);
+        assert!((right_hit.point - Vec3::new(5.0, 2.0, 0.0)).length() < 1e-9);
+
+        // The override only applies to the instance it was given to
+        assert_eq!(
+            material_of(&left_hit),
+            Arc::as_ptr(&override_material) as *const ()
+        );
+        assert_eq!(material_of(&right_hit), Arc::as_ptr(&own) as *const ());
+        let mut prototype_hit = HitRecord::new();
+        assert!(geometry.hit(&down(0.0), interval(), &mut prototype_hit));
+        assert_eq!(material_of(&prototype_hit), Arc::as_ptr(&own) as *const ());
+
+        assert_ne!(left_hit.object, right_hit.object);
+    }
+}
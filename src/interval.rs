@@ -1,62 +1,70 @@
 use std::ops;
 
-pub struct Interval {
-    min: f64,
-    max: f64,
+use crate::real::Real;
+
+// Generic over the precision like `Vector3`, with `Interval` the f64 one used for
+// rendering and `Intervalf` its f32 counterpart
+#[derive(Debug)]
+pub struct Span<T> {
+    min: T,
+    max: T,
 }
 
-impl Interval {
-    pub fn empty() -> Interval {
-        Interval {
-            min: f64::INFINITY,
-            max: -f64::INFINITY,
+pub type Interval = Span<f64>;
+pub type Intervalf = Span<f32>;
+
+impl<T: Real> Span<T> {
+    pub fn empty() -> Span<T> {
+        Span {
+            min: T::INFINITY,
+            max: -T::INFINITY,
         }
     }
 
-    pub fn univeral() -> Interval {
-        Interval {
-            min: -f64::INFINITY,
-            max: f64::INFINITY,
+    pub fn univeral() -> Span<T> {
+        Span {
+            min: -T::INFINITY,
+            max: T::INFINITY,
         }
     }
 
-    pub fn new(min: f64, max: f64) -> Interval {
-        Interval { min, max }
+    pub fn new(min: T, max: T) -> Span<T> {
+        Span { min, max }
     }
 
-    pub fn from_intervals(inter_0: Interval, inter_1: Interval) -> Interval {
-        Interval::new(inter_0.min.min(inter_1.min), inter_0.max.max(inter_1.max))
+    pub fn from_intervals(inter_0: Span<T>, inter_1: Span<T>) -> Span<T> {
+        Span::new(inter_0.min.min(inter_1.min), inter_0.max.max(inter_1.max))
     }
 
-    pub fn copy(&self) -> Interval {
-        Interval::new(self.min(), self.max())
+    pub fn copy(&self) -> Span<T> {
+        Span::new(self.min(), self.max())
     }
 
-    pub fn min(&self) -> f64 {
+    pub fn min(&self) -> T {
         self.min
     }
 
-    pub fn max(&self) -> f64 {
+    pub fn max(&self) -> T {
         self.max
     }
 
-    pub fn set_min(&mut self, val: f64) {
+    pub fn set_min(&mut self, val: T) {
         self.min = val;
     }
 
-    pub fn set_max(&mut self, val: f64) {
+    pub fn set_max(&mut self, val: T) {
         self.max = val;
     }
 
-    pub fn contains(&self, x: f64) -> bool {
+    pub fn contains(&self, x: T) -> bool {
         self.min <= x && x <= self.max
     }
 
-    pub fn surrounds(&self, x: f64) -> bool {
+    pub fn surrounds(&self, x: T) -> bool {
         self.min < x && x < self.max
     }
 
-    pub fn clamp(&self, x: f64) -> f64 {
+    pub fn clamp(&self, x: T) -> T {
         if x < self.min {
             self.min
         } else if x > self.max {
@@ -66,57 +74,64 @@ impl Interval {
         }
     }
 
-    pub fn size(&self) -> f64 {
+    pub fn size(&self) -> T {
         self.max - self.min
     }
 
-    pub fn expand(&self, delta: f64) -> Interval {
-        let padding = delta * 0.5;
-        Interval::new(self.min - padding, self.max + padding)
+    pub fn expand(&self, delta: T) -> Span<T> {
+        let padding = delta * T::from_f64(0.5);
+        Span::new(self.min - padding, self.max + padding)
     }
 }
 
 // Add
-impl ops::Add<f64> for Interval {
-    type Output = Interval;
+impl<T: Real> ops::Add<T> for Span<T> {
+    type Output = Span<T>;
 
-    fn add(self, rhs: f64) -> Interval {
-        Interval {
+    fn add(self, rhs: T) -> Span<T> {
+        Span {
             min: self.min() + rhs,
             max: self.max() + rhs,
         }
     }
 }
 
-impl<'a> ops::Add<f64> for &'a Interval {
-    type Output = Interval;
+impl<T: Real> ops::Add<T> for &Span<T> {
+    type Output = Span<T>;
 
-    fn add(self, rhs: f64) -> Interval {
-        Interval {
+    fn add(self, rhs: T) -> Span<T> {
+        Span {
             min: self.min() + rhs,
             max: self.max() + rhs,
         }
     }
 }
 
-impl ops::Add<Interval> for f64 {
-    type Output = Interval;
-
-    fn add(self, rhs: Interval) -> Interval {
-        Interval {
-            min: self + rhs.min(),
-            max: self + rhs.max(),
+macro_rules! scalar_add {
+    ($t:ident) => {
+        impl ops::Add<Span<$t>> for $t {
+            type Output = Span<$t>;
+
+            fn add(self, rhs: Span<$t>) -> Span<$t> {
+                Span {
+                    min: self + rhs.min(),
+                    max: self + rhs.max(),
+                }
+            }
         }
-    }
-}
 
-impl<'a> ops::Add<&'a Interval> for f64 {
-    type Output = Interval;
+        impl<'a> ops::Add<&'a Span<$t>> for $t {
+            type Output = Span<$t>;
 
-    fn add(self, rhs: &'a Interval) -> Interval {
-        Interval {
-            min: self + rhs.min(),
-            max: self + rhs.max(),
+            fn add(self, rhs: &'a Span<$t>) -> Span<$t> {
+                Span {
+                    min: self + rhs.min(),
+                    max: self + rhs.max(),
+                }
+            }
         }
-    }
+    };
 }
+
+scalar_add!(f32);
+scalar_add!(f64);
@@ -8,6 +8,7 @@ use crate::vector_3::Vec3;
 
 use std::f64::consts::FRAC_1_PI;
 use std::sync::Arc;
+#[derive(Debug)]
 pub struct Isotropic {
     albedo: Arc<dyn Texture + Sync + Send>,
 }
@@ -27,6 +28,10 @@ impl Material for Isotropic {
     fn scattering_pdf(&self, _ray_in: &Ray3, _hit_record: &HitRecord, _scattered: &Ray3) -> f64 {
         0.25 * FRAC_1_PI
     }
+
+    fn albedo(&self, hit_rec: &HitRecord) -> Vec3 {
+        self.albedo.value(hit_rec.u, hit_rec.v, hit_rec.point)
+    }
 }
 
 impl Isotropic {
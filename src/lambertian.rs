@@ -8,6 +8,7 @@ use crate::vector_3::Vec3;
 use std::f64::consts::FRAC_1_PI;
 use std::sync::Arc;
 
+#[derive(Debug)]
 pub struct Lambertian {
     albedo: Arc<dyn Texture + Sync + Send>,
 }
@@ -32,6 +33,10 @@ impl Material for Lambertian {
             cosine_theta * FRAC_1_PI
         }
     }
+
+    fn albedo(&self, hit_rec: &HitRecord) -> Vec3 {
+        self.albedo.value(hit_rec.u, hit_rec.v, hit_rec.point)
+    }
 }
 
 impl Lambertian {
@@ -0,0 +1,622 @@
+use crate::ray::Ray3;
+use crate::vector_3::Vec3;
+use std::fmt;
+use std::path::Path;
+
+// Following the realistic camera of Physically Based Rendering. Lens space has the film at
+// z = 0 and the elements in front of it towards -z, the same way the camera looks.
+const PUPIL_BINS: usize = 64;
+const PUPIL_GRID: usize = 64;
+
+#[derive(Debug)]
+pub enum LensError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+    CannotFocus(f64),
+}
+
+impl fmt::Display for LensError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LensError::Io(err) => write!(f, "cannot read lens file: {}", err),
+            LensError::Parse { line, message } => {
+                write!(f, "invalid lens file, line {}: {}", line, message)
+            }
+            LensError::CannotFocus(distance) => {
+                write!(f, "the lens cannot focus at {} mm", distance)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LensError {}
+
+impl From<std::io::Error> for LensError {
+    fn from(err: std::io::Error) -> LensError {
+        LensError::Io(err)
+    }
+}
+
+// One spherical surface, or the aperture stop when the radius is 0. The thickness is the
+// distance to the next surface towards the film and the index of refraction is that of
+// the material behind the surface. All lengths are in millimetres.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LensElement {
+    pub curvature_radius: f64,
+    pub thickness: f64,
+    pub ior: f64,
+    pub aperture_radius: f64,
+}
+
+impl LensElement {
+    fn is_stop(&self) -> bool {
+        self.curvature_radius == 0.0
+    }
+
+    // Air has an index of 0 in most prescriptions
+    fn medium_ior(&self) -> f64 {
+        if self.ior == 0.0 {
+            1.0
+        } else {
+            self.ior
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bounds {
+    min_x: f64,
+    min_y: f64,
+    max_x: f64,
+    max_y: f64,
+}
+
+impl Bounds {
+    fn empty() -> Bounds {
+        Bounds {
+            min_x: f64::INFINITY,
+            min_y: f64::INFINITY,
+            max_x: f64::NEG_INFINITY,
+            max_y: f64::NEG_INFINITY,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.min_x > self.max_x || self.min_y > self.max_y
+    }
+
+    fn area(&self) -> f64 {
+        if self.is_empty() {
+            0.0
+        } else {
+            (self.max_x - self.min_x) * (self.max_y - self.min_y)
+        }
+    }
+
+    fn contains(&self, x: f64, y: f64) -> bool {
+        x >= self.min_x && x <= self.max_x && y >= self.min_y && y <= self.max_y
+    }
+
+    fn include(&mut self, x: f64, y: f64) {
+        self.min_x = self.min_x.min(x);
+        self.min_y = self.min_y.min(y);
+        self.max_x = self.max_x.max(x);
+        self.max_y = self.max_y.max(y);
+    }
+
+    fn expand(&mut self, delta: f64) {
+        if !self.is_empty() {
+            self.min_x -= delta;
+            self.min_y -= delta;
+            self.max_x += delta;
+            self.max_y += delta;
+        }
+    }
+
+    fn lerp(&self, s: f64, t: f64) -> (f64, f64) {
+        (
+            self.min_x + s * (self.max_x - self.min_x),
+            self.min_y + t * (self.max_y - self.min_y),
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LensSystem {
+    pub film_diagonal: f64,
+    pub mm_per_unit: f64,
+    elements: Vec<LensElement>,
+    focused_at: Option<(f64, f64)>,
+    film_half_width: f64,
+    film_half_height: f64,
+    // Bounds on the rear element of the rays that make it through the lens, for film
+    // points on the +x axis at increasing distances from the centre
+    exit_pupil: Vec<Bounds>,
+    normalization: f64,
+}
+
+impl LensSystem {
+    pub fn new(elements: Vec<LensElement>) -> LensSystem {
+        LensSystem {
+            film_diagonal: 35.0,
+            mm_per_unit: 1000.0,
+            elements,
+            focused_at: None,
+            film_half_width: 0.0,
+            film_half_height: 0.0,
+            exit_pupil: Vec::new(),
+            normalization: 1.0,
+        }
+    }
+
+    // One element per line from the front of the lens to the back: curvature radius,
+    // thickness, index of refraction and aperture diameter. Lines starting with # are
+    // comments.
+    pub fn parse(text: &str) -> Result<LensSystem, LensError> {
+        let mut elements = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let values = line
+                .split_whitespace()
+                .map(|value| value.parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()
+                .map_err(|err| LensError::Parse {
+                    line: index + 1,
+                    message: err.to_string(),
+                })?;
+            if values.len() != 4 {
+                return Err(LensError::Parse {
+                    line: index + 1,
+                    message: format!("expected 4 values, found {}", values.len()),
+                });
+            }
+            elements.push(LensElement {
+                curvature_radius: values[0],
+                thickness: values[1],
+                ior: values[2],
+                aperture_radius: values[3] / 2.0,
+            });
+        }
+
+        if elements.is_empty() {
+            return Err(LensError::Parse {
+                line: 0,
+                message: "no lens elements".to_owned(),
+            });
+        }
+        Ok(LensSystem::new(elements))
+    }
+
+    pub fn load(path: &Path) -> Result<LensSystem, LensError> {
+        LensSystem::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn elements(&self) -> &[LensElement] {
+        &self.elements
+    }
+
+    fn rear_z(&self) -> f64 {
+        -self.elements[self.elements.len() - 1].thickness
+    }
+
+    fn front_z(&self) -> f64 {
+        -self
+            .elements
+            .iter()
+            .map(|element| element.thickness)
+            .sum::<f64>()
+    }
+
+    // Moves the film so the plane `distance` mm in front of it is in focus, and sizes the
+    // film for the aspect ratio. A lens that fails to focus blocks every ray.
+    pub fn focus(&mut self, distance: f64, aspect: f64) -> Result<(), LensError> {
+        if self.focused_at == Some((distance, aspect)) {
+            return Ok(());
+        }
+        self.focused_at = None;
+
+        let film_distance = self
+            .film_distance(distance)
+            .ok_or(LensError::CannotFocus(distance))?;
+        let last = self.elements.len() - 1;
+        self.elements[last].thickness = film_distance;
+
+        self.film_half_height = 0.5 * self.film_diagonal / (1.0 + aspect * aspect).sqrt();
+        self.film_half_width = self.film_half_height * aspect;
+
+        let half_diagonal = 0.5 * self.film_diagonal;
+        self.exit_pupil = (0..PUPIL_BINS)
+            .map(|bin| {
+                let r0 = bin as f64 / PUPIL_BINS as f64 * half_diagonal;
+                let r1 = (bin + 1) as f64 / PUPIL_BINS as f64 * half_diagonal;
+                self.bound_exit_pupil(r0, r1)
+            })
+            .collect();
+        self.normalization = self.center_weight();
+        if self.normalization <= 0.0 {
+            return Err(LensError::CannotFocus(distance));
+        }
+
+        self.focused_at = Some((distance, aspect));
+        Ok(())
+    }
+
+    // Camera space ray in scene units for image point s, t (0 to 1 from the top left)
+    // through a point u, v of the exit pupil, with the weight of its radiance. None when
+    // the lens blocks the ray.
+    pub fn sample_ray(&self, s: f64, t: f64, u: f64, v: f64) -> Option<(Ray3, f64)> {
+        self.focused_at?;
+
+        // The lens flips the image, so the top left of the image is at the bottom right
+        // of the film
+        let film = Vec3::new(
+            -(2.0 * s - 1.0) * self.film_half_width,
+            (2.0 * t - 1.0) * self.film_half_height,
+            0.0,
+        );
+        let radius = (film.x * film.x + film.y * film.y).sqrt();
+        let bin = ((radius / (0.5 * self.film_diagonal)) * PUPIL_BINS as f64) as usize;
+        let bounds = self.exit_pupil[bin.min(PUPIL_BINS - 1)];
+        if bounds.is_empty() {
+            return None;
+        }
+
+        // The bounds are for film points on the +x axis, rotate them to this one
+        let (pupil_x, pupil_y) = bounds.lerp(u, v);
+        let (sin, cos) = if radius > 0.0 {
+            (film.y / radius, film.x / radius)
+        } else {
+            (0.0, 1.0)
+        };
+        let rear = Vec3::new(
+            cos * pupil_x - sin * pupil_y,
+            sin * pupil_x + cos * pupil_y,
+            self.rear_z(),
+        );
+
+        let direction = rear - film;
+        let (origin, out_direction) = self.trace_from_film(film, direction)?;
+        let cos_theta = direction.unit_vector().z;
+        let weight = cos_theta.powi(4) * bounds.area() / self.normalization;
+        Some((
+            Ray3::new(origin / self.mm_per_unit, out_direction, 0.0),
+            weight,
+        ))
+    }
+
+    // Average unnormalized weight at the centre of the film, so an unblocked centre pixel
+    // keeps the brightness of a pinhole camera
+    fn center_weight(&self) -> f64 {
+        let bounds = self.exit_pupil[0];
+        let film = Vec3::empty();
+        let mut total = 0.0;
+        for index in 0..PUPIL_GRID * PUPIL_GRID {
+            let (x, y) = bounds.lerp(
+                ((index % PUPIL_GRID) as f64 + 0.5) / PUPIL_GRID as f64,
+                ((index / PUPIL_GRID) as f64 + 0.5) / PUPIL_GRID as f64,
+            );
+            let direction = Vec3::new(x, y, self.rear_z()) - film;
+            if self.trace_from_film(film, direction).is_some() {
+                total += direction.unit_vector().z.powi(4) * bounds.area();
+            }
+        }
+        total / (PUPIL_GRID * PUPIL_GRID) as f64
+    }
+
+    fn bound_exit_pupil(&self, r0: f64, r1: f64) -> Bounds {
+        let extent = 1.5 * self.elements[self.elements.len() - 1].aperture_radius;
+        let cell = 2.0 * extent / PUPIL_GRID as f64;
+        let sample_count = PUPIL_GRID * PUPIL_GRID;
+
+        let mut bounds = Bounds::empty();
+        for index in 0..sample_count {
+            let film_x = r0 + (r1 - r0) * (index as f64 + 0.5) / sample_count as f64;
+            let x = -extent + cell * ((index % PUPIL_GRID) as f64 + 0.5);
+            let y = -extent + cell * ((index / PUPIL_GRID) as f64 + 0.5);
+            if bounds.contains(x, y) {
+                continue;
+            }
+            let film = Vec3::new(film_x, 0.0, 0.0);
+            let rear = Vec3::new(x, y, self.rear_z());
+            if self.trace_from_film(film, rear - film).is_some() {
+                bounds.include(x, y);
+            }
+        }
+
+        // Rays between the grid points may make it through as well
+        bounds.expand(cell);
+        bounds
+    }
+
+    fn trace_from_film(&self, origin: Vec3, direction: Vec3) -> Option<(Vec3, Vec3)> {
+        let mut origin = origin;
+        let mut direction = direction;
+        let mut element_z = 0.0;
+        for index in (0..self.elements.len()).rev() {
+            let element = self.elements[index];
+            element_z -= element.thickness;
+
+            let (time, normal) = if element.is_stop() {
+                if direction.z >= 0.0 {
+                    return None;
+                }
+                ((element_z - origin.z) / direction.z, None)
+            } else {
+                let center_z = element_z + element.curvature_radius;
+                let (time, normal) =
+                    intersect_spherical(element.curvature_radius, center_z, origin, direction)?;
+                (time, Some(normal))
+            };
+
+            origin += direction * time;
+            if origin.x * origin.x + origin.y * origin.y
+                > element.aperture_radius * element.aperture_radius
+            {
+                return None;
+            }
+
+            if let Some(normal) = normal {
+                let ior_in = element.medium_ior();
+                let ior_out = if index > 0 {
+                    self.elements[index - 1].medium_ior()
+                } else {
+                    1.0
+                };
+                direction = refract(&(-direction).unit_vector(), &normal, ior_in / ior_out)?;
+            }
+        }
+        Some((origin, direction))
+    }
+
+    fn trace_from_scene(&self, origin: Vec3, direction: Vec3) -> Option<(Vec3, Vec3)> {
+        let mut origin = origin;
+        let mut direction = direction;
+        let mut element_z = self.front_z();
+        for (index, element) in self.elements.iter().enumerate() {
+            let (time, normal) = if element.is_stop() {
+                if direction.z <= 0.0 {
+                    return None;
+                }
+                ((element_z - origin.z) / direction.z, None)
+            } else {
+                let center_z = element_z + element.curvature_radius;
+                let (time, normal) =
+                    intersect_spherical(element.curvature_radius, center_z, origin, direction)?;
+                (time, Some(normal))
+            };
+
+            origin += direction * time;
+            if origin.x * origin.x + origin.y * origin.y
+                > element.aperture_radius * element.aperture_radius
+            {
+                return None;
+            }
+
+            if let Some(normal) = normal {
+                let ior_in = if index > 0 {
+                    self.elements[index - 1].medium_ior()
+                } else {
+                    1.0
+                };
+                let ior_out = element.medium_ior();
+                direction = refract(&(-direction).unit_vector(), &normal, ior_in / ior_out)?;
+            }
+            element_z += element.thickness;
+        }
+        Some((origin, direction))
+    }
+
+    // Film distance from the thick lens approximation, found by tracing rays parallel to
+    // the axis through the lens from both sides
+    fn film_distance(&self, distance: f64) -> Option<f64> {
+        let height = 0.001 * self.film_diagonal;
+
+        let scene_origin = Vec3::new(height, 0.0, self.front_z() - 1.0);
+        let (out_origin, out_direction) =
+            self.trace_from_scene(scene_origin, Vec3::new(0.0, 0.0, 1.0))?;
+        let (principal_scene, focal_scene) =
+            cardinal_points(&scene_origin, &out_origin, &out_direction);
+
+        let film_origin = Vec3::new(height, 0.0, self.rear_z() + 1.0);
+        let (out_origin, out_direction) =
+            self.trace_from_film(film_origin, Vec3::new(0.0, 0.0, -1.0))?;
+        let (principal_film, _) = cardinal_points(&film_origin, &out_origin, &out_direction);
+
+        let focal_length = focal_scene - principal_scene;
+        let z = -distance;
+        let c = (principal_film - z - principal_scene)
+            * (principal_film - z - 4.0 * focal_length - principal_scene);
+        if c <= 0.0 {
+            return None;
+        }
+        let delta = 0.5 * (principal_film - z + principal_scene - c.sqrt());
+        let film_distance = -self.rear_z() + delta;
+        if film_distance > 0.0 {
+            Some(film_distance)
+        } else {
+            None
+        }
+    }
+}
+
+// Z of the principal plane and the focal point from a ray parallel to the axis and the
+// ray it leaves the lens as
+fn cardinal_points(in_origin: &Vec3, out_origin: &Vec3, out_direction: &Vec3) -> (f64, f64) {
+    let focal_time = -out_origin.x / out_direction.x;
+    let focal_z = out_origin.z + out_direction.z * focal_time;
+    let principal_time = (in_origin.x - out_origin.x) / out_direction.x;
+    let principal_z = out_origin.z + out_direction.z * principal_time;
+    (principal_z, focal_z)
+}
+
+// The lens surface is the half of the sphere facing the film for concave surfaces seen
+// from the film side, and the other half otherwise
+fn intersect_spherical(
+    radius: f64,
+    center_z: f64,
+    origin: Vec3,
+    direction: Vec3,
+) -> Option<(f64, Vec3)> {
+    let oc = origin - Vec3::new(0.0, 0.0, center_z);
+    let a = direction.dot(&direction);
+    let b = 2.0 * direction.dot(&oc);
+    let c = oc.dot(&oc) - radius * radius;
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let root = discriminant.sqrt();
+    let q = if b < 0.0 {
+        -0.5 * (b - root)
+    } else {
+        -0.5 * (b + root)
+    };
+    let (t0, t1) = (q / a, c / q);
+    let (near, far) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+    let use_near = (direction.z > 0.0) ^ (radius < 0.0);
+    let time = if use_near { near } else { far };
+    if time < 0.0 {
+        return None;
+    }
+
+    let normal = (oc + direction * time).unit_vector();
+    let normal = if normal.dot(&direction) > 0.0 {
+        -normal
+    } else {
+        normal
+    };
+    Some((time, normal))
+}
+
+// Refracts the unit vector `incoming`, pointing away from the surface, with the ratio of
+// indices of refraction `eta`. None on total internal reflection.
+fn refract(incoming: &Vec3, normal: &Vec3, eta: f64) -> Option<Vec3> {
+    let cos_in = normal.dot(incoming);
+    let sin2_in = (1.0 - cos_in * cos_in).max(0.0);
+    let sin2_out = eta * eta * sin2_in;
+    if sin2_out >= 1.0 {
+        return None;
+    }
+    let cos_out = (1.0 - sin2_out).sqrt();
+    Some(eta * -incoming + (eta * cos_in - cos_out) * normal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::{Camera, CameraError};
+
+    // A biconvex singlet of glass with an index of 1.5 followed by the stop
+    const SINGLET: &str = "
+        # radius thickness ior diameter
+        50   5   1.5  20
+
+        -50  1   1    20
+        0    48  0    16
+    ";
+
+    // Thick lens focal length from the lensmaker's equation
+    fn singlet_focal_length() -> f64 {
+        let (r1, r2, d, n) = (50.0, -50.0, 5.0, 1.5);
+        1.0 / ((n - 1.0) * (1.0 / r1 - 1.0 / r2 + (n - 1.0) * d / (n * r1 * r2)))
+    }
+
+    // Z where a ray crosses the optical axis in the x-z plane
+    fn axis_crossing(origin: &Vec3, direction: &Vec3) -> f64 {
+        origin.z - origin.x * direction.z / direction.x
+    }
+
+    #[test]
+    fn parses_prescriptions() {
+        let lens = LensSystem::parse(SINGLET).unwrap();
+        assert_eq!(lens.elements().len(), 3);
+        assert_eq!(
+            lens.elements()[0],
+            LensElement {
+                curvature_radius: 50.0,
+                thickness: 5.0,
+                ior: 1.5,
+                aperture_radius: 10.0,
+            }
+        );
+        assert!(lens.elements()[2].is_stop());
+        assert_eq!(lens.elements()[2].medium_ior(), 1.0);
+
+        let dgauss = LensSystem::parse(include_str!("../lenses/dgauss_50mm.txt")).unwrap();
+        assert_eq!(dgauss.elements().len(), 11);
+        assert_eq!(dgauss.elements().iter().filter(|e| e.is_stop()).count(), 1);
+
+        let line = |text| match LensSystem::parse(text) {
+            Err(LensError::Parse { line, .. }) => line,
+            _ => panic!("{:?} should not parse", text),
+        };
+        assert_eq!(line("50 5 1.5 20\n-50 1 1"), 2);
+        assert_eq!(line("# comment\n\n50 5 glass 20"), 3);
+        assert_eq!(line("# only a comment"), 0);
+    }
+
+    #[test]
+    fn paraxial_rays_meet_at_the_focal_length() {
+        let lens = LensSystem::parse(SINGLET).unwrap();
+        let origin = Vec3::new(0.1, 0.0, lens.front_z() - 1.0);
+        let (out_origin, out_direction) = lens
+            .trace_from_scene(origin, Vec3::new(0.0, 0.0, 1.0))
+            .unwrap();
+        let (principal, focal) = cardinal_points(&origin, &out_origin, &out_direction);
+        assert!((focal - principal - singlet_focal_length()).abs() < 1e-3);
+
+        // The second principal plane of a symmetric biconvex lens lies inside the glass,
+        // the focal length behind it
+        let back_surface = lens.front_z() + 5.0;
+        assert!(principal > lens.front_z() && principal < back_surface);
+        assert!((axis_crossing(&out_origin, &out_direction) - focal).abs() < 1e-9);
+    }
+
+    #[test]
+    fn focusing_images_the_focus_plane_onto_the_film() {
+        for text in [SINGLET, include_str!("../lenses/dgauss_50mm.txt")] {
+            for distance in [500.0, 1000.0, 5000.0] {
+                let mut lens = LensSystem::parse(text).unwrap();
+                lens.focus(distance, 1.5).unwrap();
+
+                // Paraxial rays from a point on the axis at the focus distance converge on
+                // the film at z = 0
+                let point = Vec3::new(0.0, 0.0, -distance);
+                let target = Vec3::new(0.01, 0.0, lens.front_z());
+                let (origin, direction) = lens.trace_from_scene(point, target - point).unwrap();
+                let crossing = axis_crossing(&origin, &direction);
+                assert!(
+                    crossing.abs() < 0.01,
+                    "focused at {} mm, rays meet {} mm from the film",
+                    distance,
+                    crossing
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn lenses_that_cannot_focus_are_reported() {
+        let mut lens = LensSystem::parse(SINGLET).unwrap();
+        // Closer than the focal length there is no real image
+        assert!(matches!(
+            lens.focus(20.0, 1.5),
+            Err(LensError::CannotFocus(_))
+        ));
+        assert!(lens.sample_ray(0.5, 0.5, 0.5, 0.5).is_none());
+
+        let mut cam = Camera::new();
+        cam.image_width = 16;
+        cam.lens_system = Some(lens);
+        cam.focus_dist = 0.02;
+        assert!(matches!(cam.validate(), Err(CameraError::Lens(_))));
+
+        cam.focus_dist = 1.0;
+        assert!(cam.validate().is_ok());
+    }
+}
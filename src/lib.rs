@@ -1,9 +1,75 @@
-use softbuffer;
+pub mod aabb;
+pub mod aabb4;
+pub mod accelerator;
+pub mod affine_transforms;
+pub mod animated_transform;
+pub mod aov;
+pub mod aperture;
+pub mod bvh_node;
+pub mod camera;
+pub mod checker_texture;
+pub mod checkpoint;
+pub mod color;
+pub mod constant_medium;
+pub mod cosine_pdf;
+pub mod crop_window;
+pub mod denoiser;
+pub mod dielectric;
+pub mod diffuse_light;
+pub mod distributed;
+pub mod film;
+pub mod filter;
+pub mod hittable;
+pub mod hittable_list;
+pub mod hittable_pdf;
+pub mod image_metrics;
+pub mod image_texture;
+pub mod instance;
+pub mod interval;
+pub mod isotropic;
+pub mod lambertian;
+pub mod lens_system;
+pub mod material;
+pub mod matrix_4;
+pub mod metal;
+pub mod mixture_pdf;
+pub mod noise_texture;
+pub mod orthonormal_basis;
+pub mod pdf;
+pub mod perlin;
+pub mod projection;
+pub mod qbvh;
+pub mod quad;
+pub mod ray;
+pub mod ray_stats;
+pub mod real;
+pub mod render_progress;
+pub mod rtweekend;
+pub mod scene;
+pub mod scenes;
+pub mod shutter;
+pub mod simd;
+pub mod solid_texture;
+pub mod sphere;
+pub mod sphere_pdf;
+pub mod stereo;
+pub mod texture;
+pub mod tile_scheduler;
+pub mod transform;
+pub mod vector_3;
+
+use crate::camera::Camera;
+use crate::film::Film;
+use crate::hittable::Hittable;
+use crate::render_progress::CancelToken;
+use crate::rtweekend::degree_to_radians;
 use std::num::NonZeroU32;
 use std::rc::Rc;
-use winit::dpi::{PhysicalSize, Size};
-use winit::event::{Event, WindowEvent};
+use std::sync::Arc;
+use winit::dpi::{PhysicalPosition, PhysicalSize, Size};
+use winit::event::{ElementState, Event, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
+use winit::keyboard::{KeyCode, PhysicalKey};
 use winit::window::WindowBuilder;
 
 // Code modified from the example of https://github.com/rust-windowing/softbuffer/blob/master/README.md
@@ -60,3 +126,168 @@ pub fn render_buffer(buffer: Vec<u32>, buffer_width: u32, buffer_height: u32) {
         })
         .unwrap();
 }
+
+// Progressive preview that lets the camera be moved around the scene.
+// Left drag orbits, right drag pans, the wheel zooms, WASD/QE move and P prints the camera.
+pub fn render_interactive(
+    cam: Camera,
+    world: Arc<dyn Hittable + Sync + Send>,
+    lights: Option<Arc<dyn Hittable + Sync + Send>>,
+    threads: u32,
+) {
+    let mut cam = cam;
+    cam.initalize();
+    let buffer_width = cam.render_width() as u32;
+    let buffer_height = cam.render_height() as u32;
+    let pixel_count = (buffer_width * buffer_height) as usize;
+
+    let event_loop = EventLoop::new().unwrap();
+    let window_size = Size::Physical(PhysicalSize {
+        width: buffer_width,
+        height: buffer_height,
+    });
+    let window = Rc::new(
+        WindowBuilder::new()
+            .with_inner_size(window_size)
+            .with_resizable(false)
+            .build(&event_loop)
+            .unwrap(),
+    );
+    let context = unsafe { softbuffer::Context::new(&window) }.unwrap();
+    let mut surface = unsafe { softbuffer::Surface::new(&context, &window) }.unwrap();
+
+    let mut film = Film::new(cam.render_width(), cam.render_height());
+    let mut buffer = vec![0u32; pixel_count];
+    let mut drag_button: Option<MouseButton> = None;
+    let mut cursor: Option<PhysicalPosition<f64>> = None;
+
+    event_loop
+        .run(move |event, elwt| {
+            elwt.set_control_flow(ControlFlow::Wait);
+
+            let mut camera_moved = false;
+            match event {
+                Event::WindowEvent { window_id, event } if window_id == window.id() => {
+                    match event {
+                        WindowEvent::RedrawRequested => {
+                            let (width, height) = {
+                                let size = window.inner_size();
+                                (size.width, size.height)
+                            };
+                            surface
+                                .resize(
+                                    NonZeroU32::new(width).unwrap(),
+                                    NonZeroU32::new(height).unwrap(),
+                                )
+                                .unwrap();
+
+                            let mut render_buffer = surface.buffer_mut().unwrap();
+                            let count = render_buffer.len().min(buffer.len());
+                            render_buffer[..count].copy_from_slice(&buffer[..count]);
+
+                            render_buffer.present().unwrap();
+                        }
+                        WindowEvent::CloseRequested => {
+                            elwt.exit();
+                        }
+                        WindowEvent::KeyboardInput {
+                            event:
+                                KeyEvent {
+                                    physical_key: PhysicalKey::Code(code),
+                                    state: ElementState::Pressed,
+                                    ..
+                                },
+                            ..
+                        } => {
+                            let step = 0.05 * (cam.look_from - cam.look_at).length();
+                            camera_moved = true;
+                            match code {
+                                KeyCode::KeyW => cam.move_by(step, 0.0, 0.0),
+                                KeyCode::KeyS => cam.move_by(-step, 0.0, 0.0),
+                                KeyCode::KeyA => cam.move_by(0.0, -step, 0.0),
+                                KeyCode::KeyD => cam.move_by(0.0, step, 0.0),
+                                KeyCode::KeyQ => cam.move_by(0.0, 0.0, -step),
+                                KeyCode::KeyE => cam.move_by(0.0, 0.0, step),
+                                KeyCode::KeyP => {
+                                    println!("{}", cam.scene_snippet());
+                                    camera_moved = false;
+                                }
+                                KeyCode::Escape => {
+                                    elwt.exit();
+                                    camera_moved = false;
+                                }
+                                _ => camera_moved = false,
+                            }
+                        }
+                        WindowEvent::MouseInput { state, button, .. } => {
+                            drag_button = match state {
+                                ElementState::Pressed => Some(button),
+                                ElementState::Released => None,
+                            };
+                        }
+                        WindowEvent::CursorMoved { position, .. } => {
+                            if let (Some(button), Some(last)) = (drag_button, cursor) {
+                                let dx = position.x - last.x;
+                                let dy = position.y - last.y;
+                                match button {
+                                    MouseButton::Left => {
+                                        cam.orbit(-0.25 * dx, -0.25 * dy);
+                                        camera_moved = true;
+                                    }
+                                    MouseButton::Right | MouseButton::Middle => {
+                                        // Scale so the point under the cursor follows it on the focus plane
+                                        let distance = (cam.look_from - cam.look_at).length();
+                                        let view_height = 2.0
+                                            * distance
+                                            * (degree_to_radians(cam.vfov) / 2.0).tan();
+                                        let per_pixel = view_height / buffer_height as f64;
+                                        cam.move_by(0.0, -dx * per_pixel, dy * per_pixel);
+                                        camera_moved = true;
+                                    }
+                                    _ => {}
+                                }
+                            }
+                            cursor = Some(position);
+                        }
+                        WindowEvent::MouseWheel { delta, .. } => {
+                            let lines = match delta {
+                                MouseScrollDelta::LineDelta(_, y) => y as f64,
+                                MouseScrollDelta::PixelDelta(pixels) => pixels.y / 40.0,
+                            };
+                            cam.zoom(0.9_f64.powf(lines));
+                            camera_moved = true;
+                        }
+                        _ => {}
+                    }
+                }
+                // Keep refining until the camera's sample count is reached, and never
+                // for a crop window without pixels
+                Event::AboutToWait
+                    if cam.crop_region().is_ok_and(|crop| {
+                        (film.min_samples_in(&crop) as i32) < cam.samples_per_pixel
+                    }) =>
+                {
+                    cam.render_film(
+                        Arc::clone(&world),
+                        lights.as_ref().map(Arc::clone),
+                        threads,
+                        &mut film,
+                        1,
+                        |_| {},
+                        &CancelToken::new(),
+                    );
+                    buffer = film.to_buffer();
+                    window.request_redraw();
+                    elwt.set_control_flow(ControlFlow::Poll);
+                }
+                _ => {}
+            }
+
+            // Any camera change throws away the accumulated samples
+            if camera_moved {
+                film.clear();
+                elwt.set_control_flow(ControlFlow::Poll);
+            }
+        })
+        .unwrap();
+}
@@ -1,1111 +1,1416 @@
-mod aabb;
-mod affine_transforms;
-mod bvh_node;
-mod camera;
-mod checker_texture;
-mod color;
-mod constant_medium;
-mod cosine_pdf;
-mod dielectric;
-mod diffuse_light;
-mod hittable;
-mod hittable_list;
-mod hittable_pdf;
-mod image_texture;
-mod interval;
-mod isotropic;
-mod lambertian;
-mod material;
-mod metal;
-mod mixture_pdf;
-mod noise_texture;
-mod orthonormal_basis;
-mod pdf;
-mod perlin;
-mod quad;
-mod ray;
-mod rtweekend;
-mod solid_texture;
-mod sphere;
-mod sphere_pdf;
-mod texture;
-mod vector_3;
-use crate::affine_transforms::{RotateY, Translate};
-use crate::bvh_node::BVHNode;
-use crate::camera::Camera;
-use crate::checker_texture::CheckerTexture;
-use crate::constant_medium::ConstantMedium;
-use crate::dielectric::Dielectric;
-use crate::diffuse_light::DiffuseLight;
-use crate::hittable::Hittable;
-use crate::hittable_list::HittableList;
-use crate::image_texture::ImageTexture;
-use crate::lambertian::Lambertian;
-use crate::material::Material;
-use crate::metal::Metal;
-use crate::noise_texture::NoiseTexture;
-use crate::quad::quad_box;
-use crate::quad::Quad;
-use crate::rtweekend::random_f64_range;
-use crate::sphere::Sphere;
-use crate::texture::Texture;
-use crate::vector_3::Vec3;
 use image::RgbImage;
-use ray_tracing::render_buffer;
+use ray_tracing::accelerator::Accelerator;
+use ray_tracing::aov::AovFrame;
+use ray_tracing::aperture::{Aperture, ApertureMask};
+use ray_tracing::camera::Camera;
+use ray_tracing::checkpoint::render_checkpointed;
+use ray_tracing::color::vec_to_val;
+use ray_tracing::crop_window::CropWindow;
+use ray_tracing::denoiser::Denoiser;
+use ray_tracing::distributed::{render_distributed, serve_worker, RenderJob};
+use ray_tracing::film::Film;
+use ray_tracing::filter::{Filter, FilterMode};
+use ray_tracing::hittable::Hittable;
+use ray_tracing::image_metrics::{
+    false_color, flip_map, plot_convergence, squared_error_map, ssim_map, MetricImage, Metrics,
+};
+use ray_tracing::lens_system::LensSystem;
+use ray_tracing::projection::Projection;
+use ray_tracing::ray_stats::{self, RenderStats};
+use ray_tracing::render_progress::{CancelToken, RenderProgress};
+use ray_tracing::rtweekend::with_seed;
+use ray_tracing::scene::Scene;
+use ray_tracing::scenes::{build_scene, SCENE_COUNT};
+use ray_tracing::stereo::{combine_stereo, StereoLayout};
+use ray_tracing::tile_scheduler::available_threads;
+use ray_tracing::{render_buffer, render_interactive};
+use std::io::Write;
+use std::net::TcpListener;
 use std::path::Path;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-fn final_render_book1(width: i32, samples: i32, depth: i32, show: bool, save: bool) {
-    // World
-    let mut build_world = HittableList::new();
-
-    // Ground
-    let ground_mat = Arc::new(Lambertian::from_color(Vec3::new(0.5, 0.5, 0.5)));
-    build_world.add(Arc::new(Sphere::new(
-        Vec3::new(0.0, -1000.0, -0.0),
-        1000.0,
-        ground_mat,
-    )));
-
-    //Create random spheres
-    let compare_vec = Vec3::new(4.0, 0.2, 0.0);
-    for a in -11..11 {
-        for b in -11..11 {
-            let rand_mat = rand::random::<f64>();
-            let center = Vec3::new(
-                a as f64 + (0.9 * rand::random::<f64>()),
-                0.2,
-                b as f64 + (0.9 * rand::random::<f64>()),
-            );
+fn save_image(name: &str, buffer: &[u32], width: u32, height: u32, samples: i32, depth: i32) {
+    // Load the buffer into an image
+    let image = RgbImage::from_fn(width, height, |x, y| {
+        let index = x + (y * width);
+        let value = buffer[index as usize];
+        let red = ((value >> 16) & 0x000000FF) as u8;
+        let green = ((value >> 8) & 0x000000FF) as u8;
+        let blue = (value & 0x000000FF) as u8;
+        image::Rgb([red, green, blue])
+    });
 
-            if (center - compare_vec).length() > 0.9 {
-                let mat: Arc<dyn Material + Sync + Send>;
-                if rand_mat < 0.8 {
-                    let albedo = Vec3::random() * Vec3::random();
-                    mat = Arc::new(Lambertian::from_color(albedo));
-                    let center_end = center + Vec3::new(0.0, rand::random::<f64>() * 0.25, 0.0);
-                    build_world.add(Arc::new(Sphere::new_moving(center, 0.2, mat, center_end)));
-                } else if rand_mat < 0.95 {
-                    let albedo = Vec3::random_range(0.5, 1.0);
-                    let fuzz = random_f64_range(0.0, 0.5);
-                    mat = Arc::new(Metal::new(albedo, fuzz));
-                    build_world.add(Arc::new(Sphere::new(center, 0.2, mat)));
-                } else {
-                    mat = Arc::new(Dielectric::new(1.5));
-                    build_world.add(Arc::new(Sphere::new(center, 0.2, mat)));
-                }
-            }
+    let mut full_path = "renders/".to_owned();
+    let path_end = ".png";
+
+    // Add the file name
+    full_path.push_str(name);
+
+    // width, samples, depth
+    full_path.push_str("_w");
+    full_path.push_str(width.to_string().as_str());
+    full_path.push_str("_s");
+    full_path.push_str(samples.to_string().as_str());
+    full_path.push_str("_d");
+    full_path.push_str(depth.to_string().as_str());
+
+    full_path.push_str(path_end);
+    println!("Full Path: {}", &full_path);
+    let path = Path::new(&full_path);
+    let _ = image.save(path);
+}
+
+// Scenes are built from a fixed seed so a resumed render, or a remote worker, sees the
+// same world
+fn build_job(job: &RenderJob) -> Option<Scene> {
+    if !(1..=SCENE_COUNT).contains(&job.scene) {
+        return None;
+    }
+    let mut scene = with_seed(job.seed, || {
+        build_scene(
+            job.scene,
+            job.width,
+            job.samples,
+            job.depth,
+            job.accelerator,
+        )
+    });
+    scene.cam.seed = job.seed;
+    if let Some(projection) = job.projection {
+        scene.cam.projection = projection;
+        // A panorama covers twice as much longitude as latitude
+        if projection == Projection::Equirectangular {
+            scene.cam.aspect_ratio = 2.0;
         }
     }
+    Some(scene)
+}
 
-    // Center spheres
-    let mat = Arc::new(Dielectric::new(1.5));
-    build_world.add(Arc::new(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, mat)));
-
-    let mat = Arc::new(Lambertian::from_color(Vec3::new(0.4, 0.2, 0.1)));
-    build_world.add(Arc::new(Sphere::new(Vec3::new(-4.0, 1.0, 0.0), 1.0, mat)));
+fn run_scene(scene: Scene, job: &RenderJob, options: &Options) {
+    let Scene {
+        name,
+        mut cam,
+        world,
+        lights,
+    } = scene;
+
+    if let Some(path) = &options.lens {
+        match LensSystem::load(Path::new(path)) {
+            Ok(lens) => cam.lens_system = Some(lens),
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(aperture) = &options.aperture {
+        cam.aperture = aperture.clone();
+    }
+    if let Some(vignetting) = options.vignetting {
+        cam.optical_vignetting = vignetting;
+    }
+    if let Some(filter) = options.filter {
+        cam.filter = filter;
+    }
+    if options.splat {
+        cam.filter_mode = FilterMode::Splatting;
+    }
+    if let Some(window) = options.crop {
+        cam.crop_window = Some(window);
+    }
+    if let Some(ipd) = options.ipd {
+        cam.interpupillary_distance = ipd;
+    }
+    if let Err(err) = cam.validate() {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
 
-    let mat = Arc::new(Metal::new(Vec3::new(0.7, 0.6, 0.5), 0.0));
-    build_world.add(Arc::new(Sphere::new(Vec3::new(4.0, 1.0, 0.0), 1.0, mat)));
+    // Interactive mode replaces the final render with a progressive preview
+    if options.interactive {
+        render_interactive(cam, world, lights, available_threads());
+        return;
+    }
 
-    // Make the world into a bvh
-    let mut world = HittableList::new();
-    world.add(Arc::new(BVHNode::from_list(&build_world)));
+    if let Some(layout) = options.stereo {
+        run_stereo(name, cam, world, lights, layout, options);
+        return;
+    }
 
-    // Camera
-    let mut cam = Camera::new();
-    cam.aspect_ratio = 16.0 / 9.0;
-    cam.image_width = width;
-    cam.samples_per_pixel = samples;
-    cam.max_depth = depth;
+    // The denoiser is guided by the same first hit outputs --aovs saves
+    let features = if options.aovs.is_some() || options.denoise {
+        let samples = cam.samples_per_pixel.clamp(1, AOV_SAMPLES);
+        Some(cam.render_aovs(Arc::clone(&world), available_threads(), samples))
+    } else {
+        None
+    };
+    if let (Some(path), Some(features)) = (&options.aovs, &features) {
+        save_aovs(features, path);
+    }
 
-    cam.vfov = 20.0;
-    cam.look_from = Vec3::new(13.0, 2.0, 3.0);
-    cam.look_at = Vec3::new(0.0, 0.0, 0.0);
-    cam.v_up = Vec3::new(0.0, 1.0, 0.0);
+    // Counting costs a little on every ray, so it is only on when asked for
+    let count_rays = options.stats || options.heatmap.is_some();
+    if count_rays {
+        cam.initalize();
+        ray_stats::start(cam.render_width(), cam.render_height());
+    }
 
-    cam.defocus_angle = 0.6;
-    cam.focus_dist = 10.0;
+    let start_time = Instant::now();
+    let cancel = CancelToken::new();
+    let film = match &options.checkpoint {
+        _ if !options.workers.is_empty() => {
+            let film = render_distributed(
+                &mut cam,
+                job,
+                &options.workers,
+                Duration::from_secs(options.worker_timeout),
+                print_progress,
+                &cancel,
+            );
+            match film {
+                Ok(film) => film,
+                Err(err) => {
+                    eprintln!("\n{}", err);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(path) => {
+            let film = render_checkpointed(
+                &mut cam,
+                world,
+                lights,
+                job,
+                available_threads(),
+                Path::new(path),
+                options.resume,
+                options.checkpoint_every,
+                print_progress,
+                &cancel,
+            );
+            match film {
+                Ok(film) => film,
+                Err(err) => {
+                    eprintln!("\n{}", err);
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => {
+            cam.initalize();
+            let mut film = Film::new(cam.render_width(), cam.render_height());
+            let samples = cam.samples_per_pixel;
+            cam.render_film(
+                world,
+                lights,
+                available_threads(),
+                &mut film,
+                samples,
+                print_progress,
+                &cancel,
+            );
+            film
+        }
+    };
+    println!("\nDone! Took {} seconds", start_time.elapsed().as_secs());
+    if let (true, Some(stats)) = (count_rays, ray_stats::stop()) {
+        report_stats(&stats, start_time.elapsed(), options);
+    }
 
-    let world = Arc::new(world);
-    let buffer = cam.render(world, None, true, 6);
-    if save {
+    let (mut buffer, width, height) = crop_output(&cam, film.to_buffer(), options);
+    if options.save {
         save_image(
-            "Book1_Final",
+            name,
             &buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-            samples,
-            depth,
+            width as u32,
+            height as u32,
+            cam.samples_per_pixel,
+            cam.max_depth,
         );
     }
-    if show {
-        render_buffer(
-            buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
+
+    // The noisy render is kept next to the denoised one, which is what gets shown
+    if let (true, Some(features)) = (options.denoise, &features) {
+        let start_time = Instant::now();
+        let denoised = denoise_film(&film, features);
+        println!(
+            "Denoised in {:.1} seconds",
+            start_time.elapsed().as_secs_f64()
         );
+        buffer = crop_output(&cam, denoised, options).0;
+        if options.save {
+            save_image(
+                &format!("{}_denoised", name),
+                &buffer,
+                width as u32,
+                height as u32,
+                cam.samples_per_pixel,
+                cam.max_depth,
+            );
+        }
+    }
+    if options.show {
+        render_buffer(buffer, width as u32, height as u32);
     }
 }
 
-fn checker_spheres(width: i32, samples: i32, depth: i32, show: bool, save: bool) {
-    // World
-    let mut world = HittableList::new();
-
-    let checker: Arc<dyn Texture + Sync + Send> = Arc::new(CheckerTexture::new(
-        0.32,
-        Vec3::new(0.2, 0.3, 0.1),
-        Vec3::new(0.9, 0.9, 0.9),
-    ));
-    let ground_mat = Arc::new(Lambertian::new(Arc::clone(&checker)));
-    world.add(Arc::new(Sphere::new(
-        Vec3::new(0.0, -10.0, -0.0),
-        10.0,
-        ground_mat,
-    )));
-    let ground_mat = Arc::new(Lambertian::new(checker));
-    world.add(Arc::new(Sphere::new(
-        Vec3::new(0.0, 10.0, -0.0),
-        10.0,
-        ground_mat,
-    )));
-
-    // Camera
-    let mut cam = Camera::new();
-    cam.aspect_ratio = 16.0 / 9.0;
-    cam.image_width = width;
-    cam.samples_per_pixel = samples;
-    cam.max_depth = depth;
-
-    cam.vfov = 20.0;
-    cam.look_from = Vec3::new(13.0, 2.0, 3.0);
-    cam.look_at = Vec3::new(0.0, 0.0, 0.0);
-    cam.v_up = Vec3::new(0.0, 1.0, 0.0);
-
-    cam.defocus_angle = 0.0;
-    cam.focus_dist = 10.0;
-
-    let world = Arc::new(world);
-    let buffer = cam.render(world, None, true, 6);
-    if save {
-        save_image(
-            "Checker_Spheres",
-            &buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-            samples,
-            depth,
-        );
+fn report_stats(stats: &RenderStats, time: Duration, options: &Options) {
+    print!("{}", stats.totals.summary(time));
+    if let Some(path) = &options.heatmap {
+        let (heatmap, top) = stats.heatmap();
+        match heatmap.save(path) {
+            Ok(()) => println!(
+                "Traversal cost heatmap saved to {}, yellow is {:.0} AABB and primitive tests per sample",
+                path, top
+            ),
+            Err(err) => eprintln!("Could not save {}: {}", path, err),
+        }
     }
-    if show {
-        render_buffer(
-            buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-        );
+}
+
+// First hit outputs go to one multi-layer EXR when the path ends in .exr, otherwise the
+// path is a prefix for one file per output
+fn save_aovs(frame: &AovFrame, path: &str) {
+    let result = if path.ends_with(".exr") {
+        frame.write_exr(path)
+    } else {
+        frame.write_separate(path)
+    };
+    match result {
+        Ok(()) => println!("AOVs: {}", path),
+        Err(err) => eprintln!("cannot write AOVs to {}: {}", path, err),
     }
 }
 
-fn earth(width: i32, samples: i32, depth: i32, show: bool, save: bool) {
-    let mut world = HittableList::new();
-
-    let earth_texture = Arc::new(ImageTexture::new("src/earthmap.jpg"));
-    let ground_mat = Arc::new(Lambertian::new(earth_texture));
-    world.add(Arc::new(Sphere::new(
-        Vec3::new(0.0, 0.0, 0.0),
-        2.0,
-        ground_mat,
-    )));
-
-    // Camera
-    let mut cam = Camera::new();
-    cam.aspect_ratio = 16.0 / 9.0;
-    cam.image_width = width;
-    cam.samples_per_pixel = samples;
-    cam.max_depth = depth;
-
-    cam.vfov = 20.0;
-    cam.look_from = Vec3::new(0.0, 0.0, 12.0);
-    cam.look_at = Vec3::new(0.0, 0.0, 0.0);
-    cam.v_up = Vec3::new(0.0, 1.0, 0.0);
-
-    cam.defocus_angle = 0.0;
-
-    let world = Arc::new(world);
-    let buffer = cam.render(world, None, false, 6);
-    if save {
-        save_image(
-            "Earth",
-            &buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-            samples,
-            depth,
-        );
+// The film with the pixels the features cover replaced by their denoised radiance
+fn denoise_film(film: &Film, features: &AovFrame) -> Vec<u32> {
+    let denoised = Denoiser::new().denoise_film(film, features, available_threads());
+    let mut buffer = film.to_buffer();
+    for ((i, j), pixel) in features.region.pixels().zip(&denoised) {
+        buffer[(j * film.width() + i) as usize] = vec_to_val(pixel, 1);
     }
-    if show {
-        render_buffer(
-            buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-        );
+    buffer
+}
+
+// A cropped render is saved as just the crop window unless the full frame is asked for
+fn crop_output(cam: &Camera, buffer: Vec<u32>, options: &Options) -> (Vec<u32>, i32, i32) {
+    if cam.crop_window.is_none() || options.crop_full_frame {
+        return (buffer, cam.render_width(), cam.render_height());
     }
+    let Ok(region) = cam.crop_region() else {
+        return (buffer, cam.render_width(), cam.render_height());
+    };
+    let cropped = region
+        .pixels()
+        .map(|(i, j)| buffer[(j * cam.render_width() + i) as usize])
+        .collect();
+    (cropped, region.width, region.height)
 }
 
-fn two_perlin_sphere(width: i32, samples: i32, depth: i32, show: bool, save: bool) {
-    let mut world = HittableList::new();
-
-    let pertext: Arc<dyn Texture + Sync + Send> = Arc::new(NoiseTexture::new(4.0));
-    let mat_0 = Arc::new(Lambertian::new(Arc::clone(&pertext)));
-    world.add(Arc::new(Sphere::new(
-        Vec3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        mat_0,
-    )));
-    let mat_1 = Arc::new(Lambertian::new(Arc::clone(&pertext)));
-    world.add(Arc::new(Sphere::new(Vec3::new(0.0, 2.0, 0.0), 2.0, mat_1)));
-
-    // Camera
-    let mut cam = Camera::new();
-    cam.aspect_ratio = 16.0 / 9.0;
-    cam.image_width = width;
-    cam.samples_per_pixel = samples;
-    cam.max_depth = depth;
-
-    cam.vfov = 20.0;
-    cam.look_from = Vec3::new(13.0, 2.0, 3.0);
-    cam.look_at = Vec3::new(0.0, 0.0, 0.0);
-    cam.v_up = Vec3::new(0.0, 1.0, 0.0);
-
-    cam.defocus_angle = 0.0;
-
-    let world = Arc::new(world);
-    let buffer = cam.render(world, None, true, 6);
-    if save {
-        save_image(
-            "Perlin_Sphere",
-            &buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-            samples,
-            depth,
-        );
+fn run_stereo(
+    name: &str,
+    mut cam: Camera,
+    world: Arc<dyn Hittable + Sync + Send>,
+    lights: Option<Arc<dyn Hittable + Sync + Send>>,
+    layout: StereoLayout,
+    options: &Options,
+) {
+    let start_time = Instant::now();
+    let (left, right) = cam.render_stereo(
+        world,
+        lights,
+        available_threads(),
+        print_progress,
+        &CancelToken::new(),
+    );
+    println!("\nDone! Took {} seconds", start_time.elapsed().as_secs());
+
+    let (left, width, height) = crop_output(&cam, left, options);
+    let (right, _, _) = crop_output(&cam, right, options);
+    let (buffer, combined_width, combined_height) =
+        combine_stereo(&left, &right, width, height, layout);
+    if options.save {
+        if layout == StereoLayout::Separate {
+            for (eye, buffer) in [("left", &left), ("right", &right)] {
+                save_image(
+                    &format!("{}_{}", name, eye),
+                    buffer,
+                    width as u32,
+                    height as u32,
+                    cam.samples_per_pixel,
+                    cam.max_depth,
+                );
+            }
+        } else {
+            save_image(
+                &format!("{}_stereo", name),
+                &buffer,
+                combined_width as u32,
+                combined_height as u32,
+                cam.samples_per_pixel,
+                cam.max_depth,
+            );
+        }
     }
-    if show {
-        render_buffer(
-            buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-        );
+    if options.show {
+        render_buffer(buffer, combined_width as u32, combined_height as u32);
     }
 }
 
-fn quads(width: i32, samples: i32, depth: i32, show: bool, save: bool) {
-    let mut world = HittableList::new();
-
-    let red = Arc::new(Lambertian::from_color(Vec3::new(1.0, 0.2, 0.2)));
-    let green = Arc::new(Lambertian::from_color(Vec3::new(0.2, 1.0, 0.2)));
-    let blue = Arc::new(Lambertian::from_color(Vec3::new(0.2, 0.2, 1.0)));
-    let orange = Arc::new(Lambertian::from_color(Vec3::new(1.0, 0.5, 0.2)));
-    let teal = Arc::new(Lambertian::from_color(Vec3::new(0.2, 0.8, 0.8)));
-
-    world.add(Arc::new(Quad::new(
-        Vec3::new(-3.0, -2.0, 5.0),
-        Vec3::new(0.0, 0.0, -4.0),
-        Vec3::new(0.0, 4.0, 0.0),
-        red,
-    )));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(-2.0, -2.0, 0.0),
-        Vec3::new(4.0, 0.0, 0.0),
-        Vec3::new(0.0, 4.0, 0.0),
-        green,
-    )));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(3.0, -2.0, 1.0),
-        Vec3::new(0.0, 0.0, 4.0),
-        Vec3::new(0.0, 4.0, 0.0),
-        blue,
-    )));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(-2.0, 3.0, 1.0),
-        Vec3::new(4.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, 4.0),
-        orange,
-    )));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(-2.0, -3.0, 5.0),
-        Vec3::new(4.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, -4.0),
-        teal,
-    )));
-
-    // Camera
-    let mut cam = Camera::new();
-    cam.aspect_ratio = 1.0;
-    cam.image_width = width;
-    cam.samples_per_pixel = samples;
-    cam.max_depth = depth;
-
-    cam.vfov = 80.0;
-    cam.look_from = Vec3::new(0.0, 0.0, 9.0);
-    cam.look_at = Vec3::new(0.0, 0.0, 0.0);
-    cam.v_up = Vec3::new(0.0, 1.0, 0.0);
-
-    cam.defocus_angle = 0.0;
-
-    let world = Arc::new(world);
-    let buffer = cam.render(world, None, true, 6);
-    if save {
-        save_image(
-            "Quads",
-            &buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-            samples,
-            depth,
-        );
+struct CompareOptions {
+    reference: String,
+    test: String,
+    diff: Option<String>,
+    max_mse: Option<f64>,
+    max_rel_mse: Option<f64>,
+    min_psnr: Option<f64>,
+    min_ssim: Option<f64>,
+    max_flip: Option<f64>,
+}
+
+impl CompareOptions {
+    fn parse(args: &[String]) -> Result<CompareOptions, String> {
+        let mut paths = Vec::new();
+        let mut options = CompareOptions {
+            reference: String::new(),
+            test: String::new(),
+            diff: None,
+            max_mse: None,
+            max_rel_mse: None,
+            min_psnr: None,
+            min_ssim: None,
+            max_flip: None,
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("{} needs a value", arg))
+                    .cloned()
+            };
+            match arg.as_str() {
+                "--diff" => options.diff = Some(value()?),
+                "--max-mse" => options.max_mse = Some(parse_number(arg, &value()?)?),
+                "--max-relmse" => options.max_rel_mse = Some(parse_number(arg, &value()?)?),
+                "--min-psnr" => options.min_psnr = Some(parse_number(arg, &value()?)?),
+                "--min-ssim" => options.min_ssim = Some(parse_number(arg, &value()?)?),
+                "--max-flip" => options.max_flip = Some(parse_number(arg, &value()?)?),
+                _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+                _ => paths.push(arg.clone()),
+            }
+        }
+
+        match <[String; 2]>::try_from(paths) {
+            Ok([reference, test]) => {
+                options.reference = reference;
+                options.test = test;
+                Ok(options)
+            }
+            Err(_) => Err("compare needs a reference and a test image".to_owned()),
+        }
     }
-    if show {
-        render_buffer(
-            buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-        );
+
+    // Names of the tolerances the metrics break. A metric that came out NaN breaks any
+    // tolerance set for it.
+    fn failures(&self, metrics: &Metrics) -> Vec<String> {
+        let mut failures = Vec::new();
+        let mut check = |name: &str, limit: Option<f64>, value: f64, above: bool| {
+            if let Some(limit) = limit {
+                if value.is_nan() || (above && value > limit) || (!above && value < limit) {
+                    failures.push(format!(
+                        "{} {:.6} is past the tolerance {}",
+                        name, value, limit
+                    ));
+                }
+            }
+        };
+        check("MSE", self.max_mse, metrics.mse, true);
+        check("relMSE", self.max_rel_mse, metrics.rel_mse, true);
+        check("PSNR", self.min_psnr, metrics.psnr, false);
+        check("SSIM", self.min_ssim, metrics.ssim, false);
+        check("FLIP", self.max_flip, metrics.flip, true);
+        failures
     }
 }
 
-fn simple_light(width: i32, samples: i32, depth: i32, show: bool, save: bool) {
-    let mut world = HittableList::new();
-
-    let pertext: Arc<dyn Material + Sync + Send> =
-        Arc::new(Lambertian::new(Arc::new(NoiseTexture::new(4.0))));
-    world.add(Arc::new(Sphere::new(
-        Vec3::new(0.0, -1000.0, 0.0),
-        -1000.0,
-        Arc::clone(&pertext),
-    )));
-    world.add(Arc::new(Sphere::new(
-        Vec3::new(0.0, 2.0, 0.0),
-        2.0,
-        Arc::clone(&pertext),
-    )));
-
-    let mut lights = HittableList::new();
-    let diff_light: Arc<dyn Material + Sync + Send> =
-        Arc::new(DiffuseLight::color(Vec3::new(4.0, 4.0, 4.0)));
-    let light: Arc<dyn Hittable + Sync + Send> = Arc::new(Sphere::new(
-        Vec3::new(0.0, 7.0, 0.0),
-        2.0,
-        Arc::clone(&diff_light),
-    ));
-    world.add(Arc::clone(&light));
-    lights.add(light);
-    let light: Arc<dyn Hittable + Sync + Send> = Arc::new(Quad::new(
-        Vec3::new(3.0, 1.0, -2.0),
-        Vec3::new(2.0, 0.0, 0.0),
-        Vec3::new(0.0, 2.0, 0.0),
-        diff_light,
-    ));
-    world.add(Arc::clone(&light));
-    lights.add(light);
-
-    // Camera
-    let mut cam = Camera::new();
-    cam.aspect_ratio = 16.0 / 9.0;
-    cam.image_width = width;
-    cam.samples_per_pixel = samples;
-    cam.max_depth = depth;
-    cam.background = Vec3::new(0.0, 0.0, 0.0);
-
-    cam.vfov = 20.0;
-    cam.look_from = Vec3::new(26.0, 3.0, 6.0);
-    cam.look_at = Vec3::new(0.0, 2.0, 0.0);
-    cam.v_up = Vec3::new(0.0, 1.0, 0.0);
-
-    cam.defocus_angle = 0.0;
-
-    let world = Arc::new(world);
-    let lights = Arc::new(lights);
-    let buffer = cam.render(world, Some(lights), true, 6);
-    if save {
-        save_image(
-            "Simple_Light",
-            &buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-            samples,
-            depth,
-        );
+// Exits with 2 when a tolerance is exceeded and 1 when the images can't be compared
+fn run_compare(options: &CompareOptions) -> i32 {
+    let load =
+        |path: &str| MetricImage::load(Path::new(path)).map_err(|err| format!("{}: {}", path, err));
+    let (reference, test) = match (load(&options.reference), load(&options.test)) {
+        (Ok(reference), Ok(test)) => (reference, test),
+        (Err(err), _) | (_, Err(err)) => {
+            eprintln!("{}", err);
+            return 1;
+        }
+    };
+    let metrics = match Metrics::compare(&reference, &test) {
+        Ok(metrics) => metrics,
+        Err(err) => {
+            eprintln!("{}", err);
+            return 1;
+        }
+    };
+    print_metrics(&metrics);
+
+    if let Some(prefix) = &options.diff {
+        let (width, height) = (reference.width, reference.height);
+        let error: Vec<f64> = squared_error_map(&reference, &test)
+            .iter()
+            .map(|error| error.sqrt())
+            .collect();
+        let dissimilarity: Vec<f64> = ssim_map(&reference, &test)
+            .iter()
+            .map(|ssim| 1.0 - ssim)
+            .collect();
+        let maps = [
+            ("error", error),
+            ("ssim", dissimilarity),
+            ("flip", flip_map(&reference, &test)),
+        ];
+        for (name, map) in maps {
+            let path = format!("{}_{}.png", prefix, name);
+            match false_color(&map, width, height).save(&path) {
+                Ok(()) => println!("Difference image: {}", path),
+                Err(err) => eprintln!("cannot write {}: {}", path, err),
+            }
+        }
     }
-    if show {
-        render_buffer(
-            buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-        );
+
+    let failures = options.failures(&metrics);
+    for failure in &failures {
+        eprintln!("{}", failure);
+    }
+    if failures.is_empty() {
+        0
+    } else {
+        2
     }
 }
 
-fn cornell_box(width: i32, samples: i32, depth: i32, show: bool, save: bool) {
-    let mut world = HittableList::new();
-
-    let red = Arc::new(Lambertian::from_color(Vec3::new(0.65, 0.05, 0.05)));
-    let white: Arc<dyn Material + Sync + Send> =
-        Arc::new(Lambertian::from_color(Vec3::new(0.73, 0.73, 0.73)));
-    let green = Arc::new(Lambertian::from_color(Vec3::new(0.12, 0.45, 0.15)));
-    let light_mat = Arc::new(DiffuseLight::color(Vec3::new(15.0, 15.0, 15.0)));
-
-    // Scene
-    let light: Arc<dyn Hittable + Sync + Send> = Arc::new(Quad::new(
-        Vec3::new(343.0, 554.0, 332.0),
-        Vec3::new(-130.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, -105.0),
-        light_mat,
-    ));
-    world.add(Arc::clone(&light));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(555.0, 0.0, 0.0),
-        Vec3::new(0.0, 555.0, 0.0),
-        Vec3::new(0.0, 0.0, 555.0),
-        green,
-    )));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(0.0, 0.0, 0.0),
-        Vec3::new(0.0, 555.0, 0.0),
-        Vec3::new(0.0, 0.0, 555.0),
-        red,
-    )));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(0.0, 0.0, 0.0),
-        Vec3::new(555.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, 555.0),
-        Arc::clone(&white),
-    )));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(555.0, 555.0, 555.0),
-        Vec3::new(-555.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, -555.0),
-        Arc::clone(&white),
-    )));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(0.0, 0.0, 555.0),
-        Vec3::new(555.0, 0.0, 0.0),
-        Vec3::new(0.0, 555.0, 0.0),
-        Arc::clone(&white),
-    )));
-
-    // Boxes
-    let box_1 = quad_box(
-        Vec3::new(0.0, 0.0, 0.0),
-        Vec3::new(165.0, 330.0, 165.0),
-        Arc::clone(&white),
-    );
-    let box_1 = Arc::new(RotateY::new(box_1, 15.0));
-    let box_1 = Arc::new(Translate::new(box_1, Vec3::new(265.0, 0.0, 295.0)));
-    world.add(box_1);
-
-    let box_2 = quad_box(
-        Vec3::new(0.0, 0.0, 0.0),
-        Vec3::new(165.0, 165.0, 165.0),
-        Arc::clone(&white),
-    );
-    let box_2 = Arc::new(RotateY::new(box_2, -18.0));
-    let box_2 = Arc::new(Translate::new(box_2, Vec3::new(130.0, 0.0, 65.0)));
-    world.add(box_2);
-
-    // Camera
-    let mut cam = Camera::new();
-    cam.aspect_ratio = 1.0;
-    cam.image_width = width;
-    cam.samples_per_pixel = samples;
-    cam.max_depth = depth;
-    cam.background = Vec3::new(0.0, 0.0, 0.0);
+fn print_metrics(metrics: &Metrics) {
+    println!("MSE:    {:.8}", metrics.mse);
+    println!("relMSE: {:.8}", metrics.rel_mse);
+    println!("PSNR:   {:.3} dB", metrics.psnr);
+    println!("SSIM:   {:.6}", metrics.ssim);
+    println!("FLIP:   {:.6}", metrics.flip);
+}
 
-    cam.vfov = 40.0;
-    cam.look_from = Vec3::new(278.0, 278.0, -800.0);
-    cam.look_at = Vec3::new(278.0, 278.0, 0.0);
-    cam.v_up = Vec3::new(0.0, 1.0, 0.0);
+struct ConvergenceOptions {
+    reference: String,
+    plot: Option<String>,
+    render: Options,
+}
 
-    cam.defocus_angle = 0.0;
+impl ConvergenceOptions {
+    // The reference and --plot, then the usual options for the scene to render
+    fn parse(args: &[String]) -> Result<ConvergenceOptions, String> {
+        let reference = match args.first() {
+            Some(reference) if !reference.starts_with("--") => reference.clone(),
+            _ => return Err("convergence needs a reference image".to_owned()),
+        };
+        let mut plot = None;
+        let mut rest = Vec::new();
+        let mut args = args[1..].iter();
+        while let Some(arg) = args.next() {
+            if arg == "--plot" {
+                plot = Some(args.next().ok_or("--plot needs a value")?.clone());
+            } else {
+                rest.push(arg.clone());
+            }
+        }
+        Ok(ConvergenceOptions {
+            reference,
+            plot,
+            render: Options::parse(&rest)?,
+        })
+    }
+}
 
-    let world = Arc::new(world);
-    let buffer = cam.render(world, Some(light), true, 6);
+// Sample counts the convergence is measured at: 1, 2, 4, ... and then `samples` itself
+fn convergence_steps(samples: i32) -> Vec<i32> {
+    let samples = samples.max(1);
+    let mut steps: Vec<i32> = std::iter::successors(Some(1), |&spp: &i32| spp.checked_mul(2))
+        .take_while(|&spp| spp <= samples)
+        .collect();
+    if steps.last() != Some(&samples) {
+        steps.push(samples);
+    }
+    steps
+}
 
-    if save {
-        save_image(
-            "Cornell_Box",
-            &buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
+// Renders the scene at each of the convergence steps, adding samples to the same film, and
+// measures each step against the reference
+fn run_convergence(options: &ConvergenceOptions) -> i32 {
+    let reference = match MetricImage::load(Path::new(&options.reference)) {
+        Ok(reference) => reference,
+        Err(err) => {
+            eprintln!("{}: {}", options.reference, err);
+            return 1;
+        }
+    };
+    let render = &options.render;
+    let job = RenderJob {
+        scene: render.scene,
+        seed: render.seed,
+        width: render.width,
+        samples: render.samples,
+        depth: render.depth,
+        projection: render.projection,
+        accelerator: render.accelerator,
+    };
+    let Scene {
+        name,
+        mut cam,
+        world,
+        lights,
+    } = match build_job(&job) {
+        Some(scene) => scene,
+        None => {
+            eprintln!("unknown scene {}", render.scene);
+            return 1;
+        }
+    };
+
+    cam.initalize();
+    let mut film = Film::new(cam.render_width(), cam.render_height());
+    let mut points: Vec<(u32, Metrics)> = Vec::new();
+    println!("spp,mse,relmse,psnr,ssim,flip");
+    for spp in convergence_steps(render.samples) {
+        let samples = spp - film.min_samples() as i32;
+        cam.render_film(
+            Arc::clone(&world),
+            lights.as_ref().map(Arc::clone),
+            available_threads(),
+            &mut film,
             samples,
-            depth,
+            |_| {},
+            &CancelToken::new(),
         );
+        let test =
+            MetricImage::from_buffer(&film.to_buffer(), film.width() as u32, film.height() as u32);
+        let metrics = match Metrics::compare(&reference, &test) {
+            Ok(metrics) => metrics,
+            Err(err) => {
+                eprintln!("{}", err);
+                return 1;
+            }
+        };
+        println!(
+            "{},{:.8},{:.8},{:.4},{:.6},{:.6}",
+            spp, metrics.mse, metrics.rel_mse, metrics.psnr, metrics.ssim, metrics.flip
+        );
+        points.push((spp as u32, metrics));
     }
 
-    if show {
-        render_buffer(
-            buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-        );
+    // MSE red, relMSE orange, 1 - SSIM green and FLIP blue
+    let series = |color: [u8; 3], value: fn(&Metrics) -> f64| {
+        (
+            image::Rgb(color),
+            points
+                .iter()
+                .map(|(spp, metrics)| (*spp, value(metrics)))
+                .collect(),
+        )
+    };
+    let plot = plot_convergence(
+        &[
+            series([220, 40, 40], |metrics| metrics.mse),
+            series([240, 150, 30], |metrics| metrics.rel_mse),
+            series([40, 160, 60], |metrics| 1.0 - metrics.ssim),
+            series([40, 80, 220], |metrics| metrics.flip),
+        ],
+        640,
+        480,
+    );
+    let path = options
+        .plot
+        .clone()
+        .unwrap_or_else(|| format!("renders/{}_convergence.png", name));
+    match plot.save(&path) {
+        Ok(()) => {
+            println!("Plot: {}", path);
+            0
+        }
+        Err(err) => {
+            eprintln!("cannot write {}: {}", path, err);
+            1
+        }
     }
 }
 
-fn cornell_smoke(width: i32, samples: i32, depth: i32, show: bool, save: bool) {
-    let mut world = HittableList::new();
-    let mut lights = HittableList::new();
-
-    let red = Arc::new(Lambertian::from_color(Vec3::new(0.65, 0.05, 0.05)));
-    let white: Arc<dyn Material + Sync + Send> =
-        Arc::new(Lambertian::from_color(Vec3::new(0.73, 0.73, 0.73)));
-    let green = Arc::new(Lambertian::from_color(Vec3::new(0.12, 0.45, 0.15)));
-    let light_mat = Arc::new(DiffuseLight::color(Vec3::new(7.0, 7.0, 7.0)));
-
-    // Scene
-    let light: Arc<dyn Hittable + Sync + Send> = Arc::new(Quad::new(
-        Vec3::new(113.0, 554.0, 127.0),
-        Vec3::new(330.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, 305.0),
-        light_mat,
-    ));
-    world.add(Arc::clone(&light));
-    lights.add(light);
-    world.add(Arc::new(Quad::new(
-        Vec3::new(555.0, 0.0, 0.0),
-        Vec3::new(0.0, 555.0, 0.0),
-        Vec3::new(0.0, 0.0, 555.0),
-        green,
-    )));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(0.0, 0.0, 0.0),
-        Vec3::new(0.0, 555.0, 0.0),
-        Vec3::new(0.0, 0.0, 555.0),
-        red,
-    )));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(0.0, 0.0, 0.0),
-        Vec3::new(555.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, 555.0),
-        Arc::clone(&white),
-    )));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(555.0, 555.0, 555.0),
-        Vec3::new(-555.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, -555.0),
-        Arc::clone(&white),
-    )));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(0.0, 0.0, 555.0),
-        Vec3::new(555.0, 0.0, 0.0),
-        Vec3::new(0.0, 555.0, 0.0),
-        Arc::clone(&white),
-    )));
-
-    // Boxes
-    let box_1 = quad_box(
-        Vec3::new(0.0, 0.0, 0.0),
-        Vec3::new(165.0, 330.0, 165.0),
-        Arc::clone(&white),
-    );
-    let box_1 = Arc::new(RotateY::new(box_1, 15.0));
-    let box_1 = Arc::new(Translate::new(box_1, Vec3::new(265.0, 0.0, 295.0)));
-    world.add(Arc::new(ConstantMedium::color(
-        box_1,
-        0.01,
-        Vec3::new(0.0, 0.0, 0.0),
-    )));
-
-    let box_2 = quad_box(
-        Vec3::new(0.0, 0.0, 0.0),
-        Vec3::new(165.0, 165.0, 165.0),
-        Arc::clone(&white),
+fn print_progress(progress: &RenderProgress) {
+    let bar_width = 40;
+    let filled = ((progress.fraction() * bar_width as f64) as usize).min(bar_width);
+    let eta = match progress.eta {
+        Some(eta) => format_duration(eta),
+        None => "--:--:--".to_owned(),
+    };
+    print!(
+        "\r[{}{}] {:5.1}% {}/{} tiles, ETA {}",
+        "#".repeat(filled),
+        " ".repeat(bar_width - filled),
+        100.0 * progress.fraction(),
+        progress.tiles_done,
+        progress.tiles_total,
+        eta
     );
-    let box_2 = Arc::new(RotateY::new(box_2, -18.0));
-    let box_2 = Arc::new(Translate::new(box_2, Vec3::new(130.0, 0.0, 65.0)));
-    world.add(Arc::new(ConstantMedium::color(
-        box_2,
-        0.01,
-        Vec3::new(1.0, 1.0, 1.0),
-    )));
-
-    // Camera
-    let mut cam = Camera::new();
-    cam.aspect_ratio = 1.0;
-    cam.image_width = width;
-    cam.samples_per_pixel = samples;
-    cam.max_depth = depth;
-    cam.background = Vec3::new(0.0, 0.0, 0.0);
-
-    cam.vfov = 40.0;
-    cam.look_from = Vec3::new(278.0, 278.0, -800.0);
-    cam.look_at = Vec3::new(278.0, 278.0, 0.0);
-    cam.v_up = Vec3::new(0.0, 1.0, 0.0);
-
-    cam.defocus_angle = 0.0;
-
-    let world = Arc::new(world);
-    let lights = Arc::new(lights);
-    let buffer = cam.render(world, Some(lights), true, 6);
-
-    if save {
-        save_image(
-            "Cornell_Smoke",
-            &buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-            samples,
-            depth,
-        );
+    let _ = std::io::stdout().flush();
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}:{:02}:{:02}", secs / 3600, (secs / 60) % 60, secs % 60)
+}
+
+struct Options {
+    scene: i32,
+    width: i32,
+    samples: i32,
+    depth: i32,
+    seed: u64,
+    show: bool,
+    save: bool,
+    interactive: bool,
+    all: bool,
+    checkpoint: Option<String>,
+    resume: bool,
+    checkpoint_every: i32,
+    worker: Option<String>,
+    workers: Vec<String>,
+    worker_timeout: u64,
+    projection: Option<Projection>,
+    accelerator: Accelerator,
+    stereo: Option<StereoLayout>,
+    ipd: Option<f64>,
+    lens: Option<String>,
+    aperture: Option<Aperture>,
+    vignetting: Option<f64>,
+    filter: Option<Filter>,
+    splat: bool,
+    crop: Option<CropWindow>,
+    crop_full_frame: bool,
+    aovs: Option<String>,
+    denoise: bool,
+    stats: bool,
+    heatmap: Option<String>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Options, String> {
+        let mut options = Options {
+            scene: 9,
+            width: 800,
+            samples: 1000,
+            depth: 50,
+            seed: 0,
+            show: true,
+            save: true,
+            interactive: false,
+            all: false,
+            checkpoint: None,
+            resume: false,
+            checkpoint_every: 16,
+            worker: None,
+            workers: Vec::new(),
+            worker_timeout: 300,
+            projection: None,
+            accelerator: Accelerator::BVHNode,
+            stereo: None,
+            ipd: None,
+            lens: None,
+            aperture: None,
+            vignetting: None,
+            filter: None,
+            splat: false,
+            crop: None,
+            crop_full_frame: false,
+            aovs: None,
+            denoise: false,
+            stats: false,
+            heatmap: None,
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("{} needs a value", arg))
+                    .cloned()
+            };
+            match arg.as_str() {
+                "--scene" => options.scene = parse_number(arg, &value()?)?,
+                "--width" => options.width = parse_number(arg, &value()?)?,
+                "--samples" => options.samples = parse_number(arg, &value()?)?,
+                "--depth" => options.depth = parse_number(arg, &value()?)?,
+                "--seed" => options.seed = parse_number(arg, &value()?)?,
+                "--no-show" => options.show = false,
+                "--no-save" => options.save = false,
+                "--interactive" => options.interactive = true,
+                "--all" => options.all = true,
+                "--checkpoint" => options.checkpoint = Some(value()?),
+                "--resume" => options.resume = true,
+                "--checkpoint-every" => options.checkpoint_every = parse_number(arg, &value()?)?,
+                "--projection" => options.projection = Some(parse_projection(&value()?)?),
+                "--accelerator" => {
+                    options.accelerator = match value()?.as_str() {
+                        "bvh" => Accelerator::BVHNode,
+                        "qbvh" => Accelerator::QBVH,
+                        accelerator => return Err(format!("unknown accelerator {}", accelerator)),
+                    }
+                }
+                "--stereo" => {
+                    options.stereo = Some(match value()?.as_str() {
+                        "side-by-side" => StereoLayout::SideBySide,
+                        "over-under" => StereoLayout::OverUnder,
+                        "separate" => StereoLayout::Separate,
+                        layout => return Err(format!("unknown stereo layout {}", layout)),
+                    });
+                }
+                "--ipd" => options.ipd = Some(parse_number(arg, &value()?)?),
+                "--lens" => options.lens = Some(value()?),
+                "--aperture" => options.aperture = Some(parse_aperture(&value()?)?),
+                "--vignetting" => options.vignetting = Some(parse_number(arg, &value()?)?),
+                "--filter" => options.filter = Some(parse_filter(&value()?)?),
+                "--splat" => options.splat = true,
+                "--crop" => options.crop = Some(parse_crop(&value()?)?),
+                "--crop-full-frame" => options.crop_full_frame = true,
+                "--aovs" => options.aovs = Some(value()?),
+                "--denoise" => options.denoise = true,
+                "--stats" => options.stats = true,
+                "--stats-heatmap" => options.heatmap = Some(value()?),
+                "--worker" => options.worker = Some(value()?),
+                "--workers" => {
+                    options.workers = value()?.split(',').map(str::to_owned).collect();
+                }
+                "--worker-timeout" => options.worker_timeout = parse_number(arg, &value()?)?,
+                _ => return Err(format!("unknown option {}", arg)),
+            }
+        }
+
+        if options.resume && options.checkpoint.is_none() {
+            return Err("--resume needs --checkpoint <file>".to_owned());
+        }
+        if options.worker_timeout == 0 {
+            return Err("--worker-timeout must be at least 1 second".to_owned());
+        }
+        if !options.workers.is_empty() && (options.interactive || options.checkpoint.is_some()) {
+            return Err(
+                "--workers cannot be combined with --interactive or --checkpoint".to_owned(),
+            );
+        }
+        if (options.lens.is_some()
+            || options.aperture.is_some()
+            || options.vignetting.is_some()
+            || options.filter.is_some()
+            || options.splat)
+            && !options.workers.is_empty()
+        {
+            return Err(
+                "--lens, --aperture, --vignetting, --filter and --splat cannot be combined with --workers"
+                    .to_owned(),
+            );
+        }
+        if options.crop_full_frame && options.crop.is_none() {
+            return Err("--crop-full-frame needs --crop".to_owned());
+        }
+        // The eye distance only matters to stereo renders, which never go to workers
+        if options.ipd.is_some() && options.stereo.is_none() {
+            return Err("--ipd needs --stereo".to_owned());
+        }
+        if (options.aovs.is_some() || options.denoise)
+            && (options.interactive || options.stereo.is_some())
+        {
+            return Err(
+                "--aovs and --denoise cannot be combined with --interactive or --stereo".to_owned(),
+            );
+        }
+        if (options.stats || options.heatmap.is_some())
+            && (options.interactive || options.stereo.is_some() || !options.workers.is_empty())
+        {
+            return Err(
+                "--stats and --stats-heatmap cannot be combined with --interactive, --stereo or --workers"
+                    .to_owned(),
+            );
+        }
+        if options.stereo.is_some()
+            && (options.interactive || options.checkpoint.is_some() || !options.workers.is_empty())
+        {
+            return Err(
+                "--stereo cannot be combined with --interactive, --checkpoint or --workers"
+                    .to_owned(),
+            );
+        }
+        Ok(options)
     }
+}
 
-    if show {
-        render_buffer(
-            buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-        );
+// Fisheyes take an optional field of view in degrees, as in fisheye:220
+fn parse_projection(value: &str) -> Result<Projection, String> {
+    let (name, fov) = match value.split_once(':') {
+        Some((name, fov)) => (name, parse_number("--projection", fov)?),
+        None => (value, 180.0),
+    };
+    match name {
+        "perspective" => Ok(Projection::Perspective),
+        "orthographic" => Ok(Projection::Orthographic),
+        "fisheye" => Ok(Projection::FisheyeEquidistant { fov }),
+        "equisolid" => Ok(Projection::FisheyeEquisolid { fov }),
+        "equirectangular" => Ok(Projection::Equirectangular),
+        _ => Err(format!("unknown projection {}", value)),
     }
 }
 
-fn final_scene_book2(width: i32, samples: i32, depth: i32, show: bool, save: bool) {
-    // Create the ground
-    let mut boxes_1 = HittableList::new();
-    let ground_mat: Arc<dyn Material + Sync + Send> =
-        Arc::new(Lambertian::from_color(Vec3::new(0.48, 0.83, 0.53)));
-
-    let boxes_per_side = 20;
-    for i in 0..boxes_per_side {
-        for j in 0..boxes_per_side {
-            let w = 100.0;
-            let x0 = -1000.0 + i as f64 * w;
-            let z0 = -1000.0 + j as f64 * w;
-            let y0 = 0.0;
-            let x1 = x0 + w;
-            let y1 = random_f64_range(1.0, 101.0);
-            let z1 = z0 + w;
-
-            boxes_1.add(quad_box(
-                Vec3::new(x0, y0, z0),
-                Vec3::new(x1, y1, z1),
-                Arc::clone(&ground_mat),
-            ));
+// circle, polygon:BLADES[:ROTATION] or image:FILE
+fn parse_aperture(value: &str) -> Result<Aperture, String> {
+    let mut parts = value.splitn(3, ':');
+    match parts.next() {
+        Some("circle") => Ok(Aperture::Circle),
+        Some("polygon") => {
+            let blades = parse_number("--aperture", parts.next().unwrap_or("6"))?;
+            let rotation = parse_number("--aperture", parts.next().unwrap_or("0"))?;
+            Ok(Aperture::Polygon { blades, rotation })
         }
+        Some("image") => {
+            let path = &value["image:".len().min(value.len())..];
+            ApertureMask::new(path)
+                .map(|mask| Aperture::Image(Arc::new(mask)))
+                .map_err(|err| format!("cannot load aperture image {}: {}", path, err))
+        }
+        _ => Err(format!("unknown aperture {}", value)),
     }
+}
 
-    // Create the scene
-    let mut scene = HittableList::new();
-    scene.add(Arc::new(BVHNode::from_list(&boxes_1)));
-
-    // Light
-    let diff_light = Arc::new(DiffuseLight::color(Vec3::new(7.0, 7.0, 7.0)));
-    let light: Arc<dyn Hittable + Sync + Send> = Arc::new(Quad::new(
-        Vec3::new(123.0, 554.0, 147.0),
-        Vec3::new(300.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, 265.0),
-        diff_light,
-    ));
-    scene.add(Arc::clone(&light));
-
-    // Spheres
-    let center_1 = Vec3::new(400.0, 400.0, 200.0);
-    let center_2 = center_1 + Vec3::new(30.0, 0.0, 0.0);
-    let sphere_mat: Arc<dyn Material + Sync + Send> =
-        Arc::new(Lambertian::from_color(Vec3::new(0.7, 0.3, 0.1)));
-    scene.add(Arc::new(Sphere::new_moving(
-        center_1, 50.0, sphere_mat, center_2,
-    )));
-
-    scene.add(Arc::new(Sphere::new(
-        Vec3::new(260.0, 150.0, 45.0),
-        50.0,
-        Arc::new(Dielectric::new(1.5)),
-    )));
-    scene.add(Arc::new(Sphere::new(
-        Vec3::new(0.0, 150.0, 145.0),
-        50.0,
-        Arc::new(Metal::new(Vec3::new(0.8, 0.8, 0.9), 1.0)),
-    )));
-
-    // Fog
-    let boundary: Arc<dyn Hittable + Sync + Send> = Arc::new(Sphere::new(
-        Vec3::new(360.0, 150.0, 145.0),
-        70.0,
-        Arc::new(Dielectric::new(1.5)),
-    ));
-    scene.add(Arc::clone(&boundary));
-    scene.add(Arc::new(ConstantMedium::color(
-        boundary,
-        0.2,
-        Vec3::new(0.2, 0.4, 0.9),
-    )));
-    let fog = Arc::new(Sphere::new(
-        Vec3::new(0.0, 0.0, 0.0),
-        5000.0,
-        Arc::new(Dielectric::new(1.5)),
-    ));
-    scene.add(Arc::new(ConstantMedium::color(
-        fog,
-        0.0001,
-        Vec3::new(1.0, 1.0, 1.0),
-    )));
-
-    // Earth
-    let earth_texture = Arc::new(ImageTexture::new("src/earthmap.jpg"));
-    let emat = Arc::new(Lambertian::new(earth_texture));
-    scene.add(Arc::new(Sphere::new(
-        Vec3::new(400.0, 200.0, 400.0),
-        100.0,
-        emat,
-    )));
-
-    // Perlin
-    let pertext = Arc::new(NoiseTexture::new(0.1));
-    let pmat = Arc::new(Lambertian::new(pertext));
-    scene.add(Arc::new(Sphere::new(
-        Vec3::new(220.0, 280.0, 300.0),
-        80.0,
-        pmat,
-    )));
-
-    // Sphere box
-    let mut spheres = HittableList::new();
-    let white: Arc<dyn Material + Sync + Send> =
-        Arc::new(Lambertian::from_color(Vec3::new(0.73, 0.73, 0.73)));
-    let ns = 1000;
-    for _ in 0..ns {
-        spheres.add(Arc::new(Sphere::new(
-            Vec3::random_range(0.0, 165.0),
-            10.0,
-            Arc::clone(&white),
-        )));
-    }
-    scene.add(Arc::new(Translate::new(
-        Arc::new(RotateY::new(Arc::new(BVHNode::from_list(&spheres)), 15.0)),
-        Vec3::new(-100.0, 270.0, 395.0),
-    )));
-
-    // Render
-    let mut cam = Camera::new();
-    cam.aspect_ratio = 1.0;
-    cam.image_width = width;
-    cam.samples_per_pixel = samples;
-    cam.max_depth = depth;
-    cam.background = Vec3::new(0.0, 0.0, 0.0);
-
-    cam.vfov = 40.0;
-    cam.look_from = Vec3::new(478.0, 278.0, -600.0);
-    cam.look_at = Vec3::new(278.0, 278.0, 0.0);
-    cam.v_up = Vec3::new(0.0, 1.0, 0.0);
-
-    cam.defocus_angle = 0.0;
-
-    let world = Arc::new(scene);
-    let buffer = cam.render(world, Some(light), true, 6);
-
-    if save {
-        save_image(
-            "Book2_Final",
-            &buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-            samples,
-            depth,
-        );
+// Fractions of the frame as X_MIN,X_MAX,Y_MIN,Y_MAX from the top left
+fn parse_crop(value: &str) -> Result<CropWindow, String> {
+    let bounds = value
+        .split(',')
+        .map(|bound| parse_number("--crop", bound))
+        .collect::<Result<Vec<f64>, _>>()?;
+    match bounds[..] {
+        [x_min, x_max, y_min, y_max]
+            if bounds.iter().all(|bound| (0.0..=1.0).contains(bound))
+                && x_min < x_max
+                && y_min < y_max =>
+        {
+            Ok(CropWindow::new(x_min, x_max, y_min, y_max))
+        }
+        _ => Err(format!(
+            "--crop expects X_MIN,X_MAX,Y_MIN,Y_MAX between 0 and 1, got {}",
+            value
+        )),
     }
+}
 
-    // Render to a window
-    if show {
-        render_buffer(
-            buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-        );
+// Filter name with an optional radius in pixels, as in mitchell:2
+fn parse_filter(value: &str) -> Result<Filter, String> {
+    let (name, radius) = match value.split_once(':') {
+        Some((name, radius)) => (name, Some(parse_number("--filter", radius)?)),
+        None => (value, None),
+    };
+    if let Some(radius) = radius.filter(|radius: &f64| !(radius.is_finite() && *radius > 0.0)) {
+        return Err(format!(
+            "--filter expects a positive radius in pixels, got {}",
+            radius
+        ));
+    }
+    match name {
+        "box" => Ok(Filter::Box {
+            radius: radius.unwrap_or(0.5),
+        }),
+        "tent" => Ok(Filter::Tent {
+            radius: radius.unwrap_or(1.0),
+        }),
+        "gaussian" => {
+            let radius = radius.unwrap_or(1.5);
+            Ok(Filter::Gaussian {
+                radius,
+                sigma: radius / 3.0,
+            })
+        }
+        "mitchell" => Ok(Filter::Mitchell {
+            radius: radius.unwrap_or(2.0),
+            b: 1.0 / 3.0,
+            c: 1.0 / 3.0,
+        }),
+        "lanczos" => Ok(Filter::Lanczos {
+            radius: radius.unwrap_or(3.0),
+        }),
+        _ => Err(format!("unknown filter {}", value)),
     }
 }
 
-fn cornell_box_metal(width: i32, samples: i32, depth: i32, show: bool, save: bool) {
-    let mut world = HittableList::new();
-
-    let red = Arc::new(Lambertian::from_color(Vec3::new(0.65, 0.05, 0.05)));
-    let white: Arc<dyn Material + Sync + Send> =
-        Arc::new(Lambertian::from_color(Vec3::new(0.73, 0.73, 0.73)));
-    let green = Arc::new(Lambertian::from_color(Vec3::new(0.12, 0.45, 0.15)));
-    let light_mat = Arc::new(DiffuseLight::color(Vec3::new(15.0, 15.0, 15.0)));
-    let metal_mat: Arc<dyn Material + Sync + Send> =
-        Arc::new(Metal::new(Vec3::new(0.8, 0.85, 0.88), 0.0));
-
-    // Scene
-    let light: Arc<dyn Hittable + Sync + Send> = Arc::new(Quad::new(
-        Vec3::new(343.0, 554.0, 332.0),
-        Vec3::new(-130.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, -105.0),
-        light_mat,
-    ));
-    world.add(Arc::clone(&light));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(555.0, 0.0, 0.0),
-        Vec3::new(0.0, 555.0, 0.0),
-        Vec3::new(0.0, 0.0, 555.0),
-        green,
-    )));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(0.0, 0.0, 0.0),
-        Vec3::new(0.0, 555.0, 0.0),
-        Vec3::new(0.0, 0.0, 555.0),
-        red,
-    )));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(0.0, 0.0, 0.0),
-        Vec3::new(555.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, 555.0),
-        Arc::clone(&white),
-    )));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(555.0, 555.0, 555.0),
-        Vec3::new(-555.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, -555.0),
-        Arc::clone(&white),
-    )));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(0.0, 0.0, 555.0),
-        Vec3::new(555.0, 0.0, 0.0),
-        Vec3::new(0.0, 555.0, 0.0),
-        Arc::clone(&white),
-    )));
-
-    // Boxes
-    let box_1 = quad_box(
-        Vec3::new(0.0, 0.0, 0.0),
-        Vec3::new(165.0, 330.0, 165.0),
-        metal_mat,
-    );
-    let box_1 = Arc::new(RotateY::new(box_1, 15.0));
-    let box_1 = Arc::new(Translate::new(box_1, Vec3::new(265.0, 0.0, 295.0)));
-    world.add(box_1);
-
-    let box_2 = quad_box(
-        Vec3::new(0.0, 0.0, 0.0),
-        Vec3::new(165.0, 165.0, 165.0),
-        Arc::clone(&white),
-    );
-    let box_2 = Arc::new(RotateY::new(box_2, -18.0));
-    let box_2 = Arc::new(Translate::new(box_2, Vec3::new(130.0, 0.0, 65.0)));
-    world.add(box_2);
+fn parse_number<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} expects a number, got {}", option, value))
+}
 
-    // Camera
-    let mut cam = Camera::new();
-    cam.aspect_ratio = 1.0;
-    cam.image_width = width;
-    cam.samples_per_pixel = samples;
-    cam.max_depth = depth;
-    cam.background = Vec3::new(0.0, 0.0, 0.0);
+const USAGE: &str =
+    "usage: ray_tracing [--scene N] [--width N] [--samples N] [--depth N] [--seed N]
+                   [--no-show] [--no-save] [--interactive] [--all]
+                   [--checkpoint FILE [--resume] [--checkpoint-every N]]
+                   [--projection perspective|orthographic|fisheye[:FOV]|equisolid[:FOV]|equirectangular]
+                   [--accelerator bvh|qbvh]
+                   [--lens FILE] [--aperture circle|polygon:N[:ROT]|image:FILE]
+                   [--vignetting N]
+                   [--filter box|tent|gaussian|mitchell|lanczos[:RADIUS] [--splat]]
+                   [--crop X_MIN,X_MAX,Y_MIN,Y_MAX [--crop-full-frame]]
+                   [--aovs FILE.exr|PREFIX] [--denoise]
+                   [--stats] [--stats-heatmap FILE]
+                   [--stereo side-by-side|over-under|separate [--ipd N]]
+                   [--workers HOST:PORT,... [--worker-timeout SECONDS]]
+       ray_tracing --worker HOST:PORT
+       ray_tracing compare REFERENCE TEST [--diff PREFIX] [--max-mse N] [--max-relmse N]
+                   [--min-psnr N] [--min-ssim N] [--max-flip N]
+       ray_tracing convergence REFERENCE [--plot FILE] [--scene N] [--width N]
+                   [--samples N] [--depth N] [--seed N]";
+
+// Most rays per pixel spent on the first hit outputs
+const AOV_SAMPLES: i32 = 16;
 
-    cam.vfov = 40.0;
-    cam.look_from = Vec3::new(278.0, 278.0, -800.0);
-    cam.look_at = Vec3::new(278.0, 278.0, 0.0);
-    cam.v_up = Vec3::new(0.0, 1.0, 0.0);
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    // Comparing renders is a subcommand of its own
+    match args.first().map(String::as_str) {
+        Some("compare") => {
+            let code = match CompareOptions::parse(&args[1..]) {
+                Ok(compare) => run_compare(&compare),
+                Err(err) => {
+                    eprintln!("{}\n{}", err, USAGE);
+                    1
+                }
+            };
+            std::process::exit(code);
+        }
+        Some("convergence") => {
+            let code = match ConvergenceOptions::parse(&args[1..]) {
+                Ok(convergence) => run_convergence(&convergence),
+                Err(err) => {
+                    eprintln!("{}\n{}", err, USAGE);
+                    1
+                }
+            };
+            std::process::exit(code);
+        }
+        _ => {}
+    }
 
-    cam.defocus_angle = 0.0;
+    let options = match Options::parse(&args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            std::process::exit(1);
+        }
+    };
+
+    // Worker mode renders tiles for coordinators until the process is killed
+    if let Some(address) = &options.worker {
+        let listener = match TcpListener::bind(address) {
+            Ok(listener) => listener,
+            Err(err) => {
+                eprintln!("cannot listen on {}: {}", address, err);
+                std::process::exit(1);
+            }
+        };
+        println!("Worker listening on {}", address);
+        if let Err(err) = serve_worker(listener, available_threads(), build_job) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
 
-    let world = Arc::new(world);
-    let buffer = cam.render(world, Some(light), true, 7);
+    // Unknown scene numbers fall back to the final scene of book 2
+    let job = |index| RenderJob {
+        scene: if (1..=SCENE_COUNT).contains(&index) {
+            index
+        } else {
+            9
+        },
+        seed: options.seed,
+        width: options.width,
+        samples: options.samples,
+        depth: options.depth,
+        projection: options.projection,
+        accelerator: options.accelerator,
+    };
+
+    if options.all {
+        let options = Options {
+            show: false,
+            checkpoint: None,
+            ..options
+        };
+        for index in 1..=SCENE_COUNT {
+            let job = job(index);
+            run_scene(build_job(&job).unwrap(), &job, &options);
+        }
+    } else {
+        let job = job(options.scene);
+        run_scene(build_job(&job).unwrap(), &job, &options);
+    }
+}
 
-    if save {
-        save_image(
-            "Cornell_Box_Metal",
-            &buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exr::prelude::{
+        read_all_flat_layers_from_file, AnyChannel, AnyChannels, FlatSamples, Image, SmallVec,
+        WritableImage,
+    };
+    use ray_tracing::tile_scheduler::Tile;
+    use ray_tracing::vector_3::Vec3;
+    use std::path::PathBuf;
+
+    // Every built-in scene is rendered small and with few samples, then compared with a
+    // converged reference in tests/golden. Set UPDATE_GOLDEN=1 to render the references.
+    // Changes smaller than the references' own noise, around 0.1% of a scene, pass; those
+    // are left to the furnace and chi-square tests of the materials.
+    const GOLDEN_WIDTH: i32 = 48;
+    const GOLDEN_SAMPLES: i32 = 16;
+    const GOLDEN_DEPTH: i32 = 8;
+    const REFERENCE_SAMPLES: i32 = 1024;
+    // Pixels are compared in blocks, which evens out the noise of single pixels
+    const BLOCK: i32 = 4;
+    // A block fails when its mean is this many standard errors from the reference
+    const MAX_BLOCK_SCORE: f64 = 5.0;
+    const MAX_FAILED_BLOCKS: f64 = 0.02;
+    // A small bias over the whole image can hide in the noise of every block
+    const MAX_IMAGE_SCORE: f64 = 5.0;
+
+    fn golden_dir() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+    }
+
+    // The scene is always built from seed 0, the camera samples from `seed`
+    fn render_golden(index: i32, samples: i32, seed: u64) -> (&'static str, Film) {
+        let job = RenderJob {
+            scene: index,
+            seed: 0,
+            width: GOLDEN_WIDTH,
+            samples,
+            depth: GOLDEN_DEPTH,
+            projection: None,
+            accelerator: Accelerator::BVHNode,
+        };
+        let Scene {
+            name,
+            mut cam,
+            world,
+            lights,
+        } = build_job(&job).unwrap();
+        cam.seed = seed;
+        cam.initalize();
+        let mut film = Film::new(cam.render_width(), cam.render_height());
+        cam.render_film(
+            world,
+            lights,
+            available_threads(),
+            &mut film,
             samples,
-            depth,
+            |_| {},
+            &CancelToken::new(),
         );
+        (name, film)
     }
 
-    if show {
-        render_buffer(
-            buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-        );
+    // Radiance and its variance, as RGB channels and VR, VG, VB
+    fn write_reference(path: &Path, film: &Film) {
+        let frame = film.pixels_in(&Tile::new(0, 0, film.width(), film.height()));
+        let variance = film.variance_in(&Tile::new(0, 0, film.width(), film.height()));
+        let channel = |name: &str, values: &[Vec3], axis: u32| {
+            AnyChannel::new(
+                name,
+                FlatSamples::F32(values.iter().map(|value| value.at(axis) as f32).collect()),
+            )
+        };
+        let channels = SmallVec::from_vec(vec![
+            channel("R", &frame, 0),
+            channel("G", &frame, 1),
+            channel("B", &frame, 2),
+            channel("VR", &variance, 0),
+            channel("VG", &variance, 1),
+            channel("VB", &variance, 2),
+        ]);
+        Image::from_channels(
+            (film.width() as usize, film.height() as usize),
+            AnyChannels::sort(channels),
+        )
+        .write()
+        .to_file(path)
+        .unwrap();
     }
-}
 
-fn cornell_box_glass_sphere(width: i32, samples: i32, depth: i32, show: bool, save: bool) {
-    let mut world = HittableList::new();
-
-    let red = Arc::new(Lambertian::from_color(Vec3::new(0.65, 0.05, 0.05)));
-    let white: Arc<dyn Material + Sync + Send> =
-        Arc::new(Lambertian::from_color(Vec3::new(0.73, 0.73, 0.73)));
-    let green = Arc::new(Lambertian::from_color(Vec3::new(0.12, 0.45, 0.15)));
-    let light_mat = Arc::new(DiffuseLight::color(Vec3::new(15.0, 15.0, 15.0)));
-    let glass = Arc::new(Dielectric::new(1.5));
-
-    // Scene
-    let light: Arc<dyn Hittable + Sync + Send> = Arc::new(Quad::new(
-        Vec3::new(343.0, 554.0, 332.0),
-        Vec3::new(-130.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, -105.0),
-        light_mat,
-    ));
-    world.add(Arc::clone(&light));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(555.0, 0.0, 0.0),
-        Vec3::new(0.0, 555.0, 0.0),
-        Vec3::new(0.0, 0.0, 555.0),
-        green,
-    )));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(0.0, 0.0, 0.0),
-        Vec3::new(0.0, 555.0, 0.0),
-        Vec3::new(0.0, 0.0, 555.0),
-        red,
-    )));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(0.0, 0.0, 0.0),
-        Vec3::new(555.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, 555.0),
-        Arc::clone(&white),
-    )));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(555.0, 555.0, 555.0),
-        Vec3::new(-555.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, -555.0),
-        Arc::clone(&white),
-    )));
-    world.add(Arc::new(Quad::new(
-        Vec3::new(0.0, 0.0, 555.0),
-        Vec3::new(555.0, 0.0, 0.0),
-        Vec3::new(0.0, 555.0, 0.0),
-        Arc::clone(&white),
-    )));
-
-    // Boxes
-    let box_1 = quad_box(
-        Vec3::new(0.0, 0.0, 0.0),
-        Vec3::new(165.0, 330.0, 165.0),
-        Arc::clone(&white),
-    );
-    let box_1 = Arc::new(RotateY::new(box_1, 15.0));
-    let box_1 = Arc::new(Translate::new(box_1, Vec3::new(265.0, 0.0, 295.0)));
-    world.add(box_1);
+    struct Reference {
+        width: usize,
+        height: usize,
+        radiance: Vec<Vec3>,
+        variance: Vec<Vec3>,
+    }
 
-    let sphere = Arc::new(Sphere::new(Vec3::new(190.0, 90.0, 190.0), 90.0, glass));
-    world.add(sphere);
+    fn read_reference(path: &Path) -> Option<Reference> {
+        let image = read_all_flat_layers_from_file(path).ok()?;
+        let layer = image.layer_data.first()?;
+        let channel = |name: &str| -> Option<Vec<f64>> {
+            let channel = layer
+                .channel_data
+                .list
+                .iter()
+                .find(|channel| channel.name.eq(name))?;
+            match &channel.sample_data {
+                FlatSamples::F32(values) => Some(values.iter().map(|&v| v as f64).collect()),
+                _ => None,
+            }
+        };
+        let vectors = |names: [&str; 3]| -> Option<Vec<Vec3>> {
+            let (x, y, z) = (channel(names[0])?, channel(names[1])?, channel(names[2])?);
+            Some((0..x.len()).map(|i| Vec3::new(x[i], y[i], z[i])).collect())
+        };
+        Some(Reference {
+            width: layer.size.0,
+            height: layer.size.1,
+            radiance: vectors(["R", "G", "B"])?,
+            variance: vectors(["VR", "VG", "VB"])?,
+        })
+    }
 
-    // Camera
-    let mut cam = Camera::new();
-    cam.aspect_ratio = 1.0;
-    cam.image_width = width;
-    cam.samples_per_pixel = samples;
-    cam.max_depth = depth;
-    cam.background = Vec3::new(0.0, 0.0, 0.0);
+    // How many standard errors the mean of a region is from the reference, in the channel
+    // where it is furthest. Paths that are found rarely can be missing from a render with
+    // few samples, so its noise is judged by the reference's variance as well.
+    fn region_score(film: &Film, reference: &Reference, region: &Tile) -> f64 {
+        let samples_ratio = REFERENCE_SAMPLES as f64 / GOLDEN_SAMPLES as f64;
+        let count = region.pixel_count() as f64;
+        let mut difference = Vec3::empty();
+        let mut variance = Vec3::empty();
+        let mut level = Vec3::empty();
+        for (i, j) in region.pixels() {
+            let index = (j * film.width() + i) as usize;
+            let test_variance = film.variance(i, j);
+            let reference_variance = reference.variance[index];
+            difference += film.pixel(i, j) - reference.radiance[index];
+            level += reference.radiance[index];
+            for axis in 0..3 {
+                let spread = test_variance
+                    .at(axis)
+                    .max(reference_variance.at(axis) * samples_ratio)
+                    + reference_variance.at(axis);
+                variance.set(axis, variance.at(axis) + spread);
+            }
+        }
+        (0..3)
+            .map(|axis| {
+                // Noise free pixels, like the background, may still differ by rounding
+                let floor = 1e-3 * (1.0 + level.at(axis) / count);
+                let error = variance.at(axis) / (count * count) + floor * floor;
+                (difference.at(axis) / count).abs() / error.sqrt()
+            })
+            .fold(0.0, f64::max)
+    }
 
-    cam.vfov = 40.0;
-    cam.look_from = Vec3::new(278.0, 278.0, -800.0);
-    cam.look_at = Vec3::new(278.0, 278.0, 0.0);
-    cam.v_up = Vec3::new(0.0, 1.0, 0.0);
+    fn block_scores(film: &Film, reference: &Reference) -> Vec<f64> {
+        let mut scores = Vec::new();
+        for block_y in (0..film.height()).step_by(BLOCK as usize) {
+            for block_x in (0..film.width()).step_by(BLOCK as usize) {
+                let block = Tile::new(
+                    block_x,
+                    block_y,
+                    BLOCK.min(film.width() - block_x),
+                    BLOCK.min(film.height() - block_y),
+                );
+                scores.push(region_score(film, reference, &block));
+            }
+        }
+        scores
+    }
 
-    cam.defocus_angle = 0.0;
+    // Saves the render, the reference and the block scores next to each other
+    fn write_failure(name: &str, film: &Film, reference: &Reference, scores: &[f64]) -> PathBuf {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("target/golden");
+        std::fs::create_dir_all(&dir).unwrap();
+        let (width, height) = (film.width() as u32, film.height() as u32);
+        let to_image = |pixel: &dyn Fn(u32, u32) -> Vec3| {
+            RgbImage::from_fn(width, height, |x, y| {
+                let value = vec_to_val(&pixel(x, y), 1);
+                image::Rgb([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+            })
+        };
+        to_image(&|x, y| film.pixel(x as i32, y as i32))
+            .save(dir.join(format!("{}_render.png", name)))
+            .unwrap();
+        to_image(&|x, y| reference.radiance[(y * width + x) as usize])
+            .save(dir.join(format!("{}_reference.png", name)))
+            .unwrap();
+
+        let blocks_x = (film.width() + BLOCK - 1) / BLOCK;
+        let map: Vec<f64> = (0..height as i32)
+            .flat_map(|j| (0..width as i32).map(move |i| (i, j)))
+            .map(|(i, j)| {
+                scores[((j / BLOCK) * blocks_x + i / BLOCK) as usize] / (2.0 * MAX_BLOCK_SCORE)
+            })
+            .collect();
+        let diff = dir.join(format!("{}_diff.png", name));
+        false_color(&map, width, height).save(&diff).unwrap();
+        diff
+    }
 
-    let world = Arc::new(world);
-    let buffer = cam.render(world, Some(light), true, 7);
+    #[test]
+    fn filter_radii_must_be_positive() {
+        assert_eq!(parse_filter("mitchell:1.5").unwrap().radius(), 1.5);
+        assert_eq!(parse_filter("lanczos").unwrap().radius(), 3.0);
+        for value in [
+            "box:0",
+            "tent:-1",
+            "gaussian:NaN",
+            "mitchell:inf",
+            "lanczos:x",
+        ] {
+            assert!(parse_filter(value).is_err(), "{} was accepted", value);
+        }
+    }
 
-    if save {
-        save_image(
-            "Cornell_Box_Glass_Sphere",
-            &buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-            samples,
-            depth,
-        );
+    #[test]
+    fn ipd_needs_stereo() {
+        let parse = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+            Options::parse(&args)
+        };
+        let options = parse(&["--stereo", "side-by-side", "--ipd", "6"]).unwrap();
+        assert_eq!(options.ipd, Some(6.0));
+        assert!(parse(&["--ipd", "6"]).is_err());
+        assert!(parse(&["--ipd", "6", "--workers", "localhost:7878"]).is_err());
     }
 
-    if show {
-        render_buffer(
-            buffer,
-            cam.render_width() as u32,
-            cam.render_height() as u32,
-        );
+    #[test]
+    fn nan_metrics_break_their_tolerances() {
+        let args: Vec<String> = ["a.png", "b.png", "--max-mse", "0.01", "--min-ssim", "0.9"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        let options = CompareOptions::parse(&args).unwrap();
+        let metrics = Metrics {
+            mse: 0.001,
+            rel_mse: f64::NAN,
+            psnr: 30.0,
+            ssim: 0.95,
+            flip: 0.1,
+        };
+        // relMSE and FLIP have no tolerance set
+        assert!(options.failures(&metrics).is_empty());
+        let broken = Metrics {
+            mse: f64::NAN,
+            ssim: f64::NAN,
+            ..metrics
+        };
+        assert_eq!(options.failures(&broken).len(), 2);
     }
-}
 
-fn save_image(name: &str, buffer: &Vec<u32>, width: u32, height: u32, samples: i32, depth: i32) {
-    // Load the buffer into an image
-    let image = RgbImage::from_fn(width, height, |x, y| {
-        let index = x + (y * width);
-        let value = buffer[index as usize];
-        let red = ((value >> 16) & 0x000000FF) as u8;
-        let green = ((value >> 8) & 0x000000FF) as u8;
-        let blue = (value & 0x000000FF) as u8;
-        image::Rgb([red, green, blue])
-    });
+    #[test]
+    fn convergence_ends_at_the_requested_samples() {
+        assert_eq!(convergence_steps(1), vec![1]);
+        assert_eq!(convergence_steps(8), vec![1, 2, 4, 8]);
+        assert_eq!(convergence_steps(100), vec![1, 2, 4, 8, 16, 32, 64, 100]);
+        assert_eq!(convergence_steps(0), vec![1]);
+    }
 
-    let mut full_path = "renders/".to_owned();
-    let path_end = ".png";
+    // Book 1 draws all its spheres before it builds the hierarchy, so both hierarchies hold
+    // the same spheres and find the same closest hits
+    #[test]
+    fn accelerators_render_book1_alike() {
+        let render = |accelerator| {
+            let job = RenderJob {
+                scene: 1,
+                seed: 0,
+                width: 32,
+                samples: 2,
+                depth: 8,
+                projection: None,
+                accelerator,
+            };
+            let Scene {
+                mut cam,
+                world,
+                lights,
+                ..
+            } = build_job(&job).unwrap();
+            cam.initalize();
+            let mut film = Film::new(cam.render_width(), cam.render_height());
+            cam.render_film(
+                world,
+                lights,
+                available_threads(),
+                &mut film,
+                job.samples,
+                |_| {},
+                &CancelToken::new(),
+            );
+            film
+        };
+        assert_eq!(
+            render(Accelerator::BVHNode).to_buffer(),
+            render(Accelerator::QBVH).to_buffer()
+        );
+    }
 
-    // Add the file name
-    full_path.push_str(name);
+    #[test]
+    fn builtin_scenes_match_golden_images() {
+        let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+        let mut failures = Vec::new();
+        for index in 1..=SCENE_COUNT {
+            if update {
+                let (name, film) = render_golden(index, REFERENCE_SAMPLES, 0x5eed);
+                std::fs::create_dir_all(golden_dir()).unwrap();
+                write_reference(&golden_dir().join(format!("{}.exr", name)), &film);
+                continue;
+            }
 
-    // width, samples, depth
-    full_path.push_str("_w");
-    full_path.push_str(width.to_string().as_str());
-    full_path.push_str("_s");
-    full_path.push_str(samples.to_string().as_str());
-    full_path.push_str("_d");
-    full_path.push_str(depth.to_string().as_str());
+            let (name, film) = render_golden(index, GOLDEN_SAMPLES, 0);
+            let path = golden_dir().join(format!("{}.exr", name));
+            let reference = match read_reference(&path) {
+                Some(reference)
+                    if reference.width == film.width() as usize
+                        && reference.height == film.height() as usize =>
+                {
+                    reference
+                }
+                _ => {
+                    failures.push(format!(
+                        "{}: no reference at {}, render them with UPDATE_GOLDEN=1",
+                        name,
+                        path.display()
+                    ));
+                    continue;
+                }
+            };
 
-    full_path.push_str(path_end);
-    println!("Full Path: {}", &full_path);
-    let path = Path::new(&full_path);
-    let _ = image.save(path);
-}
+            let scores = block_scores(&film, &reference);
+            let failed = scores
+                .iter()
+                .filter(|&&score| score > MAX_BLOCK_SCORE)
+                .count();
+            if failed as f64 > MAX_FAILED_BLOCKS * scores.len() as f64 {
+                let diff = write_failure(name, &film, &reference, &scores);
+                failures.push(format!(
+                    "{}: {} of {} blocks differ from the reference, see {}",
+                    name,
+                    failed,
+                    scores.len(),
+                    diff.display()
+                ));
+            }
 
-fn main() {
-    let run_single = true;
-    if run_single {
-        let width = 800;
-        let samples = 1000;
-        let depth = 50;
-        let show = true;
-        let save = true;
-        let input = 9;
-        match input {
-            1 => final_render_book1(width, samples, depth, show, save),
-            2 => checker_spheres(width, samples, depth, show, save),
-            3 => earth(width, samples, depth, show, save),
-            4 => two_perlin_sphere(width, samples, depth, show, save),
-            5 => quads(width, samples, depth, show, save),
-            6 => simple_light(width, samples, depth, show, save),
-            7 => cornell_box(width, samples, depth, show, save),
-            8 => cornell_smoke(width, samples, depth, show, save),
-            9 => final_scene_book2(width, samples, depth, show, save),
-            10 => cornell_box_metal(width, samples, depth, show, save),
-            11 => cornell_box_glass_sphere(width, samples, depth, show, save),
-            _ => final_scene_book2(width, samples, depth, show, save),
-        };
-    } else {
-        let width = 400;
-        let samples = 10;
-        let depth = 10;
-        let show = false;
-        let save = true;
-        for index in 1..11 {
-            match index {
-                1 => final_render_book1(width, samples, depth, show, save),
-                2 => checker_spheres(width, samples, depth, show, save),
-                3 => earth(width, samples, depth, show, save),
-                4 => two_perlin_sphere(width, samples, depth, show, save),
-                5 => quads(width, samples, depth, show, save),
-                6 => simple_light(width, samples, depth, show, save),
-                7 => cornell_box(width, samples, depth, show, save),
-                8 => cornell_smoke(width, samples, depth, show, save),
-                9 => final_scene_book2(width, samples, depth, show, save),
-                10 => cornell_box_metal(width, samples, depth, show, save),
-                11 => cornell_box_glass_sphere(width, samples, depth, show, save),
-                _ => println!("Bad index in full loop: {}", index),
-            };
+            let whole = Tile::new(0, 0, film.width(), film.height());
+            let score = region_score(&film, &reference, &whole);
+            if score > MAX_IMAGE_SCORE {
+                failures.push(format!(
+                    "{}: the image is {:.1} standard errors brighter or darker than the reference",
+                    name, score
+                ));
+            }
         }
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
     }
 }
@@ -2,6 +2,7 @@ use crate::hittable::HitRecord;
 use crate::pdf::PDF;
 use crate::ray::Ray3;
 use crate::vector_3::Vec3;
+use std::fmt::Debug;
 
 pub enum ScatterPDF {
     PDF(Box<dyn PDF>),
@@ -12,7 +13,7 @@ pub struct ScatterRecord {
     pub pdf: ScatterPDF,
 }
 
-pub trait Material {
+pub trait Material: Debug {
     fn scatter(
         &self,
         _ray_in: &Ray3,
@@ -36,6 +37,17 @@ pub trait Material {
     ) -> Vec3 {
         Vec3::new(0.0, 0.0, 0.0)
     }
+
+    // Reflectance at the hit before any lighting, for the albedo output
+    fn albedo(&self, _hit_record: &HitRecord) -> Vec3 {
+        Vec3::new(0.0, 0.0, 0.0)
+    }
+}
+
+impl Default for ScatterRecord {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ScatterRecord {
@@ -45,3 +57,170 @@ impl ScatterRecord {
         ScatterRecord { attenuation, pdf }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Material;
+    use crate::camera::Camera;
+    use crate::constant_medium::ConstantMedium;
+    use crate::dielectric::Dielectric;
+    use crate::film::Film;
+    use crate::hittable::{HitRecord, Hittable};
+    use crate::isotropic::Isotropic;
+    use crate::lambertian::Lambertian;
+    use crate::metal::Metal;
+    use crate::ray::Ray3;
+    use crate::render_progress::CancelToken;
+    use crate::rtweekend::with_seed;
+    use crate::sphere::Sphere;
+    use crate::vector_3::Vec3;
+    use std::sync::Arc;
+
+    // Relative to the expected radiance. Every path returns the albedo exactly when the
+    // material is right, so there is no noise to allow for.
+    const FURNACE_TOLERANCE: f64 = 1e-3;
+
+    // Mean radiance of a unit sphere that fills the frame, lit by a white environment.
+    // A sphere can't see itself, so whatever leaves it has been scattered once off the
+    // outside, and a material that neither gains nor loses energy returns its albedo.
+    fn furnace(object: Arc<dyn Hittable + Sync + Send>) -> Vec3 {
+        let mut cam = Camera::new();
+        cam.image_width = 8;
+        cam.aspect_ratio = 1.0;
+        cam.samples_per_pixel = 256;
+        // Deep enough that no path is cut short
+        cam.max_depth = 1000;
+        cam.vfov = 20.0;
+        cam.look_from = Vec3::new(0.0, 0.0, 3.0);
+        cam.look_at = Vec3::empty();
+        cam.background = Vec3::new(1.0, 1.0, 1.0);
+        cam.initalize();
+
+        let mut film = Film::new(cam.render_width(), cam.render_height());
+        cam.render_film(object, None, 1, &mut film, 256, |_| {}, &CancelToken::new());
+        let pixels = film.pixels_in(&cam.full_frame());
+        pixels.iter().fold(Vec3::empty(), |sum, &pixel| sum + pixel) / pixels.len() as f64
+    }
+
+    fn check_furnace(name: &str, object: Arc<dyn Hittable + Sync + Send>, expected: Vec3) {
+        let radiance = furnace(object);
+        for axis in 0..3 {
+            assert!(
+                (radiance.at(axis) - expected.at(axis)).abs()
+                    <= FURNACE_TOLERANCE * expected.at(axis),
+                "{}: a white furnace gives {:?}, expected {:?}",
+                name,
+                radiance,
+                expected
+            );
+        }
+    }
+
+    fn check_surface(name: &str, material: Arc<dyn Material + Sync + Send>) {
+        let expected = material.albedo(&HitRecord::new());
+        let sphere = Arc::new(Sphere::new(Vec3::empty(), 1.0, material));
+        check_furnace(name, sphere, expected);
+    }
+
+    #[test]
+    fn lambertian_furnace() {
+        check_surface(
+            "lambertian",
+            Arc::new(Lambertian::from_color(Vec3::new(0.9, 0.5, 0.2))),
+        );
+    }
+
+    #[test]
+    fn metal_furnace() {
+        for fuzz in [0.0, 0.3, 1.0] {
+            check_surface(
+                &format!("metal with fuzz {}", fuzz),
+                Arc::new(Metal::new(Vec3::new(0.9, 0.5, 0.2), fuzz)),
+            );
+        }
+    }
+
+    #[test]
+    fn dielectric_furnace() {
+        for index in [1.0 / 1.33, 1.5, 2.4] {
+            check_surface(
+                &format!("dielectric with index {}", index),
+                Arc::new(Dielectric::new(index)),
+            );
+        }
+    }
+
+    // A white medium only moves light around, so all of it comes back out
+    #[test]
+    fn isotropic_furnace() {
+        let boundary = Arc::new(Sphere::new(
+            Vec3::empty(),
+            1.0,
+            Arc::new(Dielectric::new(1.0)),
+        ));
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        let medium = Arc::new(ConstantMedium::color(boundary, 2.0, white));
+        check_furnace("isotropic", medium, white);
+    }
+
+    // The BSDF, or phase function for volumes, with light arriving from `from` and
+    // leaving towards `to`. Surface scattering_pdf includes the cosine of the direction
+    // the light leaves in, which has to come out before the two ways round compare.
+    fn scattering(
+        material: &dyn Material,
+        hit_record: &HitRecord,
+        from: &Vec3,
+        to: &Vec3,
+        surface: bool,
+    ) -> f64 {
+        let ray_in = Ray3::new(hit_record.point + *from, -*from, 0.0);
+        let scattered = Ray3::new(hit_record.point, *to, 0.0);
+        let pdf = material.scattering_pdf(&ray_in, hit_record, &scattered);
+        if surface {
+            pdf / hit_record.normal.dot(&to.unit_vector())
+        } else {
+            pdf
+        }
+    }
+
+    #[test]
+    fn scattering_pdf_is_reciprocal() {
+        let materials: Vec<(&str, Arc<dyn Material + Sync + Send>, bool)> = vec![
+            (
+                "lambertian",
+                Arc::new(Lambertian::from_color(Vec3::new(0.5, 0.5, 0.5))),
+                true,
+            ),
+            (
+                "isotropic",
+                Arc::new(Isotropic::color(Vec3::new(0.5, 0.5, 0.5))),
+                false,
+            ),
+        ];
+
+        let mut hit_record = HitRecord::new();
+        hit_record.point = Vec3::new(0.2, -1.0, 0.5);
+        hit_record.normal = Vec3::new(-0.4, 0.2, 0.9).unit_vector();
+        hit_record.front_face = true;
+
+        with_seed(3, || {
+            for (name, material, surface) in &materials {
+                for _ in 0..1000 {
+                    let a = Vec3::random_on_hemisphere(&hit_record.normal);
+                    let b = Vec3::random_on_hemisphere(&hit_record.normal);
+                    let forward = scattering(material.as_ref(), &hit_record, &a, &b, *surface);
+                    let backward = scattering(material.as_ref(), &hit_record, &b, &a, *surface);
+                    assert!(
+                        (forward - backward).abs() <= 1e-9 * forward.abs().max(1.0),
+                        "{}: {} from {:?} to {:?} but {} the other way",
+                        name,
+                        forward,
+                        a,
+                        b,
+                        backward
+                    );
+                }
+            }
+        });
+    }
+}
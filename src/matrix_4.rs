@@ -0,0 +1,303 @@
+use core::ops;
+
+use crate::rtweekend::degree_to_radians;
+use crate::vector_3::Vec3;
+
+// Row-major 4x4 matrix for affine transforms of column vectors. Composing with `*` applies
+// the right hand matrix first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Matrix4 {
+    pub m: [[f64; 4]; 4],
+}
+
+impl Default for Matrix4 {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Matrix4 {
+    pub fn new(m: [[f64; 4]; 4]) -> Matrix4 {
+        Matrix4 { m }
+    }
+
+    pub fn identity() -> Matrix4 {
+        Matrix4::new([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn translation(offset: Vec3) -> Matrix4 {
+        Matrix4::new([
+            [1.0, 0.0, 0.0, offset.x],
+            [0.0, 1.0, 0.0, offset.y],
+            [0.0, 0.0, 1.0, offset.z],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn scale(factors: Vec3) -> Matrix4 {
+        Matrix4::new([
+            [factors.x, 0.0, 0.0, 0.0],
+            [0.0, factors.y, 0.0, 0.0],
+            [0.0, 0.0, factors.z, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // Counter-clockwise by degrees when looking down the axis towards the origin
+    pub fn rotation(axis: Vec3, degrees: f64) -> Matrix4 {
+        let a = axis.unit_vector();
+        let radians = degree_to_radians(degrees);
+        let (sin, cos) = radians.sin_cos();
+        let k = 1.0 - cos;
+        Matrix4::new([
+            [
+                a.x * a.x * k + cos,
+                a.x * a.y * k - a.z * sin,
+                a.x * a.z * k + a.y * sin,
+                0.0,
+            ],
+            [
+                a.y * a.x * k + a.z * sin,
+                a.y * a.y * k + cos,
+                a.y * a.z * k - a.x * sin,
+                0.0,
+            ],
+            [
+                a.z * a.x * k - a.y * sin,
+                a.z * a.y * k + a.x * sin,
+                a.z * a.z * k + cos,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn rotation_x(degrees: f64) -> Matrix4 {
+        Matrix4::rotation(Vec3::new(1.0, 0.0, 0.0), degrees)
+    }
+
+    pub fn rotation_y(degrees: f64) -> Matrix4 {
+        Matrix4::rotation(Vec3::new(0.0, 1.0, 0.0), degrees)
+    }
+
+    pub fn rotation_z(degrees: f64) -> Matrix4 {
+        Matrix4::rotation(Vec3::new(0.0, 0.0, 1.0), degrees)
+    }
+
+    // Each coordinate gains the others times the factors, e.g. x' = x + xy * y + xz * z
+    pub fn shear(xy: f64, xz: f64, yx: f64, yz: f64, zx: f64, zy: f64) -> Matrix4 {
+        Matrix4::new([
+            [1.0, xy, xz, 0.0],
+            [yx, 1.0, yz, 0.0],
+            [zx, zy, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn transpose(&self) -> Matrix4 {
+        let mut m = [[0.0; 4]; 4];
+        for (row, values) in m.iter_mut().enumerate() {
+            for (column, value) in values.iter_mut().enumerate() {
+                *value = self.m[column][row];
+            }
+        }
+        Matrix4::new(m)
+    }
+
+    // Gauss-Jordan elimination with partial pivoting, None when the matrix is singular
+    pub fn inverse(&self) -> Option<Matrix4> {
+        let mut a = self.m;
+        let mut inverse = Matrix4::identity().m;
+
+        for column in 0..4 {
+            let pivot = (column..4)
+                .max_by(|&x, &y| a[x][column].abs().total_cmp(&a[y][column].abs()))
+                .unwrap_or(column);
+            if a[pivot][column].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(column, pivot);
+            inverse.swap(column, pivot);
+
+            let scale = 1.0 / a[column][column];
+            for k in 0..4 {
+                a[column][k] *= scale;
+                inverse[column][k] *= scale;
+            }
+
+            for row in 0..4 {
+                if row == column {
+                    continue;
+                }
+                let factor = a[row][column];
+                for k in 0..4 {
+                    a[row][k] -= factor * a[column][k];
+                    inverse[row][k] -= factor * inverse[column][k];
+                }
+            }
+        }
+        Some(Matrix4::new(inverse))
+    }
+
+    // Determinant of the upper 3x3, i.e. how much the transform scales volumes
+    pub fn linear_determinant(&self) -> f64 {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    pub fn transform_point(&self, point: &Vec3) -> Vec3 {
+        self.transform_vector(point) + Vec3::new(self.m[0][3], self.m[1][3], self.m[2][3])
+    }
+
+    // Directions ignore the translation
+    pub fn transform_vector(&self, vec: &Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * vec.x + m[0][1] * vec.y + m[0][2] * vec.z,
+            m[1][0] * vec.x + m[1][1] * vec.y + m[1][2] * vec.z,
+            m[2][0] * vec.x + m[2][1] * vec.y + m[2][2] * vec.z,
+        )
+    }
+}
+
+impl ops::Mul<Self> for Matrix4 {
+    type Output = Matrix4;
+
+    fn mul(self, rhs: Self) -> Matrix4 {
+        let mut m = [[0.0; 4]; 4];
+        for (row, values) in m.iter_mut().enumerate() {
+            for (column, value) in values.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[row][k] * rhs.m[k][column]).sum();
+            }
+        }
+        Matrix4::new(m)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rtweekend::{random_f64_range, with_seed};
+
+    fn random_matrix() -> Matrix4 {
+        Matrix4::translation(Vec3::random_range(-100.0, 100.0))
+            * Matrix4::rotation(
+                Vec3::random_range(-1.0, 1.0),
+                random_f64_range(-180.0, 180.0),
+            )
+            * Matrix4::shear(
+                random_f64_range(-0.5, 0.5),
+                random_f64_range(-0.5, 0.5),
+                0.0,
+                random_f64_range(-0.5, 0.5),
+                0.0,
+                0.0,
+            )
+            * Matrix4::scale(Vec3::random_range(0.1, 10.0))
+    }
+
+    fn assert_near(a: Vec3, b: Vec3, tolerance: f64) {
+        assert!((a - b).length() <= tolerance, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn inverse_gives_the_identity() {
+        with_seed(1, || {
+            for _ in 0..200 {
+                let matrix = random_matrix();
+                let inverse = matrix.inverse().unwrap();
+                for product in [matrix * inverse, inverse * matrix] {
+                    for row in 0..4 {
+                        for column in 0..4 {
+                            let expected = if row == column { 1.0 } else { 0.0 };
+                            assert!((product.m[row][column] - expected).abs() < 1e-9);
+                        }
+                    }
+                }
+            }
+        });
+        let flat = Matrix4::scale(Vec3::new(1.0, 0.0, 1.0));
+        assert!(flat.inverse().is_none());
+    }
+
+    #[test]
+    fn points_vectors_and_normals_round_trip() {
+        with_seed(2, || {
+            for _ in 0..200 {
+                let matrix = random_matrix();
+                let inverse = matrix.inverse().unwrap();
+                let point = Vec3::random_range(-10.0, 10.0);
+                let vector = Vec3::random_range(-10.0, 10.0);
+                assert_near(
+                    inverse.transform_point(&matrix.transform_point(&point)),
+                    point,
+                    1e-9,
+                );
+                assert_near(
+                    inverse.transform_vector(&matrix.transform_vector(&vector)),
+                    vector,
+                    1e-9,
+                );
+                // Vectors ignore the translation, points don't
+                let difference =
+                    matrix.transform_point(&(point + vector)) - matrix.transform_point(&point);
+                assert_near(difference, matrix.transform_vector(&vector), 1e-9);
+
+                // A normal through the inverse transpose stays perpendicular to the
+                // transformed surface and comes back unchanged
+                let normal = Vec3::random_unit_vector();
+                let tangent = normal.cross(&Vec3::random_unit_vector());
+                let normal_to_world = inverse.transpose();
+                let world_normal = normal_to_world.transform_vector(&normal);
+                let world_tangent = matrix.transform_vector(&tangent);
+                assert!(
+                    world_normal
+                        .unit_vector()
+                        .dot(&world_tangent.unit_vector())
+                        .abs()
+                        < 1e-9
+                );
+                assert_near(
+                    matrix.transpose().transform_vector(&world_normal),
+                    normal,
+                    1e-9,
+                );
+            }
+        })
+    }
+
+    #[test]
+    fn rotation_y_matches_rotate_y() {
+        // RotateY turns x towards -z: x' = cos x + sin z, z' = -sin x + cos z
+        for degrees in [0.0, 15.0, -18.0, 90.0, 137.0] {
+            let (sin, cos) = degree_to_radians(degrees).sin_cos();
+            let point = Vec3::new(1.5, -2.0, 0.25);
+            let expected = Vec3::new(
+                cos * point.x + sin * point.z,
+                point.y,
+                -sin * point.x + cos * point.z,
+            );
+            assert_near(
+                Matrix4::rotation_y(degrees).transform_point(&point),
+                expected,
+                1e-12,
+            );
+        }
+    }
+
+    #[test]
+    fn products_apply_the_right_hand_matrix_first() {
+        let point = Vec3::new(1.0, 2.0, 3.0);
+        let moved = (Matrix4::translation(Vec3::new(10.0, 0.0, 0.0))
+            * Matrix4::scale(Vec3::new(2.0, 2.0, 2.0)))
+        .transform_point(&point);
+        assert_near(moved, Vec3::new(12.0, 4.0, 6.0), 0.0);
+    }
+}
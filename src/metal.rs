@@ -11,13 +11,23 @@ pub struct Metal {
 
 impl Material for Metal {
     fn scatter(&self, ray_in: &Ray3, hit_rec: &HitRecord, scatter_rec: &mut ScatterRecord) -> bool {
-        let reflected = ray_in.direction().reflect(&hit_rec.normal).unit_vector()
+        let mut reflected = ray_in.direction().reflect(&hit_rec.normal).unit_vector()
             + (self.fuzz * Vec3::random_in_unit_sphere());
+        // Fuzz can push the reflection into the surface, where it would go on bouncing
+        // inside the object and lose energy. Mirror it back out instead.
+        let into_surface = reflected.dot(&hit_rec.normal);
+        if into_surface < 0.0 {
+            reflected = reflected - (2.0 * into_surface * hit_rec.normal);
+        }
 
         scatter_rec.attenuation = self.albedo;
         scatter_rec.pdf = ScatterPDF::Skip(Ray3::new(hit_rec.point, reflected, ray_in.time()));
         true
     }
+
+    fn albedo(&self, _hit_rec: &HitRecord) -> Vec3 {
+        self.albedo
+    }
 }
 
 impl Metal {
@@ -1,4 +1,5 @@
 use crate::pdf::PDF;
+use crate::rtweekend::random_f64;
 use crate::vector_3::Vec3;
 
 pub struct MixturePDF {
@@ -12,7 +13,7 @@ impl PDF for MixturePDF {
     }
 
     fn generate(&self) -> Vec3 {
-        if rand::random::<f64>() < 0.5 {
+        if random_f64() < 0.5 {
             self.pdf_0.generate()
         } else {
             self.pdf_1.generate()
@@ -2,6 +2,7 @@ use crate::perlin::Perlin;
 use crate::texture::Texture;
 use crate::vector_3::Vec3;
 
+#[derive(Debug)]
 pub struct NoiseTexture {
     noise: Perlin,
     scale: f64,
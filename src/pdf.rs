@@ -4,3 +4,264 @@ pub trait PDF {
     fn value(&self, direction: &Vec3) -> f64;
     fn generate(&self) -> Vec3;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::PDF;
+    use crate::cosine_pdf::CosinePDF;
+    use crate::dielectric::Dielectric;
+    use crate::diffuse_light::DiffuseLight;
+    use crate::hittable::HitRecord;
+    use crate::hittable_list::HittableList;
+    use crate::hittable_pdf::HittablePDF;
+    use crate::isotropic::Isotropic;
+    use crate::lambertian::Lambertian;
+    use crate::material::{Material, ScatterPDF, ScatterRecord};
+    use crate::matrix_4::Matrix4;
+    use crate::metal::Metal;
+    use crate::mixture_pdf::MixturePDF;
+    use crate::quad::Quad;
+    use crate::ray::Ray3;
+    use crate::rtweekend::with_seed;
+    use crate::sphere::Sphere;
+    use crate::sphere_pdf::SpherePDF;
+    use crate::transform::Transform;
+    use crate::vector_3::Vec3;
+    use std::f64::consts::PI;
+    use std::sync::Arc;
+
+    // Directions are binned by cos theta and phi, where equal areas are equal solid angles
+    const COS_BINS: usize = 32;
+    const PHI_BINS: usize = 64;
+    // Each bin's side is split this many times when integrating value()
+    const SUBDIVISIONS: usize = 16;
+    const SAMPLES: usize = 200_000;
+    // Bins expecting fewer samples are pooled, as the test is unreliable for them
+    const MIN_EXPECTED: f64 = 5.0;
+    // The statistic is turned into a standard normal score, this is p below 1e-4
+    const MAX_SCORE: f64 = 3.7;
+    const INTEGRAL_TOLERANCE: f64 = 0.01;
+
+    fn bin_of(direction: &Vec3) -> usize {
+        let unit = direction.unit_vector();
+        let cos_theta = unit.z.clamp(-1.0, 1.0);
+        let phi = unit.y.atan2(unit.x).rem_euclid(2.0 * PI);
+        let i = (((cos_theta + 1.0) * 0.5 * COS_BINS as f64) as usize).min(COS_BINS - 1);
+        let j = ((phi / (2.0 * PI) * PHI_BINS as f64) as usize).min(PHI_BINS - 1);
+        i * PHI_BINS + j
+    }
+
+    // Integral of the density over each bin, by the midpoint rule
+    fn integrate(value: &dyn Fn(&Vec3) -> f64) -> Vec<f64> {
+        let rows = COS_BINS * SUBDIVISIONS;
+        let columns = PHI_BINS * SUBDIVISIONS;
+        let cell = (2.0 / rows as f64) * (2.0 * PI / columns as f64);
+        let mut integrals = vec![0.0; COS_BINS * PHI_BINS];
+        for row in 0..rows {
+            let cos_theta = -1.0 + 2.0 * (row as f64 + 0.5) / rows as f64;
+            let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+            for column in 0..columns {
+                let phi = 2.0 * PI * (column as f64 + 0.5) / columns as f64;
+                let direction = Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+                let bin = (row / SUBDIVISIONS) * PHI_BINS + column / SUBDIVISIONS;
+                integrals[bin] += value(&direction) * cell;
+            }
+        }
+        integrals
+    }
+
+    // Checks that the density integrates to one over the sphere and that the directions
+    // drawn from generate are distributed the way it says
+    fn check(name: &str, value: &dyn Fn(&Vec3) -> f64, generate: &mut dyn FnMut() -> Vec3) {
+        let integrals = integrate(value);
+        let total: f64 = integrals.iter().sum();
+        assert!(
+            (total - 1.0).abs() < INTEGRAL_TOLERANCE,
+            "{}: the pdf integrates to {}",
+            name,
+            total
+        );
+
+        let mut observed = vec![0.0; integrals.len()];
+        for _ in 0..SAMPLES {
+            let direction = generate();
+            assert!(
+                direction.length_squared() > 0.0 && direction.x.is_finite(),
+                "{}: generated {:?}",
+                name,
+                direction
+            );
+            assert!(
+                value(&direction) > 0.0,
+                "{}: generated {:?} where the pdf is zero",
+                name,
+                direction
+            );
+            observed[bin_of(&direction)] += 1.0;
+        }
+
+        // The integrals are scaled to sum to one, so a small error in the integration
+        // doesn't show up as a difference in every bin
+        let mut statistic = 0.0;
+        let mut bins = 0;
+        let (mut pooled_observed, mut pooled_expected) = (0.0, 0.0);
+        for (&integral, &count) in integrals.iter().zip(&observed) {
+            let expected = integral / total * SAMPLES as f64;
+            if expected < MIN_EXPECTED {
+                pooled_observed += count;
+                pooled_expected += expected;
+            } else {
+                statistic += (count - expected) * (count - expected) / expected;
+                bins += 1;
+            }
+        }
+        // Samples where the pdf is zero were caught above, an empty pool only means the
+        // integration grid missed a sliver of the pdf at the edge of a bin
+        if pooled_expected > 0.0 {
+            statistic += (pooled_observed - pooled_expected).powi(2) / pooled_expected;
+            bins += 1;
+        }
+
+        // Wilson-Hilferty: the cube root of a chi-square over its degrees of freedom is
+        // close to normal
+        let dof = (bins - 1).max(1) as f64;
+        let spread = 2.0 / (9.0 * dof);
+        let score = ((statistic / dof).cbrt() - (1.0 - spread)) / spread.sqrt();
+        assert!(
+            score < MAX_SCORE,
+            "{}: chi-square {:.1} with {} degrees of freedom, score {:.1}",
+            name,
+            statistic,
+            dof,
+            score
+        );
+    }
+
+    fn check_pdf(name: &str, pdf: &dyn PDF) {
+        with_seed(1, || {
+            check(name, &|direction| pdf.value(direction), &mut || {
+                pdf.generate()
+            })
+        });
+    }
+
+    fn white() -> Arc<Lambertian> {
+        Arc::new(Lambertian::from_color(Vec3::new(1.0, 1.0, 1.0)))
+    }
+
+    fn light_sphere() -> Arc<Sphere> {
+        Arc::new(Sphere::new(Vec3::new(0.3, -0.4, 1.2), 0.8, white()))
+    }
+
+    fn light_quad() -> Arc<Quad> {
+        Arc::new(Quad::new(
+            Vec3::new(-0.5, 1.0, -0.8),
+            Vec3::new(1.5, 0.0, 0.4),
+            Vec3::new(0.0, 0.3, 1.6),
+            white(),
+        ))
+    }
+
+    #[test]
+    fn cosine_pdf() {
+        check_pdf("cosine", &CosinePDF::new(&Vec3::new(0.0, 0.0, 1.0)));
+        check_pdf("tilted cosine", &CosinePDF::new(&Vec3::new(1.0, -2.0, 0.5)));
+    }
+
+    #[test]
+    fn sphere_pdf() {
+        check_pdf("sphere", &SpherePDF {});
+    }
+
+    #[test]
+    fn hittable_pdf_on_sphere() {
+        check_pdf(
+            "sphere light",
+            &HittablePDF::new(light_sphere(), Vec3::empty()),
+        );
+    }
+
+    #[test]
+    fn hittable_pdf_on_quad() {
+        check_pdf("quad light", &HittablePDF::new(light_quad(), Vec3::empty()));
+    }
+
+    #[test]
+    fn hittable_pdf_on_list() {
+        let mut lights = HittableList::new();
+        lights.add(light_sphere());
+        lights.add(light_quad());
+        check_pdf(
+            "light list",
+            &HittablePDF::new(Arc::new(lights), Vec3::empty()),
+        );
+    }
+
+    #[test]
+    fn hittable_pdf_on_transform() {
+        // A sheared and stretched sphere, sampled in object space
+        let matrix = Matrix4::translation(Vec3::new(-0.5, -1.5, 0.3))
+            * Matrix4::shear(0.4, 0.0, 0.0, 0.3, 0.0, 0.0)
+            * Matrix4::scale(Vec3::new(1.5, 0.6, 1.0));
+        let ellipsoid =
+            Transform::new(Arc::new(Sphere::new(Vec3::empty(), 1.0, white())), matrix).unwrap();
+        check_pdf(
+            "transformed sphere light",
+            &HittablePDF::new(Arc::new(ellipsoid), Vec3::empty()),
+        );
+    }
+
+    #[test]
+    fn mixture_pdf() {
+        let mixture = MixturePDF::new(
+            Box::new(HittablePDF::new(light_quad(), Vec3::empty())),
+            Box::new(CosinePDF::new(&Vec3::new(0.0, 1.0, 0.0))),
+        );
+        check_pdf("mixture", &mixture);
+    }
+
+    // Every material that importance samples its scattering is checked against its
+    // scattering_pdf, which is what the camera divides by
+    #[test]
+    fn material_pdfs() {
+        let materials: Vec<(&str, Arc<dyn Material + Sync + Send>)> = vec![
+            ("lambertian", white()),
+            (
+                "isotropic",
+                Arc::new(Isotropic::color(Vec3::new(0.5, 0.5, 0.5))),
+            ),
+            ("metal", Arc::new(Metal::new(Vec3::new(0.8, 0.8, 0.8), 0.3))),
+            ("dielectric", Arc::new(Dielectric::new(1.5))),
+            (
+                "diffuse light",
+                Arc::new(DiffuseLight::color(Vec3::new(4.0, 4.0, 4.0))),
+            ),
+        ];
+
+        let mut hit_record = HitRecord::new();
+        hit_record.normal = Vec3::new(0.3, -0.5, 0.8).unit_vector();
+        hit_record.front_face = true;
+        let ray_in = Ray3::new(Vec3::new(1.0, -2.0, 3.0), Vec3::new(-1.0, 2.0, -3.0), 0.0);
+
+        for (name, material) in materials {
+            let mut scatter_record = ScatterRecord::new();
+            if !material.scatter(&ray_in, &hit_record, &mut scatter_record) {
+                continue;
+            }
+            // Specular materials pick their direction themselves and have no density
+            let pdf = match scatter_record.pdf {
+                ScatterPDF::PDF(pdf) => pdf,
+                ScatterPDF::Skip(_) => continue,
+            };
+
+            check_pdf(name, pdf.as_ref());
+            let scattering_pdf = |direction: &Vec3| {
+                let scattered = Ray3::new(hit_record.point, *direction, 0.0);
+                material.scattering_pdf(&ray_in, &hit_record, &scattered)
+            };
+            with_seed(2, || {
+                check(name, &scattering_pdf, &mut || pdf.generate());
+            });
+        }
+    }
+}
@@ -1,9 +1,11 @@
+use crate::rtweekend::with_rng;
 use crate::vector_3::Vec3;
 
-use rand::{seq::SliceRandom, thread_rng};
+use rand::seq::SliceRandom;
 
 const PERLIN_POINT_COUNT: i32 = 256;
 
+#[derive(Debug)]
 pub struct Perlin {
     rand_vec3: Vec<Vec3>,
     perm_x: Vec<i32>,
@@ -11,11 +13,16 @@ pub struct Perlin {
     perm_z: Vec<i32>,
 }
 
+impl Default for Perlin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Perlin {
     fn generate_perm() -> Vec<i32> {
         let mut vec: Vec<i32> = (0..PERLIN_POINT_COUNT).collect();
-        let mut rng = thread_rng();
-        vec.shuffle(&mut rng);
+        with_rng(|rng| vec.shuffle(rng));
         vec
     }
 
@@ -84,7 +91,7 @@ impl Perlin {
         let mut weight = 1.0;
 
         for _ in 0..depth {
-            accumulate += weight * self.noise(temp_point.clone());
+            accumulate += weight * self.noise(temp_point);
             weight *= 0.5;
             temp_point = temp_point * 2.0;
         }
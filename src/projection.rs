@@ -0,0 +1,180 @@
+use crate::rtweekend::degree_to_radians;
+use crate::vector_3::Vec3;
+use std::f64::consts::PI;
+
+// How points on the image map to camera rays
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    // Pinhole or thin lens with the camera's vfov
+    Perspective,
+    // Parallel rays, the view is as tall as the perspective view at the focus distance
+    Orthographic,
+    // Image circle as tall as the image covering `fov` degrees, with the distance from the
+    // centre proportional to the angle off axis
+    FisheyeEquidistant { fov: f64 },
+    // Image circle where equal solid angles cover equal areas
+    FisheyeEquisolid { fov: f64 },
+    // Full 360 by 180 degree panorama, meant for an aspect ratio of 2
+    Equirectangular,
+}
+
+impl Projection {
+    // Origin and direction in camera space (x right, y up, looking down -z) of the ray
+    // through image point s, t, both running from 0 to 1 from the top left corner.
+    // Perspective rays end on the focus plane so a lens can be put in front of them.
+    // None outside the image circle of a fisheye.
+    pub fn camera_ray(
+        &self,
+        s: f64,
+        t: f64,
+        aspect: f64,
+        vfov: f64,
+        focus_dist: f64,
+    ) -> Option<(Vec3, Vec3)> {
+        // Centred coordinates in units of half the image height
+        let x = (2.0 * s - 1.0) * aspect;
+        let y = 1.0 - 2.0 * t;
+        let half_height = focus_dist * (degree_to_radians(vfov) / 2.0).tan();
+
+        match *self {
+            Projection::Perspective => Some((
+                Vec3::empty(),
+                Vec3::new(x * half_height, y * half_height, -focus_dist),
+            )),
+            Projection::Orthographic => Some((
+                Vec3::new(x * half_height, y * half_height, 0.0),
+                Vec3::new(0.0, 0.0, -1.0),
+            )),
+            Projection::FisheyeEquidistant { fov } => {
+                let radius = (x * x + y * y).sqrt();
+                if radius > 1.0 {
+                    return None;
+                }
+                let theta = radius * degree_to_radians(fov) / 2.0;
+                Some((Vec3::empty(), fisheye_direction(x, y, radius, theta)))
+            }
+            Projection::FisheyeEquisolid { fov } => {
+                let radius = (x * x + y * y).sqrt();
+                if radius > 1.0 {
+                    return None;
+                }
+                // radius = sin(theta / 2) / sin(max_theta / 2)
+                let theta = 2.0 * (radius * (degree_to_radians(fov) / 4.0).sin()).asin();
+                Some((Vec3::empty(), fisheye_direction(x, y, radius, theta)))
+            }
+            Projection::Equirectangular => {
+                let longitude = (s - 0.5) * 2.0 * PI;
+                let latitude = (0.5 - t) * PI;
+                Some((
+                    Vec3::empty(),
+                    Vec3::new(
+                        latitude.cos() * longitude.sin(),
+                        latitude.sin(),
+                        -latitude.cos() * longitude.cos(),
+                    ),
+                ))
+            }
+        }
+    }
+}
+
+// Unit direction theta radians off axis, towards x, y on the image
+fn fisheye_direction(x: f64, y: f64, radius: f64, theta: f64) -> Vec3 {
+    if radius == 0.0 {
+        return Vec3::new(0.0, 0.0, -1.0);
+    }
+    let sin_theta = theta.sin();
+    Vec3::new(sin_theta * x / radius, sin_theta * y / radius, -theta.cos())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ASPECT: f64 = 1.5;
+    const VFOV: f64 = 50.0;
+    const FOCUS: f64 = 3.0;
+
+    fn ray(projection: Projection, s: f64, t: f64) -> Option<(Vec3, Vec3)> {
+        projection.camera_ray(s, t, ASPECT, VFOV, FOCUS)
+    }
+
+    // Angle in degrees between a direction and the view direction
+    fn off_axis(direction: &Vec3) -> f64 {
+        (-direction.unit_vector().z)
+            .clamp(-1.0, 1.0)
+            .acos()
+            .to_degrees()
+    }
+
+    fn assert_close(found: f64, expected: f64) {
+        assert!(
+            (found - expected).abs() < 1e-9,
+            "{} against {}",
+            found,
+            expected
+        );
+    }
+
+    #[test]
+    fn the_centre_looks_down_the_view_direction() {
+        let projections = [
+            Projection::Perspective,
+            Projection::Orthographic,
+            Projection::FisheyeEquidistant { fov: 180.0 },
+            Projection::FisheyeEquisolid { fov: 220.0 },
+            Projection::Equirectangular,
+        ];
+        for projection in projections {
+            let (origin, direction) = ray(projection, 0.5, 0.5).unwrap();
+            assert_eq!(origin.length(), 0.0, "{:?}", projection);
+            assert_close(off_axis(&direction), 0.0);
+        }
+    }
+
+    #[test]
+    fn the_frame_edge_is_half_the_field_of_view_off_axis() {
+        // Top and bottom edges, half way across
+        for t in [0.0, 1.0] {
+            let (_, direction) = ray(Projection::Perspective, 0.5, t).unwrap();
+            assert_close(off_axis(&direction), VFOV / 2.0);
+
+            // Parallel rays starting as high as the perspective view at the focus distance
+            let (origin, direction) = ray(Projection::Orthographic, 0.5, t).unwrap();
+            assert_close(off_axis(&direction), 0.0);
+            assert_close(origin.y.abs(), FOCUS * (VFOV / 2.0).to_radians().tan());
+
+            for fov in [120.0, 180.0, 250.0] {
+                for projection in [
+                    Projection::FisheyeEquidistant { fov },
+                    Projection::FisheyeEquisolid { fov },
+                ] {
+                    let (_, direction) = ray(projection, 0.5, t).unwrap();
+                    assert_close(off_axis(&direction), fov / 2.0);
+                }
+            }
+        }
+
+        // Half way to the edge of the circle the two fisheyes part ways
+        let fov: f64 = 180.0;
+        let (_, direction) = ray(Projection::FisheyeEquidistant { fov }, 0.5, 0.25).unwrap();
+        assert_close(off_axis(&direction), fov / 4.0);
+        let (_, direction) = ray(Projection::FisheyeEquisolid { fov }, 0.5, 0.25).unwrap();
+        let expected = 2.0 * (0.5 * (fov / 4.0).to_radians().sin()).asin();
+        assert_close(off_axis(&direction), expected.to_degrees());
+
+        // The corners of a wide image are outside the image circle
+        assert!(ray(Projection::FisheyeEquidistant { fov }, 0.0, 0.0).is_none());
+        assert!(ray(Projection::FisheyeEquisolid { fov }, 1.0, 1.0).is_none());
+    }
+
+    #[test]
+    fn the_panorama_wraps_all_the_way_round() {
+        let direction = |s, t| ray(Projection::Equirectangular, s, t).unwrap().1;
+        assert!((direction(0.75, 0.5) - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-12);
+        assert!((direction(0.25, 0.5) - Vec3::new(-1.0, 0.0, 0.0)).length() < 1e-12);
+        assert!((direction(0.0, 0.5) - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+        assert!((direction(0.5, 0.0) - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-12);
+        assert!((direction(0.5, 1.0) - Vec3::new(0.0, -1.0, 0.0)).length() < 1e-12);
+    }
+}
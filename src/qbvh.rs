@@ -0,0 +1,324 @@
+use crate::aabb::AABB;
+use crate::aabb4::{BoxRay, AABB4};
+use crate::hittable::HitRecord;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::interval::Interval;
+use crate::ray::Ray3;
+use crate::ray_stats::{self, Counter};
+
+use std::sync::Arc;
+
+// Most objects a leaf holds before it is split
+const LEAF_SIZE: usize = 4;
+// Deep enough for any tree built from a median split, which is at most 32 levels deep
+// for u32 object indices and pushes at most 3 children more than it pops per level
+const STACK_SIZE: usize = 128;
+
+// A BVH with four children per node, whose boxes are tested together by one AABB4 test.
+// It is built as a binary tree, which is then collapsed by pulling grandchildren up into
+// each node. The objects are stored leaf by leaf, so a leaf is a range of them.
+#[derive(Debug)]
+pub struct QBVH {
+    nodes: Vec<QNode>,
+    objects: Vec<Arc<dyn Hittable + Sync + Send>>,
+    bbox: AABB,
+}
+
+#[derive(Debug)]
+struct QNode {
+    bounds: AABB4,
+    children: [Child; 4],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Child {
+    Empty,
+    Node(u32),
+    // First object and how many
+    Leaf(u32, u32),
+}
+
+// The binary tree the nodes are collapsed from, over a range of the objects
+struct BinaryNode {
+    bbox: AABB,
+    start: usize,
+    end: usize,
+    children: Option<Box<(BinaryNode, BinaryNode)>>,
+}
+
+impl Hittable for QBVH {
+    fn hit(&self, ray_in: &Ray3, time: Interval, hit_record: &mut HitRecord) -> bool {
+        if self.nodes.is_empty() {
+            return false;
+        }
+        let ray = BoxRay::new(ray_in);
+        let mut closest = time.max();
+        let mut hit_anything = false;
+
+        // Children still to visit with where the ray enters them, nearest on top
+        let mut stack = [(Child::Empty, 0.0f32); STACK_SIZE];
+        stack[0] = (Child::Node(0), f32::NEG_INFINITY);
+        let mut size = 1;
+        while size > 0 {
+            size -= 1;
+            let (child, near) = stack[size];
+            // A closer hit found since the child was pushed may make it pointless
+            if near as f64 > closest {
+                continue;
+            }
+            match child {
+                Child::Empty => {}
+                Child::Leaf(start, count) => {
+                    for object in &self.objects[start as usize..(start + count) as usize] {
+                        if object.hit(ray_in, Interval::new(time.min(), closest), hit_record) {
+                            hit_anything = true;
+                            closest = hit_record.time;
+                        }
+                    }
+                }
+                Child::Node(index) => {
+                    ray_stats::count(Counter::BvhNodeVisit);
+                    let node = &self.nodes[index as usize];
+                    let (mask, nears) = node.bounds.hit(&ray, &Interval::new(time.min(), closest));
+                    let nears = nears.to_array();
+                    // Push the children hit from farthest to nearest
+                    let first = size;
+                    for (lane, (&child, &near)) in node.children.iter().zip(&nears).enumerate() {
+                        if mask & (1 << lane) != 0 {
+                            let mut slot = size;
+                            while slot > first && stack[slot - 1].1 < near {
+                                stack[slot] = stack[slot - 1];
+                                slot -= 1;
+                            }
+                            stack[slot] = (child, near);
+                            size += 1;
+                        }
+                    }
+                }
+            }
+        }
+        hit_anything
+    }
+
+    fn bounding_box(&self) -> AABB {
+        AABB::copy(&self.bbox)
+    }
+}
+
+impl QBVH {
+    pub fn from_vec(objects: &[Arc<dyn Hittable + Sync + Send>]) -> QBVH {
+        let mut objects = objects.to_vec();
+        let mut qbvh = QBVH {
+            nodes: Vec::new(),
+            objects: Vec::new(),
+            bbox: AABB::empty(),
+        };
+        if objects.is_empty() {
+            return qbvh;
+        }
+        let count = objects.len();
+        let root = QBVH::build(&mut objects, 0, count);
+        qbvh.bbox = root.bbox.copy();
+        qbvh.objects = objects;
+        // A root small enough to be a leaf still goes in a node, so traversal starts at one
+        if root.children.is_none() {
+            qbvh.nodes.push(QNode {
+                bounds: AABB4::new(&[root.bbox.copy()]),
+                children: [
+                    Child::Leaf(0, count as u32),
+                    Child::Empty,
+                    Child::Empty,
+                    Child::Empty,
+                ],
+            });
+        } else {
+            qbvh.collapse(&root);
+        }
+        qbvh
+    }
+
+    pub fn from_list(list: &HittableList) -> QBVH {
+        QBVH::from_vec(&list.objects)
+    }
+
+    // Splits the objects at the median along the longest axis of their boxes, sorting
+    // them so every node covers a contiguous range
+    fn build(
+        objects: &mut [Arc<dyn Hittable + Sync + Send>],
+        start: usize,
+        end: usize,
+    ) -> BinaryNode {
+        let bbox = objects[start..end]
+            .iter()
+            .fold(AABB::empty(), |bbox, object| {
+                AABB::from_aabbs(&bbox, &object.bounding_box())
+            });
+        if end - start <= LEAF_SIZE {
+            return BinaryNode {
+                bbox,
+                start,
+                end,
+                children: None,
+            };
+        }
+        let axis = (0..3)
+            .max_by(|&a, &b| bbox.axis(a).size().total_cmp(&bbox.axis(b).size()))
+            .unwrap_or(0);
+        objects[start..end].sort_by(|a, b| {
+            let a = a.bounding_box().axis(axis);
+            let b = b.bounding_box().axis(axis);
+            (a.min() + a.max()).total_cmp(&(b.min() + b.max()))
+        });
+        let mid = start + (end - start) / 2;
+        let left = QBVH::build(objects, start, mid);
+        let right = QBVH::build(objects, mid, end);
+        BinaryNode {
+            bbox,
+            start,
+            end,
+            children: Some(Box::new((left, right))),
+        }
+    }
+
+    // Makes a four wide node from a binary interior node and returns its index. The
+    // interior child with the largest surface area is replaced by its own two children
+    // until there are four, or only leaves are left.
+    fn collapse(&mut self, binary: &BinaryNode) -> u32 {
+        let mut children: Vec<&BinaryNode> = Vec::with_capacity(4);
+        if let Some(pair) = &binary.children {
+            children.push(&pair.0);
+            children.push(&pair.1);
+        }
+        while children.len() < 4 {
+            let largest = children
+                .iter()
+                .enumerate()
+                .filter(|(_, child)| child.children.is_some())
+                .max_by(|(_, a), (_, b)| surface_area(&a.bbox).total_cmp(&surface_area(&b.bbox)))
+                .map(|(position, _)| position);
+            match largest {
+                Some(position) => {
+                    let pair = children.remove(position).children.as_ref().unwrap();
+                    children.push(&pair.0);
+                    children.push(&pair.1);
+                }
+                None => break,
+            }
+        }
+
+        let index = self.nodes.len();
+        let boxes: Vec<AABB> = children.iter().map(|child| child.bbox.copy()).collect();
+        self.nodes.push(QNode {
+            bounds: AABB4::new(&boxes),
+            children: [Child::Empty; 4],
+        });
+        for (lane, child) in children.iter().enumerate() {
+            let entry = if child.children.is_some() {
+                Child::Node(self.collapse(child))
+            } else {
+                Child::Leaf(child.start as u32, (child.end - child.start) as u32)
+            };
+            self.nodes[index].children[lane] = entry;
+        }
+        index as u32
+    }
+}
+
+fn surface_area(bbox: &AABB) -> f64 {
+    let x = bbox.axis(0).size();
+    let y = bbox.axis(1).size();
+    let z = bbox.axis(2).size();
+    2.0 * (x * y + y * z + z * x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lambertian::Lambertian;
+    use crate::quad::Quad;
+    use crate::rtweekend::{random_f64, with_seed};
+    use crate::sphere::Sphere;
+    use crate::vector_3::Vec3;
+
+    // Small spheres and axis aligned quads, which have flat boxes before padding
+    fn random_list(count: usize) -> HittableList {
+        let mat = Arc::new(Lambertian::from_color(Vec3::new(0.5, 0.5, 0.5)));
+        let mut list = HittableList::new();
+        for n in 0..count {
+            let center = Vec3::random_range(-4.0, 4.0);
+            if n % 3 == 0 {
+                list.add(Arc::new(Quad::new(
+                    center,
+                    Vec3::new(random_f64(), 0.0, 0.0),
+                    Vec3::new(0.0, 0.0, random_f64()),
+                    mat.clone(),
+                )));
+            } else {
+                list.add(Arc::new(Sphere::new(
+                    center,
+                    0.05 + 0.3 * random_f64(),
+                    mat.clone(),
+                )));
+            }
+        }
+        list
+    }
+
+    #[test]
+    fn finds_the_same_closest_hits_as_a_list() {
+        with_seed(5, || {
+            for count in [0, 1, 3, 5, 17, 64, 1000] {
+                let list = random_list(count);
+                let qbvh = QBVH::from_list(&list);
+                for _ in 0..2_000 {
+                    let origin = Vec3::random_range(-6.0, 6.0);
+                    let ray = Ray3::new(origin, Vec3::random_range(-4.0, 4.0) - origin, 0.0);
+                    let mut expected = HitRecord::new();
+                    let mut found = HitRecord::new();
+                    let interval = Interval::new(0.001, f64::INFINITY);
+                    let hit = list.hit(&ray, interval.copy(), &mut expected);
+                    assert_eq!(
+                        qbvh.hit(&ray, interval, &mut found),
+                        hit,
+                        "{} objects",
+                        count
+                    );
+                    if hit {
+                        assert_eq!(found.time, expected.time);
+                        assert_eq!(found.object, expected.object);
+                    }
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn every_object_is_in_one_leaf() {
+        with_seed(6, || {
+            for count in [1, 4, 5, 9, 100, 1000] {
+                let qbvh = QBVH::from_list(&random_list(count));
+                let mut seen = vec![0; count];
+                for node in &qbvh.nodes {
+                    let used = node.children.iter().filter(|&&child| child != Child::Empty);
+                    assert!(used.count() >= 2 || count <= LEAF_SIZE);
+                    for child in &node.children {
+                        match *child {
+                            Child::Leaf(start, size) => {
+                                assert!((1..=LEAF_SIZE as u32).contains(&size));
+                                for index in start..start + size {
+                                    seen[index as usize] += 1;
+                                }
+                            }
+                            Child::Node(index) => assert!((index as usize) < qbvh.nodes.len()),
+                            Child::Empty => {}
+                        }
+                    }
+                }
+                assert!(seen.iter().all(|&times| times == 1));
+                // Collapsing leaves close to a quarter of the binary tree's nodes
+                assert!(qbvh.nodes.len() <= count.div_ceil(LEAF_SIZE).max(1));
+            }
+        })
+    }
+}
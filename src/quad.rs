@@ -4,10 +4,13 @@ use crate::hittable_list::HittableList;
 use crate::interval::Interval;
 use crate::material::Material;
 use crate::ray::Ray3;
+use crate::ray_stats::{self, Counter};
+use crate::rtweekend::random_f64;
 use crate::vector_3::Vec3;
 use core::f64;
 use std::sync::Arc;
 
+#[derive(Debug)]
 pub struct Quad {
     mat: Arc<dyn Material + Sync + Send>,
     bbox: AABB,
@@ -22,11 +25,12 @@ pub struct Quad {
 
 impl Hittable for Quad {
     fn hit(&self, ray_in: &Ray3, time: Interval, hit_record: &mut HitRecord) -> bool {
-        let denom = self.normal.dot(&ray_in.direction());
+        ray_stats::count(Counter::PrimitiveTest);
+        let denom = self.normal.dot(ray_in.direction());
 
         let mut quad_hit = false;
         if denom.abs() >= 1e-8 {
-            let hit_time = (self.plane_const - self.normal.dot(&ray_in.origin())) / denom;
+            let hit_time = (self.plane_const - self.normal.dot(ray_in.origin())) / denom;
 
             if time.contains(hit_time) {
                 let intersection = ray_in.at(hit_time);
@@ -39,6 +43,7 @@ impl Hittable for Quad {
                     hit_record.time = hit_time;
                     hit_record.point = intersection;
                     hit_record.mat = Arc::clone(&self.mat);
+                    hit_record.object = self as *const Quad as usize;
                     hit_record.set_face_normal(ray_in, self.normal);
 
                     quad_hit = true;
@@ -66,9 +71,8 @@ impl Hittable for Quad {
     }
 
     fn random(&self, origin: &Vec3) -> Vec3 {
-        let point =
-            self.origin + (self.u * rand::random::<f64>()) + (self.v * rand::random::<f64>());
-        point - origin.clone()
+        let point = self.origin + (self.u * random_f64()) + (self.v * random_f64());
+        point - *origin
     }
 }
 
@@ -76,7 +80,9 @@ impl Quad {
     pub fn new(origin: Vec3, u: Vec3, v: Vec3, mat: Arc<dyn Material + Sync + Send>) -> Quad {
         let bbox_0 = AABB::from_vec3s(origin, origin + u + v);
         let bbox_1 = AABB::from_vec3s(origin + u, origin + v);
-        let bbox = AABB::from_aabbs(&bbox_0, &bbox_1);
+        // An axis aligned quad is flat along one axis, padding keeps its box from being
+        // empty there
+        let bbox = AABB::from_aabbs(&bbox_0, &bbox_1).pad();
         let n = u.cross(&v);
         let normal = n.unit_vector();
         let plane_const = normal.dot(&origin);
@@ -96,7 +102,7 @@ impl Quad {
     }
 
     fn interior(a: f64, b: f64, hit_record: &mut HitRecord) -> bool {
-        if a < 0.0 || 1.0 < a || b < 0.0 || 1.0 < b {
+        if !(0.0..=1.0).contains(&a) || !(0.0..=1.0).contains(&b) {
             false
         } else {
             hit_record.u = a;
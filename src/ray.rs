@@ -1,49 +1,76 @@
-use crate::vector_3::Vec3;
+use crate::real::Real;
+use crate::vector_3::Vector3;
 
-pub struct Ray3 {
-    origin: Vec3,
-    dir: Vec3,
-    time: f64,
+// `Ray3` is traced by the renderer, `Ray3f` is the f32 ray for the f32 math
+pub struct Ray<T> {
+    origin: Vector3<T>,
+    dir: Vector3<T>,
+    // Kept with the direction, as every bounding box test along the ray needs it
+    inv_dir: Vector3<T>,
+    time: T,
 }
 
-impl Ray3 {
-    pub fn new(origin: Vec3, dir: Vec3, time: f64) -> Ray3 {
-        Ray3 { origin, dir, time }
+pub type Ray3 = Ray<f64>;
+pub type Ray3f = Ray<f32>;
+
+impl<T: Real> Ray<T> {
+    pub fn new(origin: Vector3<T>, dir: Vector3<T>, time: T) -> Ray<T> {
+        Ray {
+            origin,
+            dir,
+            inv_dir: Ray::invert(dir),
+            time,
+        }
+    }
+
+    pub fn empty() -> Ray<T> {
+        Ray::new(Vector3::empty(), Vector3::empty(), T::ZERO)
     }
 
-    pub fn empty() -> Ray3 {
-        Ray3::new(Vec3::empty(), Vec3::empty(), 0.0)
+    pub fn copy(&self) -> Ray<T> {
+        Ray {
+            origin: self.origin,
+            dir: self.dir,
+            inv_dir: self.inv_dir,
+            time: self.time,
+        }
     }
 
-    pub fn copy(&self) -> Ray3 {
-        Ray3::new(self.origin.clone(), self.dir.clone(), self.time)
+    pub fn origin(&self) -> &Vector3<T> {
+        &self.origin
     }
 
-    pub fn origin(&self) -> Vec3 {
-        self.origin.clone()
+    pub fn direction(&self) -> &Vector3<T> {
+        &self.dir
     }
 
-    pub fn direction(&self) -> Vec3 {
-        self.dir.clone()
+    // Zero components give infinities, which the slab tests rely on
+    pub fn inverse_direction(&self) -> &Vector3<T> {
+        &self.inv_dir
     }
 
-    pub fn time(&self) -> f64 {
+    pub fn time(&self) -> T {
         self.time
     }
 
-    pub fn at(&self, time: f64) -> Vec3 {
-        self.origin() + (time * self.direction())
+    pub fn at(&self, time: T) -> Vector3<T> {
+        self.origin + self.dir * time
     }
 
-    pub fn set_origin(&mut self, origin: Vec3) {
+    pub fn set_origin(&mut self, origin: Vector3<T>) {
         self.origin = origin;
     }
 
-    pub fn set_direction(&mut self, direction: Vec3) {
+    pub fn set_direction(&mut self, direction: Vector3<T>) {
         self.dir = direction;
+        self.inv_dir = Ray::invert(direction);
     }
 
-    pub fn set_time(&mut self, time: f64) {
+    pub fn set_time(&mut self, time: T) {
         self.time = time;
     }
+
+    fn invert(dir: Vector3<T>) -> Vector3<T> {
+        Vector3::new(T::ONE / dir.x, T::ONE / dir.y, T::ONE / dir.z)
+    }
 }
@@ -0,0 +1,233 @@
+use crate::image_metrics::false_color;
+use image::RgbImage;
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Opt-in counters for where render time goes. Counting is off until `start`, and while
+// it is on every thread counts into its own totals, which the camera merges after each
+// tile, so the hot paths never wait on a lock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Counter {
+    PrimaryRay,
+    SecondaryRay,
+    // The renderer has no separate shadow rays. These are the secondary rays light
+    // sampling aims at a light, which stand in for them.
+    ShadowRay,
+    AabbTest,
+    PrimitiveTest,
+    BvhNodeVisit,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RayStats {
+    pub primary_rays: u64,
+    pub secondary_rays: u64,
+    pub shadow_rays: u64,
+    pub aabb_tests: u64,
+    pub primitive_tests: u64,
+    pub bvh_node_visits: u64,
+    // Paths by how many rays they are made of, from index 1
+    pub path_lengths: Vec<u64>,
+}
+
+// Totals of a render, with the traversal cost of each pixel
+pub struct RenderStats {
+    pub totals: RayStats,
+    pub width: i32,
+    pub height: i32,
+    // AABB and primitive tests made by the samples of each pixel, row by row, and how
+    // many samples there were
+    pub pixel_cost: Vec<u64>,
+    pub pixel_samples: Vec<u64>,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static COLLECTED: Mutex<Option<RenderStats>> = Mutex::new(None);
+
+#[derive(Default)]
+struct Local {
+    stats: RayStats,
+    pixel_costs: Vec<(i32, i32, u64, u64)>,
+}
+
+thread_local! {
+    static LOCAL: RefCell<Local> = RefCell::new(Local::default());
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+// Clears the counters and starts counting for an image of this size
+pub fn start(width: i32, height: i32) {
+    LOCAL.with(|local| *local.borrow_mut() = Local::default());
+    *COLLECTED.lock().unwrap() = Some(RenderStats {
+        totals: RayStats::default(),
+        width,
+        height,
+        pixel_cost: vec![0; (width.max(0) * height.max(0)) as usize],
+        pixel_samples: vec![0; (width.max(0) * height.max(0)) as usize],
+    });
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+// Stops counting and returns what was counted since `start`
+pub fn stop() -> Option<RenderStats> {
+    flush();
+    ENABLED.store(false, Ordering::Relaxed);
+    COLLECTED.lock().unwrap().take()
+}
+
+pub fn count(counter: Counter) {
+    if enabled() {
+        LOCAL.with(|local| {
+            let stats = &mut local.borrow_mut().stats;
+            match counter {
+                Counter::PrimaryRay => stats.primary_rays += 1,
+                Counter::SecondaryRay => stats.secondary_rays += 1,
+                Counter::ShadowRay => stats.shadow_rays += 1,
+                Counter::AabbTest => stats.aabb_tests += 1,
+                Counter::PrimitiveTest => stats.primitive_tests += 1,
+                Counter::BvhNodeVisit => stats.bvh_node_visits += 1,
+            }
+        });
+    }
+}
+
+// A path that ended after this many rays
+pub fn record_path(length: usize) {
+    if enabled() {
+        LOCAL.with(|local| {
+            let lengths = &mut local.borrow_mut().stats.path_lengths;
+            if lengths.len() <= length {
+                lengths.resize(length + 1, 0);
+            }
+            lengths[length] += 1;
+        });
+    }
+}
+
+// AABB and primitive tests this thread has made so far, to measure a pixel's cost
+pub fn traversal_cost() -> u64 {
+    LOCAL.with(|local| {
+        let stats = &local.borrow().stats;
+        stats.aabb_tests + stats.primitive_tests
+    })
+}
+
+pub fn add_pixel_cost(i: i32, j: i32, cost: u64, samples: u64) {
+    if enabled() {
+        LOCAL.with(|local| local.borrow_mut().pixel_costs.push((i, j, cost, samples)));
+    }
+}
+
+// Moves this thread's counts into the render's totals
+pub fn flush() {
+    if !enabled() {
+        return;
+    }
+    let local = LOCAL.with(|local| std::mem::take(&mut *local.borrow_mut()));
+    if let Some(collected) = COLLECTED.lock().unwrap().as_mut() {
+        collected.totals.add(&local.stats);
+        for (i, j, cost, samples) in local.pixel_costs {
+            if (0..collected.width).contains(&i) && (0..collected.height).contains(&j) {
+                let index = (j * collected.width + i) as usize;
+                collected.pixel_cost[index] += cost;
+                collected.pixel_samples[index] += samples;
+            }
+        }
+    }
+}
+
+impl RenderStats {
+    // AABB and primitive tests per camera sample in each pixel
+    pub fn cost_per_sample(&self) -> Vec<f64> {
+        self.pixel_cost
+            .iter()
+            .zip(&self.pixel_samples)
+            .map(|(&cost, &samples)| cost as f64 / samples.max(1) as f64)
+            .collect()
+    }
+
+    // Traversal cost as a false colour image, black for free to yellow for the most
+    // expensive pixels. The brightest colour is the 99th percentile, so a few outliers
+    // don't wash out the rest, and the cost it stands for is returned with the image.
+    pub fn heatmap(&self) -> (RgbImage, f64) {
+        let cost = self.cost_per_sample();
+        let mut sorted = cost.clone();
+        sorted.sort_by(f64::total_cmp);
+        let top = sorted
+            .get((sorted.len() as f64 * 0.99) as usize)
+            .copied()
+            .unwrap_or(0.0)
+            .max(1.0);
+        let scaled: Vec<f64> = cost.iter().map(|&value| value / top).collect();
+        (
+            false_color(&scaled, self.width as u32, self.height as u32),
+            top,
+        )
+    }
+}
+
+impl RayStats {
+    pub fn add(&mut self, other: &RayStats) {
+        self.primary_rays += other.primary_rays;
+        self.secondary_rays += other.secondary_rays;
+        self.shadow_rays += other.shadow_rays;
+        self.aabb_tests += other.aabb_tests;
+        self.primitive_tests += other.primitive_tests;
+        self.bvh_node_visits += other.bvh_node_visits;
+        if self.path_lengths.len() < other.path_lengths.len() {
+            self.path_lengths.resize(other.path_lengths.len(), 0);
+        }
+        for (total, count) in self.path_lengths.iter_mut().zip(&other.path_lengths) {
+            *total += count;
+        }
+    }
+
+    pub fn rays(&self) -> u64 {
+        self.primary_rays + self.secondary_rays
+    }
+
+    // Report of the counts, with rates over the render time
+    pub fn summary(&self, time: Duration) -> String {
+        let rays = self.rays().max(1) as f64;
+        let per_ray = |name: &str, count: u64| {
+            format!(
+                "  {:<16} {:>14}  {:8.2} per ray\n",
+                name,
+                count,
+                count as f64 / rays
+            )
+        };
+        let mut summary = String::from("Ray statistics\n");
+        summary += &format!("  primary rays     {:>14}\n", self.primary_rays);
+        summary += &format!("  secondary rays   {:>14}\n", self.secondary_rays);
+        summary += &format!("  shadow rays      {:>14}\n", self.shadow_rays);
+        summary += &format!(
+            "  rays per second  {:>14.0}\n",
+            self.rays() as f64 / time.as_secs_f64().max(1e-9)
+        );
+        summary += &per_ray("AABB tests", self.aabb_tests);
+        summary += &per_ray("primitive tests", self.primitive_tests);
+        summary += &per_ray("BVH node visits", self.bvh_node_visits);
+
+        let paths: u64 = self.path_lengths.iter().sum();
+        let most = self.path_lengths.iter().copied().max().unwrap_or(0).max(1);
+        summary += "Path lengths, in rays\n";
+        for (length, &count) in self.path_lengths.iter().enumerate().skip(1) {
+            if count > 0 {
+                summary += &format!(
+                    "  {:>4} {:>14} {:5.1}% {}\n",
+                    length,
+                    count,
+                    100.0 * count as f64 / paths as f64,
+                    "#".repeat((40 * count).div_ceil(most) as usize)
+                );
+            }
+        }
+        summary
+    }
+}
@@ -0,0 +1,73 @@
+use std::fmt::Debug;
+use std::ops;
+
+// Floating point type the core math is written over: vectors, rays, intervals, bounding
+// boxes and the sphere intersection. Rendering uses f64, which the types default to,
+// and f32 halves their size where precision matters less than memory or SIMD width.
+pub trait Real:
+    Copy
+    + Debug
+    + PartialOrd
+    + Send
+    + Sync
+    + ops::Add<Output = Self>
+    + ops::Sub<Output = Self>
+    + ops::Mul<Output = Self>
+    + ops::Div<Output = Self>
+    + ops::Neg<Output = Self>
+    + ops::AddAssign
+    + ops::SubAssign
+    + ops::MulAssign
+    + ops::DivAssign
+    + 'static
+{
+    const ZERO: Self;
+    const ONE: Self;
+    const INFINITY: Self;
+    const EPSILON: Self;
+
+    fn from_f64(value: f64) -> Self;
+    fn to_f64(self) -> f64;
+    fn sqrt(self) -> Self;
+    fn abs(self) -> Self;
+    fn min(self, other: Self) -> Self;
+    fn max(self, other: Self) -> Self;
+}
+
+macro_rules! impl_real {
+    ($t:ident) => {
+        impl Real for $t {
+            const ZERO: $t = 0.0;
+            const ONE: $t = 1.0;
+            const INFINITY: $t = $t::INFINITY;
+            const EPSILON: $t = $t::EPSILON;
+
+            fn from_f64(value: f64) -> $t {
+                value as $t
+            }
+
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+
+            fn sqrt(self) -> $t {
+                $t::sqrt(self)
+            }
+
+            fn abs(self) -> $t {
+                $t::abs(self)
+            }
+
+            fn min(self, other: $t) -> $t {
+                $t::min(self, other)
+            }
+
+            fn max(self, other: $t) -> $t {
+                $t::max(self, other)
+            }
+        }
+    };
+}
+
+impl_real!(f32);
+impl_real!(f64);
@@ -0,0 +1,103 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RenderProgress {
+    pub tiles_done: usize,
+    pub tiles_total: usize,
+    pub samples_done: u64,
+    pub samples_total: u64,
+    pub elapsed: Duration,
+    pub eta: Option<Duration>,
+}
+
+impl RenderProgress {
+    pub fn new(tiles_total: usize, samples_total: u64) -> RenderProgress {
+        RenderProgress {
+            tiles_done: 0,
+            tiles_total,
+            samples_done: 0,
+            samples_total,
+            elapsed: Duration::ZERO,
+            eta: None,
+        }
+    }
+
+    pub fn fraction(&self) -> f64 {
+        if self.samples_total == 0 {
+            1.0
+        } else {
+            self.samples_done as f64 / self.samples_total as f64
+        }
+    }
+
+    pub fn update(&mut self, tiles: usize, samples: u64, elapsed: Duration) {
+        self.tiles_done += tiles;
+        self.samples_done += samples;
+        self.elapsed = elapsed;
+
+        // Assume the remaining samples cost the same on average as the finished ones
+        self.eta = if self.samples_done > 0 {
+            let remaining = self.samples_total.saturating_sub(self.samples_done) as f64;
+            let per_sample = elapsed.as_secs_f64() / self.samples_done as f64;
+            Some(Duration::from_secs_f64(remaining * per_sample))
+        } else {
+            None
+        };
+    }
+}
+
+// Shared flag an embedding application can use to stop a render early
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken {
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eta_assumes_the_rate_so_far() {
+        let mut progress = RenderProgress::new(8, 400);
+        assert_eq!(progress.eta, None);
+        assert_eq!(progress.fraction(), 0.0);
+
+        // A quarter of the samples in 10 s leaves 30 s
+        progress.update(2, 100, Duration::from_secs(10));
+        assert_eq!(progress.eta, Some(Duration::from_secs(30)));
+        assert_eq!(progress.fraction(), 0.25);
+
+        // Half in 30 s, so the rest takes another 30 s at the slower rate
+        progress.update(2, 100, Duration::from_secs(30));
+        assert_eq!(progress.eta, Some(Duration::from_secs(30)));
+        assert_eq!((progress.tiles_done, progress.samples_done), (4, 200));
+
+        progress.update(4, 200, Duration::from_secs(50));
+        assert_eq!(progress.eta, Some(Duration::ZERO));
+        assert_eq!(progress.fraction(), 1.0);
+
+        // Tiles without samples say nothing about the rate
+        let mut empty = RenderProgress::new(1, 0);
+        empty.update(1, 0, Duration::from_secs(1));
+        assert_eq!(empty.eta, None);
+        assert_eq!(empty.fraction(), 1.0);
+    }
+}
@@ -1,13 +1,48 @@
-use rand;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cell::RefCell;
+
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
 
 pub fn degree_to_radians(degree: f64) -> f64 {
     degree * std::f64::consts::PI / 180.0
 }
 
+pub fn random_f64() -> f64 {
+    RNG.with(|rng| rng.borrow_mut().gen::<f64>())
+}
+
 pub fn random_f64_range(min: f64, max: f64) -> f64 {
-    ((max - min) * rand::random::<f64>()) + min
+    ((max - min) * random_f64()) + min
 }
 
 pub fn random_u32_range(min: u32, max: u32) -> u32 {
     (random_f64_range(min as f64, (max + 1) as f64)) as u32
 }
+
+pub fn with_rng<T>(f: impl FnOnce(&mut StdRng) -> T) -> T {
+    RNG.with(|rng| f(&mut rng.borrow_mut()))
+}
+
+// Seed for a generator from several values, so neighbouring values give unrelated sequences
+pub fn mix_seed(values: &[u64]) -> u64 {
+    // splitmix64 applied to each value in turn
+    values
+        .iter()
+        .fold(0x9e3779b97f4a7c15, |state: u64, &value| {
+            let mut z = (state ^ value).wrapping_add(0x9e3779b97f4a7c15);
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            z ^ (z >> 31)
+        })
+}
+
+// Run `f` with this thread's generator seeded, restoring the previous generator afterwards
+pub fn with_seed<T>(seed: u64, f: impl FnOnce() -> T) -> T {
+    let previous = RNG.with(|rng| rng.replace(StdRng::seed_from_u64(seed)));
+    let result = f();
+    RNG.with(|rng| rng.replace(previous));
+    result
+}
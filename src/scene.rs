@@ -0,0 +1,11 @@
+use crate::camera::Camera;
+use crate::hittable::Hittable;
+use std::sync::Arc;
+
+// A camera and the world it looks at, with the lights used for importance sampling
+pub struct Scene {
+    pub name: &'static str,
+    pub cam: Camera,
+    pub world: Arc<dyn Hittable + Sync + Send>,
+    pub lights: Option<Arc<dyn Hittable + Sync + Send>>,
+}
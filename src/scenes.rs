@@ -0,0 +1,975 @@
+use crate::accelerator::Accelerator;
+use crate::animated_transform::{AnimatedTransform, Keyframe};
+use crate::aperture::Aperture;
+use crate::camera::Camera;
+use crate::checker_texture::CheckerTexture;
+use crate::constant_medium::ConstantMedium;
+use crate::dielectric::Dielectric;
+use crate::diffuse_light::DiffuseLight;
+use crate::filter::Filter;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::image_texture::ImageTexture;
+use crate::instance::Instance;
+use crate::lambertian::Lambertian;
+use crate::material::Material;
+use crate::matrix_4::Matrix4;
+use crate::metal::Metal;
+use crate::noise_texture::NoiseTexture;
+use crate::quad::quad_box;
+use crate::quad::Quad;
+use crate::rtweekend::{random_f64, random_f64_range};
+use crate::scene::Scene;
+use crate::shutter::ShutterCurve;
+use crate::sphere::Sphere;
+use crate::texture::Texture;
+use crate::transform::Transform;
+use crate::vector_3::Vec3;
+use std::sync::Arc;
+
+// The built-in scenes, numbered from 1 as on the command line
+pub const SCENE_COUNT: i32 = 13;
+
+// The camera's eye distance is in metres. The Cornell box is 555 units across, so there
+// and in the book 2 scene a unit is about a centimetre.
+const CENTIMETRE_IPD: f64 = 6.4;
+
+// The scenes only rotate and translate, which can always be undone
+fn place(object: Arc<dyn Hittable + Sync + Send>, object_to_world: Matrix4) -> Arc<Transform> {
+    Arc::new(Transform::new(object, object_to_world).expect("scene transforms are invertible"))
+}
+
+// The red, green and white walls of the Cornell box. Returns the white, which the
+// boxes inside are usually made of.
+fn cornell_walls(world: &mut HittableList) -> Arc<dyn Material + Sync + Send> {
+    let red = Arc::new(Lambertian::from_color(Vec3::new(0.65, 0.05, 0.05)));
+    let white: Arc<dyn Material + Sync + Send> =
+        Arc::new(Lambertian::from_color(Vec3::new(0.73, 0.73, 0.73)));
+    let green = Arc::new(Lambertian::from_color(Vec3::new(0.12, 0.45, 0.15)));
+
+    world.add(Arc::new(Quad::new(
+        Vec3::new(555.0, 0.0, 0.0),
+        Vec3::new(0.0, 555.0, 0.0),
+        Vec3::new(0.0, 0.0, 555.0),
+        green,
+    )));
+    world.add(Arc::new(Quad::new(
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 555.0, 0.0),
+        Vec3::new(0.0, 0.0, 555.0),
+        red,
+    )));
+    world.add(Arc::new(Quad::new(
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(555.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 555.0),
+        Arc::clone(&white),
+    )));
+    world.add(Arc::new(Quad::new(
+        Vec3::new(555.0, 555.0, 555.0),
+        Vec3::new(-555.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -555.0),
+        Arc::clone(&white),
+    )));
+    world.add(Arc::new(Quad::new(
+        Vec3::new(0.0, 0.0, 555.0),
+        Vec3::new(555.0, 0.0, 0.0),
+        Vec3::new(0.0, 555.0, 0.0),
+        Arc::clone(&white),
+    )));
+
+    white
+}
+
+fn final_render_book1(width: i32, samples: i32, depth: i32, accelerator: Accelerator) -> Scene {
+    // World
+    let mut build_world = HittableList::new();
+
+    // Ground
+    let ground_mat = Arc::new(Lambertian::from_color(Vec3::new(0.5, 0.5, 0.5)));
+    build_world.add(Arc::new(Sphere::new(
+        Vec3::new(0.0, -1000.0, -0.0),
+        1000.0,
+        ground_mat,
+    )));
+
+    //Create random spheres
+    let compare_vec = Vec3::new(4.0, 0.2, 0.0);
+    for a in -11..11 {
+        for b in -11..11 {
+            let rand_mat = random_f64();
+            let center = Vec3::new(
+                a as f64 + (0.9 * random_f64()),
+                0.2,
+                b as f64 + (0.9 * random_f64()),
+            );
+
+            if (center - compare_vec).length() > 0.9 {
+                let mat: Arc<dyn Material + Sync + Send>;
+                if rand_mat < 0.8 {
+                    let albedo = Vec3::random() * Vec3::random();
+                    mat = Arc::new(Lambertian::from_color(albedo));
+                    let center_end = center + Vec3::new(0.0, random_f64() * 0.25, 0.0);
+                    build_world.add(Arc::new(Sphere::new_moving(center, 0.2, mat, center_end)));
+                } else if rand_mat < 0.95 {
+                    let albedo = Vec3::random_range(0.5, 1.0);
+                    let fuzz = random_f64_range(0.0, 0.5);
+                    mat = Arc::new(Metal::new(albedo, fuzz));
+                    build_world.add(Arc::new(Sphere::new(center, 0.2, mat)));
+                } else {
+                    mat = Arc::new(Dielectric::new(1.5));
+                    build_world.add(Arc::new(Sphere::new(center, 0.2, mat)));
+                }
+            }
+        }
+    }
+
+    // Center spheres
+    let mat = Arc::new(Dielectric::new(1.5));
+    build_world.add(Arc::new(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, mat)));
+
+    let mat = Arc::new(Lambertian::from_color(Vec3::new(0.4, 0.2, 0.1)));
+    build_world.add(Arc::new(Sphere::new(Vec3::new(-4.0, 1.0, 0.0), 1.0, mat)));
+
+    let mat = Arc::new(Metal::new(Vec3::new(0.7, 0.6, 0.5), 0.0));
+    build_world.add(Arc::new(Sphere::new(Vec3::new(4.0, 1.0, 0.0), 1.0, mat)));
+
+    // Make the world into a bvh
+    let mut world = HittableList::new();
+    world.add(accelerator.build(&build_world));
+
+    // Camera
+    let mut cam = Camera::new();
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = width;
+    cam.samples_per_pixel = samples;
+    cam.max_depth = depth;
+
+    cam.vfov = 20.0;
+    cam.look_from = Vec3::new(13.0, 2.0, 3.0);
+    cam.look_at = Vec3::new(0.0, 0.0, 0.0);
+    cam.v_up = Vec3::new(0.0, 1.0, 0.0);
+
+    cam.defocus_angle = 0.6;
+    cam.focus_dist = 10.0;
+    cam.aperture = Aperture::Polygon {
+        blades: 6,
+        rotation: 0.0,
+    };
+
+    let world = Arc::new(world);
+    Scene {
+        name: "Book1_Final",
+        cam,
+        world,
+        lights: None,
+    }
+}
+
+fn checker_spheres(width: i32, samples: i32, depth: i32) -> Scene {
+    // World
+    let mut world = HittableList::new();
+
+    let checker: Arc<dyn Texture + Sync + Send> = Arc::new(CheckerTexture::new(
+        0.32,
+        Vec3::new(0.2, 0.3, 0.1),
+        Vec3::new(0.9, 0.9, 0.9),
+    ));
+    let ground_mat = Arc::new(Lambertian::new(Arc::clone(&checker)));
+    world.add(Arc::new(Sphere::new(
+        Vec3::new(0.0, -10.0, -0.0),
+        10.0,
+        ground_mat,
+    )));
+    let ground_mat = Arc::new(Lambertian::new(checker));
+    world.add(Arc::new(Sphere::new(
+        Vec3::new(0.0, 10.0, -0.0),
+        10.0,
+        ground_mat,
+    )));
+
+    // Camera
+    let mut cam = Camera::new();
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = width;
+    cam.samples_per_pixel = samples;
+    cam.max_depth = depth;
+
+    cam.vfov = 20.0;
+    cam.look_from = Vec3::new(13.0, 2.0, 3.0);
+    cam.look_at = Vec3::new(0.0, 0.0, 0.0);
+    cam.v_up = Vec3::new(0.0, 1.0, 0.0);
+
+    cam.defocus_angle = 0.0;
+    cam.focus_dist = 10.0;
+
+    // The checkers shrink below a pixel towards the horizon, where a box filter aliases
+    cam.filter = Filter::Mitchell {
+        radius: 2.0,
+        b: 1.0 / 3.0,
+        c: 1.0 / 3.0,
+    };
+
+    let world = Arc::new(world);
+    Scene {
+        name: "Checker_Spheres",
+        cam,
+        world,
+        lights: None,
+    }
+}
+
+fn earth(width: i32, samples: i32, depth: i32) -> Scene {
+    let mut world = HittableList::new();
+
+    let earth_texture = Arc::new(ImageTexture::new("src/earthmap.jpg"));
+    let ground_mat = Arc::new(Lambertian::new(earth_texture));
+    world.add(Arc::new(Sphere::new(
+        Vec3::new(0.0, 0.0, 0.0),
+        2.0,
+        ground_mat,
+    )));
+
+    // Camera
+    let mut cam = Camera::new();
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = width;
+    cam.samples_per_pixel = samples;
+    cam.max_depth = depth;
+
+    cam.vfov = 20.0;
+    cam.look_from = Vec3::new(0.0, 0.0, 12.0);
+    cam.look_at = Vec3::new(0.0, 0.0, 0.0);
+    cam.v_up = Vec3::new(0.0, 1.0, 0.0);
+
+    cam.defocus_angle = 0.0;
+
+    let world = Arc::new(world);
+    Scene {
+        name: "Earth",
+        cam,
+        world,
+        lights: None,
+    }
+}
+
+fn two_perlin_sphere(width: i32, samples: i32, depth: i32) -> Scene {
+    let mut world = HittableList::new();
+
+    let pertext: Arc<dyn Texture + Sync + Send> = Arc::new(NoiseTexture::new(4.0));
+    let mat_0 = Arc::new(Lambertian::new(Arc::clone(&pertext)));
+    world.add(Arc::new(Sphere::new(
+        Vec3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        mat_0,
+    )));
+    let mat_1 = Arc::new(Lambertian::new(Arc::clone(&pertext)));
+    world.add(Arc::new(Sphere::new(Vec3::new(0.0, 2.0, 0.0), 2.0, mat_1)));
+
+    // Camera
+    let mut cam = Camera::new();
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = width;
+    cam.samples_per_pixel = samples;
+    cam.max_depth = depth;
+
+    cam.vfov = 20.0;
+    cam.look_from = Vec3::new(13.0, 2.0, 3.0);
+    cam.look_at = Vec3::new(0.0, 0.0, 0.0);
+    cam.v_up = Vec3::new(0.0, 1.0, 0.0);
+
+    cam.defocus_angle = 0.0;
+
+    let world = Arc::new(world);
+    Scene {
+        name: "Perlin_Sphere",
+        cam,
+        world,
+        lights: None,
+    }
+}
+
+fn quads(width: i32, samples: i32, depth: i32) -> Scene {
+    let mut world = HittableList::new();
+
+    let red = Arc::new(Lambertian::from_color(Vec3::new(1.0, 0.2, 0.2)));
+    let green = Arc::new(Lambertian::from_color(Vec3::new(0.2, 1.0, 0.2)));
+    let blue = Arc::new(Lambertian::from_color(Vec3::new(0.2, 0.2, 1.0)));
+    let orange = Arc::new(Lambertian::from_color(Vec3::new(1.0, 0.5, 0.2)));
+    let teal = Arc::new(Lambertian::from_color(Vec3::new(0.2, 0.8, 0.8)));
+
+    world.add(Arc::new(Quad::new(
+        Vec3::new(-3.0, -2.0, 5.0),
+        Vec3::new(0.0, 0.0, -4.0),
+        Vec3::new(0.0, 4.0, 0.0),
+        red,
+    )));
+    world.add(Arc::new(Quad::new(
+        Vec3::new(-2.0, -2.0, 0.0),
+        Vec3::new(4.0, 0.0, 0.0),
+        Vec3::new(0.0, 4.0, 0.0),
+        green,
+    )));
+    world.add(Arc::new(Quad::new(
+        Vec3::new(3.0, -2.0, 1.0),
+        Vec3::new(0.0, 0.0, 4.0),
+        Vec3::new(0.0, 4.0, 0.0),
+        blue,
+    )));
+    world.add(Arc::new(Quad::new(
+        Vec3::new(-2.0, 3.0, 1.0),
+        Vec3::new(4.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 4.0),
+        orange,
+    )));
+    world.add(Arc::new(Quad::new(
+        Vec3::new(-2.0, -3.0, 5.0),
+        Vec3::new(4.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -4.0),
+        teal,
+    )));
+
+    // Camera
+    let mut cam = Camera::new();
+    cam.aspect_ratio = 1.0;
+    cam.image_width = width;
+    cam.samples_per_pixel = samples;
+    cam.max_depth = depth;
+
+    cam.vfov = 80.0;
+    cam.look_from = Vec3::new(0.0, 0.0, 9.0);
+    cam.look_at = Vec3::new(0.0, 0.0, 0.0);
+    cam.v_up = Vec3::new(0.0, 1.0, 0.0);
+
+    cam.defocus_angle = 0.0;
+
+    let world = Arc::new(world);
+    Scene {
+        name: "Quads",
+        cam,
+        world,
+        lights: None,
+    }
+}
+
+fn simple_light(width: i32, samples: i32, depth: i32) -> Scene {
+    let mut world = HittableList::new();
+
+    let pertext: Arc<dyn Material + Sync + Send> =
+        Arc::new(Lambertian::new(Arc::new(NoiseTexture::new(4.0))));
+    world.add(Arc::new(Sphere::new(
+        Vec3::new(0.0, -1000.0, 0.0),
+        -1000.0,
+        Arc::clone(&pertext),
+    )));
+    world.add(Arc::new(Sphere::new(
+        Vec3::new(0.0, 2.0, 0.0),
+        2.0,
+        Arc::clone(&pertext),
+    )));
+
+    let mut lights = HittableList::new();
+    let diff_light: Arc<dyn Material + Sync + Send> =
+        Arc::new(DiffuseLight::color(Vec3::new(4.0, 4.0, 4.0)));
+    let light: Arc<dyn Hittable + Sync + Send> = Arc::new(Sphere::new(
+        Vec3::new(0.0, 7.0, 0.0),
+        2.0,
+        Arc::clone(&diff_light),
+    ));
+    world.add(Arc::clone(&light));
+    lights.add(light);
+    let light: Arc<dyn Hittable + Sync + Send> = Arc::new(Quad::new(
+        Vec3::new(3.0, 1.0, -2.0),
+        Vec3::new(2.0, 0.0, 0.0),
+        Vec3::new(0.0, 2.0, 0.0),
+        diff_light,
+    ));
+    world.add(Arc::clone(&light));
+    lights.add(light);
+
+    // Camera
+    let mut cam = Camera::new();
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = width;
+    cam.samples_per_pixel = samples;
+    cam.max_depth = depth;
+    cam.background = Vec3::new(0.0, 0.0, 0.0);
+
+    cam.vfov = 20.0;
+    cam.look_from = Vec3::new(26.0, 3.0, 6.0);
+    cam.look_at = Vec3::new(0.0, 2.0, 0.0);
+    cam.v_up = Vec3::new(0.0, 1.0, 0.0);
+
+    cam.defocus_angle = 0.0;
+
+    let world = Arc::new(world);
+    let lights = Arc::new(lights);
+    Scene {
+        name: "Simple_Light",
+        cam,
+        world,
+        lights: Some(lights),
+    }
+}
+
+fn cornell_box(width: i32, samples: i32, depth: i32) -> Scene {
+    let mut world = HittableList::new();
+
+    let light_mat = Arc::new(DiffuseLight::color(Vec3::new(15.0, 15.0, 15.0)));
+
+    // Scene
+    let light: Arc<dyn Hittable + Sync + Send> = Arc::new(Quad::new(
+        Vec3::new(343.0, 554.0, 332.0),
+        Vec3::new(-130.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -105.0),
+        light_mat,
+    ));
+    world.add(Arc::clone(&light));
+    let white = cornell_walls(&mut world);
+
+    // Boxes
+    let box_1 = quad_box(
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(165.0, 330.0, 165.0),
+        Arc::clone(&white),
+    );
+    let box_1 = place(
+        box_1,
+        Matrix4::translation(Vec3::new(265.0, 0.0, 295.0)) * Matrix4::rotation_y(15.0),
+    );
+    world.add(box_1);
+
+    let box_2 = quad_box(
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(165.0, 165.0, 165.0),
+        Arc::clone(&white),
+    );
+    let box_2 = place(
+        box_2,
+        Matrix4::translation(Vec3::new(130.0, 0.0, 65.0)) * Matrix4::rotation_y(-18.0),
+    );
+    world.add(box_2);
+
+    // Camera
+    let mut cam = Camera::new();
+    cam.aspect_ratio = 1.0;
+    cam.image_width = width;
+    cam.samples_per_pixel = samples;
+    cam.max_depth = depth;
+    cam.background = Vec3::new(0.0, 0.0, 0.0);
+
+    cam.vfov = 40.0;
+    cam.interpupillary_distance = CENTIMETRE_IPD;
+    cam.look_from = Vec3::new(278.0, 278.0, -800.0);
+    cam.look_at = Vec3::new(278.0, 278.0, 0.0);
+    cam.v_up = Vec3::new(0.0, 1.0, 0.0);
+
+    cam.defocus_angle = 0.0;
+
+    let world = Arc::new(world);
+    Scene {
+        name: "Cornell_Box",
+        cam,
+        world,
+        lights: Some(light),
+    }
+}
+
+fn cornell_smoke(width: i32, samples: i32, depth: i32) -> Scene {
+    let mut world = HittableList::new();
+    let mut lights = HittableList::new();
+
+    let light_mat = Arc::new(DiffuseLight::color(Vec3::new(7.0, 7.0, 7.0)));
+
+    // Scene
+    let light: Arc<dyn Hittable + Sync + Send> = Arc::new(Quad::new(
+        Vec3::new(113.0, 554.0, 127.0),
+        Vec3::new(330.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 305.0),
+        light_mat,
+    ));
+    world.add(Arc::clone(&light));
+    lights.add(light);
+    let white = cornell_walls(&mut world);
+
+    // Boxes
+    let box_1 = quad_box(
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(165.0, 330.0, 165.0),
+        Arc::clone(&white),
+    );
+    let box_1 = place(
+        box_1,
+        Matrix4::translation(Vec3::new(265.0, 0.0, 295.0)) * Matrix4::rotation_y(15.0),
+    );
+    world.add(Arc::new(ConstantMedium::color(
+        box_1,
+        0.01,
+        Vec3::new(0.0, 0.0, 0.0),
+    )));
+
+    let box_2 = quad_box(
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(165.0, 165.0, 165.0),
+        Arc::clone(&white),
+    );
+    let box_2 = place(
+        box_2,
+        Matrix4::translation(Vec3::new(130.0, 0.0, 65.0)) * Matrix4::rotation_y(-18.0),
+    );
+    world.add(Arc::new(ConstantMedium::color(
+        box_2,
+        0.01,
+        Vec3::new(1.0, 1.0, 1.0),
+    )));
+
+    // Camera
+    let mut cam = Camera::new();
+    cam.aspect_ratio = 1.0;
+    cam.image_width = width;
+    cam.samples_per_pixel = samples;
+    cam.max_depth = depth;
+    cam.background = Vec3::new(0.0, 0.0, 0.0);
+
+    cam.vfov = 40.0;
+    cam.interpupillary_distance = CENTIMETRE_IPD;
+    cam.look_from = Vec3::new(278.0, 278.0, -800.0);
+    cam.look_at = Vec3::new(278.0, 278.0, 0.0);
+    cam.v_up = Vec3::new(0.0, 1.0, 0.0);
+
+    cam.defocus_angle = 0.0;
+
+    let world = Arc::new(world);
+    let lights = Arc::new(lights);
+    Scene {
+        name: "Cornell_Smoke",
+        cam,
+        world,
+        lights: Some(lights),
+    }
+}
+
+fn final_scene_book2(width: i32, samples: i32, depth: i32, accelerator: Accelerator) -> Scene {
+    // Create the ground
+    let mut boxes_1 = HittableList::new();
+    let ground_mat: Arc<dyn Material + Sync + Send> =
+        Arc::new(Lambertian::from_color(Vec3::new(0.48, 0.83, 0.53)));
+
+    let boxes_per_side = 20;
+    for i in 0..boxes_per_side {
+        for j in 0..boxes_per_side {
+            let w = 100.0;
+            let x0 = -1000.0 + i as f64 * w;
+            let z0 = -1000.0 + j as f64 * w;
+            let y0 = 0.0;
+            let x1 = x0 + w;
+            let y1 = random_f64_range(1.0, 101.0);
+            let z1 = z0 + w;
+
+            boxes_1.add(quad_box(
+                Vec3::new(x0, y0, z0),
+                Vec3::new(x1, y1, z1),
+                Arc::clone(&ground_mat),
+            ));
+        }
+    }
+
+    // Create the scene
+    let mut scene = HittableList::new();
+    scene.add(accelerator.build(&boxes_1));
+
+    // Light
+    let diff_light = Arc::new(DiffuseLight::color(Vec3::new(7.0, 7.0, 7.0)));
+    let light: Arc<dyn Hittable + Sync + Send> = Arc::new(Quad::new(
+        Vec3::new(123.0, 554.0, 147.0),
+        Vec3::new(300.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 265.0),
+        diff_light,
+    ));
+    scene.add(Arc::clone(&light));
+
+    // Spheres
+    let center_1 = Vec3::new(400.0, 400.0, 200.0);
+    let center_2 = center_1 + Vec3::new(30.0, 0.0, 0.0);
+    let sphere_mat: Arc<dyn Material + Sync + Send> =
+        Arc::new(Lambertian::from_color(Vec3::new(0.7, 0.3, 0.1)));
+    scene.add(Arc::new(Sphere::new_moving(
+        center_1, 50.0, sphere_mat, center_2,
+    )));
+
+    scene.add(Arc::new(Sphere::new(
+        Vec3::new(260.0, 150.0, 45.0),
+        50.0,
+        Arc::new(Dielectric::new(1.5)),
+    )));
+    scene.add(Arc::new(Sphere::new(
+        Vec3::new(0.0, 150.0, 145.0),
+        50.0,
+        Arc::new(Metal::new(Vec3::new(0.8, 0.8, 0.9), 1.0)),
+    )));
+
+    // Fog
+    let boundary: Arc<dyn Hittable + Sync + Send> = Arc::new(Sphere::new(
+        Vec3::new(360.0, 150.0, 145.0),
+        70.0,
+        Arc::new(Dielectric::new(1.5)),
+    ));
+    scene.add(Arc::clone(&boundary));
+    scene.add(Arc::new(ConstantMedium::color(
+        boundary,
+        0.2,
+        Vec3::new(0.2, 0.4, 0.9),
+    )));
+    let fog = Arc::new(Sphere::new(
+        Vec3::new(0.0, 0.0, 0.0),
+        5000.0,
+        Arc::new(Dielectric::new(1.5)),
+    ));
+    scene.add(Arc::new(ConstantMedium::color(
+        fog,
+        0.0001,
+        Vec3::new(1.0, 1.0, 1.0),
+    )));
+
+    // Earth
+    let earth_texture = Arc::new(ImageTexture::new("src/earthmap.jpg"));
+    let emat = Arc::new(Lambertian::new(earth_texture));
+    scene.add(Arc::new(Sphere::new(
+        Vec3::new(400.0, 200.0, 400.0),
+        100.0,
+        emat,
+    )));
+
+    // Perlin
+    let pertext = Arc::new(NoiseTexture::new(0.1));
+    let pmat = Arc::new(Lambertian::new(pertext));
+    scene.add(Arc::new(Sphere::new(
+        Vec3::new(220.0, 280.0, 300.0),
+        80.0,
+        pmat,
+    )));
+
+    // Sphere box
+    let mut spheres = HittableList::new();
+    let white: Arc<dyn Material + Sync + Send> =
+        Arc::new(Lambertian::from_color(Vec3::new(0.73, 0.73, 0.73)));
+    let ns = 1000;
+    for _ in 0..ns {
+        spheres.add(Arc::new(Sphere::new(
+            Vec3::random_range(0.0, 165.0),
+            10.0,
+            Arc::clone(&white),
+        )));
+    }
+    scene.add(place(
+        accelerator.build(&spheres),
+        Matrix4::translation(Vec3::new(-100.0, 270.0, 395.0)) * Matrix4::rotation_y(15.0),
+    ));
+
+    // Render
+    let mut cam = Camera::new();
+    cam.aspect_ratio = 1.0;
+    cam.image_width = width;
+    cam.samples_per_pixel = samples;
+    cam.max_depth = depth;
+    cam.background = Vec3::new(0.0, 0.0, 0.0);
+
+    cam.vfov = 40.0;
+    cam.interpupillary_distance = CENTIMETRE_IPD;
+    cam.look_from = Vec3::new(478.0, 278.0, -600.0);
+    cam.look_at = Vec3::new(278.0, 278.0, 0.0);
+    cam.v_up = Vec3::new(0.0, 1.0, 0.0);
+
+    cam.defocus_angle = 0.0;
+
+    let world = Arc::new(scene);
+    Scene {
+        name: "Book2_Final",
+        cam,
+        world,
+        lights: Some(light),
+    }
+}
+
+fn cornell_box_metal(width: i32, samples: i32, depth: i32) -> Scene {
+    let mut world = HittableList::new();
+
+    let light_mat = Arc::new(DiffuseLight::color(Vec3::new(15.0, 15.0, 15.0)));
+    let metal_mat: Arc<dyn Material + Sync + Send> =
+        Arc::new(Metal::new(Vec3::new(0.8, 0.85, 0.88), 0.0));
+
+    // Scene
+    let light: Arc<dyn Hittable + Sync + Send> = Arc::new(Quad::new(
+        Vec3::new(343.0, 554.0, 332.0),
+        Vec3::new(-130.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -105.0),
+        light_mat,
+    ));
+    world.add(Arc::clone(&light));
+    let white = cornell_walls(&mut world);
+
+    // Boxes
+    let box_1 = quad_box(
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(165.0, 330.0, 165.0),
+        metal_mat,
+    );
+    let box_1 = place(
+        box_1,
+        Matrix4::translation(Vec3::new(265.0, 0.0, 295.0)) * Matrix4::rotation_y(15.0),
+    );
+    world.add(box_1);
+
+    let box_2 = quad_box(
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(165.0, 165.0, 165.0),
+        Arc::clone(&white),
+    );
+    let box_2 = place(
+        box_2,
+        Matrix4::translation(Vec3::new(130.0, 0.0, 65.0)) * Matrix4::rotation_y(-18.0),
+    );
+    world.add(box_2);
+
+    // Camera
+    let mut cam = Camera::new();
+    cam.aspect_ratio = 1.0;
+    cam.image_width = width;
+    cam.samples_per_pixel = samples;
+    cam.max_depth = depth;
+    cam.background = Vec3::new(0.0, 0.0, 0.0);
+
+    cam.vfov = 40.0;
+    cam.interpupillary_distance = CENTIMETRE_IPD;
+    cam.look_from = Vec3::new(278.0, 278.0, -800.0);
+    cam.look_at = Vec3::new(278.0, 278.0, 0.0);
+    cam.v_up = Vec3::new(0.0, 1.0, 0.0);
+
+    cam.defocus_angle = 0.0;
+
+    let world = Arc::new(world);
+    Scene {
+        name: "Cornell_Box_Metal",
+        cam,
+        world,
+        lights: Some(light),
+    }
+}
+
+fn cornell_box_glass_sphere(width: i32, samples: i32, depth: i32) -> Scene {
+    let mut world = HittableList::new();
+
+    let light_mat = Arc::new(DiffuseLight::color(Vec3::new(15.0, 15.0, 15.0)));
+    let glass = Arc::new(Dielectric::new(1.5));
+
+    // Scene
+    let light: Arc<dyn Hittable + Sync + Send> = Arc::new(Quad::new(
+        Vec3::new(343.0, 554.0, 332.0),
+        Vec3::new(-130.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -105.0),
+        light_mat,
+    ));
+    world.add(Arc::clone(&light));
+    let white = cornell_walls(&mut world);
+
+    // Boxes
+    let box_1 = quad_box(
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(165.0, 330.0, 165.0),
+        Arc::clone(&white),
+    );
+    let box_1 = place(
+        box_1,
+        Matrix4::translation(Vec3::new(265.0, 0.0, 295.0)) * Matrix4::rotation_y(15.0),
+    );
+    world.add(box_1);
+
+    let sphere = Arc::new(Sphere::new(Vec3::new(190.0, 90.0, 190.0), 90.0, glass));
+    world.add(sphere);
+
+    // Camera
+    let mut cam = Camera::new();
+    cam.aspect_ratio = 1.0;
+    cam.image_width = width;
+    cam.samples_per_pixel = samples;
+    cam.max_depth = depth;
+    cam.background = Vec3::new(0.0, 0.0, 0.0);
+
+    cam.vfov = 40.0;
+    cam.interpupillary_distance = CENTIMETRE_IPD;
+    cam.look_from = Vec3::new(278.0, 278.0, -800.0);
+    cam.look_at = Vec3::new(278.0, 278.0, 0.0);
+    cam.v_up = Vec3::new(0.0, 1.0, 0.0);
+
+    cam.defocus_angle = 0.0;
+
+    let world = Arc::new(world);
+    Scene {
+        name: "Cornell_Box_Glass_Sphere",
+        cam,
+        world,
+        lights: Some(light),
+    }
+}
+
+fn cornell_box_motion_blur(width: i32, samples: i32, depth: i32) -> Scene {
+    let mut world = HittableList::new();
+
+    let light_mat = Arc::new(DiffuseLight::color(Vec3::new(15.0, 15.0, 15.0)));
+
+    // Scene
+    let light: Arc<dyn Hittable + Sync + Send> = Arc::new(Quad::new(
+        Vec3::new(343.0, 554.0, 332.0),
+        Vec3::new(-130.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -105.0),
+        light_mat,
+    ));
+    world.add(Arc::clone(&light));
+    let white = cornell_walls(&mut world);
+
+    // The tall box spins about its own axis
+    let box_1 = quad_box(
+        Vec3::new(-82.5, 0.0, -82.5),
+        Vec3::new(82.5, 330.0, 82.5),
+        Arc::clone(&white),
+    );
+    let mut start = Keyframe::new(0.0);
+    start.translation = Vec3::new(347.5, 0.0, 377.5);
+    start.rotation_angle = 15.0;
+    let mut end = start;
+    end.time = 1.0;
+    end.rotation_angle = 75.0;
+    world.add(Arc::new(AnimatedTransform::new(box_1, vec![start, end])));
+
+    // The short box slides towards the red wall while it grows
+    let box_2 = quad_box(
+        Vec3::new(-82.5, 0.0, -82.5),
+        Vec3::new(82.5, 165.0, 82.5),
+        Arc::clone(&white),
+    );
+    let mut start = Keyframe::new(0.0);
+    start.translation = Vec3::new(212.5, 0.0, 147.5);
+    start.rotation_angle = -18.0;
+    let mut end = start;
+    end.time = 1.0;
+    end.translation = Vec3::new(162.5, 0.0, 147.5);
+    end.scale = Vec3::new(1.0, 1.3, 1.0);
+    world.add(Arc::new(AnimatedTransform::new(box_2, vec![start, end])));
+
+    // Camera
+    let mut cam = Camera::new();
+    cam.aspect_ratio = 1.0;
+    cam.image_width = width;
+    cam.samples_per_pixel = samples;
+    cam.max_depth = depth;
+    cam.background = Vec3::new(0.0, 0.0, 0.0);
+
+    cam.vfov = 40.0;
+    cam.interpupillary_distance = CENTIMETRE_IPD;
+    cam.look_from = Vec3::new(278.0, 278.0, -800.0);
+    cam.look_at = Vec3::new(278.0, 278.0, 0.0);
+    cam.v_up = Vec3::new(0.0, 1.0, 0.0);
+
+    cam.defocus_angle = 0.0;
+    cam.shutter.curve = ShutterCurve::Triangle;
+
+    let world = Arc::new(world);
+    Scene {
+        name: "Cornell_Box_Motion_Blur",
+        cam,
+        world,
+        lights: Some(light),
+    }
+}
+
+fn instanced_sphere_boxes(width: i32, samples: i32, depth: i32, accelerator: Accelerator) -> Scene {
+    let mut world = HittableList::new();
+
+    // Ground
+    let ground_mat = Arc::new(Lambertian::from_color(Vec3::new(0.5, 0.5, 0.5)));
+    world.add(Arc::new(Sphere::new(
+        Vec3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        ground_mat,
+    )));
+
+    // One box of spheres, built once and shared by every instance
+    let mut spheres = HittableList::new();
+    let white: Arc<dyn Material + Sync + Send> =
+        Arc::new(Lambertian::from_color(Vec3::new(0.73, 0.73, 0.73)));
+    for _ in 0..1000 {
+        spheres.add(Arc::new(Sphere::new(
+            Vec3::random_range(-0.5, 0.5),
+            0.03,
+            Arc::clone(&white),
+        )));
+    }
+    let geometry = accelerator.build(&spheres);
+
+    // 400 copies, each turned, scaled and coloured on its own
+    let mut instances = HittableList::new();
+    for a in -10..10 {
+        for b in -10..10 {
+            let size = random_f64_range(0.4, 0.8);
+            let placement =
+                Matrix4::translation(Vec3::new(a as f64 + 0.5, size / 2.0, b as f64 + 0.5))
+                    * Matrix4::rotation_y(random_f64_range(0.0, 90.0))
+                    * Matrix4::scale(Vec3::new(size, size, size));
+            let material: Arc<dyn Material + Sync + Send> = if random_f64() < 0.8 {
+                Arc::new(Lambertian::from_color(Vec3::random() * Vec3::random()))
+            } else {
+                Arc::new(Metal::new(Vec3::random_range(0.5, 1.0), 0.2))
+            };
+            let instance = Instance::new(Arc::clone(&geometry), placement, Some(material));
+            instances.add(Arc::new(instance.expect("scaled boxes are invertible")));
+        }
+    }
+    world.add(accelerator.build(&instances));
+
+    // Camera
+    let mut cam = Camera::new();
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = width;
+    cam.samples_per_pixel = samples;
+    cam.max_depth = depth;
+
+    cam.vfov = 30.0;
+    cam.look_from = Vec3::new(12.0, 5.0, 9.0);
+    cam.look_at = Vec3::new(0.0, 0.0, 0.0);
+    cam.v_up = Vec3::new(0.0, 1.0, 0.0);
+
+    cam.defocus_angle = 0.0;
+
+    let world = Arc::new(world);
+    Scene {
+        name: "Instanced_Sphere_Boxes",
+        cam,
+        world,
+        lights: None,
+    }
+}
+
+pub fn build_scene(
+    index: i32,
+    width: i32,
+    samples: i32,
+    depth: i32,
+    accelerator: Accelerator,
+) -> Scene {
+    match index {
+        1 => final_render_book1(width, samples, depth, accelerator),
+        2 => checker_spheres(width, samples, depth),
+        3 => earth(width, samples, depth),
+        4 => two_perlin_sphere(width, samples, depth),
+        5 => quads(width, samples, depth),
+        6 => simple_light(width, samples, depth),
+        7 => cornell_box(width, samples, depth),
+        8 => cornell_smoke(width, samples, depth),
+        9 => final_scene_book2(width, samples, depth, accelerator),
+        10 => cornell_box_metal(width, samples, depth),
+        11 => cornell_box_glass_sphere(width, samples, depth),
+        12 => cornell_box_motion_blur(width, samples, depth),
+        13 => instanced_sphere_boxes(width, samples, depth, accelerator),
+        _ => final_scene_book2(width, samples, depth, accelerator),
+    }
+}
@@ -0,0 +1,144 @@
+use crate::rtweekend::random_f64;
+
+// How far open the shutter is over the exposure
+#[derive(Debug, Clone)]
+pub enum ShutterCurve {
+    // Fully open for the whole exposure
+    Box,
+    // Opens linearly to fully open half way, then closes again
+    Triangle,
+    // Takes `ramp` of the exposure to open and the same to close
+    Trapezoid { ramp: f64 },
+    // Openness at evenly spaced times from open to close, linearly interpolated
+    Tabulated(Vec<f64>),
+}
+
+impl ShutterCurve {
+    // Openness at x, from 0 at open to 1 at close
+    pub fn openness(&self, x: f64) -> f64 {
+        match self {
+            ShutterCurve::Box => 1.0,
+            ShutterCurve::Triangle => 1.0 - (2.0 * x - 1.0).abs(),
+            ShutterCurve::Trapezoid { ramp } => {
+                let ramp = ramp.clamp(1e-6, 0.5);
+                (x.min(1.0 - x) / ramp).min(1.0)
+            }
+            ShutterCurve::Tabulated(values) => match values.len() {
+                0 => 1.0,
+                1 => values[0],
+                count => {
+                    let position = x.clamp(0.0, 1.0) * (count - 1) as f64;
+                    let index = (position as usize).min(count - 2);
+                    let fraction = position - index as f64;
+                    values[index] * (1.0 - fraction) + values[index + 1] * fraction
+                }
+            },
+        }
+    }
+
+    fn max_openness(&self) -> f64 {
+        match self {
+            ShutterCurve::Tabulated(values) => values.iter().copied().fold(0.0, f64::max),
+            _ => 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Shutter {
+    pub open: f64,
+    pub close: f64,
+    pub curve: ShutterCurve,
+}
+
+impl Default for Shutter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shutter {
+    pub fn new() -> Shutter {
+        Shutter {
+            open: 0.0,
+            close: 1.0,
+            curve: ShutterCurve::Box,
+        }
+    }
+
+    // Ray time, more likely where the shutter is further open
+    pub fn sample_time(&self) -> f64 {
+        let max_openness = self.curve.max_openness();
+        let x = match self.curve {
+            ShutterCurve::Box => random_f64(),
+            _ if max_openness <= 0.0 => random_f64(),
+            _ => loop {
+                let x = random_f64();
+                if random_f64() * max_openness < self.curve.openness(x) {
+                    break x;
+                }
+            },
+        };
+        self.open + x * (self.close - self.open)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rtweekend::with_seed;
+
+    #[test]
+    fn sampled_times_follow_the_curve() {
+        let curves = [
+            ShutterCurve::Box,
+            ShutterCurve::Triangle,
+            ShutterCurve::Trapezoid { ramp: 0.2 },
+            ShutterCurve::Tabulated(vec![0.0, 1.0, 0.2, 0.6]),
+        ];
+        let (open, close) = (0.25, 0.75);
+        let bins = 20;
+        let count = 200_000;
+        for curve in curves {
+            // Chance of each bin, from the area under the curve
+            let areas: Vec<f64> = (0..bins)
+                .map(|bin| {
+                    (0..100)
+                        .map(|step| {
+                            curve.openness((bin as f64 + (step as f64 + 0.5) / 100.0) / bins as f64)
+                        })
+                        .sum::<f64>()
+                })
+                .collect();
+            let total: f64 = areas.iter().sum();
+
+            let shutter = Shutter {
+                open,
+                close,
+                curve: curve.clone(),
+            };
+            let mut histogram = vec![0; bins];
+            with_seed(9, || {
+                for _ in 0..count {
+                    let time = shutter.sample_time();
+                    assert!((open..close).contains(&time));
+                    let x = (time - open) / (close - open);
+                    histogram[((x * bins as f64) as usize).min(bins - 1)] += 1;
+                }
+            });
+            for (bin, &found) in histogram.iter().enumerate() {
+                let p = areas[bin] / total;
+                let expected = p * count as f64;
+                let error = (count as f64 * p * (1.0 - p)).sqrt();
+                assert!(
+                    (found as f64 - expected).abs() <= 5.0 * error + 1.0,
+                    "{:?} bin {}: {} against {}",
+                    curve,
+                    bin,
+                    found,
+                    expected
+                );
+            }
+        }
+    }
+}
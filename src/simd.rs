@@ -0,0 +1,358 @@
+use std::ops;
+
+use crate::vector_3::Vec3f;
+
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+// Four f32 lanes, for testing several bounding boxes against one ray at once. On
+// x86_64 it is an SSE register, which every x86_64 CPU has, and elsewhere a plain
+// array the compiler is left to vectorise.
+#[cfg(target_arch = "x86_64")]
+#[derive(Debug, Clone, Copy)]
+pub struct F32x4(__m128);
+
+#[cfg(not(target_arch = "x86_64"))]
+#[derive(Debug, Clone, Copy)]
+pub struct F32x4([f32; 4]);
+
+#[cfg(target_arch = "x86_64")]
+impl F32x4 {
+    pub fn new(a: f32, b: f32, c: f32, d: f32) -> F32x4 {
+        unsafe { F32x4(_mm_setr_ps(a, b, c, d)) }
+    }
+
+    pub fn splat(value: f32) -> F32x4 {
+        unsafe { F32x4(_mm_set1_ps(value)) }
+    }
+
+    pub fn to_array(self) -> [f32; 4] {
+        let mut lanes = [0.0; 4];
+        unsafe { _mm_storeu_ps(lanes.as_mut_ptr(), self.0) };
+        lanes
+    }
+
+    // Lane by lane minimum and maximum. Where either lane is NaN the result is the
+    // lane of `other`, so a NaN can be dropped by passing it as `self`.
+    pub fn min(self, other: F32x4) -> F32x4 {
+        unsafe { F32x4(_mm_min_ps(self.0, other.0)) }
+    }
+
+    pub fn max(self, other: F32x4) -> F32x4 {
+        unsafe { F32x4(_mm_max_ps(self.0, other.0)) }
+    }
+
+    // Bit n is set where lane n of `self` is at most lane n of `other`
+    pub fn le_mask(self, other: F32x4) -> u32 {
+        unsafe { _mm_movemask_ps(_mm_cmple_ps(self.0, other.0)) as u32 }
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+impl F32x4 {
+    pub fn new(a: f32, b: f32, c: f32, d: f32) -> F32x4 {
+        F32x4([a, b, c, d])
+    }
+
+    pub fn splat(value: f32) -> F32x4 {
+        F32x4([value; 4])
+    }
+
+    pub fn to_array(self) -> [f32; 4] {
+        self.0
+    }
+
+    // Written to give the same lane as the SSE instructions when one is NaN
+    pub fn min(self, other: F32x4) -> F32x4 {
+        F32x4(std::array::from_fn(|n| {
+            if self.0[n] < other.0[n] {
+                self.0[n]
+            } else {
+                other.0[n]
+            }
+        }))
+    }
+
+    pub fn max(self, other: F32x4) -> F32x4 {
+        F32x4(std::array::from_fn(|n| {
+            if self.0[n] > other.0[n] {
+                self.0[n]
+            } else {
+                other.0[n]
+            }
+        }))
+    }
+
+    pub fn le_mask(self, other: F32x4) -> u32 {
+        (0..4)
+            .filter(|&n| self.0[n] <= other.0[n])
+            .fold(0, |mask, n| mask | (1 << n))
+    }
+}
+
+impl F32x4 {
+    pub fn from_array(lanes: [f32; 4]) -> F32x4 {
+        F32x4::new(lanes[0], lanes[1], lanes[2], lanes[3])
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+impl ops::Add for F32x4 {
+    type Output = F32x4;
+
+    fn add(self, rhs: F32x4) -> F32x4 {
+        unsafe { F32x4(_mm_add_ps(self.0, rhs.0)) }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+impl ops::Sub for F32x4 {
+    type Output = F32x4;
+
+    fn sub(self, rhs: F32x4) -> F32x4 {
+        unsafe { F32x4(_mm_sub_ps(self.0, rhs.0)) }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+impl ops::Mul for F32x4 {
+    type Output = F32x4;
+
+    fn mul(self, rhs: F32x4) -> F32x4 {
+        unsafe { F32x4(_mm_mul_ps(self.0, rhs.0)) }
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+impl ops::Add for F32x4 {
+    type Output = F32x4;
+
+    fn add(self, rhs: F32x4) -> F32x4 {
+        F32x4(std::array::from_fn(|n| self.0[n] + rhs.0[n]))
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+impl ops::Sub for F32x4 {
+    type Output = F32x4;
+
+    fn sub(self, rhs: F32x4) -> F32x4 {
+        F32x4(std::array::from_fn(|n| self.0[n] - rhs.0[n]))
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+impl ops::Mul for F32x4 {
+    type Output = F32x4;
+
+    fn mul(self, rhs: F32x4) -> F32x4 {
+        F32x4(std::array::from_fn(|n| self.0[n] * rhs.0[n]))
+    }
+}
+
+// Four f32 vectors, stored x, y and z lane by lane so each operation works on all
+// four at once, the SIMD counterpart of `Vec3f`
+#[derive(Debug, Clone, Copy)]
+pub struct Vec3x4 {
+    pub x: F32x4,
+    pub y: F32x4,
+    pub z: F32x4,
+}
+
+impl Vec3x4 {
+    pub fn new(x: F32x4, y: F32x4, z: F32x4) -> Vec3x4 {
+        Vec3x4 { x, y, z }
+    }
+
+    pub fn splat(vector: Vec3f) -> Vec3x4 {
+        Vec3x4::new(
+            F32x4::splat(vector.x),
+            F32x4::splat(vector.y),
+            F32x4::splat(vector.z),
+        )
+    }
+
+    pub fn from_vectors(vectors: [Vec3f; 4]) -> Vec3x4 {
+        Vec3x4::new(
+            F32x4::from_array(vectors.map(|v| v.x)),
+            F32x4::from_array(vectors.map(|v| v.y)),
+            F32x4::from_array(vectors.map(|v| v.z)),
+        )
+    }
+
+    pub fn to_vectors(self) -> [Vec3f; 4] {
+        let (x, y, z) = (self.x.to_array(), self.y.to_array(), self.z.to_array());
+        std::array::from_fn(|n| Vec3f::new(x[n], y[n], z[n]))
+    }
+
+    pub fn dot(self, rhs: Vec3x4) -> F32x4 {
+        self.x * rhs.x + self.y * rhs.y + self.z * rhs.z
+    }
+
+    pub fn cross(self, rhs: Vec3x4) -> Vec3x4 {
+        Vec3x4::new(
+            self.y * rhs.z - self.z * rhs.y,
+            self.z * rhs.x - self.x * rhs.z,
+            self.x * rhs.y - self.y * rhs.x,
+        )
+    }
+
+    pub fn length_squared(self) -> F32x4 {
+        self.dot(self)
+    }
+
+    pub fn min(self, other: Vec3x4) -> Vec3x4 {
+        Vec3x4::new(
+            self.x.min(other.x),
+            self.y.min(other.y),
+            self.z.min(other.z),
+        )
+    }
+
+    pub fn max(self, other: Vec3x4) -> Vec3x4 {
+        Vec3x4::new(
+            self.x.max(other.x),
+            self.y.max(other.y),
+            self.z.max(other.z),
+        )
+    }
+}
+
+impl ops::Add for Vec3x4 {
+    type Output = Vec3x4;
+
+    fn add(self, rhs: Vec3x4) -> Vec3x4 {
+        Vec3x4::new(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z)
+    }
+}
+
+impl ops::Sub for Vec3x4 {
+    type Output = Vec3x4;
+
+    fn sub(self, rhs: Vec3x4) -> Vec3x4 {
+        Vec3x4::new(self.x - rhs.x, self.y - rhs.y, self.z - rhs.z)
+    }
+}
+
+impl ops::Mul for Vec3x4 {
+    type Output = Vec3x4;
+
+    fn mul(self, rhs: Vec3x4) -> Vec3x4 {
+        Vec3x4::new(self.x * rhs.x, self.y * rhs.y, self.z * rhs.z)
+    }
+}
+
+impl ops::Mul<F32x4> for Vec3x4 {
+    type Output = Vec3x4;
+
+    fn mul(self, rhs: F32x4) -> Vec3x4 {
+        Vec3x4::new(self.x * rhs, self.y * rhs, self.z * rhs)
+    }
+}
+
+// Nearest f32 at or below, and at or above, an f64, so boxes converted to f32 only
+// ever grow
+pub fn round_down(value: f64) -> f32 {
+    let rounded = value as f32;
+    if rounded as f64 > value {
+        rounded.next_down()
+    } else {
+        rounded
+    }
+}
+
+pub fn round_up(value: f64) -> f32 {
+    let rounded = value as f32;
+    if (rounded as f64) < value {
+        rounded.next_up()
+    } else {
+        rounded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lanes() {
+        let a = F32x4::new(1.0, -2.0, 3.0, f32::INFINITY);
+        let b = F32x4::splat(2.0);
+        assert_eq!((a + b).to_array(), [3.0, 0.0, 5.0, f32::INFINITY]);
+        assert_eq!((a - b).to_array(), [-1.0, -4.0, 1.0, f32::INFINITY]);
+        assert_eq!((a * b).to_array(), [2.0, -4.0, 6.0, f32::INFINITY]);
+        assert_eq!(a.min(b).to_array(), [1.0, -2.0, 2.0, 2.0]);
+        assert_eq!(a.max(b).to_array(), [2.0, 2.0, 3.0, f32::INFINITY]);
+        assert_eq!(a.le_mask(b), 0b0011);
+    }
+
+    #[test]
+    fn nan_lanes_take_the_other_operand() {
+        let nan = F32x4::new(f32::NAN, 1.0, f32::NAN, 1.0);
+        let other = F32x4::new(5.0, 5.0, -5.0, -5.0);
+        assert_eq!(nan.min(other).to_array(), [5.0, 1.0, -5.0, -5.0]);
+        assert_eq!(nan.max(other).to_array(), [5.0, 5.0, -5.0, 1.0]);
+        assert_eq!(nan.le_mask(other), 0b0010);
+    }
+
+    #[test]
+    fn rounding_outwards() {
+        for value in [0.1, -0.1, 1e-40, 555.0, 1e300, -1e300, f64::INFINITY] {
+            assert!(round_down(value) as f64 <= value);
+            assert!(round_up(value) as f64 >= value);
+            assert!(
+                round_up(value) as f64 - round_down(value) as f64 <= value.abs() * 1.2e-7 + 1.5e-45
+                    || value.abs() > f32::MAX as f64
+            );
+        }
+        assert_eq!(round_down(0.5), 0.5);
+        assert_eq!(round_up(0.5), 0.5);
+    }
+
+    #[test]
+    fn vectors_match_scalar_vec3f() {
+        let a = [
+            Vec3f::new(1.0, 2.0, 3.0),
+            Vec3f::new(-0.5, 0.25, 8.0),
+            Vec3f::new(0.0, -1.0, 0.0),
+            Vec3f::new(1e3, 1e-3, -7.0),
+        ];
+        let b = [
+            Vec3f::new(4.0, -5.0, 6.0),
+            Vec3f::new(2.0, 2.0, 2.0),
+            Vec3f::new(1.0, 0.0, 0.0),
+            Vec3f::new(-3.0, 0.5, 0.125),
+        ];
+        let (a4, b4) = (Vec3x4::from_vectors(a), Vec3x4::from_vectors(b));
+        let scale = F32x4::new(1.0, -2.0, 0.5, 3.0);
+        let dots = a4.dot(b4).to_array();
+        let lengths = a4.length_squared().to_array();
+        let lanes = [
+            (a4 + b4).to_vectors(),
+            (a4 - b4).to_vectors(),
+            (a4 * b4).to_vectors(),
+            (a4 * scale).to_vectors(),
+            a4.cross(b4).to_vectors(),
+            a4.min(b4).to_vectors(),
+            a4.max(b4).to_vectors(),
+        ];
+        let same = |v: Vec3f, w: Vec3f| (v.x, v.y, v.z) == (w.x, w.y, w.z);
+        for n in 0..4 {
+            assert_eq!(dots[n], a[n].dot(&b[n]));
+            assert_eq!(lengths[n], a[n].length_squared());
+            let expected = [
+                a[n] + b[n],
+                a[n] - b[n],
+                a[n] * b[n],
+                a[n] * scale.to_array()[n],
+                a[n].cross(&b[n]),
+                Vec3f::new(a[n].x.min(b[n].x), a[n].y.min(b[n].y), a[n].z.min(b[n].z)),
+                Vec3f::new(a[n].x.max(b[n].x), a[n].y.max(b[n].y), a[n].z.max(b[n].z)),
+            ];
+            for (lane, expected) in lanes.iter().zip(expected) {
+                assert!(same(lane[n], expected), "{:?} != {:?}", lane[n], expected);
+            }
+        }
+        assert!(same(Vec3x4::splat(a[1]).to_vectors()[3], a[1]));
+    }
+}
@@ -1,6 +1,7 @@
 use crate::texture::Texture;
 use crate::vector_3::Vec3;
 
+#[derive(Debug)]
 pub struct SolidTexture {
     color: Vec3,
 }
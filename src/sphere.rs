@@ -5,12 +5,16 @@ use std::sync::Arc;
 use crate::aabb::AABB;
 use crate::hittable::HitRecord;
 use crate::hittable::Hittable;
-use crate::interval::Interval;
+use crate::interval::{Interval, Span};
 use crate::material::Material;
 use crate::orthonormal_basis::OrthonormalBasis;
-use crate::ray::Ray3;
-use crate::vector_3::Vec3;
+use crate::ray::{Ray, Ray3};
+use crate::ray_stats::{self, Counter};
+use crate::real::Real;
+use crate::rtweekend::random_f64;
+use crate::vector_3::{Vec3, Vector3};
 
+#[derive(Debug)]
 pub struct Sphere {
     center_start: Vec3,
     radius: f64,
@@ -22,39 +26,25 @@ pub struct Sphere {
 
 impl Hittable for Sphere {
     fn hit(&self, r: &Ray3, time: Interval, hit_record: &mut HitRecord) -> bool {
+        ray_stats::count(Counter::PrimitiveTest);
         let center = if self.b_moving {
             self.center(r.time())
         } else {
             self.center_start
         };
-        let oc = r.origin() - center;
-        let a = r.direction().length_squared();
-        let half_b = oc.dot(&r.direction());
-        let c = oc.length_squared() - (self.radius * self.radius);
-
-        let discriminant = (half_b * half_b) - (a * c);
-        if discriminant < 0.0 {
-            false
-        } else {
-            let d_sqrt = discriminant.sqrt();
-
-            // Find the nearest root that lies in the acceptable range
-            let mut root = (-half_b - d_sqrt) / a;
-            if !time.surrounds(root) {
-                root = (-half_b + d_sqrt) / a;
-            }
-            if !time.surrounds(root) {
-                false
-            } else {
+        match Sphere::intersect(&center, self.radius, r, &time) {
+            Some(root) => {
                 // Calculate the hit
                 hit_record.time = root;
                 hit_record.point = r.at(hit_record.time);
                 let outward_normal = (hit_record.point - center) / self.radius;
                 hit_record.set_face_normal(r, outward_normal);
                 hit_record.mat = Arc::clone(&self.mat);
+                hit_record.object = self as *const Sphere as usize;
                 Sphere::sphere_uv(outward_normal, &mut hit_record.u, &mut hit_record.v);
                 true
             }
+            None => false,
         }
     }
 
@@ -117,6 +107,33 @@ impl Sphere {
         }
     }
 
+    // Distance along the ray to the nearest crossing of the sphere inside the interval,
+    // in either precision
+    pub fn intersect<T: Real>(
+        center: &Vector3<T>,
+        radius: T,
+        r: &Ray<T>,
+        interval: &Span<T>,
+    ) -> Option<T> {
+        let oc = r.origin() - center;
+        let a = r.direction().length_squared();
+        let half_b = oc.dot(r.direction());
+        let c = oc.length_squared() - (radius * radius);
+
+        let discriminant = (half_b * half_b) - (a * c);
+        if discriminant < T::ZERO {
+            return None;
+        }
+        let d_sqrt = discriminant.sqrt();
+
+        // Find the nearest root that lies in the acceptable range
+        let mut root = (-half_b - d_sqrt) / a;
+        if !interval.surrounds(root) {
+            root = (-half_b + d_sqrt) / a;
+        }
+        interval.surrounds(root).then_some(root)
+    }
+
     pub fn center(&self, time: f64) -> Vec3 {
         self.center_start + self.center_move * time
     }
@@ -130,8 +147,8 @@ impl Sphere {
     }
 
     pub fn random_to_sphere(radius: f64, dist_sq: f64) -> Vec3 {
-        let r1 = rand::random::<f64>();
-        let r2 = rand::random::<f64>();
+        let r1 = random_f64();
+        let r2 = random_f64();
         let z = 1.0 + r2 * (f64::sqrt(1.0 - ((radius * radius) / dist_sq)) - 1.0);
 
         let z_sqrt = f64::sqrt(1.0 - (z * z));
@@ -142,3 +159,52 @@ impl Sphere {
         Vec3::new(x, y, z)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interval::Intervalf;
+    use crate::ray::Ray3f;
+    use crate::rtweekend::with_seed;
+
+    #[test]
+    fn f32_intersections_match_f64() {
+        with_seed(5, || {
+            let (mut hits, mut disagreements) = (0, 0);
+            for _ in 0..10_000 {
+                let center = Vec3::random_range(-2.0, 2.0);
+                let radius = 0.5 + random_f64();
+                // Aimed near the sphere from outside it, so most rays hit
+                let origin = 8.0 * Vec3::random_unit_vector();
+                let target = center + 1.2 * radius * Vec3::random_in_unit_sphere();
+                let ray = Ray3::new(origin, target - origin, 0.0);
+                let t =
+                    Sphere::intersect(&center, radius, &ray, &Interval::new(0.001, f64::INFINITY));
+                let ray_f = Ray3f::new(ray.origin().cast(), ray.direction().cast(), 0.0);
+                let t_f = Sphere::intersect(
+                    &center.cast::<f32>(),
+                    radius as f32,
+                    &ray_f,
+                    &Intervalf::new(0.001, f32::INFINITY),
+                );
+                match (t, t_f) {
+                    (Some(t), Some(t_f)) => {
+                        hits += 1;
+                        // The f32 root loses most precision where the ray grazes
+                        let miss = (ray.at(t) - ray_f.at(t_f).cast()).length();
+                        assert!(miss < 1e-3, "hit points {} apart", miss);
+                    }
+                    (None, None) => {}
+                    // Only rays that graze the sphere may go either way
+                    _ => disagreements += 1,
+                }
+            }
+            assert!(
+                disagreements * 1000 < hits,
+                "{} disagreements",
+                disagreements
+            );
+            assert!(hits > 1_000);
+        })
+    }
+}
@@ -0,0 +1,64 @@
+// Which eye the camera renders for. The eyes sit interpupillary_distance apart along the
+// camera's u axis, centred on look_from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eye {
+    Center,
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StereoLayout {
+    SideBySide,
+    OverUnder,
+    Separate,
+}
+
+// Puts the two eye images into one, left eye on the left or on top.
+// Separate images are shown side by side.
+pub fn combine_stereo(
+    left: &[u32],
+    right: &[u32],
+    width: i32,
+    height: i32,
+    layout: StereoLayout,
+) -> (Vec<u32>, i32, i32) {
+    let width = width as usize;
+    match layout {
+        StereoLayout::SideBySide | StereoLayout::Separate => {
+            let mut buffer = Vec::with_capacity(left.len() + right.len());
+            for (left_row, right_row) in left.chunks(width).zip(right.chunks(width)) {
+                buffer.extend_from_slice(left_row);
+                buffer.extend_from_slice(right_row);
+            }
+            (buffer, 2 * width as i32, height)
+        }
+        StereoLayout::OverUnder => {
+            let mut buffer = left.to_vec();
+            buffer.extend_from_slice(right);
+            (buffer, width as i32, 2 * height)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eyes_are_laid_out_left_first() {
+        // 3 by 2 images, left pixels count up from 0 and right ones from 100
+        let left: Vec<u32> = (0..6).collect();
+        let right: Vec<u32> = (100..106).collect();
+
+        for layout in [StereoLayout::SideBySide, StereoLayout::Separate] {
+            let (buffer, width, height) = combine_stereo(&left, &right, 3, 2, layout);
+            assert_eq!((width, height), (6, 2));
+            assert_eq!(buffer, vec![0, 1, 2, 100, 101, 102, 3, 4, 5, 103, 104, 105]);
+        }
+
+        let (buffer, width, height) = combine_stereo(&left, &right, 3, 2, StereoLayout::OverUnder);
+        assert_eq!((width, height), (3, 4));
+        assert_eq!(buffer, vec![0, 1, 2, 3, 4, 5, 100, 101, 102, 103, 104, 105]);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::vector_3::Vec3;
+use std::fmt::Debug;
 
-pub trait Texture {
+pub trait Texture: Debug {
     fn value(&self, u: f64, v: f64, point: Vec3) -> Vec3;
 }
@@ -0,0 +1,288 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Tile {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Tile {
+    pub fn new(x: i32, y: i32, width: i32, height: i32) -> Tile {
+        Tile {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn pixel_count(&self) -> usize {
+        (self.width * self.height) as usize
+    }
+
+    // Pixel coordinates covered by the tile, row by row
+    pub fn pixels(&self) -> impl Iterator<Item = (i32, i32)> {
+        let tile = *self;
+        (tile.y..tile.y + tile.height)
+            .flat_map(move |j| (tile.x..tile.x + tile.width).map(move |i| (i, j)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TileOrder {
+    Scanline,
+    Spiral,
+    Hilbert,
+}
+
+// Hands out tiles in the requested order. Each worker owns a queue and steals
+// from the back of the other queues once its own runs dry.
+pub struct TileScheduler {
+    queues: Vec<Mutex<VecDeque<Tile>>>,
+    tile_count: usize,
+}
+
+impl TileScheduler {
+    pub fn new(region: &Tile, tile_size: i32, order: TileOrder, workers: usize) -> TileScheduler {
+        let tiles = TileScheduler::ordered_tiles(region, tile_size, order);
+        let tile_count = tiles.len();
+
+        // Deal the tiles out round-robin so every queue follows the global order
+        let workers = workers.max(1);
+        let mut queues: Vec<VecDeque<Tile>> = (0..workers).map(|_| VecDeque::new()).collect();
+        for (index, tile) in tiles.into_iter().enumerate() {
+            queues[index % workers].push_back(tile);
+        }
+
+        TileScheduler {
+            queues: queues.into_iter().map(Mutex::new).collect(),
+            tile_count,
+        }
+    }
+
+    pub fn tile_count(&self) -> usize {
+        self.tile_count
+    }
+
+    pub fn next(&self, worker: usize) -> Option<Tile> {
+        let worker = worker % self.queues.len();
+        if let Some(tile) = self.queues[worker].lock().unwrap().pop_front() {
+            return Some(tile);
+        }
+
+        // Steal from the back of another worker's queue
+        for offset in 1..self.queues.len() {
+            let victim = (worker + offset) % self.queues.len();
+            if let Some(tile) = self.queues[victim].lock().unwrap().pop_back() {
+                return Some(tile);
+            }
+        }
+        None
+    }
+
+    // Split the region into tiles of at most tile_size pixels on a side
+    pub fn ordered_tiles(region: &Tile, tile_size: i32, order: TileOrder) -> Vec<Tile> {
+        let tile_size = tile_size.max(1);
+        let tiles_x = (region.width + tile_size - 1) / tile_size;
+        let tiles_y = (region.height + tile_size - 1) / tile_size;
+
+        let cells = match order {
+            TileOrder::Scanline => (0..tiles_y)
+                .flat_map(|y| (0..tiles_x).map(move |x| (x, y)))
+                .collect(),
+            TileOrder::Spiral => spiral_cells(tiles_x, tiles_y),
+            TileOrder::Hilbert => hilbert_cells(tiles_x, tiles_y),
+        };
+
+        cells
+            .into_iter()
+            .map(|(x, y)| {
+                let x = x * tile_size;
+                let y = y * tile_size;
+                Tile::new(
+                    region.x + x,
+                    region.y + y,
+                    tile_size.min(region.width - x),
+                    tile_size.min(region.height - y),
+                )
+            })
+            .collect()
+    }
+}
+
+pub fn available_threads() -> u32 {
+    std::thread::available_parallelism()
+        .map(|count| count.get() as u32)
+        .unwrap_or(1)
+}
+
+// Walk outwards from the centre tile, turning right after each run
+fn spiral_cells(tiles_x: i32, tiles_y: i32) -> Vec<(i32, i32)> {
+    let total = (tiles_x * tiles_y) as usize;
+    let mut cells = Vec::with_capacity(total);
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+    let mut x = (tiles_x - 1) / 2;
+    let mut y = (tiles_y - 1) / 2;
+    let mut run = 1;
+    let mut direction = 0;
+    while cells.len() < total {
+        for _ in 0..2 {
+            for _ in 0..run {
+                if x >= 0 && x < tiles_x && y >= 0 && y < tiles_y {
+                    cells.push((x, y));
+                }
+                x += directions[direction].0;
+                y += directions[direction].1;
+            }
+            direction = (direction + 1) % 4;
+        }
+        run += 1;
+    }
+    cells
+}
+
+// Follow a Hilbert curve over the smallest power of two grid covering the tiles
+fn hilbert_cells(tiles_x: i32, tiles_y: i32) -> Vec<(i32, i32)> {
+    let mut side = 1;
+    while side < tiles_x.max(tiles_y) {
+        side *= 2;
+    }
+
+    (0..side * side)
+        .map(|d| hilbert_d_to_xy(side, d))
+        .filter(|&(x, y)| x < tiles_x && y < tiles_y)
+        .collect()
+}
+
+fn hilbert_d_to_xy(side: i32, d: i32) -> (i32, i32) {
+    let mut x = 0;
+    let mut y = 0;
+    let mut t = d;
+    let mut s = 1;
+    while s < side {
+        let rx = 1 & (t / 2);
+        let ry = 1 & (t ^ rx);
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        x += s * rx;
+        y += s * ry;
+        t /= 4;
+        s *= 2;
+    }
+    (x, y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    const ORDERS: [TileOrder; 3] = [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert];
+
+    // Every pixel of the region covered by exactly one tile
+    fn assert_covers(region: &Tile, tiles: &[Tile]) {
+        let mut seen = HashSet::new();
+        for tile in tiles {
+            for pixel in tile.pixels() {
+                assert!(seen.insert(pixel), "pixel {:?} is in two tiles", pixel);
+            }
+        }
+        assert_eq!(seen.len(), region.pixel_count());
+        assert!(region.pixels().all(|pixel| seen.contains(&pixel)));
+    }
+
+    #[test]
+    fn every_order_visits_every_tile_once() {
+        // Non-square and non-power-of-two frames, with partial tiles at the edges
+        let regions = [
+            Tile::new(0, 0, 400, 225),
+            Tile::new(0, 0, 33, 500),
+            Tile::new(10, 20, 1000, 17),
+            Tile::new(0, 0, 96, 96),
+            Tile::new(5, 5, 1, 1),
+        ];
+        for region in &regions {
+            for tile_size in [1, 7, 16, 64] {
+                for order in ORDERS {
+                    let tiles = TileScheduler::ordered_tiles(region, tile_size, order);
+                    let tiles_x = (region.width + tile_size - 1) / tile_size;
+                    let tiles_y = (region.height + tile_size - 1) / tile_size;
+                    assert_eq!(tiles.len(), (tiles_x * tiles_y) as usize, "{:?}", order);
+                    assert_covers(region, &tiles);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn spiral_starts_in_the_centre_and_hilbert_steps_to_neighbours() {
+        let region = Tile::new(0, 0, 9 * 16, 5 * 16);
+        let spiral = TileScheduler::ordered_tiles(&region, 16, TileOrder::Spiral);
+        assert_eq!((spiral[0].x, spiral[0].y), (4 * 16, 2 * 16));
+
+        // On a power of two grid the curve never jumps
+        let region = Tile::new(0, 0, 8 * 16, 8 * 16);
+        let hilbert = TileScheduler::ordered_tiles(&region, 16, TileOrder::Hilbert);
+        for pair in hilbert.windows(2) {
+            let step = (pair[0].x - pair[1].x).abs() + (pair[0].y - pair[1].y).abs();
+            assert_eq!(step, 16);
+        }
+    }
+
+    #[test]
+    fn stealing_drains_every_queue() {
+        let region = Tile::new(0, 0, 250, 130);
+        for workers in [1, 2, 3, 7, 40] {
+            // One worker takes everything, stealing what was dealt to the others
+            let scheduler = TileScheduler::new(&region, 16, TileOrder::Spiral, workers);
+            let tiles: Vec<Tile> = std::iter::from_fn(|| scheduler.next(0)).collect();
+            assert_eq!(tiles.len(), scheduler.tile_count());
+            assert_covers(&region, &tiles);
+            for worker in 0..workers {
+                assert!(scheduler.next(worker).is_none());
+            }
+        }
+    }
+
+    #[test]
+    fn uneven_workers_share_every_tile() {
+        let region = Tile::new(0, 0, 300, 170);
+        let workers = 4;
+        let scheduler = Arc::new(TileScheduler::new(&region, 8, TileOrder::Hilbert, workers));
+        // Worker n takes n milliseconds a tile, so the fast ones steal from the slow
+        let handles: Vec<_> = (0..workers)
+            .map(|worker| {
+                let scheduler = Arc::clone(&scheduler);
+                thread::spawn(move || {
+                    let mut tiles = Vec::new();
+                    while let Some(tile) = scheduler.next(worker) {
+                        tiles.push(tile);
+                        thread::sleep(Duration::from_millis(worker as u64));
+                    }
+                    tiles
+                })
+            })
+            .collect();
+        let per_worker: Vec<Vec<Tile>> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        let dealt = scheduler.tile_count().div_ceil(workers);
+        assert!(
+            per_worker[0].len() > dealt,
+            "the fastest worker stole nothing"
+        );
+        let tiles: Vec<Tile> = per_worker.into_iter().flatten().collect();
+        assert_eq!(tiles.len(), scheduler.tile_count());
+        assert_covers(&region, &tiles);
+    }
+}
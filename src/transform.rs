@@ -0,0 +1,165 @@
+use crate::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::matrix_4::Matrix4;
+use crate::ray::Ray3;
+use crate::vector_3::Vec3;
+use std::sync::Arc;
+
+// Places an object in the world with any affine transform, so translations, rotations
+// about any axis, scales and shears can be stacked into one matrix instead of nesting
+// an instance per operation.
+#[derive(Debug)]
+pub struct Transform {
+    object: Arc<dyn Hittable + Sync + Send>,
+    object_to_world: Matrix4,
+    world_to_object: Matrix4,
+    // Normals go through the inverse transpose to stay perpendicular to the surface
+    normal_to_world: Matrix4,
+    bbox: AABB,
+}
+
+impl Hittable for Transform {
+    fn hit(&self, ray_in: &Ray3, time: Interval, hit_record: &mut HitRecord) -> bool {
+        // The direction is not normalised so the ray parameter is the same in both spaces
+        let object_ray = Ray3::new(
+            self.world_to_object.transform_point(ray_in.origin()),
+            self.world_to_object.transform_vector(ray_in.direction()),
+            ray_in.time(),
+        );
+
+        if self.object.hit(&object_ray, time, hit_record) {
+            hit_record.point = self.object_to_world.transform_point(&hit_record.point);
+            hit_record.normal = self
+                .normal_to_world
+                .transform_vector(&hit_record.normal)
+                .unit_vector();
+            hit_record.object = self as *const Transform as usize;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox.copy()
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        let local_origin = self.world_to_object.transform_point(origin);
+        let local_direction = self.world_to_object.transform_vector(direction);
+        let pdf = self.object.pdf_value(&local_origin, &local_direction);
+        if pdf == 0.0 {
+            return 0.0;
+        }
+
+        // Change of solid angle when the direction is mapped into object space
+        let ratio = direction.length() / local_direction.length();
+        pdf * self.world_to_object.linear_determinant().abs() * ratio * ratio * ratio
+    }
+
+    fn random(&self, origin: &Vec3) -> Vec3 {
+        let local_origin = self.world_to_object.transform_point(origin);
+        self.object_to_world
+            .transform_vector(&self.object.random(&local_origin))
+    }
+}
+
+impl Transform {
+    // None if the matrix cannot be inverted, as the object would be flattened
+    pub fn new(
+        object: Arc<dyn Hittable + Sync + Send>,
+        object_to_world: Matrix4,
+    ) -> Option<Transform> {
+        let world_to_object = object_to_world.inverse()?;
+
+        // The corners of the object's box bound it in any affine transform
+        let object_box = object.bounding_box();
+        let mut min = Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut max = Vec3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for corner in 0..8 {
+            let point = object_to_world.transform_point(&Vec3::new(
+                Self::corner_value(&object_box, 0, corner & 1),
+                Self::corner_value(&object_box, 1, corner & 2),
+                Self::corner_value(&object_box, 2, corner & 4),
+            ));
+            for axis in 0..3 {
+                min.set(axis, min.at(axis).min(point.at(axis)));
+                max.set(axis, max.at(axis).max(point.at(axis)));
+            }
+        }
+
+        Some(Transform {
+            object,
+            object_to_world,
+            world_to_object,
+            normal_to_world: world_to_object.transpose(),
+            bbox: AABB::from_vec3s(min, max).pad(),
+        })
+    }
+
+    pub fn matrix(&self) -> &Matrix4 {
+        &self.object_to_world
+    }
+
+    fn corner_value(bbox: &AABB, axis: u32, use_max: usize) -> f64 {
+        if use_max != 0 {
+            bbox.axis(axis).max()
+        } else {
+            bbox.axis(axis).min()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::affine_transforms::RotateY;
+    use crate::lambertian::Lambertian;
+    use crate::quad::quad_box;
+    use crate::rtweekend::with_seed;
+
+    #[test]
+    fn rotation_y_hits_like_rotate_y() {
+        let mat = Arc::new(Lambertian::from_color(Vec3::new(0.5, 0.5, 0.5)));
+        let object: Arc<dyn Hittable + Sync + Send> = quad_box(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(165.0, 330.0, 165.0),
+            mat,
+        );
+        for degrees in [15.0, -18.0, 75.0] {
+            let old = RotateY::new(Arc::clone(&object), degrees);
+            let new = Transform::new(Arc::clone(&object), Matrix4::rotation_y(degrees)).unwrap();
+            for axis in 0..3 {
+                let (old_box, new_box) =
+                    (old.bounding_box().axis(axis), new.bounding_box().axis(axis));
+                assert!((old_box.min() - new_box.min()).abs() < 1e-3);
+                assert!((old_box.max() - new_box.max()).abs() < 1e-3);
+            }
+            with_seed(3, || {
+                for _ in 0..2_000 {
+                    let origin = 400.0 * Vec3::random_unit_vector();
+                    let target = Vec3::random_range(-50.0, 250.0);
+                    let ray = Ray3::new(origin, target - origin, 0.0);
+                    let interval = Interval::new(0.001, f64::INFINITY);
+                    let (mut old_hit, mut new_hit) = (HitRecord::new(), HitRecord::new());
+                    let hit = old.hit(&ray, interval.copy(), &mut old_hit);
+                    assert_eq!(hit, new.hit(&ray, interval, &mut new_hit));
+                    if hit {
+                        assert!((old_hit.time - new_hit.time).abs() < 1e-9);
+                        assert!((old_hit.normal - new_hit.normal).length() < 1e-9);
+                        assert!((old_hit.point - new_hit.point).length() < 1e-9);
+                    }
+                }
+            })
+        }
+    }
+
+    #[test]
+    fn flattening_matrices_are_rejected() {
+        let mat = Arc::new(Lambertian::from_color(Vec3::new(0.5, 0.5, 0.5)));
+        let object = quad_box(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0), mat);
+        let flat = Matrix4::scale(Vec3::new(1.0, 1.0, 0.0));
+        assert!(Transform::new(object, flat).is_none());
+    }
+}
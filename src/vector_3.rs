@@ -1,35 +1,41 @@
 use core::ops;
 use std::f64::consts::PI;
 
-use crate::rtweekend::random_f64_range;
+use crate::real::Real;
+use crate::rtweekend::{random_f64, random_f64_range};
 
+// Generic over the precision so the core math can run in f32 as well. Rendering uses
+// `Vec3`, the f64 one, and `Vec3f` is its f32 counterpart.
 #[derive(Debug, Clone, Copy)]
-pub struct Vec3 {
-    pub x: f64,
-    pub y: f64,
-    pub z: f64,
+pub struct Vector3<T> {
+    pub x: T,
+    pub y: T,
+    pub z: T,
 }
 
-impl Vec3 {
-    pub fn new(x: f64, y: f64, z: f64) -> Vec3 {
-        Vec3 { x, y, z }
+pub type Vec3 = Vector3<f64>;
+pub type Vec3f = Vector3<f32>;
+
+impl<T: Real> Vector3<T> {
+    pub fn new(x: T, y: T, z: T) -> Vector3<T> {
+        Vector3 { x, y, z }
     }
 
-    pub fn empty() -> Vec3 {
-        Vec3::new(0.0, 0.0, 0.0)
+    pub fn empty() -> Vector3<T> {
+        Vector3::new(T::ZERO, T::ZERO, T::ZERO)
     }
 
-    pub fn set_vals(&mut self, x: f64, y: f64, z: f64) {
+    pub fn set_vals(&mut self, x: T, y: T, z: T) {
         self.x = x;
         self.y = y;
         self.z = z;
     }
 
-    pub fn set_vec(&mut self, xyz: Vec3) {
+    pub fn set_vec(&mut self, xyz: Vector3<T>) {
         self.set_vals(xyz.x, xyz.y, xyz.z);
     }
 
-    pub fn at(&self, index: u32) -> f64 {
+    pub fn at(&self, index: u32) -> T {
         if index == 0 {
             self.x
         } else if index == 1 {
@@ -39,7 +45,7 @@ impl Vec3 {
         }
     }
 
-    pub fn set(&mut self, index: u32, value: f64) {
+    pub fn set(&mut self, index: u32, value: T) {
         if index == 0 {
             self.x = value;
         } else if index == 1 {
@@ -49,39 +55,62 @@ impl Vec3 {
         }
     }
 
-    pub fn length(&self) -> f64 {
+    // The same vector in another precision, rounded to nearest
+    pub fn cast<U: Real>(&self) -> Vector3<U> {
+        Vector3::new(
+            U::from_f64(self.x.to_f64()),
+            U::from_f64(self.y.to_f64()),
+            U::from_f64(self.z.to_f64()),
+        )
+    }
+
+    pub fn length(&self) -> T {
         self.length_squared().sqrt()
     }
 
-    pub fn length_squared(&self) -> f64 {
+    pub fn length_squared(&self) -> T {
         self.dot(self)
     }
 
     pub fn near_zero(&self) -> bool {
-        self.x.abs() < f64::EPSILON && self.y.abs() < f64::EPSILON && self.z.abs() < f64::EPSILON
+        self.x.abs() < T::EPSILON && self.y.abs() < T::EPSILON && self.z.abs() < T::EPSILON
     }
 
-    pub fn dot(&self, _rhs: &Vec3) -> f64 {
-        (self.x * _rhs.x) + (self.y * _rhs.y) + (self.z * _rhs.z)
+    pub fn dot(&self, rhs: &Vector3<T>) -> T {
+        (self.x * rhs.x) + (self.y * rhs.y) + (self.z * rhs.z)
     }
 
-    pub fn cross(&self, _rhs: &Vec3) -> Vec3 {
-        Vec3 {
-            x: self.y * _rhs.z - self.z * _rhs.y,
-            y: self.z * _rhs.x - self.x * _rhs.z,
-            z: self.x * _rhs.y - self.y * _rhs.x,
+    pub fn cross(&self, rhs: &Vector3<T>) -> Vector3<T> {
+        Vector3 {
+            x: self.y * rhs.z - self.z * rhs.y,
+            y: self.z * rhs.x - self.x * rhs.z,
+            z: self.x * rhs.y - self.y * rhs.x,
         }
     }
 
-    pub fn unit_vector(&self) -> Vec3 {
-        self.clone() / self.length()
+    pub fn unit_vector(&self) -> Vector3<T> {
+        *self / self.length()
     }
 
+    pub fn reflect(&self, normal: &Vector3<T>) -> Vector3<T> {
+        let two = T::ONE + T::ONE;
+        *self - normal * (two * self.dot(normal))
+    }
+
+    pub fn refract(uv: &Vector3<T>, n: &Vector3<T>, etai_over_etat: T) -> Vector3<T> {
+        let cos_theta = (-uv).dot(n).min(T::ONE);
+        let ray_out_perp = (uv + n * cos_theta) * etai_over_etat;
+        let ray_out_parallel = n * -(T::ONE - ray_out_perp.length_squared()).abs().sqrt();
+        ray_out_perp + ray_out_parallel
+    }
+}
+
+impl Vec3 {
     pub fn random() -> Vec3 {
         Vec3 {
-            x: rand::random::<f64>(),
-            y: rand::random::<f64>(),
-            z: rand::random::<f64>(),
+            x: random_f64(),
+            y: random_f64(),
+            z: random_f64(),
         }
     }
 
@@ -124,8 +153,8 @@ impl Vec3 {
     }
 
     pub fn random_cosine_direction() -> Vec3 {
-        let r1 = rand::random::<f64>();
-        let r2 = rand::random::<f64>();
+        let r1 = random_f64();
+        let r2 = random_f64();
         let sqrt_r2 = r2.sqrt();
 
         let phi = 2.0 * PI * r1;
@@ -135,37 +164,14 @@ impl Vec3 {
 
         Vec3::new(x, y, z)
     }
-
-    pub fn reflect(&self, normal: &Vec3) -> Vec3 {
-        self.clone() - (2.0 * self.dot(normal) * normal)
-    }
-
-    pub fn refract(uv: &Vec3, n: &Vec3, etai_over_etat: f64) -> Vec3 {
-        let cos_theta = (-uv).dot(n).min(1.0);
-        let ray_out_perp = etai_over_etat * (uv.clone() + (cos_theta * n));
-        let ray_out_parallel = -(1.0 - ray_out_perp.length_squared()).abs().sqrt() * n;
-        ray_out_perp + ray_out_parallel
-    }
 }
 
 // Negate
-impl ops::Neg for Vec3 {
-    type Output = Self;
-
-    fn neg(self) -> Self {
-        Self {
-            x: -self.x,
-            y: -self.y,
-            z: -self.z,
-        }
-    }
-}
+impl<T: Real> ops::Neg for Vector3<T> {
+    type Output = Vector3<T>;
 
-impl<'a> ops::Neg for &'a Vec3 {
-    type Output = Vec3;
-
-    fn neg(self) -> Vec3 {
-        Vec3 {
+    fn neg(self) -> Vector3<T> {
+        Vector3 {
             x: -self.x,
             y: -self.y,
             z: -self.z,
@@ -173,317 +179,134 @@ impl<'a> ops::Neg for &'a Vec3 {
     }
 }
 
-// Add
-impl ops::Add<Self> for Vec3 {
-    type Output = Vec3;
-
-    fn add(self, _rhs: Self) -> Vec3 {
-        Vec3 {
-            x: self.x + _rhs.x,
-            y: self.y + _rhs.y,
-            z: self.z + _rhs.z,
-        }
-    }
-}
-
-impl<'a, 'b> ops::Add<&'a Self> for &'b Vec3 {
-    type Output = Vec3;
-
-    fn add(self, _rhs: &Self) -> Vec3 {
-        Vec3 {
-            x: self.x + _rhs.x,
-            y: self.y + _rhs.y,
-            z: self.z + _rhs.z,
-        }
-    }
-}
-
-impl ops::Add<f64> for Vec3 {
-    type Output = Vec3;
-
-    fn add(self, _rhs: f64) -> Vec3 {
-        Vec3 {
-            x: self.x + _rhs,
-            y: self.y + _rhs,
-            z: self.z + _rhs,
-        }
-    }
-}
-
-impl<'a> ops::Add<f64> for &'a Vec3 {
-    type Output = Vec3;
-
-    fn add(self, _rhs: f64) -> Vec3 {
-        Vec3 {
-            x: self.x + _rhs,
-            y: self.y + _rhs,
-            z: self.z + _rhs,
-        }
-    }
-}
-
-impl ops::Add<Vec3> for f64 {
-    type Output = Vec3;
-
-    fn add(self, _rhs: Vec3) -> Vec3 {
-        Vec3 {
-            x: self + _rhs.x,
-            y: self + _rhs.y,
-            z: self + _rhs.z,
-        }
-    }
-}
-
-impl<'a> ops::Add<&'a Vec3> for f64 {
-    type Output = Vec3;
-
-    fn add(self, _rhs: &'a Vec3) -> Vec3 {
-        Vec3 {
-            x: self + _rhs.x,
-            y: self + _rhs.y,
-            z: self + _rhs.z,
-        }
-    }
-}
-
-// Add Assign
-impl ops::AddAssign<Self> for Vec3 {
-    fn add_assign(&mut self, rhs: Self) {
-        self.x += rhs.x;
-        self.y += rhs.y;
-        self.z += rhs.z;
-    }
-}
-
-impl ops::AddAssign<f64> for Vec3 {
-    fn add_assign(&mut self, rhs: f64) {
-        self.x += rhs;
-        self.y += rhs;
-        self.z += rhs;
-    }
-}
-
-// Subtract
-impl ops::Sub<Vec3> for Vec3 {
-    type Output = Vec3;
-
-    fn sub(self, _rhs: Vec3) -> Vec3 {
-        Vec3 {
-            x: self.x - _rhs.x,
-            y: self.y - _rhs.y,
-            z: self.z - _rhs.z,
-        }
-    }
-}
-
-impl<'a, 'b> ops::Sub<&'a Vec3> for &'b Vec3 {
-    type Output = Vec3;
-
-    fn sub(self, _rhs: &'a Vec3) -> Vec3 {
-        Vec3 {
-            x: self.x - _rhs.x,
-            y: self.y - _rhs.y,
-            z: self.z - _rhs.z,
-        }
-    }
-}
-
-impl ops::Sub<f64> for Vec3 {
-    type Output = Vec3;
-
-    fn sub(self, _rhs: f64) -> Vec3 {
-        Vec3 {
-            x: self.x - _rhs,
-            y: self.y - _rhs,
-            z: self.z - _rhs,
-        }
-    }
-}
-
-impl<'a> ops::Sub<f64> for &'a Vec3 {
-    type Output = Vec3;
-
-    fn sub(self, _rhs: f64) -> Vec3 {
-        Vec3 {
-            x: self.x - _rhs,
-            y: self.y - _rhs,
-            z: self.z - _rhs,
-        }
-    }
-}
-
-impl ops::Sub<Vec3> for f64 {
-    type Output = Vec3;
+impl<T: Real> ops::Neg for &Vector3<T> {
+    type Output = Vector3<T>;
 
-    fn sub(self, _rhs: Vec3) -> Vec3 {
-        Vec3 {
-            x: self - _rhs.x,
-            y: self - _rhs.y,
-            z: self - _rhs.z,
-        }
+    fn neg(self) -> Vector3<T> {
+        -*self
     }
 }
 
-impl<'a> ops::Sub<&'a Vec3> for f64 {
-    type Output = Vec3;
+// Component by component with another vector or a scalar, on values or references
+macro_rules! vector_op {
+    ($op:ident, $method:ident) => {
+        impl<T: Real> ops::$op<Vector3<T>> for Vector3<T> {
+            type Output = Vector3<T>;
 
-    fn sub(self, _rhs: &'a Vec3) -> Vec3 {
-        Vec3 {
-            x: self - _rhs.x,
-            y: self - _rhs.y,
-            z: self - _rhs.z,
+            fn $method(self, rhs: Vector3<T>) -> Vector3<T> {
+                Vector3 {
+                    x: ops::$op::$method(self.x, rhs.x),
+                    y: ops::$op::$method(self.y, rhs.y),
+                    z: ops::$op::$method(self.z, rhs.z),
+                }
+            }
         }
-    }
-}
 
-// Multiply
-impl ops::Mul<Self> for Vec3 {
-    type Output = Vec3;
+        impl<T: Real> ops::$op<&Vector3<T>> for Vector3<T> {
+            type Output = Vector3<T>;
 
-    fn mul(self, _rhs: Self) -> Vec3 {
-        Vec3 {
-            x: self.x * _rhs.x,
-            y: self.y * _rhs.y,
-            z: self.z * _rhs.z,
+            fn $method(self, rhs: &Vector3<T>) -> Vector3<T> {
+                ops::$op::$method(self, *rhs)
+            }
         }
-    }
-}
 
-impl<'a, 'b> ops::Mul<&'a Self> for &'b Vec3 {
-    type Output = Vec3;
+        impl<T: Real> ops::$op<Vector3<T>> for &Vector3<T> {
+            type Output = Vector3<T>;
 
-    fn mul(self, _rhs: &Self) -> Vec3 {
-        Vec3 {
-            x: self.x * _rhs.x,
-            y: self.y * _rhs.y,
-            z: self.z * _rhs.z,
-        }
-    }
-}
-
-impl ops::Mul<f64> for Vec3 {
-    type Output = Vec3;
-
-    fn mul(self, _rhs: f64) -> Vec3 {
-        Vec3 {
-            x: self.x * _rhs,
-            y: self.y * _rhs,
-            z: self.z * _rhs,
+            fn $method(self, rhs: Vector3<T>) -> Vector3<T> {
+                ops::$op::$method(*self, rhs)
+            }
         }
-    }
-}
 
-impl<'a> ops::Mul<f64> for &'a Vec3 {
-    type Output = Vec3;
+        impl<T: Real> ops::$op<&Vector3<T>> for &Vector3<T> {
+            type Output = Vector3<T>;
 
-    fn mul(self, _rhs: f64) -> Vec3 {
-        Vec3 {
-            x: self.x * _rhs,
-            y: self.y * _rhs,
-            z: self.z * _rhs,
+            fn $method(self, rhs: &Vector3<T>) -> Vector3<T> {
+                ops::$op::$method(*self, *rhs)
+            }
         }
-    }
-}
 
-impl ops::Mul<Vec3> for f64 {
-    type Output = Vec3;
+        impl<T: Real> ops::$op<T> for Vector3<T> {
+            type Output = Vector3<T>;
 
-    fn mul(self, _rhs: Vec3) -> Vec3 {
-        Vec3 {
-            x: self * _rhs.x,
-            y: self * _rhs.y,
-            z: self * _rhs.z,
+            fn $method(self, rhs: T) -> Vector3<T> {
+                Vector3 {
+                    x: ops::$op::$method(self.x, rhs),
+                    y: ops::$op::$method(self.y, rhs),
+                    z: ops::$op::$method(self.z, rhs),
+                }
+            }
         }
-    }
-}
 
-impl<'a> ops::Mul<&'a Vec3> for f64 {
-    type Output = Vec3;
+        impl<T: Real> ops::$op<T> for &Vector3<T> {
+            type Output = Vector3<T>;
 
-    fn mul(self, _rhs: &'a Vec3) -> Vec3 {
-        Vec3 {
-            x: self * _rhs.x,
-            y: self * _rhs.y,
-            z: self * _rhs.z,
+            fn $method(self, rhs: T) -> Vector3<T> {
+                ops::$op::$method(*self, rhs)
+            }
         }
-    }
+    };
 }
 
-// Divide
-impl ops::Div<Vec3> for Vec3 {
-    type Output = Self;
+vector_op!(Add, add);
+vector_op!(Sub, sub);
+vector_op!(Mul, mul);
+vector_op!(Div, div);
 
-    fn div(self, _rhs: Self) -> Self {
-        Self {
-            x: self.x / _rhs.x,
-            y: self.y / _rhs.y,
-            z: self.z / _rhs.z,
-        }
-    }
-}
+// A scalar on the left, which has to name each float type
+macro_rules! scalar_op {
+    ($t:ident, $op:ident, $method:ident) => {
+        impl ops::$op<Vector3<$t>> for $t {
+            type Output = Vector3<$t>;
 
-impl<'a, 'b> ops::Div<&'a Vec3> for &'b Vec3 {
-    type Output = Vec3;
-
-    fn div(self, _rhs: &'a Vec3) -> Vec3 {
-        Vec3 {
-            x: self.x / _rhs.x,
-            y: self.y / _rhs.y,
-            z: self.z / _rhs.z,
+            fn $method(self, rhs: Vector3<$t>) -> Vector3<$t> {
+                Vector3 {
+                    x: ops::$op::$method(self, rhs.x),
+                    y: ops::$op::$method(self, rhs.y),
+                    z: ops::$op::$method(self, rhs.z),
+                }
+            }
         }
-    }
-}
 
-impl ops::Div<f64> for Vec3 {
-    type Output = Self;
+        impl<'a> ops::$op<&'a Vector3<$t>> for $t {
+            type Output = Vector3<$t>;
 
-    fn div(self, _rhs: f64) -> Self {
-        Self {
-            x: self.x / _rhs,
-            y: self.y / _rhs,
-            z: self.z / _rhs,
+            fn $method(self, rhs: &'a Vector3<$t>) -> Vector3<$t> {
+                ops::$op::$method(self, *rhs)
+            }
         }
-    }
+    };
 }
 
-impl<'a> ops::Div<f64> for &'a Vec3 {
-    type Output = Vec3;
+scalar_op!(f32, Add, add);
+scalar_op!(f32, Sub, sub);
+scalar_op!(f32, Mul, mul);
+scalar_op!(f32, Div, div);
+scalar_op!(f64, Add, add);
+scalar_op!(f64, Sub, sub);
+scalar_op!(f64, Mul, mul);
+scalar_op!(f64, Div, div);
 
-    fn div(self, _rhs: f64) -> Vec3 {
-        Vec3 {
-            x: self.x / _rhs,
-            y: self.y / _rhs,
-            z: self.z / _rhs,
-        }
+// Add Assign
+impl<T: Real> ops::AddAssign<Vector3<T>> for Vector3<T> {
+    fn add_assign(&mut self, rhs: Vector3<T>) {
+        self.x += rhs.x;
+        self.y += rhs.y;
+        self.z += rhs.z;
     }
 }
 
-impl ops::Div<Vec3> for f64 {
-    type Output = Vec3;
-
-    fn div(self, _rhs: Vec3) -> Vec3 {
-        Vec3 {
-            x: self / _rhs.x,
-            y: self / _rhs.y,
-            z: self / _rhs.z,
-        }
+impl<T: Real> ops::AddAssign<T> for Vector3<T> {
+    fn add_assign(&mut self, rhs: T) {
+        self.x += rhs;
+        self.y += rhs;
+        self.z += rhs;
     }
 }
 
-impl<'a> ops::Div<&'a Vec3> for f64 {
-    type Output = Vec3;
-
-    fn div(self, _rhs: &'a Vec3) -> Vec3 {
-        Vec3 {
-            x: self / _rhs.x,
-            y: self / _rhs.y,
-            z: self / _rhs.z,
-        }
-    }
-}
 #[cfg(test)]
 mod tests {
-    use crate::vector_3::Vec3;
+    use crate::vector_3::{Vec3, Vec3f};
 
     #[test]
     fn test_new() {
@@ -519,4 +342,20 @@ mod tests {
         assert_eq!(vec.y, 2.0);
         assert_eq!(vec.z, 3.0);
     }
+
+    #[test]
+    fn f32_matches_f64() {
+        let a = Vec3::new(0.5, -1.25, 2.0);
+        let b = Vec3::new(3.0, 0.75, -0.5);
+        let (af, bf): (Vec3f, Vec3f) = (a.cast(), b.cast());
+        // Every input and result here is exact in f32
+        assert_eq!(af.dot(&bf) as f64, a.dot(&b));
+        let cross = af.cross(&bf).cast::<f64>();
+        assert_eq!((cross - a.cross(&b)).length(), 0.0);
+        let sum = (2.0 * af + bf / 4.0 - 1.0).cast::<f64>();
+        assert_eq!((sum - (2.0 * a + b / 4.0 - 1.0)).length(), 0.0);
+        let reflected = af.reflect(&Vec3f::new(0.0, 1.0, 0.0));
+        assert_eq!((reflected.x, reflected.y, reflected.z), (0.5, 1.25, 2.0));
+        assert!((af.unit_vector().length() - 1.0).abs() < 1e-6);
+    }
 }
@@ -0,0 +1,69 @@
+// The counters are global to the process, so this test runs in its own binary where no
+// other test is rendering while they are on
+use ray_tracing::camera::Camera;
+use ray_tracing::film::Film;
+use ray_tracing::hittable::Hittable;
+use ray_tracing::hittable_list::HittableList;
+use ray_tracing::lambertian::Lambertian;
+use ray_tracing::ray_stats;
+use ray_tracing::render_progress::CancelToken;
+use ray_tracing::sphere::Sphere;
+use ray_tracing::vector_3::Vec3;
+use std::sync::Arc;
+
+#[test]
+fn one_sphere_counts_every_ray_and_path() {
+    let mut world = HittableList::new();
+    world.add(Arc::new(Sphere::new(
+        Vec3::new(0.0, 0.0, -2.0),
+        1.0,
+        Arc::new(Lambertian::from_color(Vec3::new(0.5, 0.5, 0.5))),
+    )));
+    let world: Arc<dyn Hittable + Sync + Send> = Arc::new(world);
+
+    let mut cam = Camera::new();
+    cam.image_width = 24;
+    cam.aspect_ratio = 1.5;
+    cam.max_depth = 6;
+    cam.look_from = Vec3::new(0.0, 0.0, 0.0);
+    cam.look_at = Vec3::new(0.0, 0.0, -1.0);
+    cam.tile_size = 8;
+    cam.initalize();
+    let (width, height) = (cam.render_width(), cam.render_height());
+    let samples = 5;
+
+    ray_stats::start(width, height);
+    let mut film = Film::new(width, height);
+    cam.render_film(
+        world,
+        None,
+        3,
+        &mut film,
+        samples,
+        |_| {},
+        &CancelToken::new(),
+    );
+    let stats = ray_stats::stop().unwrap();
+
+    let pixels = (width * height) as u64;
+    let totals = &stats.totals;
+    assert_eq!(totals.primary_rays, pixels * samples as u64);
+    assert!(totals.secondary_rays > 0, "no ray bounced off the sphere");
+    assert!(totals.primitive_tests >= totals.primary_rays + totals.secondary_rays);
+
+    // One path per camera sample, made of every ray that was traced
+    assert_eq!(totals.path_lengths.iter().sum::<u64>(), totals.primary_rays);
+    let rays: u64 = totals
+        .path_lengths
+        .iter()
+        .enumerate()
+        .map(|(length, &count)| length as u64 * count)
+        .sum();
+    assert_eq!(rays, totals.primary_rays + totals.secondary_rays);
+    assert!(totals.path_lengths.len() <= cam.max_depth as usize + 1);
+
+    assert!(stats
+        .pixel_samples
+        .iter()
+        .all(|&count| count == samples as u64));
+}